@@ -0,0 +1,28 @@
+use bech32::primitives::decode::CheckedHrpstring;
+use bech32::Bech32m;
+
+use crate::chainparams::params;
+
+/// Payload lengths the node accepts: a public key hash or a script hash.
+const PAYLOAD_SIZES: [usize; 2] = [20, 32];
+
+/// Checks the prefix, bech32m checksum and length of the address the
+/// coinbase pays to, so a typo never costs a block the node would reject.
+pub fn validate(address: &str) -> Result<(), String> {
+    let checked = CheckedHrpstring::new::<Bech32m>(address)
+        .map_err(|e| format!("Invalid address {}: {}", address, e))?;
+
+    let prefix = params().address_prefix;
+    if checked.hrp().to_lowercase() != prefix {
+        return Err(format!(
+            "Address {} is not for {} (expected prefix {})",
+            address,
+            params().network,
+            prefix
+        ));
+    }
+    if !PAYLOAD_SIZES.contains(&checked.byte_iter().count()) {
+        return Err(format!("Invalid address {}: wrong length", address));
+    }
+    Ok(())
+}
@@ -0,0 +1,52 @@
+use crate::block::{Block, Transaction};
+use crate::search::Search;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Hashes a synthetic header on every thread for `duration` and prints the
+/// hashrate each thread reached, plus the total.
+pub fn run(search: &Search, duration: Duration) {
+    let template = Block {
+        timestamp: 1_700_000_000,
+        prev_block_hash: [0xAB; 32],
+        hash: [0; 32],
+        transactions: vec![Transaction {
+            sender: "bench-sender".to_string(),
+            receiver: "bench-receiver".to_string(),
+            amount: 1.0,
+            fee: 0.0,
+            timestamp: 1_700_000_000,
+            nonce: 0,
+            script: None,
+            lock_time: 0,
+            relative_lock: 0,
+            data: None,
+            token: None,
+            signature: None,
+        }],
+        nonce: 0,
+        reward: 50.0,
+        // Out of reach, so the search runs for the whole benchmark.
+        target: [0; 32],
+        height: 1,
+    };
+
+    println!("Benchmarking for {} seconds...", duration.as_secs());
+
+    let start_time = Instant::now();
+    thread::scope(|scope| {
+        scope.spawn(|| {
+            thread::sleep(duration);
+            search.stop();
+        });
+        search.run(&template, "bench-miner");
+    });
+    let elapsed = start_time.elapsed().as_secs_f64();
+
+    let thread_hashes = search.thread_hashes();
+    for (i, hashes) in thread_hashes.iter().enumerate() {
+        println!("Thread {}: {:.0} hashes/s", i, *hashes as f64 / elapsed);
+    }
+    let total: u64 = thread_hashes.iter().sum();
+    println!("Total: {:.0} hashes/s", total as f64 / elapsed);
+}
@@ -0,0 +1,317 @@
+use sha2::{Digest, Sha256};
+
+pub const HEADER_SIZE: usize = 80;
+pub const COINBASE_SENDER: &str = "Coinbase";
+
+#[derive(serde::Deserialize, serde::Serialize, Debug, Clone)]
+pub struct Block {
+    pub timestamp: u64,
+    pub prev_block_hash: [u8; 32],
+    pub hash: [u8; 32],
+    pub transactions: Vec<Transaction>,
+    pub nonce: u64,
+    /// Block reward the coinbase claims on top of the fees. Templates come
+    /// without it; the miner takes it from the node's chain parameters.
+    #[serde(skip)]
+    pub reward: f64,
+    /// The largest hash the block may have, from the node's difficulty.
+    #[serde(skip)]
+    pub target: [u8; 32],
+    /// Height the block will have, which its coinbase must carry.
+    #[serde(skip)]
+    pub height: u64,
+}
+
+/// The largest header hash with `difficulty` leading zero hex digits.
+pub fn target(difficulty: usize) -> [u8; 32] {
+    let mut target = [0xFF; 32];
+    for digit in 0..difficulty.min(64) {
+        target[digit / 2] &= if digit % 2 == 0 { 0x0F } else { 0x00 };
+    }
+    target
+}
+
+#[derive(serde::Deserialize, serde::Serialize, Debug, Clone)]
+pub struct Transaction {
+    pub sender: String,
+    pub receiver: String,
+    pub amount: f64,
+    #[serde(default)]
+    pub fee: f64,
+    pub timestamp: u64,
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub nonce: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub script: Option<String>,
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub lock_time: u64,
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub relative_lock: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub data: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub token: Option<TokenOp>,
+    pub signature: Option<String>,
+}
+
+/// A token operation, carried only so the transaction root can commit to
+/// it the way the node does.
+#[derive(serde::Deserialize, serde::Serialize, Debug, Clone)]
+#[serde(tag = "op", rename_all = "lowercase")]
+pub enum TokenOp {
+    Issue {
+        name: String,
+        symbol: String,
+        decimals: u8,
+        supply: u64,
+        #[serde(default)]
+        mintable: bool,
+    },
+    Transfer {
+        token: String,
+        amount: u64,
+    },
+    Mint {
+        token: String,
+        amount: u64,
+    },
+    Burn {
+        token: String,
+        amount: u64,
+    },
+}
+
+impl Transaction {
+    /// The bytes the node has the sender sign: every field but the
+    /// signature, with each optional field tagged and length-prefixed.
+    fn signing_data(&self) -> Vec<u8> {
+        let mut data = Vec::new();
+        data.extend_from_slice(self.sender.as_bytes());
+        data.extend_from_slice(self.receiver.as_bytes());
+        data.extend_from_slice(&self.amount.to_le_bytes());
+        data.extend_from_slice(&self.fee.to_le_bytes());
+        data.extend_from_slice(&self.timestamp.to_le_bytes());
+        if self.nonce != 0 {
+            push_field(&mut data, FIELD_NONCE, &self.nonce.to_le_bytes());
+        }
+        if let Some(script) = &self.script {
+            push_field(&mut data, FIELD_SCRIPT, script.as_bytes());
+        }
+        if self.lock_time != 0 {
+            push_field(&mut data, FIELD_LOCK_TIME, &self.lock_time.to_le_bytes());
+        }
+        if self.relative_lock != 0 {
+            push_field(
+                &mut data,
+                FIELD_RELATIVE_LOCK,
+                &self.relative_lock.to_le_bytes(),
+            );
+        }
+        if let Some(payload) = &self.data {
+            push_field(&mut data, FIELD_DATA, payload.as_bytes());
+        }
+        if let Some(token) = &self.token {
+            push_field(&mut data, FIELD_TOKEN, &token.signing_bytes());
+        }
+        data
+    }
+}
+
+impl TokenOp {
+    fn signing_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        let (kind, token, amount) = match self {
+            TokenOp::Issue {
+                name,
+                symbol,
+                decimals,
+                supply,
+                mintable,
+            } => {
+                bytes.push(1);
+                bytes.push(name.len() as u8);
+                bytes.extend_from_slice(name.as_bytes());
+                bytes.push(symbol.len() as u8);
+                bytes.extend_from_slice(symbol.as_bytes());
+                bytes.push(*decimals);
+                bytes.extend_from_slice(&supply.to_le_bytes());
+                bytes.push(*mintable as u8);
+                return bytes;
+            }
+            TokenOp::Transfer { token, amount } => (2, token, amount),
+            TokenOp::Mint { token, amount } => (3, token, amount),
+            TokenOp::Burn { token, amount } => (4, token, amount),
+        };
+        bytes.push(kind);
+        bytes.extend_from_slice(token.as_bytes());
+        bytes.extend_from_slice(&amount.to_le_bytes());
+        bytes
+    }
+}
+
+impl Block {
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).unwrap()
+    }
+
+    /// Serializes the header as timestamp, previous hash, transaction root and
+    /// nonce. Everything but the nonce fits in the first 64-byte SHA-256 block
+    /// plus eight bytes, so the miner only recompresses the tail per nonce.
+    pub fn header(&self) -> [u8; HEADER_SIZE] {
+        let mut header = [0u8; HEADER_SIZE];
+        header[0..8].copy_from_slice(&self.timestamp.to_le_bytes());
+        header[8..40].copy_from_slice(&self.prev_block_hash);
+        header[40..72].copy_from_slice(&self.transaction_root());
+        header[72..80].copy_from_slice(&self.nonce.to_le_bytes());
+        header
+    }
+
+    /// SHA-256 over every transaction's signing data and signature, in
+    /// order.
+    pub fn transaction_root(&self) -> [u8; 32] {
+        let mut hasher = Sha256::new();
+        for tx in &self.transactions {
+            hasher.update(tx.signing_data());
+            if let Some(signature) = &tx.signature {
+                hasher.update(signature.as_bytes());
+            }
+        }
+        hasher.finalize().into()
+    }
+
+    /// Puts the coinbase paying `address` the block reward plus every fee at
+    /// the front of the block, carrying the block's height as its nonce and
+    /// `extranonce` in its signature slot the way a coinbase script would.
+    pub fn set_coinbase(&mut self, address: &str, extranonce: u64) {
+        let fees: f64 = self
+            .transactions
+            .iter()
+            .filter(|tx| tx.sender != COINBASE_SENDER)
+            .map(|tx| tx.fee)
+            .sum();
+        let coinbase = Transaction {
+            sender: COINBASE_SENDER.to_string(),
+            receiver: address.to_string(),
+            amount: self.reward + fees,
+            fee: 0.0,
+            timestamp: self.timestamp,
+            nonce: self.height,
+            script: None,
+            lock_time: 0,
+            relative_lock: 0,
+            data: None,
+            token: None,
+            signature: Some(format!("{:016x}", extranonce)),
+        };
+
+        match self.transactions.first_mut() {
+            Some(tx) if tx.sender == COINBASE_SENDER => *tx = coinbase,
+            _ => self.transactions.insert(0, coinbase),
+        }
+    }
+}
+
+/// Tags the node gives the optional fields in the signing data.
+const FIELD_SCRIPT: u8 = 1;
+const FIELD_LOCK_TIME: u8 = 2;
+const FIELD_RELATIVE_LOCK: u8 = 3;
+const FIELD_DATA: u8 = 4;
+const FIELD_TOKEN: u8 = 5;
+const FIELD_NONCE: u8 = 6;
+
+/// Writes an optional field as its tag, its length and its contents.
+fn push_field(data: &mut Vec<u8>, tag: u8, bytes: &[u8]) {
+    data.push(tag);
+    data.extend_from_slice(&(bytes.len() as u32).to_le_bytes());
+    data.extend_from_slice(bytes);
+}
+
+fn is_zero(value: &u64) -> bool {
+    *value == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ADDRESS: &str = "flrt14sdcqezufk9g8pnn0sve5yk2wqfllsg02qxxv2";
+
+    /// A template with one transaction of each shape the node signs.
+    fn template() -> Block {
+        let transaction = |extra: serde_json::Value| {
+            let mut tx = serde_json::json!({
+                "sender": ADDRESS,
+                "receiver": "flrt13v0g8knefk035awswd2pvthk4wsd3w55kgxdzx",
+                "amount": 1.5,
+                "fee": 0.01,
+                "timestamp": 1_792_384_545u64,
+                "signature": "ab".repeat(97),
+            });
+            tx.as_object_mut()
+                .unwrap()
+                .extend(extra.as_object().unwrap().clone());
+            tx
+        };
+        let transactions = vec![
+            transaction(serde_json::json!({})),
+            transaction(serde_json::json!({"nonce": 4})),
+            transaction(serde_json::json!({"script": "51"})),
+            transaction(serde_json::json!({"lock_time": 120, "relative_lock": 6})),
+            transaction(serde_json::json!({"data": "deadbeef"})),
+            transaction(serde_json::json!({"token": {
+                "op": "issue", "name": "Gold", "symbol": "GLD",
+                "decimals": 2, "supply": 1000, "mintable": true,
+            }})),
+            transaction(serde_json::json!({"token": {
+                "op": "transfer", "token": "00".repeat(32), "amount": 7,
+            }})),
+        ];
+        let (prev_block_hash, hash) = ([7u8; 32], [0u8; 32]);
+        serde_json::from_value(serde_json::json!({
+            "timestamp": 1_792_384_545u64,
+            "prev_block_hash": prev_block_hash,
+            "hash": hash,
+            "transactions": transactions,
+            "nonce": 3,
+        }))
+        .unwrap()
+    }
+
+    fn on_node(block: &Block) -> fluerion::block::Block {
+        serde_json::from_str(&block.to_json()).unwrap()
+    }
+
+    #[test]
+    fn header_matches_the_node() {
+        let mut block = template();
+        block.reward = 50.0;
+        block.height = 12;
+        block.set_coinbase(ADDRESS, 9);
+        let node = on_node(&block);
+        assert_eq!(block.transaction_root(), node.transaction_root());
+        assert_eq!(block.header(), node.header());
+    }
+
+    #[test]
+    fn coinbase_matches_the_node() {
+        let mut block = template();
+        let fees: f64 = block.transactions.iter().map(|tx| tx.fee).sum();
+        block.reward = 50.0;
+        block.height = 12;
+        block.set_coinbase(ADDRESS, 9);
+        let mut expected =
+            fluerion::transaction::Transaction::coinbase(ADDRESS.to_string(), 50.0 + fees, 12, 9);
+        expected.timestamp = block.timestamp;
+        let node = on_node(&block);
+        let coinbase = &node.get_transactions()[0];
+        assert_eq!(coinbase.signing_data(), expected.signing_data());
+        assert_eq!(coinbase.signature, expected.signature);
+    }
+
+    #[test]
+    fn target_has_the_leading_zero_digits() {
+        assert_eq!(target(0), [0xFF; 32]);
+        assert_eq!(&target(3)[..3], &[0x00, 0x0F, 0xFF]);
+        assert_eq!(target(64), [0; 32]);
+    }
+}
@@ -0,0 +1,107 @@
+use serde::Deserialize;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::OnceLock;
+
+/// Which chain to mine on; must match the node's.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Network {
+    #[default]
+    Mainnet,
+    Testnet,
+    Regtest,
+}
+
+impl fmt::Display for Network {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Network::Mainnet => "mainnet",
+            Network::Testnet => "testnet",
+            Network::Regtest => "regtest",
+        })
+    }
+}
+
+impl FromStr for Network {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, String> {
+        match name {
+            "mainnet" | "main" => Ok(Network::Mainnet),
+            "testnet" | "test" => Ok(Network::Testnet),
+            "regtest" => Ok(Network::Regtest),
+            _ => Err(format!(
+                "Unknown network {} (expected mainnet, testnet or regtest)",
+                name
+            )),
+        }
+    }
+}
+
+/// The parts of the node's chain parameters a miner needs before it can
+/// reach the node. Difficulty and reward come from the node itself.
+#[derive(Debug)]
+pub struct ChainParams {
+    pub network: Network,
+    /// Human-readable part of the network's bech32m addresses.
+    pub address_prefix: &'static str,
+    pub default_port: u16,
+    pub magic: [u8; 4],
+}
+
+impl ChainParams {
+    pub fn for_network(network: Network) -> Self {
+        let (address_prefix, default_port, magic) = match network {
+            Network::Mainnet => ("fl", 8080, [0xf1, 0x75, 0xe7, 0x10]),
+            Network::Testnet => ("tf", 18080, [0x0b, 0x11, 0x09, 0x07]),
+            Network::Regtest => ("flrt", 28080, [0xfa, 0xbf, 0xb5, 0xda]),
+        };
+        ChainParams {
+            network,
+            address_prefix,
+            default_port,
+            magic,
+        }
+    }
+
+    /// `address` with the default port added if it names none.
+    pub fn with_default_port(&self, address: &str) -> String {
+        if address.contains(':') {
+            address.to_string()
+        } else {
+            format!("{}:{}", address, self.default_port)
+        }
+    }
+}
+
+static PARAMS: OnceLock<ChainParams> = OnceLock::new();
+
+/// Chooses the network for the rest of the process; later calls are
+/// ignored.
+pub fn select(network: Network) {
+    let _ = PARAMS.set(ChainParams::for_network(network));
+}
+
+/// Parameters of the selected network, mainnet unless another was chosen.
+pub fn params() -> &'static ChainParams {
+    PARAMS.get_or_init(|| ChainParams::for_network(Network::Mainnet))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches_the_node() {
+        for network in [Network::Mainnet, Network::Testnet, Network::Regtest] {
+            let miner = ChainParams::for_network(network);
+            let node = fluerion::chainparams::ChainParams::for_network(
+                network.to_string().parse().unwrap(),
+            );
+            assert_eq!(miner.address_prefix, node.address_prefix);
+            assert_eq!(miner.default_port, node.default_port);
+            assert_eq!(miner.magic, node.magic);
+        }
+    }
+}
@@ -0,0 +1,249 @@
+use crate::chainparams::{self, Network};
+use crate::log::LogFormat;
+use serde::Deserialize;
+use std::fs;
+
+pub const USAGE: &str =
+    "Usage: fluerion-miner [--config <file>] [--network mainnet|testnet|regtest]
+                      [--node <addr>]... [--address <addr>]
+                      [--threads <n>] [--priority <nice>] [--log-format text|json]
+                      [--stats <addr>] [--backend <name>] [--bench [seconds]]";
+
+/// Miner settings, read from an optional TOML file and then overridden by
+/// command-line flags. For example:
+///
+/// ```toml
+/// network = "mainnet"
+/// nodes = ["127.0.0.1:8080", "10.0.0.2:8080"]
+/// address = "fl1a0e605ww8n9rtrzprtydlu95350f4shtxm4y9a"
+/// threads = 4
+/// priority = 10
+/// log_format = "json"
+/// stats = "127.0.0.1:9100"
+/// ```
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Network to mine on; every node must be on it too.
+    pub network: Network,
+    /// Nodes to fetch work from, tried in order when one stops answering.
+    pub nodes: Vec<String>,
+    /// Address the coinbase of every mined block pays to.
+    pub address: Option<String>,
+    pub threads: usize,
+    /// Niceness applied to the whole process, from -20 to 19.
+    pub priority: Option<i32>,
+    pub log_format: LogFormat,
+    /// Where to serve the JSON stats endpoint, if anywhere.
+    pub stats: Option<String>,
+    pub backend: String,
+    /// Run the benchmark for this many seconds instead of mining.
+    #[serde(skip)]
+    pub bench: Option<u64>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            network: Network::Mainnet,
+            nodes: Vec::new(),
+            address: None,
+            threads: num_cpus::get(),
+            priority: None,
+            log_format: LogFormat::Text,
+            stats: None,
+            backend: "auto".to_string(),
+            bench: None,
+        }
+    }
+}
+
+impl Config {
+    pub fn from_args(args: &[String]) -> Result<Self, String> {
+        let mut config = match flag_value(args, "--config")? {
+            Some(path) => Self::from_file(path)?,
+            None => Config::default(),
+        };
+
+        let mut nodes = Vec::new();
+        let mut i = 1;
+        while i < args.len() {
+            let flag = args[i].as_str();
+            let value = args.get(i + 1).map(String::as_str);
+            let mut takes_value = true;
+            match flag {
+                "--config" => {}
+                "--network" => config.network = parse(flag, value)?,
+                "--node" => nodes.push(required(flag, value)?.to_string()),
+                "--address" => config.address = Some(required(flag, value)?.to_string()),
+                "--threads" => config.threads = parse(flag, value)?,
+                "--priority" => config.priority = Some(parse(flag, value)?),
+                "--log-format" => config.log_format = parse(flag, value)?,
+                "--stats" => config.stats = Some(required(flag, value)?.to_string()),
+                "--backend" => config.backend = required(flag, value)?.to_string(),
+                "--bench" => match value.and_then(|v| v.parse().ok()) {
+                    Some(seconds) => config.bench = Some(seconds),
+                    None => {
+                        config.bench = Some(10);
+                        takes_value = false;
+                    }
+                },
+                _ => return Err(format!("Unknown argument: {}", flag)),
+            }
+            i += if takes_value { 2 } else { 1 };
+        }
+
+        if !nodes.is_empty() {
+            config.nodes = nodes;
+        }
+        chainparams::select(config.network);
+        let params = chainparams::params();
+        config.nodes = config
+            .nodes
+            .iter()
+            .map(|node| params.with_default_port(node))
+            .collect();
+        if config.threads == 0 {
+            return Err("--threads must be at least 1".to_string());
+        }
+        Ok(config)
+    }
+
+    fn from_file(path: &str) -> Result<Self, String> {
+        let contents =
+            fs::read_to_string(path).map_err(|e| format!("Cannot read {}: {}", path, e))?;
+        toml::from_str(&contents).map_err(|e| format!("Invalid config {}: {}", path, e))
+    }
+}
+
+fn flag_value<'a>(args: &'a [String], flag: &str) -> Result<Option<&'a str>, String> {
+    match args.iter().position(|arg| arg == flag) {
+        Some(i) => required(flag, args.get(i + 1).map(String::as_str)).map(Some),
+        None => Ok(None),
+    }
+}
+
+fn required<'a>(flag: &str, value: Option<&'a str>) -> Result<&'a str, String> {
+    value.ok_or_else(|| format!("{} needs a value", flag))
+}
+
+fn parse<T: std::str::FromStr>(flag: &str, value: Option<&str>) -> Result<T, String> {
+    let value = required(flag, value)?;
+    value
+        .parse()
+        .map_err(|_| format!("Invalid value for {}: {}", flag, value))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        std::iter::once("fluerion-miner")
+            .chain(args.iter().copied())
+            .map(str::to_string)
+            .collect()
+    }
+
+    /// Writes `contents` to a config file only the calling test uses.
+    fn config_file(name: &str, contents: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!(
+            "fluerion-miner-{}-{}.toml",
+            name,
+            std::process::id()
+        ));
+        fs::write(&path, contents).unwrap();
+        path
+    }
+
+    const FILE: &str = r#"
+network = "testnet"
+nodes = ["10.0.0.1:18080", "10.0.0.2:18080"]
+address = "tf1address"
+threads = 4
+priority = 10
+log_format = "json"
+stats = "127.0.0.1:9100"
+backend = "scalar"
+"#;
+
+    #[test]
+    fn flags_override_the_config_file() {
+        let path = config_file("override", FILE);
+        let path = path.to_str().unwrap();
+
+        let config = Config::from_args(&args(&["--config", path])).unwrap();
+        assert_eq!(config.network, Network::Testnet);
+        assert_eq!(config.nodes, ["10.0.0.1:18080", "10.0.0.2:18080"]);
+        assert_eq!(config.address.as_deref(), Some("tf1address"));
+        assert_eq!(config.threads, 4);
+        assert_eq!(config.priority, Some(10));
+        assert_eq!(config.log_format, LogFormat::Json);
+        assert_eq!(config.stats.as_deref(), Some("127.0.0.1:9100"));
+        assert_eq!(config.backend, "scalar");
+
+        let config = Config::from_args(&args(&[
+            "--threads",
+            "2",
+            "--network",
+            "regtest",
+            "--config",
+            path,
+            "--node",
+            "10.0.0.3:1",
+            "--log-format",
+            "text",
+            "--backend",
+            "sha2",
+        ]))
+        .unwrap();
+        fs::remove_file(path).unwrap();
+        assert_eq!(config.network, Network::Regtest);
+        assert_eq!(config.nodes, ["10.0.0.3:1"]);
+        assert_eq!(config.threads, 2);
+        assert_eq!(config.log_format, LogFormat::Text);
+        assert_eq!(config.backend, "sha2");
+        // Left alone by the flags, so still from the file.
+        assert_eq!(config.address.as_deref(), Some("tf1address"));
+        assert_eq!(config.priority, Some(10));
+        assert_eq!(config.stats.as_deref(), Some("127.0.0.1:9100"));
+    }
+
+    #[test]
+    fn falls_back_to_defaults() {
+        let config = Config::from_args(&args(&["--node", "10.0.0.1"])).unwrap();
+        assert_eq!(config.network, Network::Mainnet);
+        let port = chainparams::params().default_port;
+        assert_eq!(config.nodes, [format!("10.0.0.1:{}", port)]);
+        assert_eq!(config.threads, num_cpus::get());
+        assert_eq!(config.backend, "auto");
+        assert_eq!(config.log_format, LogFormat::Text);
+        assert_eq!(config.bench, None);
+
+        let config = Config::from_args(&args(&["--bench"])).unwrap();
+        assert_eq!(config.bench, Some(10));
+        let config = Config::from_args(&args(&["--bench", "3", "--threads", "1"])).unwrap();
+        assert_eq!((config.bench, config.threads), (Some(3), 1));
+    }
+
+    #[test]
+    fn refuses_bad_settings() {
+        for bad in [
+            &["--threads", "0"][..],
+            &["--threads", "many"],
+            &["--threads"],
+            &["--network", "simnet"],
+            &["--log-format", "xml"],
+            &["--turbo"],
+            &["--config"],
+        ] {
+            assert!(Config::from_args(&args(bad)).is_err(), "{:?}", bad);
+        }
+
+        let path = config_file("unknown", "threads = 2\nturbo = true\n");
+        let e = Config::from_args(&args(&["--config", path.to_str().unwrap()])).unwrap_err();
+        fs::remove_file(&path).unwrap();
+        assert!(e.starts_with("Invalid config"), "{}", e);
+    }
+}
@@ -0,0 +1,63 @@
+use serde::Deserialize;
+use std::str::FromStr;
+use std::sync::OnceLock;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    Text,
+    Json,
+}
+
+impl FromStr for LogFormat {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, ()> {
+        match s {
+            "text" => Ok(LogFormat::Text),
+            "json" => Ok(LogFormat::Json),
+            _ => Err(()),
+        }
+    }
+}
+
+static FORMAT: OnceLock<LogFormat> = OnceLock::new();
+
+pub fn init(format: LogFormat) {
+    let _ = FORMAT.set(format);
+}
+
+/// Writes one log line, as plain text or as a JSON object per line.
+pub fn write(level: &str, message: &str) {
+    match FORMAT.get().copied().unwrap_or(LogFormat::Text) {
+        LogFormat::Text if level == "error" => eprintln!("{}", message),
+        LogFormat::Text => println!("{}", message),
+        LogFormat::Json => {
+            let timestamp = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap()
+                .as_secs();
+            let line = serde_json::json!({
+                "timestamp": timestamp,
+                "level": level,
+                "message": message,
+            });
+            println!("{}", line);
+        }
+    }
+}
+
+macro_rules! info {
+    ($($arg:tt)*) => {
+        $crate::log::write("info", &format!($($arg)*))
+    };
+}
+
+macro_rules! error {
+    ($($arg:tt)*) => {
+        $crate::log::write("error", &format!($($arg)*))
+    };
+}
+
+pub(crate) use {error, info};
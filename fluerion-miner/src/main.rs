@@ -1,200 +1,216 @@
-use std::net::SocketAddr;
-use std::str::FromStr;
-use std::io::{self, BufRead, Write};
-use tokio::net::TcpStream;
-use tokio::io::{AsyncReadExt, AsyncWriteExt};
-use serde_json;
-use sha2::{Digest, Sha256};
-use std::sync::{Arc, Mutex};
-use std::thread;
-use std::time::{Duration, Instant};
-use num_cpus;
-
-#[derive(serde::Deserialize, serde::Serialize,Debug,Clone)]
-struct Block {
-    timestamp: u64,
-    prev_block_hash: [u8; 32],
-    hash: [u8; 32],
-    transactions: Vec<Transaction>,
-    nonce: u64,
-}
-
-impl Block {
-    fn to_json(&self) -> String {
-        serde_json::to_string(self).unwrap()
-    }
-}
-
-#[derive(serde::Deserialize, serde::Serialize,Debug,Clone)]
-struct Transaction {
-    sender: String,
-    receiver: String,
-    amount: f64,
-    timestamp: u64,
-    signature: Option<String>,
-}
+mod address;
+mod bench;
+mod block;
+mod chainparams;
+mod config;
+mod log;
+mod node;
+mod search;
+mod sha256;
+mod stats;
+
+use config::Config;
+use log::{error, info};
+use node::{NodeClient, Submission};
+use search::Search;
+use sha256::Sha256Backend;
+use stats::Stats;
+use std::env;
+use std::io::{self, BufRead, IsTerminal, Write};
+use std::process;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::net::TcpListener;
+
+/// How often the miner asks the node whether its template has changed.
+const TEMPLATE_POLL_INTERVAL: Duration = Duration::from_secs(1);
 
 #[tokio::main]
 async fn main() -> io::Result<()> {
-    println!("Fluerion Miner");
+    let args: Vec<String> = env::args().collect();
+    let config = Config::from_args(&args).unwrap_or_else(|e| {
+        eprintln!("{}\n{}", e, config::USAGE);
+        process::exit(2);
+    });
+    log::init(config.log_format);
 
-    let mut address = String::new();
-    print!("Enter your miner address: ");
-    io::stdout().flush()?;
-    io::stdin().lock().read_line(&mut address)?;
-    let address = address.trim().to_string();
+    info!("Fluerion Miner on {}", config.network);
 
-    let mut node_addr = String::new();
-    print!("Enter the node address to connect to (e.g., 127.0.0.1:8080): ");
-    io::stdout().flush()?;
-    io::stdin().lock().read_line(&mut node_addr)?;
-    let node_addr = SocketAddr::from_str(node_addr.trim()).expect("Invalid address");
+    if let Some(nice) = config.priority {
+        set_priority(nice);
+    }
 
-    println!("Connecting to node at {}", node_addr);
+    let backend = load_backend(&config.backend);
+    info!("Using {} SHA-256 backend", backend.name());
 
-    let mut stream = TcpStream::connect(node_addr).await?;
-    println!("Miner {} connected to node", address);
+    if let Some(seconds) = config.bench {
+        let search = Search::new(backend, config.threads);
+        bench::run(&search, Duration::from_secs(seconds));
+        return Ok(());
+    }
+
+    let address = match config.address {
+        Some(address) => address,
+        None => prompt("Enter your miner address: ")?,
+    };
+    if let Err(e) = address::validate(&address) {
+        eprintln!("{}", e);
+        process::exit(2);
+    }
+    let nodes = if config.nodes.is_empty() {
+        let node = prompt("Enter the node address to connect to (e.g., 127.0.0.1:8080): ")?;
+        vec![chainparams::params().with_default_port(&node)]
+    } else {
+        config.nodes
+    };
+
+    let search = Arc::new(Search::new(backend, config.threads));
+    let stats = Arc::new(Stats::new());
+
+    if let Some(stats_addr) = &config.stats {
+        let listener = TcpListener::bind(stats_addr).await?;
+        info!("Serving stats on http://{}", stats_addr);
+        tokio::spawn(stats::serve(
+            listener,
+            Arc::clone(&stats),
+            Arc::clone(&search),
+        ));
+    }
+
+    let mut client = NodeClient::new(nodes);
+    info!(
+        "Miner {} mining on {} threads via node {}",
+        address,
+        config.threads,
+        client.current()
+    );
 
     loop {
-        println!("Waiting for block to mine...");
-        match receive_block(&mut stream).await? {
-            Some(block) => {
-                println!("Received block to mine");
-                let mined_block = mine_block_multi_threaded(block);
-                println!("Block mined successfully!");
-                send_mined_block(&mut stream, &mined_block).await?;
-                println!("Mined block sent to node");
+        info!("Waiting for block to mine...");
+        // Taken before the template, so that a change in between shows.
+        let version = match client.template_version().await {
+            Ok(version) => Some(version),
+            Err(e) => {
+                error!("Cannot poll the node for new work: {}", e);
+                None
             }
-            None => {
-                println!("No block available to mine. Waiting for 5 seconds...");
+        };
+        let block = match client.get_block_to_mine().await {
+            Ok(Some(block)) => block,
+            Ok(None) => {
+                info!("No block available to mine. Waiting for 5 seconds...");
                 tokio::time::sleep(Duration::from_secs(5)).await;
+                continue;
+            }
+            Err(e) => {
+                error!("Cannot fetch work: {}. Retrying in 5 seconds...", e);
+                tokio::time::sleep(Duration::from_secs(5)).await;
+                continue;
+            }
+        };
+
+        info!("Received block to mine");
+        let mut mining = {
+            let search = Arc::clone(&search);
+            let address = address.clone();
+            tokio::task::spawn_blocking(move || search.run(&block, &address))
+        };
+        let mut poll = tokio::time::interval(TEMPLATE_POLL_INTERVAL);
+        poll.tick().await;
+        let mined_block = loop {
+            tokio::select! {
+                mined = &mut mining => break mined.expect("mining thread panicked"),
+                _ = poll.tick(), if version.is_some() => {
+                    // Stopping again on every tick covers a search that had
+                    // not started yet the first time.
+                    if let Ok(latest) = client.template_version().await {
+                        if Some(latest) != version {
+                            info!("Node has new work, abandoning this block");
+                            search.stop();
+                        }
+                    }
+                }
             }
+        };
+
+        let Some(mined_block) = mined_block else {
+            info!("Mining stopped before a block was found");
+            continue;
+        };
+        info!("Found a nonce, submitting the block");
+
+        match client.submit_block(&mined_block).await {
+            Ok(submission) => {
+                match &submission {
+                    Submission::Accepted => info!("Block mined successfully!"),
+                    Submission::Stale => info!("Mined block is stale; the chain moved on"),
+                    Submission::Rejected(reason) => {
+                        error!("Node rejected the mined block: {}", reason)
+                    }
+                }
+                stats.record(&submission);
+            }
+            Err(e) => error!("Cannot submit mined block: {}", e),
         }
     }
 }
 
-async fn receive_block(stream: &mut TcpStream) -> io::Result<Option<Block>> {
-    println!("Sending request to get block to mine...");
-    stream.write_all(b"GET_BLOCK_TO_MINE").await?;
-    println!("Request sent.");
+/// Asks on stdin for a setting that was neither configured nor passed as a
+/// flag. Without a terminal there is nobody to ask, so the miner gives up.
+fn prompt(question: &str) -> io::Result<String> {
+    if !io::stdin().is_terminal() {
+        eprintln!("Missing setting: {}\n{}", question.trim(), config::USAGE);
+        process::exit(2);
+    }
 
-    let mut buffer = [0; 1024];
-    let n = stream.read(&mut buffer).await?;
-    let response = String::from_utf8_lossy(&buffer[..n]);
-    println!("Received response: {}", response);
+    let mut answer = String::new();
+    print!("{}", question);
+    io::stdout().flush()?;
+    io::stdin().lock().read_line(&mut answer)?;
+    Ok(answer.trim().to_string())
+}
 
-    if response.trim().is_empty() {
-        Ok(None)
-    } else if response.starts_with("NO_BLOCK_AVAILABLE") {
-        Ok(None)
-    } else {
-        match serde_json::from_str(&response) {
-            Ok(block) => Ok(Some(block)),
-            Err(e) => {
-                println!("Error parsing block data: {}", e);
-                Ok(None)
-            }
-        }
+#[cfg(unix)]
+fn set_priority(nice: i32) {
+    // SAFETY: setpriority only reads its plain integer arguments.
+    let result = unsafe { libc::setpriority(libc::PRIO_PROCESS, 0, nice) };
+    if result != 0 {
+        error!(
+            "Cannot set priority {}: {}",
+            nice,
+            io::Error::last_os_error()
+        );
     }
 }
 
-fn mine_block_multi_threaded(mut block: Block) -> Block {
-    let mut target = [0xFF; 32];
-    target[0] = 0x00; // Adjust this value to set the desired difficulty
-    let block = Arc::new(Mutex::new(block));
-    let found = Arc::new(Mutex::new(false));
-    let num_threads = num_cpus::get();
-
-    let start_time = Instant::now();
-    let progress = Arc::new(Mutex::new(0u64));
-
-    let threads: Vec<_> = (0..num_threads)
-        .map(|i| {
-            let block = Arc::clone(&block);
-            let found = Arc::clone(&found);
-            let progress = Arc::clone(&progress);
-
-            thread::spawn(move || {
-                let mut local_block = block.lock().unwrap().clone();
-                let mut nonce = i as u64;
-                let mut local_progress = 0u64;
-
-                while !*found.lock().unwrap() {
-                    local_block.nonce = nonce;
-                    let hash = calculate_hash(&local_block);
-                    if hash < target {
-                        let mut block = block.lock().unwrap();
-                        *block = local_block;
-                        block.hash = hash;
-                        *found.lock().unwrap() = true;
-                        println!("Nonce found: {}", nonce);
-                        break;
-                    }
-                    nonce += num_threads as u64;
-                    local_progress += 1;
-
-                    // if local_progress % 10_000 == 0 {
-                        // println!("Thread {}: Nonce {} - Local progress: {} hashes", i, nonce, local_progress);
-                    // }
+#[cfg(not(unix))]
+fn set_priority(nice: i32) {
+    error!(
+        "Setting priority {} is not supported on this platform",
+        nice
+    );
+}
 
-                    if local_progress % 100_000 == 0 {
-                        *progress.lock().unwrap() += local_progress;
-                        local_progress = 0;
-                    }
-                }
-            })
-        })
-        .collect();
-
-    let progress_thread = thread::spawn(move || {
-        while !*found.lock().unwrap() {
-            thread::sleep(Duration::from_secs(5));
-            let total_progress = *progress.lock().unwrap();
-            let elapsed = start_time.elapsed().as_secs();
-            let hash_rate = if elapsed > 0 { total_progress / elapsed } else { 0 };
-            let estimated_time = if hash_rate > 0 {
-                let target_value = u64::from_be_bytes(target[0..8].try_into().unwrap());
-                let max_nonce = u64::MAX;
-                (max_nonce - target_value) / hash_rate
-            } else {
-                0
-            };
-            println!("Mining progress: {} hashes/s, Estimated time: {} seconds", hash_rate, estimated_time);
+/// Checks every backend this CPU offers against `sha2` and returns the one
+/// asked for. A backend that gets a hash wrong is never used.
+fn load_backend(name: &str) -> Box<dyn Sha256Backend> {
+    let mut valid = Vec::new();
+    for backend in sha256::available_backends() {
+        match sha256::validate(backend.as_ref()) {
+            Ok(()) => valid.push(backend.name()),
+            Err(e) => error!("Disabling backend: {}", e),
         }
-    });
-
-    for thread in threads {
-        thread.join().unwrap();
     }
 
-    progress_thread.join().unwrap();
-
-    Arc::try_unwrap(block).unwrap().into_inner().unwrap()
-}
-
-fn calculate_hash(block: &Block) -> [u8; 32] {
-    let mut hasher = Sha256::new();
-    hasher.update(&block.timestamp.to_le_bytes());
-    hasher.update(&block.prev_block_hash);
-    hasher.update(&block.nonce.to_le_bytes());
-    
-    // Include transactions data
-    for tx in &block.transactions {
-        hasher.update(tx.sender.as_bytes());
-        hasher.update(tx.receiver.as_bytes());
-        hasher.update(&tx.amount.to_le_bytes());
-        hasher.update(&tx.timestamp.to_le_bytes());
+    match sha256::select_backend(name) {
+        Some(backend) if valid.contains(&backend.name()) => backend,
+        _ => {
+            error!(
+                "SHA-256 backend {:?} is not available (valid: {})",
+                name,
+                valid.join(", ")
+            );
+            process::exit(1);
+        }
     }
-    
-    let result = hasher.finalize();
-    let mut hash = [0; 32];
-    hash.copy_from_slice(&result);
-    hash
 }
-
-async fn send_mined_block(stream: &mut TcpStream, block: &Block) -> io::Result<()> {
-    let block_json = block.to_json();
-    let message = format!("MINED_BLOCK:{}", block_json);
-    stream.write_all(message.as_bytes()).await?;
-    Ok(())
-}
\ No newline at end of file
@@ -0,0 +1,155 @@
+use crate::block::{self, Block};
+use crate::chainparams::params;
+use crate::log::{error, info};
+use serde::Deserialize;
+use std::io;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+/// What the node made of a block we submitted.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Submission {
+    Accepted,
+    /// The node turned the block down, for the reason it gave.
+    Rejected(String),
+    /// Another block extended the chain while we were mining this one.
+    Stale,
+}
+
+#[derive(Deserialize)]
+struct NetworkInfo {
+    network: String,
+    magic: String,
+    difficulty: usize,
+    /// Height of the node's tip.
+    height: u64,
+    /// Reward for the block after the node's tip.
+    block_reward: f64,
+}
+
+/// Talks to the configured nodes, moving on to the next one whenever the
+/// current one cannot be reached or turns out to be on another network.
+pub struct NodeClient {
+    nodes: Vec<String>,
+    current: usize,
+    /// Which nodes have been found to be on the miner's network.
+    checked: Vec<bool>,
+}
+
+impl NodeClient {
+    pub fn new(nodes: Vec<String>) -> Self {
+        let checked = vec![false; nodes.len()];
+        NodeClient {
+            nodes,
+            current: 0,
+            checked,
+        }
+    }
+
+    pub fn current(&self) -> &str {
+        &self.nodes[self.current]
+    }
+
+    /// Sends one command and waits for the node to answer and hang up.
+    async fn request(&mut self, message: &str) -> io::Result<String> {
+        for _ in 0..self.nodes.len() {
+            match self.send_checked(message).await {
+                Ok(response) => return Ok(response),
+                Err(e) => {
+                    error!("Cannot use node {}: {}", self.current(), e);
+                    self.current = (self.current + 1) % self.nodes.len();
+                    info!("Switching to node {}", self.current());
+                }
+            }
+        }
+        Err(io::Error::new(
+            io::ErrorKind::NotConnected,
+            "no configured node is reachable",
+        ))
+    }
+
+    /// Sends to the current node once it is known to be on our network.
+    async fn send_checked(&mut self, message: &str) -> io::Result<String> {
+        if !self.checked[self.current] {
+            let info = network_info(&Self::send(self.current(), "GET_NETWORK").await?)?;
+            if info.magic != hex(&params().magic) {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("node is on {}, not {}", info.network, params().network),
+                ));
+            }
+            self.checked[self.current] = true;
+        }
+        Self::send(self.current(), message).await
+    }
+
+    async fn send(node: &str, message: &str) -> io::Result<String> {
+        let mut stream = TcpStream::connect(node).await?;
+        stream
+            .write_all(format!("{}\n", message).as_bytes())
+            .await?;
+
+        let mut response = Vec::new();
+        stream.read_to_end(&mut response).await?;
+        Ok(String::from_utf8_lossy(&response).into_owned())
+    }
+
+    /// A template to mine, with the height, reward and target the node's
+    /// chain parameters give the block after its tip. Should the tip move
+    /// in between, the template is stale anyway.
+    pub async fn get_block_to_mine(&mut self) -> io::Result<Option<Block>> {
+        let response = self.request("GET_BLOCK_TO_MINE").await?;
+
+        if response.trim().is_empty() || response.starts_with("NO_BLOCK_AVAILABLE") {
+            Ok(None)
+        } else {
+            match serde_json::from_str::<Block>(&response) {
+                Ok(mut block) => {
+                    let info = network_info(&self.request("GET_NETWORK").await?)?;
+                    block.reward = info.block_reward;
+                    block.height = info.height + 1;
+                    block.target = block::target(info.difficulty);
+                    Ok(Some(block))
+                }
+                Err(e) => {
+                    error!("Error parsing block data: {}", e);
+                    Ok(None)
+                }
+            }
+        }
+    }
+
+    /// The node's template version, which changes whenever the block it
+    /// would hand out to mine does: a new tip or new transactions.
+    pub async fn template_version(&mut self) -> io::Result<u64> {
+        let response = self.request("GET_TEMPLATE_VERSION").await?;
+        response.trim().parse().map_err(|_| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("unexpected template version {:?}", response.trim()),
+            )
+        })
+    }
+
+    pub async fn submit_block(&mut self, block: &Block) -> io::Result<Submission> {
+        let message = format!("MINED_BLOCK:{}", block.to_json());
+        let response = self.request(&message).await?;
+
+        Ok(if response.starts_with("Mined block added") {
+            Submission::Accepted
+        } else if response.starts_with("Mined block is stale") {
+            Submission::Stale
+        } else {
+            let reason = response.strip_prefix("Error: ").unwrap_or(&response);
+            Submission::Rejected(reason.trim().to_string())
+        })
+    }
+}
+
+fn network_info(response: &str) -> io::Result<NetworkInfo> {
+    serde_json::from_str(response).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
@@ -0,0 +1,268 @@
+use crate::block::Block;
+use crate::log::info;
+use crate::sha256::{Midstate, Sha256Backend};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How many seconds past the template timestamp a job may roll the clock
+/// before moving on to the next extranonce.
+pub const MAX_TIME_ROLL: u64 = 60;
+
+/// Hashes between checks of the stop flag and updates of the thread counter.
+const BATCH_SIZE: u64 = 4096;
+
+/// Hashes handed to the backend in one call.
+const CHUNK_SIZE: usize = 64;
+
+/// One unit of work: the template with a given extranonce and timestamp roll.
+/// Every thread walks the same job sequence, but only ever inside its own
+/// slice of the nonce space, so no header is hashed twice.
+struct Job {
+    block: Block,
+    midstate: Midstate,
+}
+
+impl Job {
+    fn new(backend: &dyn Sha256Backend, template: &Block, address: &str, index: u64) -> Self {
+        let mut block = template.clone();
+        block.timestamp = template.timestamp + index % (MAX_TIME_ROLL + 1);
+        block.set_coinbase(address, index / (MAX_TIME_ROLL + 1));
+        let midstate = Midstate::new(backend, &block.header());
+        Job { block, midstate }
+    }
+}
+
+/// Splits the nonce space into `threads` disjoint, contiguous ranges.
+pub fn nonce_range(thread: usize, threads: usize) -> (u64, u64) {
+    let len = u64::MAX / threads as u64;
+    let start = thread as u64 * len;
+    let end = if thread + 1 == threads {
+        u64::MAX
+    } else {
+        start + len
+    };
+    (start, end)
+}
+
+pub struct Search {
+    backend: Box<dyn Sha256Backend>,
+    threads: usize,
+    stop: AtomicBool,
+    counters: Vec<AtomicU64>,
+    hash_rate: AtomicU64,
+}
+
+impl Search {
+    pub fn new(backend: Box<dyn Sha256Backend>, threads: usize) -> Self {
+        Search {
+            backend,
+            threads,
+            stop: AtomicBool::new(false),
+            counters: (0..threads).map(|_| AtomicU64::new(0)).collect(),
+            hash_rate: AtomicU64::new(0),
+        }
+    }
+
+    /// Asks every worker to give up on the current search.
+    pub fn stop(&self) {
+        self.stop.store(true, Ordering::Relaxed);
+    }
+
+    pub fn threads(&self) -> usize {
+        self.threads
+    }
+
+    /// Hashes per second over the current search, or zero while idle.
+    pub fn hash_rate(&self) -> u64 {
+        self.hash_rate.load(Ordering::Relaxed)
+    }
+
+    /// Hashes computed by each thread since the current search started.
+    pub fn thread_hashes(&self) -> Vec<u64> {
+        self.counters
+            .iter()
+            .map(|c| c.load(Ordering::Relaxed))
+            .collect()
+    }
+
+    fn total_hashes(&self) -> u64 {
+        self.thread_hashes().iter().sum()
+    }
+
+    /// Searches for a block paying `address` that meets the template's
+    /// target. Returns `None` only when the search was stopped from outside.
+    pub fn run(&self, template: &Block, address: &str) -> Option<Block> {
+        self.stop.store(false, Ordering::Relaxed);
+        for counter in &self.counters {
+            counter.store(0, Ordering::Relaxed);
+        }
+        let found = Mutex::new(None);
+        let start_time = Instant::now();
+
+        thread::scope(|scope| {
+            for i in 0..self.threads {
+                let found = &found;
+                scope.spawn(move || {
+                    if let Some(block) = self.work(i, template, address) {
+                        if !self.stop.swap(true, Ordering::Relaxed) {
+                            info!("Nonce found: {}", block.nonce);
+                            *found.lock().unwrap() = Some(block);
+                        }
+                    }
+                });
+            }
+
+            scope.spawn(|| self.report_progress(&template.target, start_time));
+        });
+        self.hash_rate.store(0, Ordering::Relaxed);
+
+        found.into_inner().unwrap()
+    }
+
+    fn work(&self, thread: usize, template: &Block, address: &str) -> Option<Block> {
+        let (start, end) = nonce_range(thread, self.threads);
+        let counter = &self.counters[thread];
+
+        for index in 0.. {
+            let job = Job::new(self.backend.as_ref(), template, address, index);
+            let mut hashes = [[0u8; 32]; CHUNK_SIZE];
+            let mut nonce = start;
+
+            while nonce < end {
+                if self.stop.load(Ordering::Relaxed) {
+                    return None;
+                }
+
+                let batch_end = end.min(nonce.saturating_add(BATCH_SIZE));
+                let mut chunk_start = nonce;
+                while chunk_start < batch_end {
+                    let len = (batch_end - chunk_start).min(CHUNK_SIZE as u64) as usize;
+                    self.backend
+                        .hash_batch(&job.midstate, chunk_start, &mut hashes[..len]);
+
+                    for (n, hash) in (chunk_start..).zip(&hashes[..len]) {
+                        if *hash < template.target {
+                            counter.fetch_add(n - nonce + 1, Ordering::Relaxed);
+                            let mut block = job.block;
+                            block.nonce = n;
+                            block.hash = *hash;
+                            return Some(block);
+                        }
+                    }
+                    chunk_start += len as u64;
+                }
+                counter.fetch_add(batch_end - nonce, Ordering::Relaxed);
+                nonce = batch_end;
+            }
+        }
+        None
+    }
+
+    fn report_progress(&self, target: &[u8; 32], start_time: Instant) {
+        let mut last_report = Instant::now();
+        while !self.stop.load(Ordering::Relaxed) {
+            thread::sleep(Duration::from_millis(100));
+            if last_report.elapsed() < Duration::from_secs(5) {
+                continue;
+            }
+            last_report = Instant::now();
+
+            let elapsed = start_time.elapsed().as_secs().max(1);
+            let hash_rate = self.total_hashes() / elapsed;
+            self.hash_rate.store(hash_rate, Ordering::Relaxed);
+            let estimated_time = expected_hashes(target).checked_div(hash_rate).unwrap_or(0);
+            info!(
+                "Mining progress: {} hashes/s, Estimated time: {} seconds",
+                hash_rate, estimated_time
+            );
+        }
+    }
+}
+
+/// Average number of hashes needed to land below `target`.
+fn expected_hashes(target: &[u8; 32]) -> u64 {
+    let target = target.iter().fold(0f64, |acc, &b| acc * 256.0 + b as f64);
+    (2f64.powi(256) / (target + 1.0)) as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::block::{self, Transaction};
+    use crate::sha256::ScalarBackend;
+    use sha2::{Digest, Sha256};
+    use std::sync::Arc;
+
+    const ADDRESS: &str = "flrt14sdcqezufk9g8pnn0sve5yk2wqfllsg02qxxv2";
+
+    fn template(target: [u8; 32]) -> Block {
+        Block {
+            timestamp: 1_700_000_000,
+            prev_block_hash: [0xAB; 32],
+            hash: [0; 32],
+            transactions: vec![Transaction {
+                sender: "Coinbase".to_string(),
+                receiver: ADDRESS.to_string(),
+                amount: 0.0,
+                fee: 0.0,
+                timestamp: 1_700_000_000,
+                nonce: 0,
+                script: None,
+                lock_time: 0,
+                relative_lock: 0,
+                data: None,
+                token: None,
+                signature: None,
+            }],
+            nonce: 0,
+            reward: 50.0,
+            target,
+            height: 1,
+        }
+    }
+
+    #[test]
+    fn nonce_ranges_split_the_nonce_space() {
+        for threads in [1, 2, 3, 7, 64, 1000] {
+            let ranges: Vec<(u64, u64)> = (0..threads).map(|i| nonce_range(i, threads)).collect();
+            assert_eq!(ranges[0].0, 0);
+            assert_eq!(ranges[threads - 1].1, u64::MAX);
+            for (start, end) in &ranges {
+                assert!(start < end, "{} threads: empty range", threads);
+            }
+            for pair in ranges.windows(2) {
+                assert_eq!(pair[0].1, pair[1].0, "{} threads: gap or overlap", threads);
+            }
+        }
+    }
+
+    #[test]
+    fn finds_a_block_below_the_target() {
+        let search = Search::new(Box::new(ScalarBackend), 2);
+        let block = search.run(&template(block::target(2)), ADDRESS).unwrap();
+
+        let hash: [u8; 32] = Sha256::digest(block.header()).into();
+        assert_eq!(block.hash, hash);
+        assert!(hash < block::target(2));
+        assert_eq!(block.transactions[0].receiver, ADDRESS);
+        assert_eq!(block.transactions[0].amount, 50.0);
+    }
+
+    #[test]
+    fn stops_when_asked() {
+        let search = Arc::new(Search::new(Box::new(ScalarBackend), 2));
+        let stopper = {
+            let search = Arc::clone(&search);
+            thread::spawn(move || {
+                thread::sleep(Duration::from_millis(100));
+                search.stop();
+            })
+        };
+        assert!(search.run(&template([0; 32]), ADDRESS).is_none());
+        stopper.join().unwrap();
+        assert!(search.thread_hashes().iter().all(|&hashes| hashes > 0));
+        assert_eq!(search.hash_rate(), 0);
+    }
+}
@@ -0,0 +1,223 @@
+mod scalar;
+mod simd;
+mod software;
+
+use crate::block::HEADER_SIZE;
+use sha2::{Digest, Sha256};
+
+pub use scalar::ScalarBackend;
+pub use simd::SimdBackend;
+pub use software::SoftwareBackend;
+
+pub const IV: [u32; 8] = [
+    0x6a09e667, 0xbb67ae85, 0x3c6ef372, 0xa54ff53a, 0x510e527f, 0x9b05688c, 0x1f83d9ab, 0x5be0cd19,
+];
+
+pub const K: [u32; 64] = [
+    0x428a2f98, 0x71374491, 0xb5c0fbcf, 0xe9b5dba5, 0x3956c25b, 0x59f111f1, 0x923f82a4, 0xab1c5ed5,
+    0xd807aa98, 0x12835b01, 0x243185be, 0x550c7dc3, 0x72be5d74, 0x80deb1fe, 0x9bdc06a7, 0xc19bf174,
+    0xe49b69c1, 0xefbe4786, 0x0fc19dc6, 0x240ca1cc, 0x2de92c6f, 0x4a7484aa, 0x5cb0a9dc, 0x76f988da,
+    0x983e5152, 0xa831c66d, 0xb00327c8, 0xbf597fc7, 0xc6e00bf3, 0xd5a79147, 0x06ca6351, 0x14292967,
+    0x27b70a85, 0x2e1b2138, 0x4d2c6dfc, 0x53380d13, 0x650a7354, 0x766a0abb, 0x81c2c92e, 0x92722c85,
+    0xa2bfe8a1, 0xa81a664b, 0xc24b8b70, 0xc76c51a3, 0xd192e819, 0xd6990624, 0xf40e3585, 0x106aa070,
+    0x19a4c116, 0x1e376c08, 0x2748774c, 0x34b0bcb5, 0x391c0cb3, 0x4ed8aa4a, 0x5b9cca4f, 0x682e6ff3,
+    0x748f82ee, 0x78a5636f, 0x84c87814, 0x8cc70208, 0x90befffa, 0xa4506ceb, 0xbef9a3f7, 0xc67178f2,
+];
+
+/// A SHA-256 compression function the miner can hash headers with.
+pub trait Sha256Backend: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    fn compress(&self, state: &mut [u32; 8], block: &[u8; 64]);
+
+    /// Hashes the header for every nonce from `first_nonce` onwards, one per
+    /// slot in `hashes`. Backends that can work on several buffers at once
+    /// override this.
+    fn hash_batch(&self, midstate: &Midstate, first_nonce: u64, hashes: &mut [[u8; 32]]) {
+        for (nonce, hash) in (first_nonce..).zip(hashes.iter_mut()) {
+            let mut state = midstate.state;
+            self.compress(&mut state, &midstate.tail_with_nonce(nonce));
+            *hash = state_to_bytes(&state);
+        }
+    }
+}
+
+/// SHA-256 state after compressing the first 64 bytes of a header, plus the
+/// padded second block with everything but the nonce filled in.
+pub struct Midstate {
+    pub state: [u32; 8],
+    tail: [u8; 64],
+}
+
+impl Midstate {
+    pub fn new(backend: &dyn Sha256Backend, header: &[u8; HEADER_SIZE]) -> Self {
+        let mut state = IV;
+        backend.compress(&mut state, header[..64].try_into().unwrap());
+
+        let mut tail = [0u8; 64];
+        tail[..16].copy_from_slice(&header[64..]);
+        tail[16] = 0x80;
+        tail[56..].copy_from_slice(&((HEADER_SIZE as u64) * 8).to_be_bytes());
+
+        Midstate { state, tail }
+    }
+
+    pub fn tail_with_nonce(&self, nonce: u64) -> [u8; 64] {
+        let mut block = self.tail;
+        block[8..16].copy_from_slice(&nonce.to_le_bytes());
+        block
+    }
+}
+
+pub fn state_to_bytes(state: &[u32; 8]) -> [u8; 32] {
+    let mut hash = [0u8; 32];
+    for (chunk, word) in hash.chunks_exact_mut(4).zip(state) {
+        chunk.copy_from_slice(&word.to_be_bytes());
+    }
+    hash
+}
+
+/// Every backend this CPU can run, fastest first.
+pub fn available_backends() -> Vec<Box<dyn Sha256Backend>> {
+    let mut backends: Vec<Box<dyn Sha256Backend>> = Vec::new();
+    if let Some(simd) = SimdBackend::detect() {
+        backends.push(Box::new(simd));
+    }
+    backends.push(Box::new(SoftwareBackend));
+    backends.push(Box::new(ScalarBackend));
+    backends
+}
+
+/// Picks a backend by name, or the fastest one for `"auto"`.
+pub fn select_backend(name: &str) -> Option<Box<dyn Sha256Backend>> {
+    available_backends()
+        .into_iter()
+        .find(|backend| name == "auto" || backend.name() == name)
+}
+
+/// Checks `backend` against a plain `sha2` digest of a few synthetic headers,
+/// both through the midstate and through `hash_batch`.
+pub fn validate(backend: &dyn Sha256Backend) -> Result<(), String> {
+    const NONCES: usize = 19;
+
+    for seed in 0u8..4 {
+        let mut header = [0u8; HEADER_SIZE];
+        for (i, byte) in header.iter_mut().enumerate() {
+            *byte = (i as u8).wrapping_mul(31).wrapping_add(seed);
+        }
+
+        let midstate = Midstate::new(backend, &header);
+        let first_nonce = u64::from(seed) << 60;
+        let mut hashes = [[0u8; 32]; NONCES];
+        backend.hash_batch(&midstate, first_nonce, &mut hashes);
+
+        for (nonce, hash) in (first_nonce..).zip(hashes) {
+            header[72..80].copy_from_slice(&nonce.to_le_bytes());
+            let expected: [u8; 32] = Sha256::digest(header).into();
+            if hash != expected {
+                return Err(format!(
+                    "{} backend disagrees with sha2 at nonce {}",
+                    backend.name(),
+                    nonce
+                ));
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// xorshift64*, enough to spread headers and nonces around without a
+    /// dependency.
+    struct Rng(u64);
+
+    impl Rng {
+        fn next(&mut self) -> u64 {
+            self.0 ^= self.0 >> 12;
+            self.0 ^= self.0 << 25;
+            self.0 ^= self.0 >> 27;
+            self.0.wrapping_mul(0x2545_f491_4f6c_dd1d)
+        }
+
+        fn fill(&mut self, bytes: &mut [u8]) {
+            for chunk in bytes.chunks_mut(8) {
+                let n = chunk.len();
+                chunk.copy_from_slice(&self.next().to_le_bytes()[..n]);
+            }
+        }
+    }
+
+    /// Checks `backend` against `sha2` on random headers, nonces and batch
+    /// lengths, and its compression function on random states and blocks.
+    pub(crate) fn check_against_sha2(backend: &dyn Sha256Backend) {
+        let mut rng = Rng(0x9e37_79b9_7f4a_7c15);
+        for _ in 0..32 {
+            let mut header = [0u8; HEADER_SIZE];
+            rng.fill(&mut header);
+            let midstate = Midstate::new(backend, &header);
+            let first_nonce = rng.next() >> 1;
+            let mut hashes = vec![[0u8; 32]; 1 + (rng.next() % 70) as usize];
+            backend.hash_batch(&midstate, first_nonce, &mut hashes);
+
+            for (nonce, hash) in (first_nonce..).zip(&hashes) {
+                header[72..].copy_from_slice(&nonce.to_le_bytes());
+                let expected: [u8; 32] = Sha256::digest(header).into();
+                assert_eq!(*hash, expected, "{} at nonce {}", backend.name(), nonce);
+            }
+        }
+
+        for _ in 0..32 {
+            let mut state = [0u32; 8];
+            for word in &mut state {
+                *word = rng.next() as u32;
+            }
+            let mut block = [0u8; 64];
+            rng.fill(&mut block);
+            let mut expected = state;
+            sha2::compress256(&mut expected, &[block.into()]);
+            backend.compress(&mut state, &block);
+            assert_eq!(state, expected, "{}", backend.name());
+        }
+    }
+
+    /// A header whose bytes all differ, nonce included.
+    fn header() -> [u8; HEADER_SIZE] {
+        let mut header = [0u8; HEADER_SIZE];
+        for (i, byte) in header.iter_mut().enumerate() {
+            *byte = (i as u8).wrapping_mul(73).wrapping_add(5);
+        }
+        header
+    }
+
+    #[test]
+    fn midstate_matches_sha2() {
+        let header = header();
+        for backend in available_backends() {
+            let midstate = Midstate::new(backend.as_ref(), &header);
+
+            let mut state = IV;
+            let first: [u8; 64] = header[..64].try_into().unwrap();
+            sha2::compress256(&mut state, &[first.into()]);
+            assert_eq!(midstate.state, state, "{}", backend.name());
+
+            let nonce = u64::from_le_bytes(header[72..].try_into().unwrap());
+            let mut state = midstate.state;
+            backend.compress(&mut state, &midstate.tail_with_nonce(nonce));
+            let expected: [u8; 32] = Sha256::digest(header).into();
+            assert_eq!(state_to_bytes(&state), expected, "{}", backend.name());
+        }
+    }
+
+    #[test]
+    fn every_backend_agrees_with_sha2() {
+        let backends = available_backends();
+        assert!(backends.iter().any(|backend| backend.name() == "scalar"));
+        for backend in backends {
+            check_against_sha2(backend.as_ref());
+            assert_eq!(validate(backend.as_ref()), Ok(()));
+        }
+    }
+}
@@ -0,0 +1,252 @@
+use super::{Midstate, Sha256Backend};
+
+#[derive(Clone, Copy)]
+enum Kind {
+    ShaNi,
+    Avx2,
+}
+
+/// Uses the SHA extensions when the CPU has them, otherwise hashes eight
+/// nonces at a time across the lanes of AVX2 registers.
+pub struct SimdBackend {
+    kind: Kind,
+}
+
+impl SimdBackend {
+    #[cfg(target_arch = "x86_64")]
+    pub fn detect() -> Option<Self> {
+        if is_x86_feature_detected!("sha")
+            && is_x86_feature_detected!("sse4.1")
+            && is_x86_feature_detected!("ssse3")
+        {
+            Some(SimdBackend { kind: Kind::ShaNi })
+        } else if is_x86_feature_detected!("avx2") {
+            Some(SimdBackend { kind: Kind::Avx2 })
+        } else {
+            None
+        }
+    }
+
+    #[cfg(not(target_arch = "x86_64"))]
+    pub fn detect() -> Option<Self> {
+        None
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+impl Sha256Backend for SimdBackend {
+    fn name(&self) -> &'static str {
+        match self.kind {
+            Kind::ShaNi => "sha-ni",
+            Kind::Avx2 => "avx2",
+        }
+    }
+
+    fn compress(&self, state: &mut [u32; 8], block: &[u8; 64]) {
+        // SAFETY: `detect` only builds this backend once the features checked
+        // there are known to be present.
+        unsafe {
+            match self.kind {
+                Kind::ShaNi => x86::compress_sha_ni(state, block),
+                Kind::Avx2 => {
+                    let mut states = [*state; x86::LANES];
+                    x86::compress_avx2(&mut states, &[*block; x86::LANES]);
+                    *state = states[0];
+                }
+            }
+        }
+    }
+
+    fn hash_batch(&self, midstate: &Midstate, first_nonce: u64, hashes: &mut [[u8; 32]]) {
+        if let Kind::ShaNi = self.kind {
+            for (nonce, hash) in (first_nonce..).zip(hashes.iter_mut()) {
+                let mut state = midstate.state;
+                self.compress(&mut state, &midstate.tail_with_nonce(nonce));
+                *hash = super::state_to_bytes(&state);
+            }
+            return;
+        }
+
+        let mut nonce = first_nonce;
+        for chunk in hashes.chunks_mut(x86::LANES) {
+            let mut states = [midstate.state; x86::LANES];
+            let mut blocks = [[0u8; 64]; x86::LANES];
+            for (lane, block) in blocks.iter_mut().enumerate() {
+                *block = midstate.tail_with_nonce(nonce.wrapping_add(lane as u64));
+            }
+            // SAFETY: see `compress`.
+            unsafe { x86::compress_avx2(&mut states, &blocks) };
+            for (hash, state) in chunk.iter_mut().zip(&states) {
+                *hash = super::state_to_bytes(state);
+            }
+            nonce = nonce.wrapping_add(x86::LANES as u64);
+        }
+    }
+}
+
+#[cfg(not(target_arch = "x86_64"))]
+impl Sha256Backend for SimdBackend {
+    fn name(&self) -> &'static str {
+        match self.kind {
+            Kind::ShaNi => "sha-ni",
+            Kind::Avx2 => "avx2",
+        }
+    }
+
+    fn compress(&self, _state: &mut [u32; 8], _block: &[u8; 64]) {
+        unreachable!("SIMD backend is never detected on this architecture")
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+mod x86 {
+    use super::super::K;
+    use std::arch::x86_64::*;
+
+    pub const LANES: usize = 8;
+
+    #[target_feature(enable = "sha,sse2,ssse3,sse4.1")]
+    pub unsafe fn compress_sha_ni(state: &mut [u32; 8], block: &[u8; 64]) {
+        let mask = _mm_set_epi64x(0x0c0d_0e0f_0809_0a0b, 0x0405_0607_0001_0203);
+
+        // Rearrange ABCD/EFGH into the ABEF/CDGH order the instructions use.
+        let abcd = _mm_loadu_si128(state.as_ptr() as *const __m128i);
+        let efgh = _mm_loadu_si128(state.as_ptr().add(4) as *const __m128i);
+        let cdab = _mm_shuffle_epi32(abcd, 0xB1);
+        let hgfe = _mm_shuffle_epi32(efgh, 0x1B);
+        let mut abef = _mm_alignr_epi8(cdab, hgfe, 8);
+        let mut cdgh = _mm_blend_epi16(hgfe, cdab, 0xF0);
+        let abef_save = abef;
+        let cdgh_save = cdgh;
+
+        let mut w = [_mm_setzero_si128(); 4];
+        for i in 0..16 {
+            w[i % 4] = if i < 4 {
+                let bytes = _mm_loadu_si128(block.as_ptr().add(16 * i) as *const __m128i);
+                _mm_shuffle_epi8(bytes, mask)
+            } else {
+                let carry = _mm_alignr_epi8(w[(i + 3) % 4], w[(i + 2) % 4], 4);
+                let sum = _mm_add_epi32(_mm_sha256msg1_epu32(w[i % 4], w[(i + 1) % 4]), carry);
+                _mm_sha256msg2_epu32(sum, w[(i + 3) % 4])
+            };
+
+            let k = _mm_loadu_si128(K.as_ptr().add(4 * i) as *const __m128i);
+            let msg = _mm_add_epi32(w[i % 4], k);
+            cdgh = _mm_sha256rnds2_epu32(cdgh, abef, msg);
+            abef = _mm_sha256rnds2_epu32(abef, cdgh, _mm_shuffle_epi32(msg, 0x0E));
+        }
+
+        abef = _mm_add_epi32(abef, abef_save);
+        cdgh = _mm_add_epi32(cdgh, cdgh_save);
+
+        let feba = _mm_shuffle_epi32(abef, 0x1B);
+        let dchg = _mm_shuffle_epi32(cdgh, 0xB1);
+        let abcd = _mm_blend_epi16(feba, dchg, 0xF0);
+        let efgh = _mm_alignr_epi8(dchg, feba, 8);
+        _mm_storeu_si128(state.as_mut_ptr() as *mut __m128i, abcd);
+        _mm_storeu_si128(state.as_mut_ptr().add(4) as *mut __m128i, efgh);
+    }
+
+    macro_rules! rotr {
+        ($x:expr, $n:literal) => {
+            _mm256_or_si256(_mm256_srli_epi32($x, $n), _mm256_slli_epi32($x, 32 - $n))
+        };
+    }
+
+    #[target_feature(enable = "avx2")]
+    pub unsafe fn compress_avx2(states: &mut [[u32; 8]; LANES], blocks: &[[u8; 64]; LANES]) {
+        let lanes = |f: &dyn Fn(usize) -> u32| {
+            _mm256_setr_epi32(
+                f(0) as i32,
+                f(1) as i32,
+                f(2) as i32,
+                f(3) as i32,
+                f(4) as i32,
+                f(5) as i32,
+                f(6) as i32,
+                f(7) as i32,
+            )
+        };
+
+        let mut w = [_mm256_setzero_si256(); 64];
+        for (t, word) in w.iter_mut().take(16).enumerate() {
+            *word = lanes(&|lane| {
+                u32::from_be_bytes(blocks[lane][4 * t..4 * t + 4].try_into().unwrap())
+            });
+        }
+        for t in 16..64 {
+            let s0 = _mm256_xor_si256(
+                _mm256_xor_si256(rotr!(w[t - 15], 7), rotr!(w[t - 15], 18)),
+                _mm256_srli_epi32(w[t - 15], 3),
+            );
+            let s1 = _mm256_xor_si256(
+                _mm256_xor_si256(rotr!(w[t - 2], 17), rotr!(w[t - 2], 19)),
+                _mm256_srli_epi32(w[t - 2], 10),
+            );
+            w[t] = _mm256_add_epi32(
+                _mm256_add_epi32(w[t - 16], s0),
+                _mm256_add_epi32(w[t - 7], s1),
+            );
+        }
+
+        let initial: [__m256i; 8] = std::array::from_fn(|i| lanes(&|lane| states[lane][i]));
+        let [mut a, mut b, mut c, mut d, mut e, mut f, mut g, mut h] = initial;
+        for t in 0..64 {
+            let s1 = _mm256_xor_si256(_mm256_xor_si256(rotr!(e, 6), rotr!(e, 11)), rotr!(e, 25));
+            let ch = _mm256_xor_si256(_mm256_and_si256(e, f), _mm256_andnot_si256(e, g));
+            let t1 = _mm256_add_epi32(
+                _mm256_add_epi32(_mm256_add_epi32(h, s1), ch),
+                _mm256_add_epi32(_mm256_set1_epi32(K[t] as i32), w[t]),
+            );
+            let s0 = _mm256_xor_si256(_mm256_xor_si256(rotr!(a, 2), rotr!(a, 13)), rotr!(a, 22));
+            let maj = _mm256_xor_si256(
+                _mm256_xor_si256(_mm256_and_si256(a, b), _mm256_and_si256(a, c)),
+                _mm256_and_si256(b, c),
+            );
+            let t2 = _mm256_add_epi32(s0, maj);
+
+            h = g;
+            g = f;
+            f = e;
+            e = _mm256_add_epi32(d, t1);
+            d = c;
+            c = b;
+            b = a;
+            a = _mm256_add_epi32(t1, t2);
+        }
+
+        for (i, (start, value)) in initial.iter().zip([a, b, c, d, e, f, g, h]).enumerate() {
+            let mut words = [0u32; LANES];
+            _mm256_storeu_si256(
+                words.as_mut_ptr() as *mut __m256i,
+                _mm256_add_epi32(*start, value),
+            );
+            for (state, word) in states.iter_mut().zip(words) {
+                state[i] = word;
+            }
+        }
+    }
+}
+
+#[cfg(all(test, target_arch = "x86_64"))]
+mod tests {
+    use super::*;
+    use crate::sha256::tests::check_against_sha2;
+
+    #[test]
+    fn sha_ni_agrees_with_sha2() {
+        if is_x86_feature_detected!("sha")
+            && is_x86_feature_detected!("sse4.1")
+            && is_x86_feature_detected!("ssse3")
+        {
+            check_against_sha2(&SimdBackend { kind: Kind::ShaNi });
+        }
+    }
+
+    #[test]
+    fn avx2_agrees_with_sha2() {
+        if is_x86_feature_detected!("avx2") {
+            check_against_sha2(&SimdBackend { kind: Kind::Avx2 });
+        }
+    }
+}
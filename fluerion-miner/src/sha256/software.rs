@@ -0,0 +1,15 @@
+use super::Sha256Backend;
+use sha2::digest::generic_array::GenericArray;
+
+/// Whatever compression function the `sha2` crate picks for this build.
+pub struct SoftwareBackend;
+
+impl Sha256Backend for SoftwareBackend {
+    fn name(&self) -> &'static str {
+        "sha2"
+    }
+
+    fn compress(&self, state: &mut [u32; 8], block: &[u8; 64]) {
+        sha2::compress256(state, &[GenericArray::from(*block)]);
+    }
+}
@@ -0,0 +1,123 @@
+use crate::node::Submission;
+use crate::search::Search;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Instant;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpListener;
+
+pub struct Stats {
+    started: Instant,
+    accepted: AtomicU64,
+    rejected: AtomicU64,
+    stale: AtomicU64,
+}
+
+impl Stats {
+    pub fn new() -> Self {
+        Stats {
+            started: Instant::now(),
+            accepted: AtomicU64::new(0),
+            rejected: AtomicU64::new(0),
+            stale: AtomicU64::new(0),
+        }
+    }
+
+    pub fn record(&self, submission: &Submission) {
+        let counter = match submission {
+            Submission::Accepted => &self.accepted,
+            Submission::Rejected(_) => &self.rejected,
+            Submission::Stale => &self.stale,
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn to_json(&self, search: &Search) -> String {
+        serde_json::json!({
+            "hashrate": search.hash_rate(),
+            "threads": search.threads(),
+            "accepted": self.accepted.load(Ordering::Relaxed),
+            "rejected": self.rejected.load(Ordering::Relaxed),
+            "stale": self.stale.load(Ordering::Relaxed),
+            "uptime": self.started.elapsed().as_secs(),
+        })
+        .to_string()
+    }
+}
+
+/// Answers every HTTP request on `listener` with the current stats as JSON.
+pub async fn serve(listener: TcpListener, stats: Arc<Stats>, search: Arc<Search>) {
+    loop {
+        let Ok((mut socket, _)) = listener.accept().await else {
+            continue;
+        };
+        let body = stats.to_json(&search);
+        tokio::spawn(async move {
+            let mut buffer = [0; 1024];
+            let _ = socket.read(&mut buffer).await;
+            let response = format!(
+                "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                body.len(),
+                body
+            );
+            let _ = socket.write_all(response.as_bytes()).await;
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sha256::ScalarBackend;
+    use tokio::net::TcpStream;
+
+    fn stats() -> Stats {
+        let stats = Stats::new();
+        stats.record(&Submission::Accepted);
+        stats.record(&Submission::Accepted);
+        stats.record(&Submission::Stale);
+        stats.record(&Submission::Rejected("bad proof".to_string()));
+        stats
+    }
+
+    #[test]
+    fn counts_submissions() {
+        let search = Search::new(Box::new(ScalarBackend), 3);
+        let json: serde_json::Value = serde_json::from_str(&stats().to_json(&search)).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!({
+                "hashrate": 0,
+                "threads": 3,
+                "accepted": 2,
+                "rejected": 1,
+                "stale": 1,
+                "uptime": 0,
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn serves_the_stats_over_http() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let search = Arc::new(Search::new(Box::new(ScalarBackend), 1));
+        tokio::spawn(serve(listener, Arc::new(stats()), search));
+
+        let mut stream = TcpStream::connect(address).await.unwrap();
+        stream
+            .write_all(b"GET / HTTP/1.1\r\nHost: localhost\r\n\r\n")
+            .await
+            .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+
+        let (head, body) = response.split_once("\r\n\r\n").unwrap();
+        assert!(head.starts_with("HTTP/1.1 200 OK\r\n"), "{}", head);
+        assert!(head.contains("Content-Type: application/json"));
+        assert!(head.contains(&format!("Content-Length: {}", body.len())));
+        let json: serde_json::Value = serde_json::from_str(body).unwrap();
+        assert_eq!(json["accepted"], 2);
+        assert_eq!(json["threads"], 1);
+    }
+}
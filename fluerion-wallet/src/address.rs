@@ -0,0 +1,105 @@
+use bech32::primitives::decode::CheckedHrpstring;
+use bech32::{Bech32m, Hrp};
+use sha2::{Digest, Sha256};
+
+use crate::chainparams::params;
+
+pub const PUBKEY_HASH_SIZE: usize = 20;
+pub const SCRIPT_HASH_SIZE: usize = 32;
+
+/// What an address commits to, told apart by the length of the payload.
+#[derive(Debug, PartialEq)]
+pub enum Payload {
+    /// A single key: the hash of its compressed public key.
+    PubkeyHash([u8; PUBKEY_HASH_SIZE]),
+    /// An m-of-n key set or a locking script: see
+    /// `MultisigAddress::redeem_hash`.
+    ScriptHash([u8; SCRIPT_HASH_SIZE]),
+}
+
+/// The first 20 bytes of the SHA-256 of a compressed public key.
+pub fn pubkey_hash(public_key: &[u8]) -> [u8; PUBKEY_HASH_SIZE] {
+    let digest = Sha256::digest(public_key);
+    let mut hash = [0u8; PUBKEY_HASH_SIZE];
+    hash.copy_from_slice(&digest[..PUBKEY_HASH_SIZE]);
+    hash
+}
+
+/// Encodes a public key or script hash as a bech32m string with the
+/// network's prefix, e.g. `fl1...` on mainnet.
+pub fn encode(hash: &[u8]) -> String {
+    let hrp = Hrp::parse(params().address_prefix).expect("valid address prefix");
+    bech32::encode::<Bech32m>(hrp, hash).expect("address fits in bech32m")
+}
+
+/// Checks the prefix, checksum and length of an address and returns what
+/// it commits to.
+pub fn decode(address: &str) -> Result<Payload, String> {
+    let checked = CheckedHrpstring::new::<Bech32m>(address)
+        .map_err(|e| format!("Invalid address {}: {}", address, e))?;
+
+    let prefix = params().address_prefix;
+    if checked.hrp().to_lowercase() != prefix {
+        return Err(format!(
+            "Address {} is not for this network (expected prefix {})",
+            address, prefix
+        ));
+    }
+
+    let bytes: Vec<u8> = checked.byte_iter().collect();
+    match bytes.len() {
+        PUBKEY_HASH_SIZE => Ok(Payload::PubkeyHash(bytes.try_into().unwrap())),
+        SCRIPT_HASH_SIZE => Ok(Payload::ScriptHash(bytes.try_into().unwrap())),
+        _ => Err(format!("Invalid address {}: wrong length", address)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn encode_as(prefix: &str, hash: &[u8]) -> String {
+        bech32::encode::<Bech32m>(Hrp::parse(prefix).unwrap(), hash).unwrap()
+    }
+
+    #[test]
+    fn reads_back_what_it_encodes() {
+        let key_hash = pubkey_hash(&[2; 33]);
+        let address = encode(&key_hash);
+        assert!(address.starts_with(&format!("{}1", params().address_prefix)));
+        assert_eq!(decode(&address), Ok(Payload::PubkeyHash(key_hash)));
+        assert_eq!(
+            decode(&address.to_uppercase()),
+            Ok(Payload::PubkeyHash(key_hash))
+        );
+        assert_eq!(
+            decode(&encode(&[7; SCRIPT_HASH_SIZE])),
+            Ok(Payload::ScriptHash([7; SCRIPT_HASH_SIZE]))
+        );
+    }
+
+    #[test]
+    fn rejects_addresses_for_other_networks() {
+        let prefix = params().address_prefix;
+        for other in ["fl", "tf", "flrt"].into_iter().filter(|&p| p != prefix) {
+            let e = decode(&encode_as(other, &[1; PUBKEY_HASH_SIZE])).unwrap_err();
+            assert!(e.contains("not for this network"), "{}", e);
+        }
+    }
+
+    #[test]
+    fn rejects_malformed_addresses() {
+        let address = encode(&[1; PUBKEY_HASH_SIZE]);
+        let mut typo = address.clone();
+        let last = if typo.ends_with('q') { 'p' } else { 'q' };
+        typo.replace_range(typo.len() - 1.., &last.to_string());
+        assert!(decode(&typo).is_err());
+
+        let prefix = Hrp::parse(params().address_prefix).unwrap();
+        let bech32 = bech32::encode::<bech32::Bech32>(prefix, &[1; PUBKEY_HASH_SIZE]).unwrap();
+        assert!(decode(&bech32).is_err());
+
+        let e = decode(&encode(&[1; 25])).unwrap_err();
+        assert!(e.ends_with("wrong length"), "{}", e);
+    }
+}
@@ -0,0 +1,1587 @@
+use crate::address;
+use crate::config::{Command, Config, TxSource};
+use crate::hd::{Account, GAP_LIMIT};
+use crate::htlc::{self, Contract};
+use crate::keys;
+use crate::multisig::{MultisigAddress, PartialSignature};
+use crate::node::{Balance, HistoryEntry, NodeClient, TokenBalance};
+use crate::offline::PortableTransaction;
+use crate::rescan::{self, Filters};
+use crate::token::{self, TokenOp};
+use crate::transaction::{Extras, Transaction};
+use crate::wallet::Wallet;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::fs;
+use std::io::{self, IsTerminal};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+pub const EXIT_OK: i32 = 0;
+/// The node answered but refused the request, e.g. rejected a transaction.
+pub const EXIT_REJECTED: i32 = 1;
+pub const EXIT_USAGE: i32 = 2;
+pub const EXIT_NODE_UNREACHABLE: i32 = 3;
+/// The wallet file is missing or corrupt, or could not be unlocked.
+pub const EXIT_WALLET: i32 = 4;
+
+/// Environment variable commands read the wallet passphrase from when they
+/// need the private keys and there is no terminal to ask on.
+pub const PASSPHRASE_VAR: &str = "FLUERION_WALLET_PASSPHRASE";
+
+/// Page size used when collecting an account's history address by address.
+const HISTORY_FETCH_SIZE: usize = 100;
+
+struct Failure {
+    code: i32,
+    message: String,
+}
+
+impl Failure {
+    fn new(code: i32, message: impl Into<String>) -> Self {
+        Failure {
+            code,
+            message: message.into(),
+        }
+    }
+
+    fn wallet(message: impl Into<String>) -> Self {
+        Self::new(EXIT_WALLET, message)
+    }
+
+    fn usage(message: impl Into<String>) -> Self {
+        Self::new(EXIT_USAGE, message)
+    }
+}
+
+/// Connection failures mean the node is down or misaddressed; anything else
+/// came back from the node itself.
+impl From<io::Error> for Failure {
+    fn from(e: io::Error) -> Self {
+        let code = match e.kind() {
+            io::ErrorKind::InvalidInput | io::ErrorKind::InvalidData => EXIT_REJECTED,
+            _ => EXIT_NODE_UNREACHABLE,
+        };
+        Failure::new(code, e.to_string())
+    }
+}
+
+/// Output of one command, printed as JSON with `--json` and as plain text
+/// otherwise.
+struct Output {
+    json: serde_json::Value,
+    text: String,
+}
+
+/// Runs a single command and returns the process exit code.
+pub async fn run(config: &Config, command: Command) -> i32 {
+    match execute(config, command).await {
+        Ok(output) => {
+            if config.json {
+                println!("{}", output.json);
+            } else {
+                println!("{}", output.text);
+            }
+            EXIT_OK
+        }
+        Err(failure) => {
+            if config.json {
+                println!(
+                    "{}",
+                    json!({ "error": failure.message, "code": failure.code })
+                );
+            } else {
+                eprintln!("{}", failure.message);
+            }
+            failure.code
+        }
+    }
+}
+
+async fn execute(config: &Config, command: Command) -> Result<Output, Failure> {
+    match command {
+        Command::Balance { account, address } => {
+            let wallet = open(config)?;
+            let addresses = addresses(&wallet, account, address)?;
+            balance(&client(config)?, &wallet, addresses).await
+        }
+        Command::History {
+            account,
+            address,
+            offset,
+            limit,
+        } => {
+            let addresses = addresses(&open(config)?, account, address)?;
+            history(&client(config)?, addresses, offset, limit).await
+        }
+        Command::NewAddress { account } => new_address(&mut open(config)?, account),
+        Command::ListAddresses { account } => list_addresses(&open(config)?, account),
+        Command::Send {
+            to,
+            amount,
+            fee,
+            target,
+            from,
+            account,
+            extras,
+        } => {
+            let fee = FeeChoice { fee, target };
+            send(
+                config,
+                &mut open(config)?,
+                to,
+                amount,
+                fee,
+                extras,
+                from,
+                account,
+            )
+            .await
+        }
+        Command::Export { mnemonic } => export(&mut open(config)?, mnemonic),
+        Command::CreateWatchOnly { export } => create_watch_only(config, &export),
+        Command::CreateTx {
+            to,
+            amount,
+            fee,
+            target,
+            from,
+            account,
+            extras,
+            out,
+        } => {
+            let fee = FeeChoice { fee, target };
+            create_tx(
+                config,
+                &open(config)?,
+                to,
+                amount,
+                fee,
+                extras,
+                from,
+                account,
+                out,
+            )
+            .await
+        }
+        Command::SignTx { source, out } => sign_tx(&mut open(config)?, source, out),
+        Command::BroadcastTx { source } => broadcast_tx(config, source).await,
+        Command::FinalizeTx { source, out } => finalize_tx(source, out),
+        Command::GetPubkey { account, address } => get_pubkey(&open(config)?, account, address),
+        Command::CreateMultisig { threshold, pubkeys } => {
+            create_multisig(&mut open(config)?, threshold, pubkeys)
+        }
+        Command::ImportAddress { address, label } => {
+            let mut wallet = open(config)?;
+            wallet
+                .import_address(&address, label)
+                .map_err(Failure::usage)?;
+            Ok(watching(address))
+        }
+        Command::ImportPubkey { pubkey, label } => {
+            let mut wallet = open(config)?;
+            let address = wallet
+                .import_pubkey(&pubkey, label)
+                .map_err(Failure::usage)?;
+            Ok(watching(address))
+        }
+        Command::AddContact { label, address } => {
+            let mut wallet = open(config)?;
+            wallet
+                .set_contact(&label, &address)
+                .map_err(Failure::usage)?;
+            Ok(Output {
+                text: format!("Saved {} as {}", address, label),
+                json: json!({ "label": label, "address": address }),
+            })
+        }
+        Command::RemoveContact { label } => {
+            let address = open(config)?
+                .remove_contact(&label)
+                .map_err(Failure::usage)?;
+            Ok(Output {
+                text: format!("Removed {} ({})", label, address),
+                json: json!({ "label": label, "address": address }),
+            })
+        }
+        Command::ListContacts => list_contacts(&open(config)?),
+        Command::Rescan { start } => rescan(&client(config)?, &mut open(config)?, start).await,
+        Command::InitiateSwap {
+            to,
+            amount,
+            timeout,
+            secret_hash,
+            fee,
+            target,
+            from,
+            account,
+        } => {
+            let fee = FeeChoice { fee, target };
+            let swap = Swap {
+                to,
+                amount,
+                timeout,
+                secret_hash,
+            };
+            initiate_swap(config, &mut open(config)?, swap, fee, from, account).await
+        }
+        Command::RedeemSwap {
+            contract,
+            secret,
+            fee,
+            target,
+        } => {
+            let fee = FeeChoice { fee, target };
+            let secret = hex::decode(&secret).map_err(|_| Failure::usage("Secret is not hex"))?;
+            claim_swap(config, &mut open(config)?, &contract, Some(secret), fee).await
+        }
+        Command::RefundSwap {
+            contract,
+            fee,
+            target,
+        } => {
+            let fee = FeeChoice { fee, target };
+            claim_swap(config, &mut open(config)?, &contract, None, fee).await
+        }
+        Command::ExtractSecret { contract } => extract_secret(&client(config)?, &contract).await,
+        Command::FindData { data } => find_data(&client(config)?, &data).await,
+        Command::GetTransaction { txid } => get_transaction(&client(config)?, &txid).await,
+        Command::IssueToken {
+            name,
+            symbol,
+            decimals,
+            supply,
+            mintable,
+            to,
+            fee,
+            target,
+            from,
+            account,
+        } => {
+            let fee = FeeChoice { fee, target };
+            let op = TokenOp::Issue {
+                name,
+                symbol,
+                decimals,
+                supply,
+                mintable,
+            };
+            issue_token(config, &mut open(config)?, op, to, fee, from, account).await
+        }
+        Command::SendToken {
+            token,
+            to,
+            amount,
+            fee,
+            target,
+            from,
+            account,
+        } => {
+            let fee = FeeChoice { fee, target };
+            let send = TokenSend {
+                token,
+                to: Some(to),
+                amount,
+            };
+            move_token(
+                config,
+                &mut open(config)?,
+                send,
+                Move::Transfer,
+                fee,
+                from,
+                account,
+            )
+            .await
+        }
+        Command::MintToken {
+            token,
+            to,
+            amount,
+            fee,
+            target,
+        } => {
+            let fee = FeeChoice { fee, target };
+            let send = TokenSend { token, to, amount };
+            move_token(config, &mut open(config)?, send, Move::Mint, fee, None, 0).await
+        }
+        Command::BurnToken {
+            token,
+            amount,
+            fee,
+            target,
+        } => {
+            let fee = FeeChoice { fee, target };
+            let send = TokenSend {
+                token,
+                to: None,
+                amount,
+            };
+            move_token(config, &mut open(config)?, send, Move::Burn, fee, None, 0).await
+        }
+        Command::TokenBalance { account, address } => {
+            let addresses = addresses(&open(config)?, account, address)?;
+            token_balance(&client(config)?, addresses).await
+        }
+    }
+}
+
+fn open(config: &Config) -> Result<Wallet, Failure> {
+    let auto_lock = Duration::from_secs(config.auto_lock);
+    Wallet::open(&config.wallet, auto_lock).map_err(Failure::wallet)
+}
+
+fn client(config: &Config) -> Result<NodeClient, Failure> {
+    match &config.node {
+        Some(node) => Ok(NodeClient::new(node.clone())),
+        None => Err(Failure::usage(
+            "No node address; pass --node or set it in the config",
+        )),
+    }
+}
+
+fn check_account(wallet: &Wallet, account: u32) -> Result<(), Failure> {
+    if (account as usize) < wallet.accounts.len() {
+        Ok(())
+    } else {
+        Err(Failure::usage(format!("No account #{}", account)))
+    }
+}
+
+/// The addresses a query covers: the one given, or every address of the
+/// account followed by the watched addresses.
+fn addresses(
+    wallet: &Wallet,
+    account: u32,
+    address: Option<String>,
+) -> Result<Vec<String>, Failure> {
+    if let Some(address) = address {
+        address::decode(&address).map_err(Failure::usage)?;
+        return Ok(vec![address]);
+    }
+    check_account(wallet, account)?;
+    let mut addresses = wallet.accounts[account as usize].addresses();
+    addresses.extend(
+        wallet
+            .watched_addresses()
+            .iter()
+            .map(|watched| watched.address.clone()),
+    );
+    Ok(addresses)
+}
+
+/// Totals over all `addresses`, with the watched share broken out since the
+/// wallet cannot spend it.
+async fn balance(
+    client: &NodeClient,
+    wallet: &Wallet,
+    addresses: Vec<String>,
+) -> Result<Output, Failure> {
+    let mut balances: Vec<Balance> = Vec::new();
+    for address in &addresses {
+        balances.push(client.balance(address).await?);
+    }
+    let confirmed: f64 = balances.iter().map(|balance| balance.confirmed).sum();
+    let unconfirmed: f64 = balances.iter().map(|balance| balance.unconfirmed).sum();
+    let watched: Vec<&Balance> = balances
+        .iter()
+        .filter(|balance| wallet.is_watched(&balance.address))
+        .collect();
+    let watched_confirmed: f64 = watched.iter().map(|balance| balance.confirmed).sum();
+    let watched_unconfirmed: f64 = watched.iter().map(|balance| balance.unconfirmed).sum();
+
+    let mut text = format!("Confirmed: {}\nUnconfirmed: {:+}", confirmed, unconfirmed);
+    if !watched.is_empty() {
+        text.push_str(&format!(
+            "\nWatch-only: {} ({:+})",
+            watched_confirmed, watched_unconfirmed
+        ));
+    }
+    if balances.len() > 1 {
+        for balance in &balances {
+            text.push_str(&format!(
+                "\n  {} {} ({:+})",
+                balance.address, balance.confirmed, balance.unconfirmed
+            ));
+            if wallet.is_watched(&balance.address) {
+                text.push_str(" watch-only");
+            }
+        }
+    }
+    Ok(Output {
+        json: json!({
+            "confirmed": confirmed,
+            "unconfirmed": unconfirmed,
+            "watch_only": {
+                "confirmed": watched_confirmed,
+                "unconfirmed": watched_unconfirmed,
+            },
+            "addresses": balances,
+        }),
+        text,
+    })
+}
+
+#[derive(Serialize)]
+struct AddressHistoryEntry {
+    address: String,
+    #[serde(flatten)]
+    entry: HistoryEntry,
+}
+
+/// Merges the history of every address, newest first, then applies the
+/// page. With a single address the node does the paging itself.
+async fn history(
+    client: &NodeClient,
+    addresses: Vec<String>,
+    offset: usize,
+    limit: usize,
+) -> Result<Output, Failure> {
+    let total;
+    let mut entries = Vec::new();
+    if let [address] = addresses.as_slice() {
+        let history = client.history(address, offset, limit).await?;
+        total = history.total;
+        entries.extend(
+            history
+                .transactions
+                .into_iter()
+                .map(|entry| AddressHistoryEntry {
+                    address: address.clone(),
+                    entry,
+                }),
+        );
+    } else {
+        for address in &addresses {
+            let mut fetched = 0;
+            loop {
+                let history = client.history(address, fetched, HISTORY_FETCH_SIZE).await?;
+                fetched += history.transactions.len();
+                entries.extend(
+                    history
+                        .transactions
+                        .into_iter()
+                        .map(|entry| AddressHistoryEntry {
+                            address: address.clone(),
+                            entry,
+                        }),
+                );
+                if fetched >= history.total || fetched == 0 {
+                    break;
+                }
+            }
+        }
+        // Pending transactions (no height) sort before every confirmed one.
+        entries.sort_by_key(|item| std::cmp::Reverse(item.entry.height.unwrap_or(u64::MAX)));
+        total = entries.len();
+        entries = entries.into_iter().skip(offset).take(limit).collect();
+    }
+
+    let text = entries
+        .iter()
+        .map(|item| {
+            let entry = &item.entry;
+            let status = match entry.height {
+                Some(height) => format!("block {}, {} conf", height, entry.confirmations),
+                None => "pending".to_string(),
+            };
+            let line = format!(
+                "{} {} {} {} -> {} ({})",
+                item.address,
+                entry.direction,
+                entry.transaction.amount,
+                entry.transaction.sender,
+                entry.transaction.receiver,
+                status
+            );
+            match &entry.transaction.data {
+                Some(data) => format!("{} data {}", line, data),
+                None => line,
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+    Ok(Output {
+        json: json!({ "total": total, "offset": offset, "transactions": entries }),
+        text: format!("{} of {} transactions\n{}", entries.len(), total, text)
+            .trim_end()
+            .to_string(),
+    })
+}
+
+/// The account and address index to spend from: `from` if given, else the
+/// account's most recent address.
+fn spending_address(
+    wallet: &Wallet,
+    from: Option<&str>,
+    account: u32,
+) -> Result<(u32, u32), Failure> {
+    if let Some(from) = from {
+        if wallet.is_watched(from) {
+            return Err(Failure::usage(format!(
+                "{} is watch-only; this wallet holds no key for it",
+                from
+            )));
+        }
+        return wallet
+            .find_address(from)
+            .ok_or_else(|| Failure::usage(format!("{} is not an address of this wallet", from)));
+    }
+    check_account(wallet, account)?;
+    match wallet.accounts[account as usize].address_count {
+        0 => Err(Failure::usage(format!(
+            "Account #{} has no addresses",
+            account
+        ))),
+        count => Ok((account, count - 1)),
+    }
+}
+
+/// The account and address index to make `payment` from: `from` if given,
+/// else the account's newest address, other than the receiver, that the
+/// node says can spend the payment's amount and fee.
+async fn funded_address(
+    config: &Config,
+    wallet: &Wallet,
+    from: Option<&str>,
+    account: u32,
+    payment: &Transaction,
+) -> Result<(u32, u32), Failure> {
+    if from.is_some() {
+        return spending_address(wallet, from, account);
+    }
+    check_account(wallet, account)?;
+    let client = client(config)?;
+    let needed = payment.amount + payment.fee;
+    let addresses = &wallet.accounts[account as usize];
+    for index in (0..addresses.address_count).rev() {
+        let address = addresses.address(index);
+        if address == payment.receiver {
+            continue;
+        }
+        // Balances are floats, so allow for rounding.
+        if client.balance(&address).await?.spendable + 1e-9 >= needed {
+            return Ok((account, index));
+        }
+    }
+    Err(Failure::new(
+        EXIT_REJECTED,
+        format!(
+            "No address of account #{} can pay {}; fund one or pick it with --from",
+            account, needed
+        ),
+    ))
+}
+
+/// A fee given outright, or the confirmation target to ask the node to
+/// estimate one for.
+struct FeeChoice {
+    fee: Option<f64>,
+    target: usize,
+}
+
+impl FeeChoice {
+    /// Sets the transaction's fee, estimating it for a signature of
+    /// `signature_len` characters unless one was given.
+    async fn apply(
+        &self,
+        config: &Config,
+        transaction: &mut Transaction,
+        signature_len: usize,
+    ) -> Result<(), Failure> {
+        transaction.fee = match self.fee {
+            Some(fee) => fee,
+            None => {
+                let fee_rate = client(config)?.estimate_fee(self.target).await?;
+                transaction.fee_for(fee_rate, signature_len)
+            }
+        };
+        Ok(())
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn send(
+    config: &Config,
+    wallet: &mut Wallet,
+    to: String,
+    amount: f64,
+    fee: FeeChoice,
+    extras: Extras,
+    from: Option<String>,
+    account: u32,
+) -> Result<Output, Failure> {
+    let transaction = sign_and_send(config, wallet, to, amount, fee, extras, from, account).await?;
+    Ok(Output {
+        text: format!(
+            "Sent {} from {} to {} (fee {})\nTransaction id: {}",
+            amount,
+            transaction.sender,
+            transaction.receiver,
+            transaction.fee,
+            transaction.id()
+        ),
+        json: json!({
+            "status": "sent",
+            "txid": transaction.id(),
+            "transaction": transaction,
+            "fee": transaction.fee,
+        }),
+    })
+}
+
+/// Signs a payment with one of the wallet's own keys and hands it to the
+/// node, returning the transaction as sent.
+#[allow(clippy::too_many_arguments)]
+async fn sign_and_send(
+    config: &Config,
+    wallet: &mut Wallet,
+    to: String,
+    amount: f64,
+    fee: FeeChoice,
+    extras: Extras,
+    from: Option<String>,
+    account: u32,
+) -> Result<Transaction, Failure> {
+    let to = wallet.resolve_receiver(&to).map_err(Failure::usage)?;
+    if wallet.is_watch_only() {
+        return Err(Failure::wallet(
+            "Watch-only wallet cannot sign; use createtx and sign offline",
+        ));
+    }
+
+    // Every address of the wallet is as long as any other, so the fee does
+    // not depend on which one ends up paying.
+    let (account, index) = spending_address(wallet, from.as_deref(), account)?;
+    let sender = wallet.accounts[account as usize].address(index);
+    let mut transaction = Transaction::new(sender, to, amount, 0.0);
+    transaction.set_extras(extras);
+    fee.apply(config, &mut transaction, 2 * keys::SIGNATURE_SIZE)
+        .await?;
+    let (account, index) =
+        funded_address(config, wallet, from.as_deref(), account, &transaction).await?;
+    let client = client(config)?;
+    unlock(wallet)?;
+    let key_pair = wallet.key_pair(account, index).map_err(Failure::wallet)?;
+    transaction.sender = key_pair.address();
+    transaction.nonce = client.next_nonce(&transaction.sender).await?;
+    transaction.sign(&key_pair).map_err(Failure::wallet)?;
+    client.send_transaction(&transaction).await?;
+    Ok(transaction)
+}
+
+/// Builds an unsigned transaction from public keys alone. Asks the node for
+/// the sender's next nonce and, when the fee has to be estimated or without
+/// `from`, for the fee rate and which address can pay.
+#[allow(clippy::too_many_arguments)]
+async fn create_tx(
+    config: &Config,
+    wallet: &Wallet,
+    to: String,
+    amount: f64,
+    fee: FeeChoice,
+    extras: Extras,
+    from: Option<String>,
+    account: u32,
+    out: Option<PathBuf>,
+) -> Result<Output, Failure> {
+    let to = wallet.resolve_receiver(&to).map_err(Failure::usage)?;
+    if let Some(multisig) = from.as_deref().and_then(|from| wallet.find_multisig(from)) {
+        let mut transaction = Transaction::new(multisig.address(), to, amount, 0.0);
+        transaction.set_extras(extras);
+        fee.apply(config, &mut transaction, multisig.signature_len())
+            .await?;
+        transaction.nonce = client(config)?.next_nonce(&transaction.sender).await?;
+        let portable = PortableTransaction::new_multisig(transaction, multisig.clone());
+        return write_tx(&portable, out);
+    }
+
+    let (account, index) = spending_address(wallet, from.as_deref(), account)?;
+    let sender = wallet.accounts[account as usize].address(index);
+    let mut transaction = Transaction::new(sender, to, amount, 0.0);
+    transaction.set_extras(extras);
+    fee.apply(config, &mut transaction, 2 * keys::SIGNATURE_SIZE)
+        .await?;
+    let (account, index) =
+        funded_address(config, wallet, from.as_deref(), account, &transaction).await?;
+    transaction.sender = wallet.accounts[account as usize].address(index);
+    transaction.nonce = client(config)?.next_nonce(&transaction.sender).await?;
+    let portable = PortableTransaction::new(transaction, account, index);
+    write_tx(&portable, out)
+}
+
+/// Signs a portable transaction with the key it names, after checking that
+/// key really is the sender's. For a multisig spend, adds a partial
+/// signature for every key of the set this wallet holds.
+fn sign_tx(wallet: &mut Wallet, source: TxSource, out: Option<PathBuf>) -> Result<Output, Failure> {
+    let mut portable = read_tx(&source)?;
+    if portable.transaction.signature.is_some() {
+        return Err(Failure::usage("Transaction is already signed"));
+    }
+    if portable.multisig.is_some() {
+        sign_multisig(wallet, &mut portable)?;
+        return write_tx(&portable, out);
+    }
+
+    let (Some(account), Some(index)) = (portable.account, portable.index) else {
+        return Err(Failure::usage("Transaction names no signing key"));
+    };
+    check_account(wallet, account)?;
+    unlock(wallet)?;
+    let key_pair = wallet.key_pair(account, index).map_err(Failure::wallet)?;
+    portable
+        .transaction
+        .sign(&key_pair)
+        .map_err(Failure::wallet)?;
+    write_tx(&portable, out)
+}
+
+fn sign_multisig(wallet: &mut Wallet, portable: &mut PortableTransaction) -> Result<(), Failure> {
+    let multisig = portable.multisig.clone().unwrap();
+    if multisig.address() != portable.transaction.sender {
+        return Err(Failure::usage("Key set does not match the sender address"));
+    }
+
+    let signed: Vec<u8> = portable.partial_signatures.iter().map(|p| p.key).collect();
+    let mut ours = Vec::new();
+    for (key, pubkey) in multisig.keys().enumerate() {
+        let address = address::encode(&address::pubkey_hash(pubkey));
+        if let Some(path) = wallet.find_address(&address) {
+            if !signed.contains(&(key as u8)) {
+                ours.push((key as u8, path));
+            }
+        }
+    }
+    if ours.is_empty() {
+        return Err(Failure::wallet(
+            "This wallet holds no unsigned key of the multisig address",
+        ));
+    }
+
+    unlock(wallet)?;
+    let data = portable.transaction.signing_data();
+    for (key, (account, index)) in ours {
+        let key_pair = wallet.key_pair(account, index).map_err(Failure::wallet)?;
+        portable.partial_signatures.push(PartialSignature {
+            key,
+            signature: hex::encode(key_pair.sign_compact(&data)),
+        });
+    }
+    portable
+        .partial_signatures
+        .sort_by_key(|partial| partial.key);
+    Ok(())
+}
+
+/// Turns enough partial signatures into the transaction's signature. Needs
+/// no keys, so any instance can do it.
+fn finalize_tx(source: TxSource, out: Option<PathBuf>) -> Result<Output, Failure> {
+    let mut portable = read_tx(&source)?;
+    let Some(multisig) = &portable.multisig else {
+        return Err(Failure::usage("Only multisig transactions need finalizing"));
+    };
+    let data = portable.transaction.signing_data();
+    let signature = multisig
+        .finalize(&data, &portable.partial_signatures)
+        .map_err(|e| Failure::new(EXIT_REJECTED, e))?;
+
+    portable.transaction.signature = Some(signature);
+    portable.partial_signatures.clear();
+    portable
+        .transaction
+        .verify()
+        .map_err(|e| Failure::new(EXIT_REJECTED, e))?;
+    write_tx(&portable, out)
+}
+
+/// The public key behind one of this wallet's addresses, to share with the
+/// other signers of a multisig address.
+fn get_pubkey(wallet: &Wallet, account: u32, address: Option<String>) -> Result<Output, Failure> {
+    let (account, index) = spending_address(wallet, address.as_deref(), account)?;
+    let pubkey = hex::encode(wallet.accounts[account as usize].public_key(index));
+    Ok(Output {
+        json: json!({
+            "address": wallet.accounts[account as usize].address(index),
+            "pubkey": pubkey,
+        }),
+        text: pubkey,
+    })
+}
+
+/// Records an m-of-n address in the wallet. Every signer runs this with
+/// the same keys in the same order and gets the same address.
+fn create_multisig(
+    wallet: &mut Wallet,
+    threshold: u8,
+    pubkeys: Vec<String>,
+) -> Result<Output, Failure> {
+    let pubkeys = pubkeys.into_iter().map(|key| key.to_lowercase()).collect();
+    let multisig = MultisigAddress::new(threshold, pubkeys).map_err(Failure::usage)?;
+    let address = multisig.address();
+    let text = format!(
+        "{} ({}-of-{})",
+        address,
+        multisig.threshold(),
+        multisig.pubkeys().len()
+    );
+    let json = json!({
+        "address": address,
+        "threshold": multisig.threshold(),
+        "pubkeys": multisig.pubkeys(),
+    });
+    wallet.add_multisig(multisig).map_err(Failure::wallet)?;
+    Ok(Output { json, text })
+}
+
+/// Verifies a signed transaction locally, then hands it to the node.
+async fn broadcast_tx(config: &Config, source: TxSource) -> Result<Output, Failure> {
+    let portable = read_tx(&source)?;
+    if portable.transaction.signature.is_none() && !portable.partial_signatures.is_empty() {
+        return Err(Failure::usage("Multisig transaction is not finalized yet"));
+    }
+    let transaction = portable.transaction;
+    transaction
+        .verify()
+        .map_err(|e| Failure::new(EXIT_REJECTED, e))?;
+
+    client(config)?.send_transaction(&transaction).await?;
+    Ok(Output {
+        text: format!(
+            "Sent {} from {} to {}",
+            transaction.amount, transaction.sender, transaction.receiver
+        ),
+        json: json!({ "status": "sent", "transaction": transaction }),
+    })
+}
+
+fn read_tx(source: &TxSource) -> Result<PortableTransaction, Failure> {
+    match source {
+        TxSource::File(path) => PortableTransaction::read(path),
+        TxSource::Blob(blob) => PortableTransaction::parse(blob),
+    }
+    .map_err(Failure::usage)
+}
+
+/// Writes the transaction to `out` as JSON, or prints it as a base64 blob.
+fn write_tx(portable: &PortableTransaction, out: Option<PathBuf>) -> Result<Output, Failure> {
+    let signed = portable.transaction.signature.is_some();
+    let mut json = json!({
+        "signed": signed,
+        "partial_signatures": portable.partial_signatures.len(),
+        "transaction": portable.transaction,
+    });
+    let text = match out {
+        Some(path) => {
+            portable.write(&path).map_err(Failure::usage)?;
+            json["file"] = json!(path);
+            let state = match (signed, portable.partial_signatures.len()) {
+                (true, _) => "signed".to_string(),
+                (false, 0) => "unsigned".to_string(),
+                (false, n) => format!("partially signed ({} signatures)", n),
+            };
+            format!("Wrote {} transaction to {}", state, path.display())
+        }
+        None => {
+            let blob = portable.to_base64();
+            json["blob"] = json!(blob);
+            blob
+        }
+    };
+    Ok(Output { json, text })
+}
+
+/// Turns the output of `export --json` into a wallet that can show balances
+/// and build transactions but holds no private keys.
+fn create_watch_only(config: &Config, export: &Path) -> Result<Output, Failure> {
+    #[derive(Deserialize)]
+    struct Export {
+        accounts: Vec<ExportedAccount>,
+        #[serde(default)]
+        birthday: u64,
+    }
+    #[derive(Deserialize)]
+    struct ExportedAccount {
+        index: u32,
+        xpub: String,
+        addresses: Vec<String>,
+    }
+
+    if config.wallet.exists() {
+        return Err(Failure::wallet(format!(
+            "{} already exists",
+            config.wallet.display()
+        )));
+    }
+    let contents = fs::read_to_string(export)
+        .map_err(|e| Failure::usage(format!("Cannot read {}: {}", export.display(), e)))?;
+    let export: Export = serde_json::from_str(&contents)
+        .map_err(|e| Failure::usage(format!("Invalid export {}: {}", export.display(), e)))?;
+
+    let accounts = export
+        .accounts
+        .iter()
+        .map(|account| {
+            Account::from_xpub(account.index, &account.xpub, account.addresses.len() as u32)
+        })
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(Failure::usage)?;
+    if accounts.is_empty() {
+        return Err(Failure::usage("Export has no accounts"));
+    }
+    let count = accounts.len();
+    let auto_lock = Duration::from_secs(config.auto_lock);
+    let mut wallet =
+        Wallet::create_watch_only(&config.wallet, accounts, auto_lock).map_err(Failure::wallet)?;
+    wallet
+        .set_birthday(export.birthday)
+        .map_err(Failure::wallet)?;
+    Ok(Output {
+        json: json!({ "wallet": config.wallet, "accounts": count }),
+        text: format!(
+            "Created watch-only wallet {} with {} accounts",
+            config.wallet.display(),
+            count
+        ),
+    })
+}
+
+fn new_address(wallet: &mut Wallet, account: u32) -> Result<Output, Failure> {
+    check_account(wallet, account)?;
+    let address = wallet
+        .new_address(account as usize)
+        .map_err(Failure::wallet)?;
+    Ok(Output {
+        json: json!({ "account": account, "address": address }),
+        text: address,
+    })
+}
+
+fn list_addresses(wallet: &Wallet, account: Option<u32>) -> Result<Output, Failure> {
+    let accounts = match account {
+        Some(account) => {
+            check_account(wallet, account)?;
+            vec![&wallet.accounts[account as usize]]
+        }
+        None => wallet.accounts.iter().collect(),
+    };
+    let mut entries = Vec::new();
+    let mut text = Vec::new();
+    for account in accounts {
+        for (index, address) in account.addresses().into_iter().enumerate() {
+            text.push(format!("{}/{} {}", account.index, index, address));
+            entries.push(json!({
+                "account": account.index,
+                "index": index,
+                "address": address,
+            }));
+        }
+    }
+    for multisig in wallet.multisig_addresses() {
+        let kind = format!("{}-of-{}", multisig.threshold(), multisig.pubkeys().len());
+        text.push(format!("{} {}", kind, multisig.address()));
+        entries.push(json!({ "multisig": kind, "address": multisig.address() }));
+    }
+    for watched in wallet.watched_addresses() {
+        let label = watched.label.as_deref().unwrap_or_default();
+        text.push(
+            format!("watch {} {}", watched.address, label)
+                .trim_end()
+                .to_string(),
+        );
+        entries.push(json!({ "watch_only": true, "address": watched.address, "label": label }));
+    }
+    Ok(Output {
+        json: json!(entries),
+        text: text.join("\n"),
+    })
+}
+
+/// Rescans from `start` or the wallet's birthday. Before fetching any
+/// block, looks `GAP_LIMIT` addresses past the end of every account in the
+/// filters, so funds sent to addresses another copy of the wallet handed out
+/// are found too.
+async fn rescan(
+    client: &NodeClient,
+    wallet: &mut Wallet,
+    start: Option<u64>,
+) -> Result<Output, Failure> {
+    let filters = Filters::download(client, start.unwrap_or(wallet.birthday())).await?;
+
+    let mut text = Vec::new();
+    let mut accounts = Vec::new();
+    for i in 0..wallet.accounts.len() {
+        let account = &wallet.accounts[i];
+        let mut count = account.address_count;
+        let mut index = count;
+        while index < count + GAP_LIMIT {
+            if filters.contains(&account.address(index)) {
+                count = index + 1;
+            }
+            index += 1;
+        }
+        if wallet.extend_addresses(i, count).map_err(Failure::wallet)? {
+            text.push(format!("Account #{} now has {} addresses", i, count));
+        }
+        accounts.push(json!({ "index": i, "address_count": count }));
+    }
+
+    let mut addresses: Vec<String> = wallet
+        .accounts
+        .iter()
+        .flat_map(|account| account.addresses())
+        .collect();
+    addresses.extend(wallet.multisig_addresses().iter().map(|m| m.address()));
+    addresses.extend(wallet.watched_addresses().iter().map(|w| w.address.clone()));
+    let (matched, found) = rescan::rescan(client, &filters, &addresses).await?;
+
+    text.insert(
+        0,
+        format!(
+            "Scanned blocks {} to {}: {} matched, {} transactions",
+            filters.start,
+            filters.tip,
+            matched.len(),
+            found.len()
+        ),
+    );
+    for found in &found {
+        let tx = &found.transaction;
+        text.push(format!(
+            "block {} {} {} -> {}",
+            found.height, tx.amount, tx.sender, tx.receiver
+        ));
+    }
+    let transactions: Vec<_> = found
+        .iter()
+        .map(|found| json!({ "height": found.height, "transaction": found.transaction }))
+        .collect();
+    Ok(Output {
+        json: json!({
+            "start": filters.start,
+            "tip": filters.tip,
+            "matched_blocks": matched,
+            "transactions": transactions,
+            "accounts": accounts,
+        }),
+        text: text.join("\n"),
+    })
+}
+
+/// The terms of a swap contract about to be funded.
+struct Swap {
+    to: String,
+    amount: f64,
+    timeout: u64,
+    secret_hash: Option<String>,
+}
+
+/// Funds a swap contract from one of this wallet's addresses, which the
+/// contract refunds to. Prints the contract, for the counterparty to check
+/// and later redeem, and any secret it made.
+async fn initiate_swap(
+    config: &Config,
+    wallet: &mut Wallet,
+    swap: Swap,
+    fee: FeeChoice,
+    from: Option<String>,
+    account: u32,
+) -> Result<Output, Failure> {
+    let to = wallet.resolve_receiver(&swap.to).map_err(Failure::usage)?;
+    let address::Payload::PubkeyHash(recipient) = address::decode(&to).map_err(Failure::usage)?
+    else {
+        return Err(Failure::usage("A swap can only pay a single-key address"));
+    };
+    let (secret, secret_hash) = match &swap.secret_hash {
+        Some(hash) => {
+            let hash = hex::decode(hash)
+                .ok()
+                .and_then(|hash| hash.try_into().ok())
+                .ok_or_else(|| Failure::usage("--hash must be 32 bytes of hex"))?;
+            (None, hash)
+        }
+        None => {
+            let (secret, hash) = htlc::new_secret();
+            (Some(secret), hash)
+        }
+    };
+
+    let contract_refunding = |sender: &str| -> Result<Contract, Failure> {
+        let address::Payload::PubkeyHash(refund) =
+            address::decode(sender).map_err(Failure::wallet)?
+        else {
+            unreachable!("wallet addresses are single-key");
+        };
+        Ok(Contract {
+            secret_hash,
+            recipient,
+            refund,
+            lock_time: swap.timeout,
+        })
+    };
+    // The contract refunds to the paying address, so pick that first, with
+    // the fee for a contract of the same size.
+    let (account, index) = spending_address(wallet, from.as_deref(), account)?;
+    let newest = wallet.accounts[account as usize].address(index);
+    let contract = contract_refunding(&newest)?;
+    let mut payment = Transaction::new(newest, contract.address(), swap.amount, 0.0);
+    fee.apply(config, &mut payment, 2 * keys::SIGNATURE_SIZE)
+        .await?;
+    let (account, index) =
+        funded_address(config, wallet, from.as_deref(), account, &payment).await?;
+    let sender = wallet.accounts[account as usize].address(index);
+    let contract = contract_refunding(&sender)?;
+
+    let extras = Extras::default();
+    let sent = send(
+        config,
+        wallet,
+        contract.address(),
+        swap.amount,
+        fee,
+        extras,
+        Some(sender),
+        account,
+    )
+    .await?;
+
+    let script = hex::encode(contract.script());
+    let mut text = vec![
+        sent.text,
+        format!("Contract: {}", script),
+        format!("Contract address: {}", contract.address()),
+        format!("Secret hash: {}", hex::encode(secret_hash)),
+        if contract.lock_time < htlc::LOCKTIME_THRESHOLD {
+            format!("Refundable from height {}", contract.lock_time)
+        } else {
+            format!("Refundable from Unix time {}", contract.lock_time)
+        },
+    ];
+    if let Some(secret) = secret {
+        text.push(format!(
+            "Secret: {} (keep it private until the counterparty has locked their side)",
+            hex::encode(secret)
+        ));
+    }
+    Ok(Output {
+        text: text.join("\n"),
+        json: json!({
+            "status": "initiated",
+            "contract": script,
+            "contract_address": contract.address(),
+            "secret_hash": hex::encode(secret_hash),
+            "secret": secret.map(hex::encode),
+            "timeout": contract.lock_time,
+            "transaction": sent.json["transaction"],
+        }),
+    })
+}
+
+/// Moves everything confirmed at a swap contract to this wallet: to the
+/// recipient with `secret`, or back to the refund address without it once
+/// the timeout has passed.
+async fn claim_swap(
+    config: &Config,
+    wallet: &mut Wallet,
+    contract_hex: &str,
+    secret: Option<Vec<u8>>,
+    fee: FeeChoice,
+) -> Result<Output, Failure> {
+    let script = hex::decode(contract_hex).map_err(|_| Failure::usage("Contract is not hex"))?;
+    let contract = Contract::parse(&script).map_err(Failure::usage)?;
+    let claimant = match &secret {
+        Some(secret) => {
+            if !contract.is_secret(secret) {
+                return Err(Failure::usage("Secret does not match the contract"));
+            }
+            contract.recipient_address()
+        }
+        None => contract.refund_address(),
+    };
+    let (account, index) = wallet
+        .find_address(&claimant)
+        .ok_or_else(|| Failure::usage(format!("{} is not an address of this wallet", claimant)))?;
+    if wallet.is_watch_only() {
+        return Err(Failure::wallet("Watch-only wallet cannot sign"));
+    }
+
+    let client = client(config)?;
+    let contract_balance = client.balance(&contract.address()).await?;
+    let locked = contract_balance.confirmed;
+    unlock(wallet)?;
+    let key_pair = wallet.key_pair(account, index).map_err(Failure::wallet)?;
+
+    let mut transaction = Transaction::new(contract.address(), claimant, locked, 0.0);
+    transaction.nonce = contract_balance.next_nonce;
+    transaction.script = Some(hex::encode(&script));
+    if secret.is_none() {
+        transaction.lock_time = contract.lock_time;
+    }
+    fee.apply(
+        config,
+        &mut transaction,
+        htlc::unlocking_len(secret.as_deref()),
+    )
+    .await?;
+    transaction.amount = locked - transaction.fee;
+    if transaction.amount <= 0.0 {
+        return Err(Failure::usage(format!(
+            "{} holds {} confirmed, not enough to pay the fee",
+            contract.address(),
+            locked
+        )));
+    }
+
+    let signature = key_pair.sign_compact(&transaction.signing_data());
+    let unlocking = match &secret {
+        Some(secret) => htlc::redeem_script(&signature, &key_pair.public_key(), secret),
+        None => htlc::refund_script(&signature, &key_pair.public_key()),
+    };
+    transaction.signature = Some(hex::encode(unlocking));
+    client.send_transaction(&transaction).await?;
+
+    let action = if secret.is_some() {
+        "Redeemed"
+    } else {
+        "Refunded"
+    };
+    Ok(Output {
+        text: format!(
+            "{} {} from {} to {} (fee {})",
+            action, transaction.amount, transaction.sender, transaction.receiver, transaction.fee
+        ),
+        json: json!({
+            "status": action.to_lowercase(),
+            "transaction": transaction,
+            "fee": transaction.fee,
+        }),
+    })
+}
+
+/// Looks through the contract address's history for the redeem that
+/// revealed its secret.
+async fn extract_secret(client: &NodeClient, contract_hex: &str) -> Result<Output, Failure> {
+    let script = hex::decode(contract_hex).map_err(|_| Failure::usage("Contract is not hex"))?;
+    let contract = Contract::parse(&script).map_err(Failure::usage)?;
+    let address = contract.address();
+    let script = hex::encode(&script);
+
+    let mut offset = 0;
+    loop {
+        let page = client.history(&address, offset, HISTORY_FETCH_SIZE).await?;
+        for entry in &page.transactions {
+            let tx = &entry.transaction;
+            if tx.sender != address || tx.script.as_ref() != Some(&script) {
+                continue;
+            }
+            let unlocking = tx.signature.as_deref().and_then(|s| hex::decode(s).ok());
+            if let Some(secret) =
+                unlocking.and_then(|unlocking| contract.extract_secret(&unlocking))
+            {
+                let secret = hex::encode(secret);
+                return Ok(Output {
+                    text: format!("Secret: {}", secret),
+                    json: json!({ "secret": secret, "transaction": tx }),
+                });
+            }
+        }
+        offset += page.transactions.len();
+        if page.transactions.is_empty() || offset >= page.total {
+            return Err(Failure::usage(format!(
+                "{} has not been redeemed yet",
+                address
+            )));
+        }
+    }
+}
+
+async fn find_data(client: &NodeClient, data: &str) -> Result<Output, Failure> {
+    let found = client.find_data(data).await?;
+    let text = found
+        .transactions
+        .iter()
+        .map(|found| {
+            let status = match found.height {
+                Some(height) => format!("block {}, {} conf", height, found.confirmations),
+                None => "pending".to_string(),
+            };
+            let tx = &found.transaction;
+            format!(
+                "{} {} -> {} ({})",
+                tx.amount, tx.sender, tx.receiver, status
+            )
+        })
+        .collect::<Vec<_>>();
+    Ok(Output {
+        text: format!(
+            "{} transactions carry {}\n{}",
+            text.len(),
+            data,
+            text.join("\n")
+        )
+        .trim_end()
+        .to_string(),
+        json: json!({ "data": data, "transactions": found.transactions }),
+    })
+}
+
+async fn get_transaction(client: &NodeClient, txid: &str) -> Result<Output, Failure> {
+    let info = client.transaction(txid).await?;
+    let tx = &info.transaction;
+    let status = match (&info.block_hash, info.height) {
+        (Some(block_hash), Some(height)) => format!(
+            "block {} at height {}, {} conf",
+            block_hash, height, info.confirmations
+        ),
+        _ => "pending in the mempool".to_string(),
+    };
+    Ok(Output {
+        text: format!(
+            "{} {} -> {} (fee {})\n{}",
+            tx.amount, tx.sender, tx.receiver, tx.fee, status
+        ),
+        json: json!(info),
+    })
+}
+
+/// Issues a token from one of the wallet's addresses, which becomes its
+/// issuer; the supply goes to `to`, or to the issuer itself.
+async fn issue_token(
+    config: &Config,
+    wallet: &mut Wallet,
+    op: TokenOp,
+    to: Option<String>,
+    fee: FeeChoice,
+    from: Option<String>,
+    account: u32,
+) -> Result<Output, Failure> {
+    let (account, index) = spending_address(wallet, from.as_deref(), account)?;
+    let issuer = wallet.accounts[account as usize].address(index);
+    let to = to.unwrap_or_else(|| issuer.clone());
+    let extras = Extras {
+        token: Some(op.clone()),
+        ..Extras::default()
+    };
+    let transaction =
+        sign_and_send(config, wallet, to, 0.0, fee, extras, Some(issuer), account).await?;
+    let TokenOp::Issue {
+        symbol,
+        decimals,
+        supply,
+        ..
+    } = op
+    else {
+        unreachable!("issue_token is only given issues");
+    };
+    let id = transaction.token_id();
+    Ok(Output {
+        text: format!(
+            "Issued {} {} to {} (fee {})\nToken id: {}",
+            token::format_units(supply as u128, decimals),
+            symbol,
+            transaction.receiver,
+            transaction.fee,
+            id
+        ),
+        json: json!({
+            "status": "issued",
+            "token": id,
+            "transaction": transaction,
+            "fee": transaction.fee,
+        }),
+    })
+}
+
+/// A token amount, still in whole tokens, and where it goes.
+struct TokenSend {
+    token: String,
+    to: Option<String>,
+    amount: String,
+}
+
+#[derive(Clone, Copy)]
+enum Move {
+    Transfer,
+    Mint,
+    Burn,
+}
+
+/// Transfers, mints or burns a token. Minting and burning can only come
+/// from the issuer's address, so the wallet has to hold it.
+#[allow(clippy::too_many_arguments)]
+async fn move_token(
+    config: &Config,
+    wallet: &mut Wallet,
+    send: TokenSend,
+    kind: Move,
+    fee: FeeChoice,
+    from: Option<String>,
+    account: u32,
+) -> Result<Output, Failure> {
+    let info = client(config)?.token(&send.token).await?;
+    let amount = token::parse_units(&send.amount, info.decimals).map_err(Failure::usage)?;
+    if amount == 0 {
+        return Err(Failure::usage("--amount must be positive"));
+    }
+    let from = match kind {
+        Move::Transfer => from,
+        Move::Mint | Move::Burn => {
+            if wallet.find_address(&info.issuer).is_none() {
+                return Err(Failure::usage(format!(
+                    "Only the issuer {} can mint or burn {}",
+                    info.issuer, info.symbol
+                )));
+            }
+            Some(info.issuer.clone())
+        }
+    };
+    let (account, index) = spending_address(wallet, from.as_deref(), account)?;
+    let sender = wallet.accounts[account as usize].address(index);
+    let to = match (kind, send.to) {
+        (Move::Burn, _) | (Move::Mint, None) => sender.clone(),
+        (_, Some(to)) => to,
+        (Move::Transfer, None) => unreachable!("sendtoken always names a receiver"),
+    };
+    let token = send.token;
+    let (op, verb) = match kind {
+        Move::Transfer => (TokenOp::Transfer { token, amount }, "Sent"),
+        Move::Mint => (TokenOp::Mint { token, amount }, "Minted"),
+        Move::Burn => (TokenOp::Burn { token, amount }, "Burned"),
+    };
+    let extras = Extras {
+        token: Some(op),
+        ..Extras::default()
+    };
+    let transaction =
+        sign_and_send(config, wallet, to, 0.0, fee, extras, Some(sender), account).await?;
+    let amount = token::format_units(amount as u128, info.decimals);
+    let text = match kind {
+        Move::Burn => format!(
+            "{} {} {} from {} (fee {})",
+            verb, amount, info.symbol, transaction.sender, transaction.fee
+        ),
+        _ => format!(
+            "{} {} {} from {} to {} (fee {})",
+            verb, amount, info.symbol, transaction.sender, transaction.receiver, transaction.fee
+        ),
+    };
+    Ok(Output {
+        text,
+        json: json!({
+            "status": "sent",
+            "token": info.id,
+            "amount": amount,
+            "transaction": transaction,
+            "fee": transaction.fee,
+        }),
+    })
+}
+
+/// Token holdings over all `addresses`, summed per token.
+async fn token_balance(client: &NodeClient, addresses: Vec<String>) -> Result<Output, Failure> {
+    let mut totals: Vec<TokenBalance> = Vec::new();
+    for address in &addresses {
+        for balance in client.token_balances(address).await?.balances {
+            match totals.iter_mut().find(|total| total.token == balance.token) {
+                Some(total) => {
+                    total.confirmed += balance.confirmed;
+                    total.unconfirmed += balance.unconfirmed;
+                }
+                None => totals.push(balance),
+            }
+        }
+    }
+    let text = totals
+        .iter()
+        .map(|balance| {
+            let mut line = format!(
+                "{} {} ({})",
+                token::format_units(balance.confirmed as u128, balance.decimals),
+                balance.symbol,
+                balance.token
+            );
+            if balance.unconfirmed != 0 {
+                line.push_str(&format!(
+                    ", {} unconfirmed",
+                    token::format_change(balance.unconfirmed, balance.decimals)
+                ));
+            }
+            line
+        })
+        .collect::<Vec<_>>();
+    Ok(Output {
+        text: if text.is_empty() {
+            "No tokens".to_string()
+        } else {
+            text.join("\n")
+        },
+        json: json!({ "addresses": addresses, "balances": totals }),
+    })
+}
+
+fn watching(address: String) -> Output {
+    Output {
+        text: format!("Watching {}", address),
+        json: json!({ "address": address, "watch_only": true }),
+    }
+}
+
+fn list_contacts(wallet: &Wallet) -> Result<Output, Failure> {
+    let book = wallet.address_book();
+    let text = book
+        .iter()
+        .map(|(label, address)| format!("{} {}", label, address))
+        .collect::<Vec<_>>()
+        .join("\n");
+    Ok(Output {
+        json: json!(book),
+        text,
+    })
+}
+
+fn export(wallet: &mut Wallet, mnemonic: bool) -> Result<Output, Failure> {
+    let accounts: Vec<_> = wallet
+        .accounts
+        .iter()
+        .map(|account| {
+            json!({
+                "index": account.index,
+                "xpub": account.xpub(),
+                "addresses": account.addresses(),
+            })
+        })
+        .collect();
+    let mut text: Vec<String> = wallet
+        .accounts
+        .iter()
+        .map(|account| format!("Account #{}: {}", account.index, account.xpub()))
+        .collect();
+
+    let mut json = json!({ "accounts": accounts, "birthday": wallet.birthday() });
+    if mnemonic {
+        unlock(wallet)?;
+        let phrase = wallet.phrase().map_err(Failure::wallet)?;
+        text.insert(0, format!("Mnemonic: {}", phrase));
+        json["mnemonic"] = json!(phrase);
+    }
+    Ok(Output {
+        json,
+        text: text.join("\n"),
+    })
+}
+
+/// Unlocks with the passphrase from the environment, or by asking on the
+/// terminal when there is one.
+fn unlock(wallet: &mut Wallet) -> Result<(), Failure> {
+    let passphrase = match std::env::var(PASSPHRASE_VAR) {
+        Ok(passphrase) => passphrase,
+        Err(_) if io::stdin().is_terminal() => crate::prompt_secret("Wallet passphrase: ")
+            .map_err(|e| Failure::wallet(e.to_string()))?,
+        Err(_) => {
+            return Err(Failure::wallet(format!(
+                "Wallet is locked; set {} to unlock it",
+                PASSPHRASE_VAR
+            )))
+        }
+    };
+    wallet.unlock(&passphrase).map_err(Failure::wallet)
+}
@@ -0,0 +1,761 @@
+use crate::chainparams::{self, Network};
+use crate::token::{self, MAX_DECIMALS};
+use crate::transaction::{Extras, MAX_DATA_SIZE};
+use serde::Deserialize;
+use std::fs;
+use std::path::PathBuf;
+
+/// Where a command reads a portable transaction from.
+#[derive(Debug)]
+pub enum TxSource {
+    File(PathBuf),
+    Blob(String),
+}
+
+pub const USAGE: &str =
+    "Usage: fluerion-wallet [--config <file>] [--network mainnet|testnet|regtest]
+                       [--node <addr>] [--wallet <path>] [--auto-lock <secs>] [--json]
+                       [<command> [options]]
+
+Commands:
+  balance        [--account <n>] [--address <addr>]
+  history        [--account <n>] [--address <addr>] [--offset <n>] [--limit <n>]
+  newaddress     [--account <n>]
+  listaddresses  [--account <n>]
+  send           --to <addr> --amount <x> [--fee <x> | --target <blocks>] [--from <addr>]
+                 [--account <n>] [--lock-time <n>] [--relative-lock <blocks>] [--data <hex>]
+  export         [--mnemonic]
+  createwatchonly --in <export.json>
+  createtx       --to <addr> --amount <x> [--fee <x> | --target <blocks>] [--from <addr>]
+                 [--account <n>] [--lock-time <n>] [--relative-lock <blocks>] [--data <hex>]
+                 [--out <file>]
+  signtx         --in <file> | --tx <base64> [--out <file>]
+  finalizetx     --in <file> | --tx <base64> [--out <file>]
+  broadcasttx    --in <file> | --tx <base64>
+  getpubkey      [--account <n>] [--address <addr>]
+  createmultisig --threshold <m> --pubkey <hex>...
+  importaddress  --address <addr> [--label <name>]
+  importpubkey   --pubkey <hex> [--label <name>]
+  addcontact     --label <name> --address <addr>
+  removecontact  --label <name>
+  listcontacts
+  rescan         [--start <height>]
+  initiateswap   --to <addr> --amount <x> --timeout <n> [--hash <hex>]
+                 [--fee <x> | --target <blocks>] [--from <addr>] [--account <n>]
+  redeemswap     --contract <hex> --secret <hex> [--fee <x> | --target <blocks>]
+  refundswap     --contract <hex> [--fee <x> | --target <blocks>]
+  extractsecret  --contract <hex>
+  finddata       --data <hex>
+  gettransaction --txid <hex>
+  issuetoken     --name <s> --symbol <s> [--decimals <n>] [--supply <x>] [--mintable]
+                 [--to <addr>] [--fee <x> | --target <blocks>] [--from <addr>] [--account <n>]
+  sendtoken      --token <id> --to <addr> --amount <x> [--fee <x> | --target <blocks>]
+                 [--from <addr>] [--account <n>]
+  minttoken      --token <id> --amount <x> [--to <addr>] [--fee <x> | --target <blocks>]
+  burntoken      --token <id> --amount <x> [--fee <x> | --target <blocks>]
+  tokenbalance   [--account <n>] [--address <addr>]
+
+Without a command the wallet runs its interactive menu. A wallet file belongs
+to the network it was made on, and the node must be on that network too; a
+--node without a port gets the network's default port. Commands that need
+the private keys read the wallet passphrase from FLUERION_WALLET_PASSPHRASE.
+Imported addresses count in balance and history but cannot be spent from.
+The --to of send and createtx also takes a contact label. rescan fetches only
+the block filters from --start (default: the wallet's birthday) and the blocks
+that match them.
+Without --fee, send and createtx ask the node for a fee rate that should get
+the transaction mined within --target blocks (default 6).
+--lock-time keeps a transaction out of blocks below that height or, from
+500000000 on, until the chain's median time passes that Unix time.
+--relative-lock keeps it out until the sender's last incoming funds are that
+many blocks deep.
+--data attaches up to 80 bytes, such as a document hash, to a transaction;
+finddata lists the transactions carrying exactly those bytes.
+gettransaction looks a transaction up by the id send prints. The node finds
+confirmed ones only if it runs with --txindex.
+initiateswap locks funds in a contract --to can claim with a secret, or the
+sender can take back once the chain reaches --timeout (a height or, from
+500000000 on, a Unix time). Without --hash it makes a new secret; the side
+that does must pick the longer timeout. The counterparty locks its side with
+--hash set to the same secret hash.
+issuetoken makes the sending address the token's issuer and credits the
+supply to --to (default: the issuer) and prints the token's id, the hash of
+the issuing transaction's signed bytes. Only the issuer can mint, if the token
+was issued --mintable, or burn from its own balance. Token amounts take the
+token's decimal places.";
+
+pub const DEFAULT_WALLET_PATH: &str = "fluerion-wallet.json";
+pub const DEFAULT_AUTO_LOCK: u64 = 300;
+pub const DEFAULT_HISTORY_LIMIT: usize = 20;
+pub const DEFAULT_FEE_TARGET: usize = 6;
+
+/// Wallet settings, read from an optional TOML file and then overridden by
+/// command-line flags. For example:
+///
+/// ```toml
+/// network = "testnet"
+/// node = "127.0.0.1:18080"
+/// wallet = "/home/alice/.fluerion/wallet.json"
+/// auto_lock = 120
+/// ```
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub network: Network,
+    /// Node to query and send transactions to.
+    pub node: Option<String>,
+    /// Encrypted keystore file.
+    pub wallet: PathBuf,
+    /// Seconds of inactivity after which an unlocked wallet locks again.
+    pub auto_lock: u64,
+    /// Print command results as JSON instead of text.
+    #[serde(skip)]
+    pub json: bool,
+    #[serde(skip)]
+    pub command: Option<Command>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            network: Network::Mainnet,
+            node: None,
+            wallet: PathBuf::from(DEFAULT_WALLET_PATH),
+            auto_lock: DEFAULT_AUTO_LOCK,
+            json: false,
+            command: None,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum Command {
+    Balance {
+        account: u32,
+        address: Option<String>,
+    },
+    History {
+        account: u32,
+        address: Option<String>,
+        offset: usize,
+        limit: usize,
+    },
+    NewAddress {
+        account: u32,
+    },
+    ListAddresses {
+        account: Option<u32>,
+    },
+    /// `fee` overrides the node's estimate for `target` blocks.
+    Send {
+        to: String,
+        amount: f64,
+        fee: Option<f64>,
+        target: usize,
+        from: Option<String>,
+        account: u32,
+        extras: Extras,
+    },
+    Export {
+        mnemonic: bool,
+    },
+    /// Builds a watch-only wallet from the output of `export --json`.
+    CreateWatchOnly {
+        export: PathBuf,
+    },
+    CreateTx {
+        to: String,
+        amount: f64,
+        fee: Option<f64>,
+        target: usize,
+        from: Option<String>,
+        account: u32,
+        extras: Extras,
+        out: Option<PathBuf>,
+    },
+    SignTx {
+        source: TxSource,
+        out: Option<PathBuf>,
+    },
+    BroadcastTx {
+        source: TxSource,
+    },
+    /// Combines the partial signatures of a multisig spend.
+    FinalizeTx {
+        source: TxSource,
+        out: Option<PathBuf>,
+    },
+    GetPubkey {
+        account: u32,
+        address: Option<String>,
+    },
+    CreateMultisig {
+        threshold: u8,
+        pubkeys: Vec<String>,
+    },
+    /// Watches an address without its key.
+    ImportAddress {
+        address: String,
+        label: Option<String>,
+    },
+    ImportPubkey {
+        pubkey: String,
+        label: Option<String>,
+    },
+    AddContact {
+        label: String,
+        address: String,
+    },
+    RemoveContact {
+        label: String,
+    },
+    ListContacts,
+    /// Finds the wallet's transactions through compact block filters.
+    Rescan {
+        start: Option<u64>,
+    },
+    /// Locks funds in a hash time-locked contract. `secret_hash` is given
+    /// when answering a swap someone else started.
+    InitiateSwap {
+        to: String,
+        amount: f64,
+        timeout: u64,
+        secret_hash: Option<String>,
+        fee: Option<f64>,
+        target: usize,
+        from: Option<String>,
+        account: u32,
+    },
+    RedeemSwap {
+        contract: String,
+        secret: String,
+        fee: Option<f64>,
+        target: usize,
+    },
+    RefundSwap {
+        contract: String,
+        fee: Option<f64>,
+        target: usize,
+    },
+    /// Finds the secret a redeemed contract revealed.
+    ExtractSecret {
+        contract: String,
+    },
+    FindData {
+        data: String,
+    },
+    GetTransaction {
+        txid: String,
+    },
+    /// Creates a token; `supply` is in base units.
+    IssueToken {
+        name: String,
+        symbol: String,
+        decimals: u8,
+        supply: u64,
+        mintable: bool,
+        to: Option<String>,
+        fee: Option<f64>,
+        target: usize,
+        from: Option<String>,
+        account: u32,
+    },
+    /// `amount` is in whole tokens, converted once the token's decimals are
+    /// known.
+    SendToken {
+        token: String,
+        to: String,
+        amount: String,
+        fee: Option<f64>,
+        target: usize,
+        from: Option<String>,
+        account: u32,
+    },
+    MintToken {
+        token: String,
+        to: Option<String>,
+        amount: String,
+        fee: Option<f64>,
+        target: usize,
+    },
+    BurnToken {
+        token: String,
+        amount: String,
+        fee: Option<f64>,
+        target: usize,
+    },
+    TokenBalance {
+        account: u32,
+        address: Option<String>,
+    },
+}
+
+/// Options given after a command name; which ones a command accepts is
+/// checked when the command is built.
+#[derive(Default)]
+struct Options {
+    given: Vec<&'static str>,
+    account: Option<u32>,
+    address: Option<String>,
+    label: Option<String>,
+    start: Option<u64>,
+    offset: Option<usize>,
+    limit: Option<usize>,
+    to: Option<String>,
+    amount: Option<String>,
+    fee: Option<f64>,
+    target: Option<usize>,
+    lock_time: Option<u64>,
+    relative_lock: Option<u64>,
+    timeout: Option<u64>,
+    hash: Option<String>,
+    contract: Option<String>,
+    secret: Option<String>,
+    data: Option<String>,
+    txid: Option<String>,
+    name: Option<String>,
+    symbol: Option<String>,
+    decimals: Option<u8>,
+    supply: Option<String>,
+    mintable: bool,
+    token: Option<String>,
+    from: Option<String>,
+    mnemonic: bool,
+    input: Option<PathBuf>,
+    out: Option<PathBuf>,
+    tx: Option<String>,
+    threshold: Option<u8>,
+    pubkeys: Vec<String>,
+}
+
+impl Config {
+    pub fn from_args(args: &[String]) -> Result<Self, String> {
+        let mut config = match args.iter().position(|arg| arg == "--config") {
+            Some(i) => Self::from_file(required("--config", args.get(i + 1))?)?,
+            None => Config::default(),
+        };
+
+        let mut command = None;
+        let mut options = Options::default();
+        let mut i = 1;
+        while i < args.len() {
+            let flag = args[i].as_str();
+            let value = args.get(i + 1);
+            let mut takes_value = true;
+            match flag {
+                "--config" => {}
+                "--network" => config.network = parse(flag, value)?,
+                "--node" => config.node = Some(required(flag, value)?.to_string()),
+                "--wallet" => config.wallet = PathBuf::from(required(flag, value)?),
+                "--auto-lock" => config.auto_lock = parse(flag, value)?,
+                "--json" => {
+                    config.json = true;
+                    takes_value = false;
+                }
+                "--account" => options.account = Some(options.set("--account", value)?),
+                "--address" => options.address = Some(options.set("--address", value)?),
+                "--start" => options.start = Some(options.set("--start", value)?),
+                "--label" => options.label = Some(options.set("--label", value)?),
+                "--offset" => options.offset = Some(options.set("--offset", value)?),
+                "--limit" => options.limit = Some(options.set("--limit", value)?),
+                "--to" => options.to = Some(options.set("--to", value)?),
+                "--amount" => options.amount = Some(options.set("--amount", value)?),
+                "--fee" => options.fee = Some(options.set("--fee", value)?),
+                "--target" => options.target = Some(options.set("--target", value)?),
+                "--lock-time" => options.lock_time = Some(options.set("--lock-time", value)?),
+                "--relative-lock" => {
+                    options.relative_lock = Some(options.set("--relative-lock", value)?)
+                }
+                "--timeout" => options.timeout = Some(options.set("--timeout", value)?),
+                "--hash" => options.hash = Some(options.set("--hash", value)?),
+                "--contract" => options.contract = Some(options.set("--contract", value)?),
+                "--secret" => options.secret = Some(options.set("--secret", value)?),
+                "--data" => options.data = Some(options.set("--data", value)?),
+                "--txid" => options.txid = Some(options.set("--txid", value)?),
+                "--name" => options.name = Some(options.set("--name", value)?),
+                "--symbol" => options.symbol = Some(options.set("--symbol", value)?),
+                "--decimals" => options.decimals = Some(options.set("--decimals", value)?),
+                "--supply" => options.supply = Some(options.set("--supply", value)?),
+                "--token" => options.token = Some(options.set("--token", value)?),
+                "--mintable" => {
+                    options.given.push("--mintable");
+                    options.mintable = true;
+                    takes_value = false;
+                }
+                "--from" => options.from = Some(options.set("--from", value)?),
+                "--in" => options.input = Some(options.set("--in", value)?),
+                "--out" => options.out = Some(options.set("--out", value)?),
+                "--tx" => options.tx = Some(options.set("--tx", value)?),
+                "--threshold" => options.threshold = Some(options.set("--threshold", value)?),
+                "--pubkey" => {
+                    let pubkey = options.set("--pubkey", value)?;
+                    options.pubkeys.push(pubkey);
+                }
+                "--mnemonic" => {
+                    options.given.push("--mnemonic");
+                    options.mnemonic = true;
+                    takes_value = false;
+                }
+                _ if flag.starts_with("--") => return Err(format!("Unknown argument: {}", flag)),
+                _ if command.is_none() => {
+                    command = Some(flag.to_string());
+                    takes_value = false;
+                }
+                _ => return Err(format!("Unexpected argument: {}", flag)),
+            }
+            i += if takes_value { 2 } else { 1 };
+        }
+
+        // Addresses in the command are read with the network's prefix.
+        chainparams::select(config.network);
+        let params = chainparams::params();
+        config.node = config.node.map(|node| params.with_default_port(&node));
+        config.command = match command {
+            Some(name) => Some(options.into_command(&name)?),
+            None if !options.given.is_empty() => {
+                return Err(format!("{} needs a command", options.given[0]))
+            }
+            None => None,
+        };
+        Ok(config)
+    }
+
+    fn from_file(path: &str) -> Result<Self, String> {
+        let contents =
+            fs::read_to_string(path).map_err(|e| format!("Cannot read {}: {}", path, e))?;
+        toml::from_str(&contents).map_err(|e| format!("Invalid config {}: {}", path, e))
+    }
+}
+
+impl Options {
+    fn set<T: std::str::FromStr>(
+        &mut self,
+        flag: &'static str,
+        value: Option<&String>,
+    ) -> Result<T, String> {
+        self.given.push(flag);
+        parse(flag, value)
+    }
+
+    fn into_command(self, name: &str) -> Result<Command, String> {
+        let allowed: &[&str] = match name {
+            "balance" => &["--account", "--address"],
+            "history" => &["--account", "--address", "--offset", "--limit"],
+            "newaddress" => &["--account"],
+            "listaddresses" => &["--account"],
+            "send" => &[
+                "--to",
+                "--amount",
+                "--fee",
+                "--target",
+                "--from",
+                "--account",
+                "--lock-time",
+                "--relative-lock",
+                "--data",
+            ],
+            "export" => &["--mnemonic"],
+            "createwatchonly" => &["--in"],
+            "createtx" => &[
+                "--to",
+                "--amount",
+                "--fee",
+                "--target",
+                "--from",
+                "--account",
+                "--lock-time",
+                "--relative-lock",
+                "--data",
+                "--out",
+            ],
+            "signtx" => &["--in", "--tx", "--out"],
+            "broadcasttx" => &["--in", "--tx"],
+            "finalizetx" => &["--in", "--tx", "--out"],
+            "getpubkey" => &["--account", "--address"],
+            "createmultisig" => &["--threshold", "--pubkey"],
+            "importaddress" => &["--address", "--label"],
+            "importpubkey" => &["--pubkey", "--label"],
+            "addcontact" => &["--label", "--address"],
+            "removecontact" => &["--label"],
+            "listcontacts" => &[],
+            "rescan" => &["--start"],
+            "initiateswap" => &[
+                "--to",
+                "--amount",
+                "--timeout",
+                "--hash",
+                "--fee",
+                "--target",
+                "--from",
+                "--account",
+            ],
+            "redeemswap" => &["--contract", "--secret", "--fee", "--target"],
+            "refundswap" => &["--contract", "--fee", "--target"],
+            "extractsecret" => &["--contract"],
+            "finddata" => &["--data"],
+            "gettransaction" => &["--txid"],
+            "issuetoken" => &[
+                "--name",
+                "--symbol",
+                "--decimals",
+                "--supply",
+                "--mintable",
+                "--to",
+                "--fee",
+                "--target",
+                "--from",
+                "--account",
+            ],
+            "sendtoken" => &[
+                "--token",
+                "--to",
+                "--amount",
+                "--fee",
+                "--target",
+                "--from",
+                "--account",
+            ],
+            "minttoken" => &["--token", "--amount", "--to", "--fee", "--target"],
+            "burntoken" => &["--token", "--amount", "--fee", "--target"],
+            "tokenbalance" => &["--account", "--address"],
+            _ => return Err(format!("Unknown command: {}", name)),
+        };
+        if let Some(flag) = self.given.iter().find(|flag| !allowed.contains(flag)) {
+            return Err(format!("{} does not take {}", name, flag));
+        }
+
+        let account = self.account.unwrap_or(0);
+        let extras = Extras {
+            lock_time: self.lock_time.unwrap_or(0),
+            relative_lock: self.relative_lock.unwrap_or(0),
+            data: self.data.as_deref().map(data_payload).transpose()?,
+            token: None,
+        };
+        Ok(match name {
+            "balance" => Command::Balance {
+                account,
+                address: self.address,
+            },
+            "history" => Command::History {
+                account,
+                address: self.address,
+                offset: self.offset.unwrap_or(0),
+                limit: self.limit.unwrap_or(DEFAULT_HISTORY_LIMIT),
+            },
+            "newaddress" => Command::NewAddress { account },
+            "listaddresses" => Command::ListAddresses {
+                account: self.account,
+            },
+            "send" => Command::Send {
+                amount: positive_amount(name, self.amount)?,
+                fee: fee(self.fee, self.target)?,
+                target: self.target.unwrap_or(DEFAULT_FEE_TARGET),
+                to: self.to.ok_or("send needs --to")?,
+                from: self.from,
+                account,
+                extras,
+            },
+            "export" => Command::Export {
+                mnemonic: self.mnemonic,
+            },
+            "createwatchonly" => Command::CreateWatchOnly {
+                export: self.input.ok_or("createwatchonly needs --in")?,
+            },
+            "createtx" => Command::CreateTx {
+                amount: positive_amount(name, self.amount)?,
+                fee: fee(self.fee, self.target)?,
+                target: self.target.unwrap_or(DEFAULT_FEE_TARGET),
+                to: self.to.ok_or("createtx needs --to")?,
+                from: self.from,
+                account,
+                extras,
+                out: self.out,
+            },
+            "signtx" => Command::SignTx {
+                source: tx_source(name, self.input, self.tx)?,
+                out: self.out,
+            },
+            "broadcasttx" => Command::BroadcastTx {
+                source: tx_source(name, self.input, self.tx)?,
+            },
+            "finalizetx" => Command::FinalizeTx {
+                source: tx_source(name, self.input, self.tx)?,
+                out: self.out,
+            },
+            "getpubkey" => Command::GetPubkey {
+                account,
+                address: self.address,
+            },
+            "createmultisig" => Command::CreateMultisig {
+                threshold: self.threshold.ok_or("createmultisig needs --threshold")?,
+                pubkeys: self.pubkeys,
+            },
+            "importaddress" => Command::ImportAddress {
+                address: self.address.ok_or("importaddress needs --address")?,
+                label: self.label,
+            },
+            "importpubkey" => match <[String; 1]>::try_from(self.pubkeys) {
+                Ok([pubkey]) => Command::ImportPubkey {
+                    pubkey,
+                    label: self.label,
+                },
+                Err(_) => return Err("importpubkey needs one --pubkey".to_string()),
+            },
+            "addcontact" => Command::AddContact {
+                label: self.label.ok_or("addcontact needs --label")?,
+                address: self.address.ok_or("addcontact needs --address")?,
+            },
+            "removecontact" => Command::RemoveContact {
+                label: self.label.ok_or("removecontact needs --label")?,
+            },
+            "listcontacts" => Command::ListContacts,
+            "rescan" => Command::Rescan { start: self.start },
+            "initiateswap" => Command::InitiateSwap {
+                amount: positive_amount(name, self.amount)?,
+                fee: fee(self.fee, self.target)?,
+                target: self.target.unwrap_or(DEFAULT_FEE_TARGET),
+                to: self.to.ok_or("initiateswap needs --to")?,
+                timeout: match self.timeout {
+                    Some(0) => return Err("--timeout must be positive".to_string()),
+                    Some(timeout) => timeout,
+                    None => return Err("initiateswap needs --timeout".to_string()),
+                },
+                secret_hash: self.hash,
+                from: self.from,
+                account,
+            },
+            "redeemswap" => Command::RedeemSwap {
+                fee: fee(self.fee, self.target)?,
+                target: self.target.unwrap_or(DEFAULT_FEE_TARGET),
+                contract: self.contract.ok_or("redeemswap needs --contract")?,
+                secret: self.secret.ok_or("redeemswap needs --secret")?,
+            },
+            "refundswap" => Command::RefundSwap {
+                fee: fee(self.fee, self.target)?,
+                target: self.target.unwrap_or(DEFAULT_FEE_TARGET),
+                contract: self.contract.ok_or("refundswap needs --contract")?,
+            },
+            "extractsecret" => Command::ExtractSecret {
+                contract: self.contract.ok_or("extractsecret needs --contract")?,
+            },
+            "finddata" => Command::FindData {
+                data: data_payload(self.data.as_deref().ok_or("finddata needs --data")?)?,
+            },
+            "gettransaction" => Command::GetTransaction {
+                txid: txid(self.txid.as_deref().ok_or("gettransaction needs --txid")?)?,
+            },
+            "issuetoken" => {
+                let decimals = self.decimals.unwrap_or(0);
+                if decimals > MAX_DECIMALS {
+                    return Err(format!("--decimals cannot exceed {}", MAX_DECIMALS));
+                }
+                let supply = match self.supply.as_deref() {
+                    Some(supply) => token::parse_units(supply, decimals)?,
+                    None if self.mintable => 0,
+                    None => return Err("issuetoken needs --supply unless --mintable".to_string()),
+                };
+                Command::IssueToken {
+                    name: self.name.ok_or("issuetoken needs --name")?,
+                    symbol: self.symbol.ok_or("issuetoken needs --symbol")?,
+                    decimals,
+                    supply,
+                    mintable: self.mintable,
+                    to: self.to,
+                    fee: fee(self.fee, self.target)?,
+                    target: self.target.unwrap_or(DEFAULT_FEE_TARGET),
+                    from: self.from,
+                    account,
+                }
+            }
+            "sendtoken" => Command::SendToken {
+                token: self.token.ok_or("sendtoken needs --token")?,
+                to: self.to.ok_or("sendtoken needs --to")?,
+                amount: self.amount.ok_or("sendtoken needs --amount")?,
+                fee: fee(self.fee, self.target)?,
+                target: self.target.unwrap_or(DEFAULT_FEE_TARGET),
+                from: self.from,
+                account,
+            },
+            "minttoken" => Command::MintToken {
+                token: self.token.ok_or("minttoken needs --token")?,
+                to: self.to,
+                amount: self.amount.ok_or("minttoken needs --amount")?,
+                fee: fee(self.fee, self.target)?,
+                target: self.target.unwrap_or(DEFAULT_FEE_TARGET),
+            },
+            "burntoken" => Command::BurnToken {
+                token: self.token.ok_or("burntoken needs --token")?,
+                amount: self.amount.ok_or("burntoken needs --amount")?,
+                fee: fee(self.fee, self.target)?,
+                target: self.target.unwrap_or(DEFAULT_FEE_TARGET),
+            },
+            _ => Command::TokenBalance {
+                account,
+                address: self.address,
+            },
+        })
+    }
+}
+
+fn positive_amount(command: &str, amount: Option<String>) -> Result<f64, String> {
+    let amount = amount.ok_or_else(|| format!("{} needs --amount", command))?;
+    match amount.parse::<f64>() {
+        Ok(amount) if amount > 0.0 && amount.is_finite() => Ok(amount),
+        Ok(_) => Err("--amount must be positive".to_string()),
+        Err(_) => Err(format!("Invalid value for --amount: {}", amount)),
+    }
+}
+
+fn fee(fee: Option<f64>, target: Option<usize>) -> Result<Option<f64>, String> {
+    match (fee, target) {
+        (Some(_), Some(_)) => Err("Give either --fee or --target, not both".to_string()),
+        (Some(fee), None) if !(fee >= 0.0 && fee.is_finite()) => {
+            Err("--fee cannot be negative".to_string())
+        }
+        (_, Some(0)) => Err("--target must be at least 1".to_string()),
+        (fee, _) => Ok(fee),
+    }
+}
+
+/// Checks a `--data` payload and returns it as lowercase hex.
+fn data_payload(data: &str) -> Result<String, String> {
+    match hex::decode(data) {
+        Ok(bytes) if !bytes.is_empty() && bytes.len() <= MAX_DATA_SIZE => Ok(hex::encode(bytes)),
+        _ => Err(format!(
+            "--data must be 1 to {} bytes of hex",
+            MAX_DATA_SIZE
+        )),
+    }
+}
+
+fn txid(txid: &str) -> Result<String, String> {
+    match hex::decode(txid) {
+        Ok(bytes) if bytes.len() == 32 => Ok(hex::encode(bytes)),
+        _ => Err("--txid must be 32 bytes of hex".to_string()),
+    }
+}
+
+fn tx_source(
+    command: &str,
+    input: Option<PathBuf>,
+    blob: Option<String>,
+) -> Result<TxSource, String> {
+    match (input, blob) {
+        (Some(path), None) => Ok(TxSource::File(path)),
+        (None, Some(blob)) => Ok(TxSource::Blob(blob)),
+        _ => Err(format!("{} needs exactly one of --in and --tx", command)),
+    }
+}
+
+fn required<'a>(flag: &str, value: Option<&'a String>) -> Result<&'a str, String> {
+    value
+        .map(String::as_str)
+        .ok_or_else(|| format!("{} needs a value", flag))
+}
+
+fn parse<T: std::str::FromStr>(flag: &str, value: Option<&String>) -> Result<T, String> {
+    let value = required(flag, value)?;
+    value
+        .parse()
+        .map_err(|_| format!("Invalid value for {}: {}", flag, value))
+}
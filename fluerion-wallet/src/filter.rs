@@ -0,0 +1,177 @@
+use sha2::{Digest, Sha256};
+use siphasher::sip::SipHasher24;
+
+/// Golomb-Rice parameter and false positive rate; must match the node.
+const FILTER_P: u8 = 19;
+const FILTER_M: u64 = 784_931;
+
+/// Whether a block filter from the node may contain any of `items`. A false
+/// positive costs one block download; a false negative cannot happen. A
+/// filter that does not decode matches everything, so a broken node can
+/// waste downloads but never hide a transaction.
+pub fn matches_any(filter: &[u8], block_hash: &[u8; 32], items: &[&str]) -> bool {
+    decode_and_match(filter, block_hash, items).unwrap_or(true)
+}
+
+fn decode_and_match(filter: &[u8], block_hash: &[u8; 32], items: &[&str]) -> Option<bool> {
+    let (n, used) = compact_size(filter)?;
+    if n == 0 || items.is_empty() {
+        return Some(false);
+    }
+
+    let range = n.checked_mul(FILTER_M)?;
+    let k0 = u64::from_le_bytes(block_hash[0..8].try_into().unwrap());
+    let k1 = u64::from_le_bytes(block_hash[8..16].try_into().unwrap());
+    let key = SipHasher24::new_with_keys(k0, k1);
+    let mut wanted: Vec<u64> = items
+        .iter()
+        .map(|item| ((key.hash(item.as_bytes()) as u128 * range as u128) >> 64) as u64)
+        .collect();
+    wanted.sort();
+
+    // Both lists are sorted, so walk them together.
+    let mut reader = BitReader::new(&filter[used..]);
+    let mut wanted = wanted.into_iter().peekable();
+    let mut value = 0u64;
+    for _ in 0..n {
+        let mut quotient = 0u64;
+        while reader.read(1)? == 1 {
+            quotient += 1;
+        }
+        let remainder = reader.read(FILTER_P)?;
+        value = value.checked_add((quotient << FILTER_P) + remainder)?;
+
+        while let Some(&next) = wanted.peek() {
+            if next == value {
+                return Some(true);
+            }
+            if next > value {
+                break;
+            }
+            wanted.next();
+        }
+        if wanted.peek().is_none() {
+            return Some(false);
+        }
+    }
+    Some(false)
+}
+
+/// The header the node commits to for `filter`, chained onto the previous
+/// one.
+pub fn header(filter: &[u8], prev_header: &[u8; 32]) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    hasher.update(Sha256::digest(filter));
+    hasher.update(prev_header);
+    hasher.finalize().into()
+}
+
+/// Reads a Bitcoin CompactSize integer, returning it and its length.
+fn compact_size(bytes: &[u8]) -> Option<(u64, usize)> {
+    let width = match *bytes.first()? {
+        0xfd => 2,
+        0xfe => 4,
+        0xff => 8,
+        n => return Some((n as u64, 1)),
+    };
+    let mut value = [0u8; 8];
+    value[..width].copy_from_slice(bytes.get(1..1 + width)?);
+    Some((u64::from_le_bytes(value), 1 + width))
+}
+
+/// Reads bits most significant first.
+struct BitReader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl<'a> BitReader<'a> {
+    fn new(bytes: &'a [u8]) -> Self {
+        BitReader { bytes, position: 0 }
+    }
+
+    fn read(&mut self, count: u8) -> Option<u64> {
+        let mut value = 0;
+        for _ in 0..count {
+            let byte = self.bytes.get(self.position / 8)?;
+            let bit = (byte >> (7 - self.position % 8)) & 1;
+            value = (value << 1) | bit as u64;
+            self.position += 1;
+        }
+        Some(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use fluerion::block::Block;
+    use fluerion::transaction::Transaction;
+
+    fn address(i: usize) -> String {
+        format!("flrt1address{:04}", i)
+    }
+
+    /// A node block paying between the first `count` addresses, and its
+    /// filter as the node builds it.
+    fn block(count: usize) -> (Block, Vec<u8>) {
+        let transactions = (0..count)
+            .map(|i| Transaction::new(address(i), address((i + 1) % count), 1.0))
+            .collect();
+        let mut block = Block::new(transactions, [3; 32]);
+        block.set_hash(block.calculate_hash());
+        let filter = fluerion::filter::build(&block);
+        (block, filter)
+    }
+
+    #[test]
+    fn parameters_match_the_node() {
+        assert_eq!(FILTER_P, fluerion::filter::FILTER_P);
+        assert_eq!(FILTER_M, fluerion::filter::FILTER_M);
+    }
+
+    #[test]
+    fn matches_every_address_in_a_node_filter() {
+        let (block, filter) = block(50);
+        for i in 0..50 {
+            let item = address(i);
+            assert!(
+                matches_any(&filter, &block.get_hash(), &[&item]),
+                "{}",
+                item
+            );
+        }
+        let items: Vec<String> = (0..50).map(address).collect();
+        let items: Vec<&str> = items.iter().map(String::as_str).collect();
+        assert!(matches_any(&filter, &block.get_hash(), &items));
+    }
+
+    #[test]
+    fn rarely_matches_other_addresses() {
+        let (block, filter) = block(50);
+        let false_positives = (50..5050)
+            .filter(|&i| matches_any(&filter, &block.get_hash(), &[&address(i)]))
+            .count();
+        assert!(false_positives <= 3, "{} false positives", false_positives);
+        assert!(!matches_any(&filter, &block.get_hash(), &[]));
+    }
+
+    #[test]
+    fn matches_everything_when_the_filter_does_not_decode() {
+        let (block, filter) = block(50);
+        let item = address(5000);
+        assert!(matches_any(&filter[..3], &block.get_hash(), &[&item]));
+        assert!(matches_any(&[0xfd], &block.get_hash(), &[&item]));
+        assert!(!matches_any(&[0], &block.get_hash(), &[&item]));
+    }
+
+    #[test]
+    fn chains_headers_like_the_node() {
+        let (_, filter) = block(50);
+        let prev = [9; 32];
+        assert_eq!(
+            header(&filter, &prev),
+            fluerion::filter::header(&filter, &prev)
+        );
+    }
+}
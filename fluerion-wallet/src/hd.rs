@@ -0,0 +1,226 @@
+use crate::address;
+use crate::keys::KeyPair;
+use bip32::{ChildNumber, DerivationPath, Language, Mnemonic, Prefix, XPrv, XPub};
+use rand_core::OsRng;
+
+/// Fluerion has no registered SLIP-44 coin type, so it uses one from the
+/// unassigned range.
+pub const COIN_TYPE: u32 = 7_337;
+
+/// How many unused addresses in a row end the search during recovery.
+pub const GAP_LIMIT: u32 = 20;
+
+/// Keys derived BIP32-style from a BIP39 mnemonic along
+/// `m/44'/COIN_TYPE'/account'/0/index`, so the phrase alone brings back
+/// every account and address. Only needed while the wallet is unlocked;
+/// accounts keep the public half to hand out addresses.
+pub struct HdWallet {
+    mnemonic: Mnemonic,
+    root: XPrv,
+}
+
+/// The receiving branch of one account, as an extended public key.
+pub struct Account {
+    pub index: u32,
+    xpub: XPub,
+    /// Number of receiving addresses handed out so far.
+    pub address_count: u32,
+}
+
+impl HdWallet {
+    pub fn generate(passphrase: &str) -> Self {
+        Self::from_mnemonic(Mnemonic::random(OsRng, Language::English), passphrase)
+    }
+
+    pub fn from_phrase(phrase: &str, passphrase: &str) -> Result<Self, String> {
+        let mnemonic = Mnemonic::new(phrase.trim(), Language::English)
+            .map_err(|_| "Invalid mnemonic phrase".to_string())?;
+        Ok(Self::from_mnemonic(mnemonic, passphrase))
+    }
+
+    fn from_mnemonic(mnemonic: Mnemonic, passphrase: &str) -> Self {
+        let seed = mnemonic.to_seed(passphrase);
+        let root = XPrv::new(seed.as_bytes()).expect("BIP39 seed derives a master key");
+        HdWallet { mnemonic, root }
+    }
+
+    pub fn phrase(&self) -> &str {
+        self.mnemonic.phrase()
+    }
+
+    fn branch_key(&self, account: u32) -> XPrv {
+        self.derive(&format!("m/44'/{}'/{}'/0", COIN_TYPE, account))
+    }
+
+    fn derive(&self, path: &str) -> XPrv {
+        let path: DerivationPath = path.parse().expect("valid derivation path");
+        let key = path
+            .iter()
+            .try_fold(self.root.clone(), |key, child| key.derive_child(child))
+            .expect("derivation from a valid key");
+        key
+    }
+
+    pub fn account(&self, index: u32) -> Account {
+        Account {
+            index,
+            xpub: self.branch_key(index).public_key(),
+            address_count: 0,
+        }
+    }
+
+    pub fn key_pair(&self, account: u32, index: u32) -> KeyPair {
+        let child = ChildNumber::new(index, false).expect("non-hardened index");
+        let key = self
+            .branch_key(account)
+            .derive_child(child)
+            .expect("child key derivation");
+        KeyPair::new(key.private_key().clone())
+    }
+
+    /// Rebuilds accounts and addresses after a restore. Within an account,
+    /// addresses are scanned until `GAP_LIMIT` unused ones in a row; accounts
+    /// are scanned until one has never been used at all.
+    pub fn discover(&self, is_used: impl Fn(&str) -> bool) -> Vec<Account> {
+        let mut accounts = Vec::new();
+        loop {
+            let mut account = self.account(accounts.len() as u32);
+            let mut gap = 0;
+            let mut index = 0;
+            while gap < GAP_LIMIT {
+                if is_used(&account.address(index)) {
+                    account.address_count = index + 1;
+                    gap = 0;
+                } else {
+                    gap += 1;
+                }
+                index += 1;
+            }
+
+            if account.address_count == 0 && !accounts.is_empty() {
+                break;
+            }
+            let done = account.address_count == 0;
+            accounts.push(account);
+            if done {
+                break;
+            }
+        }
+        accounts
+    }
+}
+
+impl Account {
+    pub fn from_xpub(index: u32, xpub: &str, address_count: u32) -> Result<Self, String> {
+        let xpub = xpub
+            .parse()
+            .map_err(|_| format!("Invalid extended public key for account #{}", index))?;
+        Ok(Account {
+            index,
+            xpub,
+            address_count,
+        })
+    }
+
+    pub fn xpub(&self) -> String {
+        self.xpub.to_string(Prefix::XPUB)
+    }
+
+    /// The compressed public key of the address at `index`.
+    pub fn public_key(&self, index: u32) -> Vec<u8> {
+        let child = ChildNumber::new(index, false).expect("non-hardened index");
+        let key = self.xpub.derive_child(child).expect("child key derivation");
+        key.to_bytes().to_vec()
+    }
+
+    pub fn address(&self, index: u32) -> String {
+        address::encode(&address::pubkey_hash(&self.public_key(index)))
+    }
+
+    pub fn new_address(&mut self) -> String {
+        self.address_count += 1;
+        self.address(self.address_count - 1)
+    }
+
+    pub fn addresses(&self) -> Vec<String> {
+        (0..self.address_count)
+            .map(|index| self.address(index))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    /// The all-zero 256-bit entropy BIP39 test vector.
+    const PHRASE: &str = "abandon abandon abandon abandon abandon abandon abandon abandon \
+                          abandon abandon abandon abandon abandon abandon abandon abandon \
+                          abandon abandon abandon abandon abandon abandon abandon art";
+
+    #[test]
+    fn derives_the_bip39_master_key() {
+        let wallet = HdWallet::from_phrase(PHRASE, "TREZOR").unwrap();
+        assert_eq!(
+            wallet.root.to_string(Prefix::XPRV).as_str(),
+            "xprv9s21ZrQH143K32qBagUJAMU2LsHg3ka7jqMcV98Y7gVeVyNStwYS3U7yVVoDZ4btbRNf4h6ibWpY22iRmXq35qgLs79f312g2kj5539ebPM"
+        );
+        assert!(HdWallet::from_phrase("abandon abandon abandon", "").is_err());
+        assert!(HdWallet::from_phrase(&PHRASE.replace("art", "abandon"), "").is_err());
+    }
+
+    #[test]
+    fn follows_bip32_paths() {
+        let wallet = HdWallet {
+            root: XPrv::new(hex::decode("000102030405060708090a0b0c0d0e0f").unwrap()).unwrap(),
+            ..HdWallet::from_phrase(PHRASE, "").unwrap()
+        };
+        let key = wallet.derive("m/0'/1/2'/2/1000000000");
+        assert_eq!(
+            key.to_string(Prefix::XPRV).as_str(),
+            "xprvA41z7zogVVwxVSgdKUHDy1SKmdb533PjDz7J6N6mV6uS3ze1ai8FHa8kmHScGpWmj4WggLyQjgPie1rFSruoUihUZREPSL39UNdE3BBDu76"
+        );
+        assert_eq!(
+            key.public_key().to_string(Prefix::XPUB),
+            "xpub6H1LXWLaKsWFhvm6RVpEL9P4KfRZSW7abD2ttkWP3SSQvnyA8FSVqNTEcYFgJS2UaFcxupHiYkro49S8yGasTvXEYBVPamhGW6cFJodrTHy"
+        );
+    }
+
+    #[test]
+    fn accounts_hand_out_the_signing_keys_addresses() {
+        let wallet = HdWallet::from_phrase(PHRASE, "").unwrap();
+        let mut account = wallet.account(1);
+        assert_eq!(account.new_address(), wallet.key_pair(1, 0).address());
+        assert_eq!(account.new_address(), wallet.key_pair(1, 1).address());
+        assert_eq!(account.public_key(1), wallet.key_pair(1, 1).public_key());
+        assert_ne!(account.address(0), wallet.account(0).address(0));
+
+        let watching = Account::from_xpub(1, &account.xpub(), 2).unwrap();
+        assert_eq!(watching.addresses(), account.addresses());
+        assert!(Account::from_xpub(1, "xpub", 0).is_err());
+    }
+
+    #[test]
+    fn discovers_up_to_the_gap_limit() {
+        let wallet = HdWallet::from_phrase(PHRASE, "").unwrap();
+        let first = wallet.account(0);
+        let used: HashSet<String> = [
+            first.address(0),
+            first.address(GAP_LIMIT),
+            // GAP_LIMIT unused addresses in a row come before it.
+            first.address(2 * GAP_LIMIT + 1),
+            wallet.account(1).address(3),
+        ]
+        .into();
+
+        let accounts = wallet.discover(|address| used.contains(address));
+        assert_eq!(accounts.len(), 2);
+        assert_eq!(accounts[0].address_count, GAP_LIMIT + 1);
+        assert_eq!(accounts[1].address_count, 4);
+
+        let accounts = wallet.discover(|_| false);
+        assert_eq!(accounts.len(), 1);
+        assert_eq!(accounts[0].address_count, 0);
+    }
+}
@@ -0,0 +1,294 @@
+use crate::address::{self, PUBKEY_HASH_SIZE};
+use crate::keys::PUBKEY_SIZE;
+use rand_core::{OsRng, RngCore};
+use sha2::{Digest, Sha256};
+
+/// Size of the swap secret; the node only relays contracts that pin it.
+pub const SECRET_SIZE: usize = 32;
+
+/// Lock times below this are block heights, from it on Unix times.
+pub const LOCKTIME_THRESHOLD: u64 = 500_000_000;
+
+const OP_0: u8 = 0x00;
+const OP_1: u8 = 0x51;
+const OP_IF: u8 = 0x63;
+const OP_ELSE: u8 = 0x67;
+const OP_ENDIF: u8 = 0x68;
+const OP_DROP: u8 = 0x75;
+const OP_DUP: u8 = 0x76;
+const OP_SIZE: u8 = 0x82;
+const OP_EQUALVERIFY: u8 = 0x88;
+const OP_SHA256: u8 = 0xa8;
+const OP_PUBKEYHASH: u8 = 0xa9;
+const OP_CHECKSIG: u8 = 0xac;
+const OP_CHECKLOCKTIMEVERIFY: u8 = 0xb1;
+
+const ECDSA_SIZE: usize = 64;
+
+/// A hash time-locked contract, the node's standard HTLC script:
+///
+/// ```text
+/// OP_IF
+///     OP_SIZE 32 OP_EQUALVERIFY OP_SHA256 <secret_hash> OP_EQUALVERIFY
+///     OP_DUP OP_PUBKEYHASH <recipient>
+/// OP_ELSE
+///     <lock_time> OP_CHECKLOCKTIMEVERIFY OP_DROP
+///     OP_DUP OP_PUBKEYHASH <refund>
+/// OP_ENDIF
+/// OP_EQUALVERIFY OP_CHECKSIG
+/// ```
+///
+/// Funds sent to its address go to `recipient` with the secret, or back to
+/// `refund` once the chain reaches `lock_time`.
+#[derive(Debug, PartialEq)]
+pub struct Contract {
+    pub secret_hash: [u8; 32],
+    pub recipient: [u8; PUBKEY_HASH_SIZE],
+    pub refund: [u8; PUBKEY_HASH_SIZE],
+    pub lock_time: u64,
+}
+
+impl Contract {
+    pub fn script(&self) -> Vec<u8> {
+        let mut script = vec![OP_IF, OP_SIZE];
+        push(&mut script, &encode_num(SECRET_SIZE as u64));
+        script.extend_from_slice(&[OP_EQUALVERIFY, OP_SHA256]);
+        push(&mut script, &self.secret_hash);
+        script.extend_from_slice(&[OP_EQUALVERIFY, OP_DUP, OP_PUBKEYHASH]);
+        push(&mut script, &self.recipient);
+        script.push(OP_ELSE);
+        push(&mut script, &encode_num(self.lock_time));
+        script.extend_from_slice(&[OP_CHECKLOCKTIMEVERIFY, OP_DROP, OP_DUP, OP_PUBKEYHASH]);
+        push(&mut script, &self.refund);
+        script.extend_from_slice(&[OP_ENDIF, OP_EQUALVERIFY, OP_CHECKSIG]);
+        script
+    }
+
+    /// Reads a contract back from its script, accepting nothing but the
+    /// exact template.
+    pub fn parse(script: &[u8]) -> Result<Self, String> {
+        let invalid = || "Not a swap contract".to_string();
+        let mut reader = Reader {
+            script,
+            position: 0,
+        };
+        reader.expect(&[OP_IF, OP_SIZE])?;
+        reader.push()?;
+        reader.expect(&[OP_EQUALVERIFY, OP_SHA256])?;
+        let secret_hash = reader.push()?.try_into().map_err(|_| invalid())?;
+        reader.expect(&[OP_EQUALVERIFY, OP_DUP, OP_PUBKEYHASH])?;
+        let recipient = reader.push()?.try_into().map_err(|_| invalid())?;
+        reader.expect(&[OP_ELSE])?;
+        let lock_time = decode_num(reader.push()?).ok_or_else(invalid)?;
+        reader.expect(&[OP_CHECKLOCKTIMEVERIFY, OP_DROP, OP_DUP, OP_PUBKEYHASH])?;
+        let refund = reader.push()?.try_into().map_err(|_| invalid())?;
+        reader.expect(&[OP_ENDIF, OP_EQUALVERIFY, OP_CHECKSIG])?;
+
+        let contract = Contract {
+            secret_hash,
+            recipient,
+            refund,
+            lock_time,
+        };
+        if contract.script() != script {
+            return Err(invalid());
+        }
+        Ok(contract)
+    }
+
+    /// The script address funds are locked at.
+    pub fn address(&self) -> String {
+        address::encode(&Sha256::digest(self.script()))
+    }
+
+    /// Whether `secret` claims the contract.
+    pub fn is_secret(&self, secret: &[u8]) -> bool {
+        secret.len() == SECRET_SIZE && Sha256::digest(secret)[..] == self.secret_hash
+    }
+
+    /// The secret a redeem unlocking script revealed, if it is one.
+    pub fn extract_secret(&self, unlocking: &[u8]) -> Option<Vec<u8>> {
+        let mut reader = Reader {
+            script: unlocking,
+            position: 0,
+        };
+        reader.push().ok()?;
+        reader.push().ok()?;
+        let secret = reader.push().ok()?.to_vec();
+        reader.expect(&[OP_1]).ok()?;
+        (reader.position == unlocking.len() && self.is_secret(&secret)).then_some(secret)
+    }
+
+    pub fn recipient_address(&self) -> String {
+        address::encode(&self.recipient)
+    }
+
+    pub fn refund_address(&self) -> String {
+        address::encode(&self.refund)
+    }
+}
+
+/// A fresh random secret and its hash.
+pub fn new_secret() -> ([u8; SECRET_SIZE], [u8; 32]) {
+    let mut secret = [0u8; SECRET_SIZE];
+    OsRng.fill_bytes(&mut secret);
+    (secret, Sha256::digest(secret).into())
+}
+
+/// Unlocking script taking the recipient's branch.
+pub fn redeem_script(signature: &[u8], pubkey: &[u8], secret: &[u8]) -> Vec<u8> {
+    let mut script = Vec::new();
+    push(&mut script, signature);
+    push(&mut script, pubkey);
+    push(&mut script, secret);
+    script.push(OP_1);
+    script
+}
+
+/// Unlocking script taking the refund branch.
+pub fn refund_script(signature: &[u8], pubkey: &[u8]) -> Vec<u8> {
+    let mut script = Vec::new();
+    push(&mut script, signature);
+    push(&mut script, pubkey);
+    script.push(OP_0);
+    script
+}
+
+/// Length in hex characters of a redeem or, without a secret, refund
+/// unlocking script, for estimating its fee before signing.
+pub fn unlocking_len(secret: Option<&[u8]>) -> usize {
+    let placeholder = [0u8; ECDSA_SIZE];
+    let pubkey = [0u8; PUBKEY_SIZE];
+    let script = match secret {
+        Some(secret) => redeem_script(&placeholder, &pubkey, secret),
+        None => refund_script(&placeholder, &pubkey),
+    };
+    2 * script.len()
+}
+
+/// Appends a direct push; every item here is well under 76 bytes.
+fn push(script: &mut Vec<u8>, data: &[u8]) {
+    script.push(data.len() as u8);
+    script.extend_from_slice(data);
+}
+
+/// Minimal little-endian script number, as the node encodes it.
+fn encode_num(value: u64) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    let mut rest = value;
+    while rest > 0 {
+        bytes.push(rest as u8);
+        rest >>= 8;
+    }
+    if bytes.last().is_some_and(|last| last & 0x80 != 0) {
+        bytes.push(0);
+    }
+    bytes
+}
+
+/// Reads back a positive number of at most five bytes.
+fn decode_num(bytes: &[u8]) -> Option<u64> {
+    let last = *bytes.last()?;
+    if bytes.len() > 5 || last & 0x80 != 0 {
+        return None;
+    }
+    let value = bytes
+        .iter()
+        .rev()
+        .fold(0u64, |value, &byte| (value << 8) | byte as u64);
+    (value > 0).then_some(value)
+}
+
+struct Reader<'a> {
+    script: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn expect(&mut self, opcodes: &[u8]) -> Result<(), String> {
+        let end = self.position + opcodes.len();
+        if self.script.get(self.position..end) != Some(opcodes) {
+            return Err("Not a swap contract".to_string());
+        }
+        self.position = end;
+        Ok(())
+    }
+
+    fn push(&mut self) -> Result<&'a [u8], String> {
+        let truncated = || "Not a swap contract".to_string();
+        let len = *self.script.get(self.position).ok_or_else(truncated)? as usize;
+        if len == 0 || len > 0x4b {
+            return Err(truncated());
+        }
+        let start = self.position + 1;
+        let data = self.script.get(start..start + len).ok_or_else(truncated)?;
+        self.position = start + len;
+        Ok(data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn contract(secret: &[u8], lock_time: u64) -> Contract {
+        Contract {
+            secret_hash: Sha256::digest(secret).into(),
+            recipient: [1; PUBKEY_HASH_SIZE],
+            refund: [2; PUBKEY_HASH_SIZE],
+            lock_time,
+        }
+    }
+
+    #[test]
+    fn parses_its_own_script() {
+        let (secret, _) = new_secret();
+        for lock_time in [1, 0x80, 144, LOCKTIME_THRESHOLD + 3_600] {
+            let contract = contract(&secret, lock_time);
+            assert_eq!(Contract::parse(&contract.script()), Ok(contract));
+        }
+    }
+
+    #[test]
+    fn parses_nothing_but_the_template() {
+        let script = contract(&[9; SECRET_SIZE], 144).script();
+        let mut longer = script.clone();
+        longer.push(OP_1);
+        assert!(Contract::parse(&longer).is_err());
+        assert!(Contract::parse(&script[..script.len() - 1]).is_err());
+
+        let mut other_opcode = script.clone();
+        *other_opcode.last_mut().unwrap() = OP_EQUALVERIFY;
+        assert!(Contract::parse(&other_opcode).is_err());
+
+        // A lock time of zero, or one pushed with a padding byte.
+        let mut zero = script.clone();
+        let at = zero.iter().position(|&op| op == OP_ELSE).unwrap() + 1;
+        zero.splice(at..at + 3, [1, 0]);
+        assert!(Contract::parse(&zero).is_err());
+        let mut padded = script;
+        padded.splice(at..at + 3, [3, 144, 0, 0]);
+        assert!(Contract::parse(&padded).is_err());
+    }
+
+    #[test]
+    fn extracts_the_secret_from_a_redeem() {
+        let (secret, hash) = new_secret();
+        let contract = contract(&secret, 144);
+        assert_eq!(contract.secret_hash, hash);
+        let signature = [3; ECDSA_SIZE];
+        let pubkey = [2; PUBKEY_SIZE];
+
+        let redeem = redeem_script(&signature, &pubkey, &secret);
+        assert_eq!(contract.extract_secret(&redeem), Some(secret.to_vec()));
+        assert_eq!(2 * redeem.len(), unlocking_len(Some(&secret)));
+
+        let wrong = redeem_script(&signature, &pubkey, &[0; SECRET_SIZE]);
+        assert_eq!(contract.extract_secret(&wrong), None);
+        let refund = refund_script(&signature, &pubkey);
+        assert_eq!(contract.extract_secret(&refund), None);
+        let mut trailing = redeem;
+        trailing.push(OP_1);
+        assert_eq!(contract.extract_secret(&trailing), None);
+    }
+}
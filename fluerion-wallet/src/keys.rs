@@ -0,0 +1,72 @@
+use crate::address::{self, Payload};
+use crate::multisig;
+use k256::ecdsa::signature::{Signer, Verifier};
+use k256::ecdsa::{Signature, SigningKey, VerifyingKey};
+
+/// A signature is the signer's 33-byte compressed public key followed by a
+/// 64-byte compact ECDSA signature, hex-encoded, so the node can check it
+/// against the sender address alone.
+pub const PUBKEY_SIZE: usize = 33;
+pub const SIGNATURE_SIZE: usize = PUBKEY_SIZE + 64;
+
+pub struct KeyPair {
+    signing_key: SigningKey,
+}
+
+impl KeyPair {
+    pub fn new(signing_key: SigningKey) -> Self {
+        KeyPair { signing_key }
+    }
+
+    /// The 33-byte compressed SEC1 encoding of the public key.
+    pub fn public_key(&self) -> Vec<u8> {
+        self.signing_key
+            .verifying_key()
+            .to_encoded_point(true)
+            .as_bytes()
+            .to_vec()
+    }
+
+    pub fn address(&self) -> String {
+        address::encode(&address::pubkey_hash(&self.public_key()))
+    }
+
+    pub fn sign(&self, data: &[u8]) -> String {
+        let mut bytes = self.public_key();
+        bytes.extend_from_slice(&self.sign_compact(data));
+        hex::encode(bytes)
+    }
+
+    /// The bare 64-byte signature, as one share of a multisig spend.
+    pub fn sign_compact(&self, data: &[u8]) -> Vec<u8> {
+        let signature: Signature = self.signing_key.sign(data);
+        signature.to_bytes().to_vec()
+    }
+}
+
+/// Checks a signature over `data` against the address it should come from:
+/// one made by `KeyPair::sign` for a single-key address, or a finalized
+/// multisig signature for a multisig address.
+pub fn verify(address: &str, data: &[u8], signature: &str) -> Result<(), String> {
+    let bytes = hex::decode(signature).map_err(|_| "Malformed signature")?;
+    match address::decode(address)? {
+        Payload::PubkeyHash(hash) => {
+            if bytes.len() != SIGNATURE_SIZE {
+                return Err("Malformed signature".to_string());
+            }
+            let (pubkey, signature) = bytes.split_at(PUBKEY_SIZE);
+            if address::pubkey_hash(pubkey) != hash {
+                return Err(format!("Signature key does not match {}", address));
+            }
+            verify_compact(pubkey, data, signature)
+        }
+        Payload::ScriptHash(hash) => multisig::verify(&hash, data, &bytes),
+    }
+}
+
+pub fn verify_compact(pubkey: &[u8], data: &[u8], signature: &[u8]) -> Result<(), String> {
+    let key = VerifyingKey::from_sec1_bytes(pubkey).map_err(|_| "Malformed public key")?;
+    let signature = Signature::from_slice(signature).map_err(|_| "Malformed signature")?;
+    key.verify(data, &signature)
+        .map_err(|_| "Invalid signature".to_string())
+}
@@ -0,0 +1,318 @@
+use crate::chainparams::{params, Network};
+use crate::multisig::MultisigAddress;
+use argon2::{Algorithm, Argon2, Params, Version};
+use chacha20poly1305::aead::{Aead, KeyInit};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use rand_core::{OsRng, RngCore};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+pub const KEYSTORE_VERSION: u32 = 1;
+
+const SALT_SIZE: usize = 16;
+const NONCE_SIZE: usize = 12;
+
+/// Everything that lets someone spend from the wallet. It only ever reaches
+/// the disk encrypted.
+#[derive(Serialize, Deserialize)]
+pub struct Secret {
+    pub mnemonic: String,
+    pub passphrase: String,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct AccountRecord {
+    pub index: u32,
+    pub xpub: String,
+    pub address_count: u32,
+}
+
+/// An address the wallet follows without holding its key. Importing the
+/// public key rather than the address records it for later multisig use.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct WatchedAddress {
+    pub address: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pubkey: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub label: Option<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct KdfParams {
+    algorithm: String,
+    salt: String,
+    m_cost: u32,
+    t_cost: u32,
+    p_cost: u32,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Encrypted {
+    cipher: String,
+    nonce: String,
+    ciphertext: String,
+}
+
+/// The on-disk wallet file. Account extended public keys stay readable so
+/// addresses can be listed while the wallet is locked; the mnemonic is
+/// sealed with ChaCha20-Poly1305 under a key stretched from the passphrase
+/// with Argon2id. A watch-only wallet has the accounts and nothing else.
+#[derive(Serialize, Deserialize)]
+pub struct Keystore {
+    version: u32,
+    /// Network the wallet was made on. Files from before networks existed
+    /// are mainnet wallets.
+    #[serde(default)]
+    pub network: Network,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    kdf: Option<KdfParams>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    secret: Option<Encrypted>,
+    pub accounts: Vec<AccountRecord>,
+    /// Multisig addresses this wallet holds a key for, or watches.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub multisig: Vec<MultisigAddress>,
+    /// Height of the tip when the wallet was made; no earlier block can
+    /// hold its transactions, so rescans start here.
+    #[serde(default)]
+    pub birthday: u64,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub watched: Vec<WatchedAddress>,
+    /// Counterparty addresses by label.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub address_book: BTreeMap<String, String>,
+    #[serde(skip)]
+    path: PathBuf,
+}
+
+impl Keystore {
+    pub fn create(
+        path: &Path,
+        secret: &Secret,
+        passphrase: &str,
+        accounts: Vec<AccountRecord>,
+    ) -> Result<Self, String> {
+        let (kdf, secret) = seal(secret, passphrase)?;
+        let keystore = Keystore {
+            version: KEYSTORE_VERSION,
+            network: params().network,
+            kdf: Some(kdf),
+            secret: Some(secret),
+            accounts,
+            multisig: Vec::new(),
+            birthday: 0,
+            watched: Vec::new(),
+            address_book: BTreeMap::new(),
+            path: path.to_path_buf(),
+        };
+        keystore.save()?;
+        Ok(keystore)
+    }
+
+    /// A wallet file holding only account public keys, for an online
+    /// machine that builds transactions but never signs them.
+    pub fn create_watch_only(path: &Path, accounts: Vec<AccountRecord>) -> Result<Self, String> {
+        let keystore = Keystore {
+            version: KEYSTORE_VERSION,
+            network: params().network,
+            kdf: None,
+            secret: None,
+            accounts,
+            multisig: Vec::new(),
+            birthday: 0,
+            watched: Vec::new(),
+            address_book: BTreeMap::new(),
+            path: path.to_path_buf(),
+        };
+        keystore.save()?;
+        Ok(keystore)
+    }
+
+    pub fn is_watch_only(&self) -> bool {
+        self.secret.is_none()
+    }
+
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let contents = fs::read_to_string(path)
+            .map_err(|e| format!("Cannot read wallet {}: {}", path.display(), e))?;
+        let mut keystore: Keystore = serde_json::from_str(&contents)
+            .map_err(|e| format!("Corrupt wallet {}: {}", path.display(), e))?;
+
+        if keystore.version != KEYSTORE_VERSION {
+            return Err(format!(
+                "Unsupported wallet version {} (expected {})",
+                keystore.version, KEYSTORE_VERSION
+            ));
+        }
+        keystore.path = path.to_path_buf();
+        Ok(keystore)
+    }
+
+    pub fn decrypt(&self, passphrase: &str) -> Result<Secret, String> {
+        let (Some(kdf), Some(secret)) = (&self.kdf, &self.secret) else {
+            return Err("Watch-only wallet has no private keys".to_string());
+        };
+        let key = derive_key(kdf, passphrase)?;
+        let nonce = hex::decode(&secret.nonce).map_err(|_| "Corrupt wallet nonce")?;
+        let ciphertext =
+            hex::decode(&secret.ciphertext).map_err(|_| "Corrupt wallet ciphertext")?;
+        if nonce.len() != NONCE_SIZE {
+            return Err("Corrupt wallet nonce".to_string());
+        }
+
+        let plaintext = ChaCha20Poly1305::new(&key)
+            .decrypt(Nonce::from_slice(&nonce), ciphertext.as_ref())
+            .map_err(|_| "Wrong passphrase")?;
+        serde_json::from_slice(&plaintext).map_err(|_| "Corrupt wallet secret".to_string())
+    }
+
+    /// Re-encrypts the secret under a new passphrase with a fresh salt.
+    pub fn change_passphrase(&mut self, old: &str, new: &str) -> Result<(), String> {
+        let secret = self.decrypt(old)?;
+        let (kdf, sealed) = seal(&secret, new)?;
+        self.kdf = Some(kdf);
+        self.secret = Some(sealed);
+        self.save()
+    }
+
+    /// Writes the keystore next to its final path, syncs it, then renames it
+    /// into place, so a crash leaves either the old file or the new one.
+    pub fn save(&self) -> Result<(), String> {
+        let json = serde_json::to_string_pretty(self).unwrap();
+        let tmp_path = self.path.with_extension("tmp");
+        let write = || -> std::io::Result<()> {
+            let mut file = create_private(&tmp_path)?;
+            file.write_all(json.as_bytes())?;
+            file.sync_all()?;
+            fs::rename(&tmp_path, &self.path)?;
+            if let Some(dir) = self.path.parent().filter(|p| !p.as_os_str().is_empty()) {
+                File::open(dir)?.sync_all()?;
+            }
+            Ok(())
+        };
+        write().map_err(|e| format!("Cannot write wallet {}: {}", self.path.display(), e))
+    }
+}
+
+fn seal(secret: &Secret, passphrase: &str) -> Result<(KdfParams, Encrypted), String> {
+    let mut salt = [0u8; SALT_SIZE];
+    OsRng.fill_bytes(&mut salt);
+    let kdf = KdfParams {
+        algorithm: "argon2id".to_string(),
+        salt: hex::encode(salt),
+        m_cost: Params::DEFAULT_M_COST,
+        t_cost: Params::DEFAULT_T_COST,
+        p_cost: Params::DEFAULT_P_COST,
+    };
+    let key = derive_key(&kdf, passphrase)?;
+
+    let mut nonce = [0u8; NONCE_SIZE];
+    OsRng.fill_bytes(&mut nonce);
+    let plaintext = serde_json::to_vec(secret).unwrap();
+    let ciphertext = ChaCha20Poly1305::new(&key)
+        .encrypt(Nonce::from_slice(&nonce), plaintext.as_ref())
+        .map_err(|_| "Encryption failed")?;
+
+    let encrypted = Encrypted {
+        cipher: "chacha20poly1305".to_string(),
+        nonce: hex::encode(nonce),
+        ciphertext: hex::encode(ciphertext),
+    };
+    Ok((kdf, encrypted))
+}
+
+fn derive_key(kdf: &KdfParams, passphrase: &str) -> Result<Key, String> {
+    if kdf.algorithm != "argon2id" {
+        return Err(format!("Unsupported key derivation {}", kdf.algorithm));
+    }
+    let salt = hex::decode(&kdf.salt).map_err(|_| "Corrupt wallet salt")?;
+    let params = Params::new(kdf.m_cost, kdf.t_cost, kdf.p_cost, Some(32))
+        .map_err(|e| format!("Invalid key derivation parameters: {}", e))?;
+
+    let mut key = Key::default();
+    Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+        .hash_password_into(passphrase.as_bytes(), &salt, &mut key)
+        .map_err(|e| format!("Key derivation failed: {}", e))?;
+    Ok(key)
+}
+
+/// Creates `path` readable and writable by its owner alone, since it will
+/// hold the encrypted keys.
+fn create_private(path: &Path) -> std::io::Result<File> {
+    let _ = fs::remove_file(path);
+    let mut options = OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    options.open(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!(
+            "fluerion-keystore-{}-{}.json",
+            std::process::id(),
+            name
+        ))
+    }
+
+    fn secret() -> Secret {
+        Secret {
+            mnemonic: "abandon art".to_string(),
+            passphrase: "extra words".to_string(),
+        }
+    }
+
+    #[test]
+    fn opens_only_with_its_passphrase() {
+        let path = temp_path("seal");
+        let account = AccountRecord {
+            index: 0,
+            xpub: "xpub".to_string(),
+            address_count: 3,
+        };
+        Keystore::create(&path, &secret(), "right", vec![account]).unwrap();
+
+        let keystore = Keystore::load(&path).unwrap();
+        assert!(!keystore.is_watch_only());
+        assert_eq!(keystore.accounts[0].address_count, 3);
+        let opened = keystore.decrypt("right").unwrap();
+        assert_eq!(opened.mnemonic, "abandon art");
+        assert_eq!(opened.passphrase, "extra words");
+        assert_eq!(keystore.decrypt("wrong").err().unwrap(), "Wrong passphrase");
+
+        let contents = fs::read_to_string(&path).unwrap();
+        assert!(!contents.contains("abandon"));
+        let _ = fs::remove_file(&path);
+    }
+
+    #[test]
+    fn changes_the_passphrase() {
+        let path = temp_path("change");
+        let mut keystore = Keystore::create(&path, &secret(), "old", Vec::new()).unwrap();
+        assert!(keystore.change_passphrase("wrong", "new").is_err());
+        keystore.change_passphrase("old", "new").unwrap();
+
+        let keystore = Keystore::load(&path).unwrap();
+        assert!(keystore.decrypt("old").is_err());
+        assert_eq!(keystore.decrypt("new").unwrap().mnemonic, "abandon art");
+        let _ = fs::remove_file(&path);
+    }
+
+    #[test]
+    fn watch_only_wallets_hold_no_secret() {
+        let path = temp_path("watch-only");
+        Keystore::create_watch_only(&path, Vec::new()).unwrap();
+        let keystore = Keystore::load(&path).unwrap();
+        assert!(keystore.is_watch_only());
+        assert!(keystore.decrypt("").is_err());
+        let _ = fs::remove_file(&path);
+    }
+}
@@ -1,53 +1,144 @@
+mod address;
+mod chainparams;
+mod commands;
+mod config;
+mod filter;
+mod hd;
+mod htlc;
+mod keys;
+mod keystore;
+mod multisig;
+mod node;
+mod offline;
+mod rescan;
+mod token;
+mod transaction;
+mod wallet;
+
+use config::{Config, DEFAULT_FEE_TARGET, USAGE};
+use hd::HdWallet;
+use node::{NodeClient, HISTORY_PAGE_SIZE};
+use rescan::Filters;
+use std::io::{self, BufRead, IsTerminal, Write};
 use std::net::SocketAddr;
+use std::path::Path;
 use std::str::FromStr;
-use std::io::{self, BufRead, Write};
-use tokio::net::TcpStream;
-use tokio::io::{AsyncReadExt, AsyncWriteExt};
-use serde::{Serialize, Deserialize};
-
-#[derive(Serialize, Deserialize)]
-struct Transaction {
-    sender: String,
-    receiver: String,
-    amount: f64,
-    timestamp: u64,
-    signature: Option<String>,
-}
+use std::time::Duration;
+use transaction::Transaction;
+use wallet::Wallet;
 
 #[tokio::main]
 async fn main() -> io::Result<()> {
-    println!("Fluerion Wallet");
+    let args: Vec<String> = std::env::args().collect();
+    let mut config = match Config::from_args(&args) {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("{}\n{}", e, USAGE);
+            std::process::exit(commands::EXIT_USAGE);
+        }
+    };
 
-    let mut address = String::new();
-    print!("Enter your wallet address: ");
-    io::stdout().flush()?;
-    io::stdin().lock().read_line(&mut address)?;
-    let address = address.trim().to_string();
+    if let Some(command) = config.command.take() {
+        std::process::exit(commands::run(&config, command).await);
+    }
 
-    let mut node_addr = String::new();
-    print!("Enter the node address to connect to (e.g., 127.0.0.1:8080): ");
-    io::stdout().flush()?;
-    io::stdin().lock().read_line(&mut node_addr)?;
-    let node_addr = SocketAddr::from_str(node_addr.trim()).expect("Invalid address");
+    println!("Fluerion Wallet");
 
-    println!("Connecting to node at {}", node_addr);
+    let node_addr = match config.node {
+        Some(node) => node,
+        None => {
+            let node = prompt("Enter the node address to connect to (e.g., 127.0.0.1:8080): ")?;
+            chainparams::params().with_default_port(&node)
+        }
+    };
+    let node_addr = match SocketAddr::from_str(&node_addr) {
+        Ok(node_addr) => node_addr,
+        Err(_) => {
+            eprintln!("Invalid node address: {}\n{}", node_addr, USAGE);
+            std::process::exit(commands::EXIT_USAGE);
+        }
+    };
+    let client = NodeClient::new(node_addr.to_string());
 
-    let mut stream = TcpStream::connect(node_addr).await?;
-    println!("Wallet {} connected to node", address);
+    let path = config.wallet;
+    let auto_lock = Duration::from_secs(config.auto_lock);
+    let mut wallet = if path.exists() {
+        match Wallet::open(&path, auto_lock) {
+            Ok(wallet) => {
+                println!("Opened wallet {} (locked)", path.display());
+                wallet
+            }
+            Err(e) => {
+                eprintln!("{}", e);
+                std::process::exit(commands::EXIT_WALLET);
+            }
+        }
+    } else {
+        open_wallet(&client, &path, auto_lock).await?
+    };
+    let mut account = 0;
 
     loop {
-        let mut choice = String::new();
-        println!("\n1. Send transaction");
+        println!("\nAccount #{}", account);
+        println!("1. Send transaction");
         println!("2. Check balance");
-        println!("3. Exit");
-        print!("Enter your choice: ");
-        io::stdout().flush()?;
-        io::stdin().lock().read_line(&mut choice)?;
-
-        match choice.trim() {
-            "1" => send_transaction(&mut stream, &address).await?,
-            "2" => check_balance(&mut stream, &address).await?,
-            "3" => break,
+        println!("3. Transaction history");
+        println!("4. New address");
+        println!("5. List addresses");
+        println!("6. Switch account");
+        println!("7. Lock / unlock");
+        println!("8. Change passphrase");
+        println!("9. Show mnemonic");
+        println!("10. Exit");
+
+        match prompt("Enter your choice: ")?.as_str() {
+            "1" => {
+                if let Some(index) = choose_address(&mut wallet, account)? {
+                    if unlock_if_needed(&mut wallet)? {
+                        send_transaction(&client, &mut wallet, account, index).await?;
+                    }
+                }
+            }
+            "2" => {
+                if let Some(index) = choose_address(&mut wallet, account)? {
+                    let address = wallet.accounts[account].address(index);
+                    check_balance(&client, &address).await?;
+                }
+            }
+            "3" => {
+                if let Some(index) = choose_address(&mut wallet, account)? {
+                    let address = wallet.accounts[account].address(index);
+                    show_history(&client, &address).await?;
+                }
+            }
+            "4" => match wallet.new_address(account) {
+                Ok(address) => println!("New address: {}", address),
+                Err(e) => println!("{}", e),
+            },
+            "5" => {
+                for (i, address) in wallet.accounts[account].addresses().iter().enumerate() {
+                    println!("{}. {}", i, address);
+                }
+            }
+            "6" => account = switch_account(&mut wallet)?,
+            "7" => {
+                if wallet.is_locked() {
+                    unlock_if_needed(&mut wallet)?;
+                } else {
+                    wallet.lock();
+                    println!("Wallet locked");
+                }
+            }
+            "8" => change_passphrase(&mut wallet)?,
+            "9" => {
+                if unlock_if_needed(&mut wallet)? {
+                    match wallet.phrase() {
+                        Ok(phrase) => println!("Mnemonic: {}", phrase),
+                        Err(e) => println!("{}", e),
+                    }
+                }
+            }
+            "10" => break,
             _ => println!("Invalid choice"),
         }
     }
@@ -55,53 +146,320 @@ async fn main() -> io::Result<()> {
     Ok(())
 }
 
-async fn send_transaction(stream: &mut TcpStream, sender: &str) -> io::Result<()> {
-    let mut receiver = String::new();
-    print!("Enter receiver address: ");
+fn prompt(question: &str) -> io::Result<String> {
+    let mut answer = String::new();
+    print!("{}", question);
     io::stdout().flush()?;
-    io::stdin().lock().read_line(&mut receiver)?;
-    let receiver = receiver.trim().to_string();
+    io::stdin().lock().read_line(&mut answer)?;
+    Ok(answer.trim().to_string())
+}
 
-    let mut amount = String::new();
-    print!("Enter amount: ");
+/// Like `prompt`, without echoing the answer when reading from a terminal,
+/// for passphrases and mnemonics.
+fn prompt_secret(question: &str) -> io::Result<String> {
+    if !io::stdin().is_terminal() {
+        return prompt(question);
+    }
+    print!("{}", question);
     io::stdout().flush()?;
-    io::stdin().lock().read_line(&mut amount)?;
-    let amount: f64 = amount.trim().parse().expect("Invalid amount");
-
-    let timestamp = std::time::SystemTime::now()
-        .duration_since(std::time::UNIX_EPOCH)
-        .unwrap()
-        .as_secs();
-
-    let transaction = Transaction {
-        sender: sender.to_string(),
-        receiver,
-        amount,
-        timestamp,
-        signature: None,
-    };
+    Ok(rpassword::read_password()?.trim().to_string())
+}
 
-    let tx_json = serde_json::to_string(&transaction).unwrap();
-    let message = format!("NEW_TRANSACTION:{}", tx_json);
+/// Asks for a new passphrase twice, until both entries match.
+fn prompt_new_passphrase() -> io::Result<String> {
+    loop {
+        let passphrase = prompt_secret("New wallet passphrase: ")?;
+        if passphrase.is_empty() {
+            println!("The wallet passphrase cannot be empty");
+            continue;
+        }
+        if prompt_secret("Repeat passphrase: ")? == passphrase {
+            return Ok(passphrase);
+        }
+        println!("Passphrases do not match");
+    }
+}
 
-    stream.write_all(message.as_bytes()).await?;
+/// Creates a wallet from a fresh mnemonic, or restores one from a written
+/// down phrase and rediscovers its addresses from the chain, then encrypts
+/// it into a new wallet file.
+async fn open_wallet(client: &NodeClient, path: &Path, auto_lock: Duration) -> io::Result<Wallet> {
+    let (hd, accounts, mnemonic_passphrase, birthday) = loop {
+        println!("\n1. Create new wallet");
+        println!("2. Restore wallet from mnemonic");
 
-    let mut buffer = [0; 1024];
-    let n = stream.read(&mut buffer).await?;
-    let response = String::from_utf8_lossy(&buffer[..n]);
-    println!("Response: {}", response);
+        match prompt("Enter your choice: ")?.as_str() {
+            "1" => {
+                let passphrase =
+                    prompt_secret("Optional mnemonic passphrase (press Enter for none): ")?;
+                let hd = HdWallet::generate(&passphrase);
+                let mut account = hd.account(0);
+                println!("\nMnemonic: {}", hd.phrase());
+                println!("Write these words down; they restore every address in this wallet.");
+                println!("First address: {}", account.new_address());
+                let birthday = client.height().await.unwrap_or(0);
+                break (hd, vec![account], passphrase, birthday);
+            }
+            "2" => {
+                let phrase = prompt_secret("Enter mnemonic: ")?;
+                let passphrase = prompt_secret("Mnemonic passphrase (press Enter for none): ")?;
+                let hd = match HdWallet::from_phrase(&phrase, &passphrase) {
+                    Ok(hd) => hd,
+                    Err(e) => {
+                        println!("{}", e);
+                        continue;
+                    }
+                };
 
+                let birthday =
+                    match prompt("Wallet birthday height (press Enter for 0): ")?.as_str() {
+                        "" => 0,
+                        height => match height.parse() {
+                            Ok(height) => height,
+                            Err(_) => {
+                                println!("Invalid height");
+                                continue;
+                            }
+                        },
+                    };
+
+                println!("Scanning block filters for used addresses...");
+                let filters = Filters::download(client, birthday).await?;
+                let accounts = hd.discover(|address| filters.contains(address));
+                for account in &accounts {
+                    println!(
+                        "Account #{}: {} addresses",
+                        account.index, account.address_count
+                    );
+                }
+                break (hd, accounts, passphrase, birthday);
+            }
+            _ => println!("Invalid choice"),
+        }
+    };
+
+    println!("\nChoose a passphrase to encrypt {}", path.display());
+    let passphrase = prompt_new_passphrase()?;
+    let mut wallet = Wallet::create(
+        path,
+        hd,
+        accounts,
+        &mnemonic_passphrase,
+        &passphrase,
+        auto_lock,
+    )
+    .map_err(io::Error::other)?;
+    wallet.set_birthday(birthday).map_err(io::Error::other)?;
+    Ok(wallet)
+}
+
+/// Prompts for the passphrase when the wallet is locked. Returns whether the
+/// wallet ends up unlocked.
+fn unlock_if_needed(wallet: &mut Wallet) -> io::Result<bool> {
+    if !wallet.is_locked() {
+        return Ok(true);
+    }
+    let passphrase = prompt_secret("Wallet passphrase: ")?;
+    match wallet.unlock(&passphrase) {
+        Ok(()) => {
+            println!("Wallet unlocked");
+            Ok(true)
+        }
+        Err(e) => {
+            println!("{}", e);
+            Ok(false)
+        }
+    }
+}
+
+fn change_passphrase(wallet: &mut Wallet) -> io::Result<()> {
+    let old = prompt_secret("Current wallet passphrase: ")?;
+    let new = prompt_new_passphrase()?;
+    match wallet.change_passphrase(&old, &new) {
+        Ok(()) => println!("Passphrase changed"),
+        Err(e) => println!("{}", e),
+    }
     Ok(())
 }
 
-async fn check_balance(stream: &mut TcpStream, address: &str) -> io::Result<()> {
-    let message = format!("GET_BALANCE:{}", address);
-    stream.write_all(message.as_bytes()).await?;
+/// Asks which of the account's addresses to use, creating the first one if
+/// the account has none yet. Returns the address index.
+fn choose_address(wallet: &mut Wallet, account: usize) -> io::Result<Option<u32>> {
+    if wallet.accounts[account].address_count == 0 {
+        if let Err(e) = wallet.new_address(account) {
+            println!("{}", e);
+            return Ok(None);
+        }
+    }
 
-    let mut buffer = [0; 1024];
-    let n = stream.read(&mut buffer).await?;
-    let response = String::from_utf8_lossy(&buffer[..n]);
-    println!("Balance: {}", response);
+    let addresses = wallet.accounts[account].addresses();
+    for (i, address) in addresses.iter().enumerate() {
+        println!("{}. {}", i, address);
+    }
+    let choice = prompt("Use address # (press Enter for the latest): ")?;
+    if choice.is_empty() {
+        return Ok(Some(addresses.len() as u32 - 1));
+    }
+    match choice.parse::<u32>() {
+        Ok(i) if (i as usize) < addresses.len() => Ok(Some(i)),
+        _ => {
+            println!("Invalid choice");
+            Ok(None)
+        }
+    }
+}
+
+fn switch_account(wallet: &mut Wallet) -> io::Result<usize> {
+    for account in &wallet.accounts {
+        println!("{}. Account #{}", account.index, account.index);
+    }
+    println!("{}. New account", wallet.accounts.len());
+
+    let choice = prompt("Enter your choice: ")?;
+    match choice.parse::<usize>() {
+        Ok(i) if i < wallet.accounts.len() => Ok(i),
+        Ok(i) if i == wallet.accounts.len() => {
+            if !unlock_if_needed(wallet)? {
+                return Ok(0);
+            }
+            match wallet.add_account() {
+                Ok(index) => Ok(index as usize),
+                Err(e) => {
+                    println!("{}", e);
+                    Ok(0)
+                }
+            }
+        }
+        _ => {
+            println!("Invalid choice");
+            Ok(0)
+        }
+    }
+}
+
+async fn send_transaction(
+    client: &NodeClient,
+    wallet: &mut Wallet,
+    account: usize,
+    index: u32,
+) -> io::Result<()> {
+    let key_pair = match wallet.key_pair(account as u32, index) {
+        Ok(key_pair) => key_pair,
+        Err(e) => {
+            println!("{}", e);
+            return Ok(());
+        }
+    };
+
+    let receiver = prompt("Enter receiver address or contact: ")?;
+    let receiver = match wallet.resolve_receiver(&receiver) {
+        Ok(receiver) => receiver,
+        Err(e) => {
+            println!("{}", e);
+            return Ok(());
+        }
+    };
+
+    let amount = match prompt("Enter amount: ")?.parse::<f64>() {
+        Ok(amount) if amount > 0.0 && amount.is_finite() => amount,
+        _ => {
+            println!("Invalid amount");
+            return Ok(());
+        }
+    };
+
+    let mut transaction = Transaction::new(key_pair.address(), receiver, amount, 0.0);
+    let estimate = match client.estimate_fee(DEFAULT_FEE_TARGET).await {
+        Ok(fee_rate) => transaction.fee_for(fee_rate, 2 * keys::SIGNATURE_SIZE),
+        Err(e) => {
+            println!("{}", e);
+            return Ok(());
+        }
+    };
+    let fee = prompt(&format!("Enter fee [{}]: ", estimate))?;
+    transaction.fee = if fee.is_empty() {
+        estimate
+    } else {
+        match fee.parse() {
+            Ok(fee) if fee >= 0.0 => fee,
+            _ => {
+                println!("Invalid fee");
+                return Ok(());
+            }
+        }
+    };
+    transaction.nonce = match client.next_nonce(&transaction.sender).await {
+        Ok(nonce) => nonce,
+        Err(e) => {
+            println!("{}", e);
+            return Ok(());
+        }
+    };
+    transaction
+        .sign(&key_pair)
+        .expect("key pair derived for the sender");
+    match client.send_transaction(&transaction).await {
+        Ok(()) => println!("Transaction sent"),
+        Err(e) => println!("{}", e),
+    }
+    Ok(())
+}
 
+async fn check_balance(client: &NodeClient, address: &str) -> io::Result<()> {
+    match client.balance(address).await {
+        Ok(balance) => {
+            println!("Confirmed balance: {}", balance.confirmed);
+            if balance.unconfirmed != 0.0 {
+                println!("Unconfirmed: {:+}", balance.unconfirmed);
+            }
+        }
+        Err(e) => println!("{}", e),
+    }
     Ok(())
-}
\ No newline at end of file
+}
+
+/// Pages through the address's transactions, newest first.
+async fn show_history(client: &NodeClient, address: &str) -> io::Result<()> {
+    let mut offset = 0;
+    loop {
+        let history = match client.history(address, offset, HISTORY_PAGE_SIZE).await {
+            Ok(history) => history,
+            Err(e) => {
+                println!("{}", e);
+                return Ok(());
+            }
+        };
+        if history.total == 0 {
+            println!("No transactions");
+            return Ok(());
+        }
+
+        for entry in &history.transactions {
+            let (sign, counterparty) = match entry.direction.as_str() {
+                "received" => ("+", &entry.transaction.sender),
+                "sent" => ("-", &entry.transaction.receiver),
+                _ => (" ", &entry.transaction.receiver),
+            };
+            let status = match entry.height {
+                Some(height) => format!("block {}, {} conf", height, entry.confirmations),
+                None => "pending".to_string(),
+            };
+            println!(
+                "{}{} {} {} ({})",
+                sign, entry.transaction.amount, entry.direction, counterparty, status
+            );
+        }
+
+        offset += history.transactions.len();
+        if offset >= history.total {
+            return Ok(());
+        }
+        let more = prompt(&format!(
+            "Showing {} of {}. Press Enter for more, q to stop: ",
+            offset, history.total
+        ))?;
+        if more == "q" {
+            return Ok(());
+        }
+    }
+}
@@ -0,0 +1,232 @@
+use crate::address;
+use crate::keys::{verify_compact, PUBKEY_SIZE};
+use k256::PublicKey;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+/// Largest key set a multisig address may commit to.
+pub const MAX_KEYS: usize = 15;
+
+const ECDSA_SIZE: usize = 64;
+
+/// An m-of-n address: spending needs signatures from `threshold` of the
+/// listed keys. Key order is part of the address. Deserializing checks it
+/// the same way `new` does, so a key set read from a file or blob is as
+/// sound as one built here.
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(try_from = "KeySet")]
+pub struct MultisigAddress {
+    threshold: u8,
+    /// Hex-encoded compressed public keys.
+    pubkeys: Vec<String>,
+    /// The same keys decoded, back to back.
+    #[serde(skip)]
+    key_bytes: Vec<u8>,
+}
+
+/// A key set as written, before it is checked.
+#[derive(Deserialize)]
+struct KeySet {
+    threshold: u8,
+    pubkeys: Vec<String>,
+}
+
+impl TryFrom<KeySet> for MultisigAddress {
+    type Error = String;
+
+    fn try_from(set: KeySet) -> Result<Self, String> {
+        MultisigAddress::new(set.threshold, set.pubkeys)
+    }
+}
+
+/// One signer's share of a multisig spend.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct PartialSignature {
+    /// Position of the signing key in `MultisigAddress::pubkeys`.
+    pub key: u8,
+    /// Hex-encoded 64-byte compact signature.
+    pub signature: String,
+}
+
+impl MultisigAddress {
+    pub fn new(threshold: u8, pubkeys: Vec<String>) -> Result<Self, String> {
+        if pubkeys.len() > MAX_KEYS {
+            return Err(format!("At most {} keys are allowed", MAX_KEYS));
+        }
+        if threshold == 0 || threshold as usize > pubkeys.len() {
+            return Err(format!("Threshold must be between 1 and {}", pubkeys.len()));
+        }
+        let mut key_bytes = Vec::with_capacity(pubkeys.len() * PUBKEY_SIZE);
+        for (i, pubkey) in pubkeys.iter().enumerate() {
+            let bytes =
+                hex::decode(pubkey).map_err(|_| format!("Invalid public key {}", pubkey))?;
+            if bytes.len() != PUBKEY_SIZE || PublicKey::from_sec1_bytes(&bytes).is_err() {
+                return Err(format!("Invalid public key {}", pubkey));
+            }
+            if pubkeys[..i].contains(pubkey) {
+                return Err(format!("Duplicate public key {}", pubkey));
+            }
+            key_bytes.extend_from_slice(&bytes);
+        }
+        Ok(MultisigAddress {
+            threshold,
+            pubkeys,
+            key_bytes,
+        })
+    }
+
+    pub fn threshold(&self) -> u8 {
+        self.threshold
+    }
+
+    /// Hex-encoded compressed public keys, in address order.
+    pub fn pubkeys(&self) -> &[String] {
+        &self.pubkeys
+    }
+
+    /// The public keys decoded, in address order.
+    pub fn keys(&self) -> impl Iterator<Item = &[u8]> {
+        self.key_bytes.chunks(PUBKEY_SIZE)
+    }
+
+    /// SHA-256 over the threshold, the key count and the keys in order, the
+    /// same hash the node checks.
+    pub fn redeem_hash(&self) -> [u8; 32] {
+        redeem_hash(self.threshold, &self.key_bytes)
+    }
+
+    pub fn address(&self) -> String {
+        address::encode(&self.redeem_hash())
+    }
+
+    /// Length of the finalized signature as hex, for sizing the fee before
+    /// any share exists.
+    pub fn signature_len(&self) -> usize {
+        let keys = self.pubkeys.len() * PUBKEY_SIZE;
+        let shares = self.threshold as usize * (1 + ECDSA_SIZE);
+        2 * (2 + keys + shares)
+    }
+
+    /// Checks one share against the key it claims to come from.
+    pub fn verify_partial(&self, data: &[u8], partial: &PartialSignature) -> Result<(), String> {
+        let pubkey = self
+            .keys()
+            .nth(partial.key as usize)
+            .ok_or("Partial signature for an unknown key")?;
+        let signature = hex::decode(&partial.signature).map_err(|_| "Malformed signature")?;
+        verify_compact(pubkey, data, &signature)
+    }
+
+    /// Combines the first `threshold` shares, by key order, into the
+    /// signature a transaction carries:
+    ///
+    /// ```text
+    /// threshold (1) | n (1) | n public keys (33 each) | threshold × (key index (1) | signature (64))
+    /// ```
+    pub fn finalize(&self, data: &[u8], partials: &[PartialSignature]) -> Result<String, String> {
+        let mut partials = partials.to_vec();
+        partials.sort_by_key(|partial| partial.key);
+        partials.dedup_by_key(|partial| partial.key);
+        if partials.len() < self.threshold as usize {
+            return Err(format!(
+                "Need {} signatures, have {}",
+                self.threshold,
+                partials.len()
+            ));
+        }
+
+        // `new` allows at most `MAX_KEYS`, so the count fits its byte.
+        let mut bytes = vec![self.threshold, self.pubkeys.len() as u8];
+        bytes.extend_from_slice(&self.key_bytes);
+        for partial in &partials[..self.threshold as usize] {
+            self.verify_partial(data, partial)?;
+            let signature = hex::decode(&partial.signature).map_err(|_| "Malformed signature")?;
+            bytes.push(partial.key);
+            bytes.extend_from_slice(&signature);
+        }
+        Ok(hex::encode(bytes))
+    }
+}
+
+fn redeem_hash(threshold: u8, pubkeys: &[u8]) -> [u8; 32] {
+    let mut data = vec![threshold, (pubkeys.len() / PUBKEY_SIZE) as u8];
+    data.extend_from_slice(pubkeys);
+    Sha256::digest(&data).into()
+}
+
+/// Checks a finalized multisig signature, mirroring the node.
+pub fn verify(hash: &[u8; 32], data: &[u8], bytes: &[u8]) -> Result<(), String> {
+    let malformed = || "Malformed multisig signature".to_string();
+    let (&threshold, rest) = bytes.split_first().ok_or_else(malformed)?;
+    let (&count, rest) = rest.split_first().ok_or_else(malformed)?;
+    let (threshold, count) = (threshold as usize, count as usize);
+    if threshold == 0 || threshold > count || count > MAX_KEYS {
+        return Err(malformed());
+    }
+    if rest.len() != count * PUBKEY_SIZE + threshold * (1 + ECDSA_SIZE) {
+        return Err(malformed());
+    }
+
+    let (pubkeys, signatures) = rest.split_at(count * PUBKEY_SIZE);
+    if redeem_hash(threshold as u8, pubkeys) != *hash {
+        return Err("Multisig keys do not match the sender address".to_string());
+    }
+    let pubkeys: Vec<&[u8]> = pubkeys.chunks(PUBKEY_SIZE).collect();
+
+    let mut next_key = 0;
+    for signature in signatures.chunks(1 + ECDSA_SIZE) {
+        let key = signature[0] as usize;
+        if key < next_key || key >= count {
+            return Err("Multisig signatures out of key order".to_string());
+        }
+        verify_compact(pubkeys[key], data, &signature[1..])?;
+        next_key = key + 1;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KEY: &str = "0279be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798";
+    const OTHER_KEY: &str = "02c6047f9441ed7d6d3045406e95c07cd85c778e4b8cef3ca7abac09b95c709ee5";
+
+    fn parse(threshold: u8, pubkeys: &[&str]) -> Result<MultisigAddress, serde_json::Error> {
+        serde_json::from_value(serde_json::json!({
+            "threshold": threshold,
+            "pubkeys": pubkeys,
+        }))
+    }
+
+    #[test]
+    fn reads_back_what_it_writes() {
+        let multisig = MultisigAddress::new(1, vec![KEY.into(), OTHER_KEY.into()]).unwrap();
+        let json = serde_json::to_string(&multisig).unwrap();
+        let read: MultisigAddress = serde_json::from_str(&json).unwrap();
+        assert_eq!(read.address(), multisig.address());
+        assert_eq!(read.keys().count(), 2);
+    }
+
+    #[test]
+    fn checks_key_sets_it_reads() {
+        assert!(parse(1, &["zz"]).is_err());
+        assert!(parse(1, &[&KEY[..64]]).is_err());
+        assert!(parse(2, &[KEY, KEY]).is_err());
+        assert!(parse(0, &[KEY]).is_err());
+        assert!(parse(3, &[KEY, OTHER_KEY]).is_err());
+        assert!(parse(1, &[KEY; MAX_KEYS + 1]).is_err());
+        assert!(parse(2, &[KEY, OTHER_KEY]).is_ok());
+    }
+
+    #[test]
+    fn refuses_a_malformed_share() {
+        let multisig = MultisigAddress::new(1, vec![KEY.into()]).unwrap();
+        let share = |key, signature: &str| PartialSignature {
+            key,
+            signature: signature.to_string(),
+        };
+        assert!(multisig.finalize(b"data", &[share(0, "zz")]).is_err());
+        assert!(multisig.finalize(b"data", &[share(5, "00")]).is_err());
+    }
+}
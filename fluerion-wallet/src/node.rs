@@ -0,0 +1,317 @@
+use crate::chainparams::params;
+use crate::transaction::Transaction;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::io;
+use std::sync::atomic::{AtomicBool, Ordering};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+pub const HISTORY_PAGE_SIZE: usize = 10;
+
+/// A block as the node serves it, less the hash it claims: the wallet
+/// goes by `calculate_hash` instead.
+#[derive(Deserialize)]
+pub struct Block {
+    pub timestamp: u64,
+    pub prev_block_hash: [u8; 32],
+    pub transactions: Vec<Transaction>,
+    pub nonce: u64,
+}
+
+impl Block {
+    /// SHA-256 of the 80-byte header the node hashes: timestamp, previous
+    /// hash, transaction root and nonce. Any change to the transactions
+    /// changes the root and so the hash.
+    pub fn calculate_hash(&self) -> [u8; 32] {
+        let mut header = Vec::with_capacity(80);
+        header.extend_from_slice(&self.timestamp.to_le_bytes());
+        header.extend_from_slice(&self.prev_block_hash);
+        header.extend_from_slice(&self.transaction_root());
+        header.extend_from_slice(&self.nonce.to_le_bytes());
+        Sha256::digest(&header).into()
+    }
+
+    /// SHA-256 over every transaction's signing data and signature, in
+    /// order.
+    pub fn transaction_root(&self) -> [u8; 32] {
+        let mut data = Vec::new();
+        for tx in &self.transactions {
+            data.extend_from_slice(&tx.signing_data());
+            if let Some(signature) = &tx.signature {
+                data.extend_from_slice(signature.as_bytes());
+            }
+        }
+        Sha256::digest(&data).into()
+    }
+}
+
+#[derive(Deserialize)]
+pub struct BlockFilter {
+    pub height: u64,
+    pub block_hash: String,
+    pub filter: String,
+}
+
+#[derive(Deserialize)]
+pub struct BlockFilters {
+    pub filters: Vec<BlockFilter>,
+}
+
+#[derive(Deserialize)]
+pub struct FilterHeaders {
+    pub tip: u64,
+    pub prev_header: String,
+    pub headers: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct Balance {
+    pub address: String,
+    pub confirmed: f64,
+    pub unconfirmed: f64,
+    /// Confirmed amount less what pending transactions already spend.
+    pub spendable: f64,
+    /// The nonce the address's next transaction must carry.
+    pub next_nonce: u64,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct HistoryEntry {
+    pub transaction: Transaction,
+    pub height: Option<u64>,
+    pub confirmations: u64,
+    /// "received", "sent" or "self".
+    pub direction: String,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct DataMatch {
+    pub transaction: Transaction,
+    pub height: Option<u64>,
+    pub confirmations: u64,
+}
+
+#[derive(Deserialize)]
+pub struct DataMatches {
+    pub transactions: Vec<DataMatch>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct TransactionInfo {
+    pub transaction: Transaction,
+    pub in_mempool: bool,
+    pub block_hash: Option<String>,
+    pub height: Option<u64>,
+    pub position: Option<usize>,
+    pub confirmations: u64,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct TokenInfo {
+    pub id: String,
+    pub name: String,
+    pub symbol: String,
+    pub decimals: u8,
+    pub issuer: String,
+    pub mintable: bool,
+    pub supply: u64,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct TokenBalance {
+    pub token: String,
+    pub symbol: String,
+    pub decimals: u8,
+    pub confirmed: u64,
+    pub unconfirmed: i128,
+}
+
+#[derive(Deserialize)]
+pub struct TokenBalances {
+    pub balances: Vec<TokenBalance>,
+}
+
+#[derive(Deserialize)]
+struct NetworkInfo {
+    network: String,
+    magic: String,
+}
+
+#[derive(Deserialize)]
+struct FeeEstimate {
+    fee_rate: f64,
+}
+
+#[derive(Deserialize)]
+pub struct History {
+    pub total: usize,
+    pub transactions: Vec<HistoryEntry>,
+}
+
+/// The node answers one command per connection, so every request opens its
+/// own stream and reads until the node hangs up.
+pub struct NodeClient {
+    address: String,
+    /// Whether the node has been found to be on the wallet's network.
+    checked: AtomicBool,
+}
+
+impl NodeClient {
+    pub fn new(address: String) -> Self {
+        NodeClient {
+            address,
+            checked: AtomicBool::new(false),
+        }
+    }
+
+    /// Sends one command, first making sure the node is on the wallet's
+    /// network so nothing is ever sent to the wrong chain.
+    pub async fn request(&self, message: &str) -> io::Result<String> {
+        if !self.checked.load(Ordering::Relaxed) {
+            self.check_network().await?;
+        }
+        self.exchange(message).await
+    }
+
+    async fn check_network(&self) -> io::Result<()> {
+        let response = self.exchange("GET_NETWORK").await?;
+        let info: NetworkInfo = serde_json::from_str(&response)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        let params = params();
+        if info.magic != hex::encode(params.magic) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "Node {} is on {}, not {}",
+                    self.address, info.network, params.network
+                ),
+            ));
+        }
+        self.checked.store(true, Ordering::Relaxed);
+        Ok(())
+    }
+
+    async fn exchange(&self, message: &str) -> io::Result<String> {
+        let mut stream = TcpStream::connect(&self.address).await?;
+        stream
+            .write_all(format!("{}\n", message).as_bytes())
+            .await?;
+
+        let mut response = Vec::new();
+        stream.read_to_end(&mut response).await?;
+        Ok(String::from_utf8_lossy(&response).into_owned())
+    }
+
+    /// Sends a query whose answer is JSON, turning the node's `Error:`
+    /// replies into errors.
+    async fn query<T: serde::de::DeserializeOwned>(&self, message: &str) -> io::Result<T> {
+        let response = self.request(message).await?;
+        if let Some(e) = response.strip_prefix("Error: ") {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, e));
+        }
+        serde_json::from_str(&response).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    pub async fn balance(&self, address: &str) -> io::Result<Balance> {
+        self.query(&format!("GET_BALANCE:{}", address)).await
+    }
+
+    /// The nonce the node expects on the next transaction from `address`.
+    pub async fn next_nonce(&self, address: &str) -> io::Result<u64> {
+        Ok(self.balance(address).await?.next_nonce)
+    }
+
+    /// Hands a transaction to the node's mempool. Returns the node's reason
+    /// as an `InvalidInput` error when it turns the transaction down.
+    pub async fn send_transaction(&self, transaction: &Transaction) -> io::Result<()> {
+        let message = format!("NEW_TRANSACTION:{}", serde_json::to_string(transaction)?);
+        let response = self.request(&message).await?;
+        if response == "Transaction added" {
+            Ok(())
+        } else {
+            Err(io::Error::new(io::ErrorKind::InvalidInput, response))
+        }
+    }
+
+    /// Fee rate, per 1000 bytes, the node expects to get a transaction mined
+    /// within `target_blocks` blocks.
+    pub async fn estimate_fee(&self, target_blocks: usize) -> io::Result<f64> {
+        let estimate: FeeEstimate = self
+            .query(&format!("ESTIMATE_FEE:{}", target_blocks))
+            .await?;
+        Ok(estimate.fee_rate)
+    }
+
+    pub async fn history(&self, address: &str, offset: usize, limit: usize) -> io::Result<History> {
+        self.query(&format!("GET_HISTORY:{}:{}:{}", address, offset, limit))
+            .await
+    }
+
+    /// Transactions whose data payload is exactly the hex `data`.
+    pub async fn find_data(&self, data: &str) -> io::Result<DataMatches> {
+        self.query(&format!("FIND_DATA:{}", data)).await
+    }
+
+    /// The transaction with the hex id `txid`, pending or confirmed.
+    pub async fn transaction(&self, txid: &str) -> io::Result<TransactionInfo> {
+        self.query(&format!("GETTRANSACTION:{}", txid)).await
+    }
+
+    pub async fn token(&self, id: &str) -> io::Result<TokenInfo> {
+        self.query(&format!("GET_TOKEN:{}", id)).await
+    }
+
+    pub async fn token_balances(&self, address: &str) -> io::Result<TokenBalances> {
+        self.query(&format!("GET_TOKEN_BALANCES:{}", address)).await
+    }
+
+    pub async fn height(&self) -> io::Result<u64> {
+        self.query("GET_HEIGHT").await
+    }
+
+    pub async fn block(&self, height: u64) -> io::Result<Block> {
+        self.query(&format!("GET_BLOCK:{}", height)).await
+    }
+
+    /// Compact filters from `start`; the node may return fewer than asked.
+    pub async fn filters(&self, start: u64) -> io::Result<BlockFilters> {
+        self.query(&format!("GET_CFILTERS:{}", start)).await
+    }
+
+    /// Filter headers from `start`; the node may return fewer than asked.
+    pub async fn filter_headers(&self, start: u64) -> io::Result<FilterHeaders> {
+        self.query(&format!("GET_CFHEADERS:{}", start)).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Block 1 of a regtest chain, as the node serves it.
+    const BLOCK: &str = r#"{"timestamp":1792386967,"prev_block_hash":[14,108,67,134,122,173,184,129,241,161,191,15,142,112,42,156,193,70,170,214,240,120,195,41,69,127,50,116,96,78,192,204],"hash":[2,19,213,67,136,46,62,87,93,193,128,108,219,249,115,13,140,244,34,124,254,60,148,250,234,210,237,250,146,210,84,153],"transactions":[{"sender":"Coinbase","receiver":"flrt14sdcqezufk9g8pnn0sve5yk2wqfllsg02qxxv2","amount":50.0,"fee":0.0,"timestamp":1792386967,"nonce":1,"signature":"0000000000000000"}],"nonce":8}"#;
+
+    const HASH: &str = "0213d543882e3e575dc1806cdbf9730d8cf4227cfe3c94faead2edfa92d25499";
+
+    #[test]
+    fn hashes_a_block_as_the_node_does() {
+        let block: Block = serde_json::from_str(BLOCK).unwrap();
+        assert_eq!(hex::encode(block.calculate_hash()), HASH);
+    }
+
+    #[test]
+    fn other_transactions_change_the_hash() {
+        let mut block: Block = serde_json::from_str(BLOCK).unwrap();
+        block.transactions[0].amount = 5000.0;
+        assert_ne!(hex::encode(block.calculate_hash()), HASH);
+    }
+
+    #[test]
+    fn hashes_match_the_node() {
+        let block: Block = serde_json::from_str(BLOCK).unwrap();
+        let node: fluerion::block::Block = serde_json::from_str(BLOCK).unwrap();
+        assert_eq!(block.calculate_hash(), node.calculate_hash());
+        assert_eq!(block.transaction_root(), node.transaction_root());
+    }
+}
@@ -0,0 +1,95 @@
+use crate::multisig::{MultisigAddress, PartialSignature};
+use crate::transaction::Transaction;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+
+pub const FORMAT_VERSION: u32 = 1;
+
+/// A transaction on its way between an online watch-only wallet and an
+/// offline signer. A single-key spend names the account and address index
+/// of the signing key, so the signer can derive it without scanning its
+/// addresses. A multisig spend carries the key set instead and collects one
+/// partial signature per signer until it can be finalized.
+///
+/// Files hold it as JSON; on the command line it travels as base64 of the
+/// same JSON.
+#[derive(Serialize, Deserialize)]
+pub struct PortableTransaction {
+    pub version: u32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub account: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub index: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub multisig: Option<MultisigAddress>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub partial_signatures: Vec<PartialSignature>,
+    pub transaction: Transaction,
+}
+
+impl PortableTransaction {
+    pub fn new(transaction: Transaction, account: u32, index: u32) -> Self {
+        PortableTransaction {
+            version: FORMAT_VERSION,
+            account: Some(account),
+            index: Some(index),
+            multisig: None,
+            partial_signatures: Vec::new(),
+            transaction,
+        }
+    }
+
+    pub fn new_multisig(transaction: Transaction, multisig: MultisigAddress) -> Self {
+        PortableTransaction {
+            version: FORMAT_VERSION,
+            account: None,
+            index: None,
+            multisig: Some(multisig),
+            partial_signatures: Vec::new(),
+            transaction,
+        }
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).unwrap()
+    }
+
+    pub fn to_base64(&self) -> String {
+        BASE64.encode(serde_json::to_vec(self).unwrap())
+    }
+
+    /// Accepts either form, so a file may also carry the base64 blob.
+    pub fn parse(input: &str) -> Result<Self, String> {
+        let input = input.trim();
+        let json = if input.starts_with('{') {
+            input.as_bytes().to_vec()
+        } else {
+            BASE64
+                .decode(input)
+                .map_err(|_| "Transaction is neither JSON nor base64".to_string())?
+        };
+        let portable: PortableTransaction =
+            serde_json::from_slice(&json).map_err(|e| format!("Invalid transaction: {}", e))?;
+        if portable.version != FORMAT_VERSION {
+            return Err(format!(
+                "Unsupported transaction format {} (expected {})",
+                portable.version, FORMAT_VERSION
+            ));
+        }
+        Ok(portable)
+    }
+
+    pub fn read(path: &Path) -> Result<Self, String> {
+        let contents = fs::read_to_string(path)
+            .map_err(|e| format!("Cannot read {}: {}", path.display(), e))?;
+        Self::parse(&contents)
+    }
+
+    pub fn write(&self, path: &Path) -> Result<(), String> {
+        fs::write(path, self.to_json())
+            .map_err(|e| format!("Cannot write {}: {}", path.display(), e))
+    }
+}
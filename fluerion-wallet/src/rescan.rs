@@ -0,0 +1,144 @@
+use crate::filter;
+use crate::node::NodeClient;
+use crate::transaction::Transaction;
+use std::io;
+
+struct Filter {
+    height: u64,
+    block_hash: [u8; 32],
+    bytes: Vec<u8>,
+}
+
+/// The compact filters of every block from a start height to the tip, each
+/// checked against the chain of filter headers the node commits to.
+pub struct Filters {
+    pub start: u64,
+    pub tip: u64,
+    filters: Vec<Filter>,
+}
+
+impl Filters {
+    pub async fn download(client: &NodeClient, start: u64) -> io::Result<Self> {
+        let invalid = |message: String| io::Error::new(io::ErrorKind::InvalidData, message);
+
+        let first = client.filter_headers(start).await?;
+        if start > first.tip {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("Start height {} is past the tip {}", start, first.tip),
+            ));
+        }
+        let tip = first.tip;
+        let mut prev_header = decode_hash(&first.prev_header).map_err(invalid)?;
+        let mut headers = first.headers;
+        while (headers.len() as u64) < tip - start + 1 {
+            let more = client.filter_headers(start + headers.len() as u64).await?;
+            if more.headers.is_empty() {
+                return Err(invalid("Node stopped serving filter headers".to_string()));
+            }
+            headers.extend(more.headers);
+        }
+        headers.truncate((tip - start + 1) as usize);
+
+        let mut filters = Vec::with_capacity(headers.len());
+        while filters.len() < headers.len() {
+            let height = start + filters.len() as u64;
+            let batch = client.filters(height).await?;
+            if batch.filters.is_empty() {
+                return Err(invalid("Node stopped serving filters".to_string()));
+            }
+            for served in batch.filters {
+                let i = filters.len();
+                if i == headers.len() {
+                    break;
+                }
+                let bytes = hex::decode(&served.filter).map_err(|_| {
+                    invalid(format!("Malformed filter at height {}", served.height))
+                })?;
+                let header = filter::header(&bytes, &prev_header);
+                if served.height != start + i as u64 || hex::encode(header) != headers[i] {
+                    return Err(invalid(format!(
+                        "Filter at height {} does not match its header",
+                        served.height
+                    )));
+                }
+                prev_header = header;
+                filters.push(Filter {
+                    height: served.height,
+                    block_hash: decode_hash(&served.block_hash).map_err(invalid)?,
+                    bytes,
+                });
+            }
+        }
+        Ok(Filters {
+            start,
+            tip,
+            filters,
+        })
+    }
+
+    /// Heights of the blocks whose filter matches any of `addresses`.
+    pub fn matching(&self, addresses: &[&str]) -> Vec<u64> {
+        self.filters
+            .iter()
+            .filter(|f| filter::matches_any(&f.bytes, &f.block_hash, addresses))
+            .map(|f| f.height)
+            .collect()
+    }
+
+    /// Whether `address` probably appears in a block. Good enough for
+    /// address discovery, where a rare false positive only means one
+    /// address too many.
+    pub fn contains(&self, address: &str) -> bool {
+        self.filters
+            .iter()
+            .any(|f| filter::matches_any(&f.bytes, &f.block_hash, &[address]))
+    }
+}
+
+pub struct Found {
+    pub height: u64,
+    pub transaction: Transaction,
+}
+
+/// Downloads only the blocks whose filters match and returns the
+/// transactions in them that touch `addresses`, oldest first.
+pub async fn rescan(
+    client: &NodeClient,
+    filters: &Filters,
+    addresses: &[String],
+) -> io::Result<(Vec<u64>, Vec<Found>)> {
+    let items: Vec<&str> = addresses.iter().map(String::as_str).collect();
+    let heights = filters.matching(&items);
+
+    let mut found = Vec::new();
+    for &height in &heights {
+        // The hash is recomputed from the block's own contents, so a node
+        // cannot serve other transactions under the hash the filter names.
+        let block = client.block(height).await?;
+        if block.calculate_hash() != filters.filters[(height - filters.start) as usize].block_hash {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Block at height {} does not match its filter", height),
+            ));
+        }
+        found.extend(
+            block
+                .transactions
+                .into_iter()
+                .filter(|tx| addresses.contains(&tx.sender) || addresses.contains(&tx.receiver))
+                .map(|transaction| Found {
+                    height,
+                    transaction,
+                }),
+        );
+    }
+    Ok((heights, found))
+}
+
+fn decode_hash(hex: &str) -> Result<[u8; 32], String> {
+    hex::decode(hex)
+        .ok()
+        .and_then(|bytes| bytes.try_into().ok())
+        .ok_or_else(|| format!("Malformed hash {}", hex))
+}
@@ -0,0 +1,103 @@
+use serde::{Deserialize, Serialize};
+
+/// Most decimal places a token may have; must match the node.
+pub const MAX_DECIMALS: u8 = 18;
+
+/// A token operation, laid out exactly as the node signs it. Tokens are
+/// named by the hex `token_id` of the transaction that issued them.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(tag = "op", rename_all = "lowercase")]
+pub enum TokenOp {
+    Issue {
+        name: String,
+        symbol: String,
+        decimals: u8,
+        supply: u64,
+        #[serde(default)]
+        mintable: bool,
+    },
+    Transfer {
+        token: String,
+        amount: u64,
+    },
+    Mint {
+        token: String,
+        amount: u64,
+    },
+    Burn {
+        token: String,
+        amount: u64,
+    },
+}
+
+impl TokenOp {
+    pub fn signing_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        let (kind, token, amount) = match self {
+            TokenOp::Issue {
+                name,
+                symbol,
+                decimals,
+                supply,
+                mintable,
+            } => {
+                bytes.push(1);
+                bytes.push(name.len() as u8);
+                bytes.extend_from_slice(name.as_bytes());
+                bytes.push(symbol.len() as u8);
+                bytes.extend_from_slice(symbol.as_bytes());
+                bytes.push(*decimals);
+                bytes.extend_from_slice(&supply.to_le_bytes());
+                bytes.push(*mintable as u8);
+                return bytes;
+            }
+            TokenOp::Transfer { token, amount } => (2, token, amount),
+            TokenOp::Mint { token, amount } => (3, token, amount),
+            TokenOp::Burn { token, amount } => (4, token, amount),
+        };
+        bytes.push(kind);
+        bytes.extend_from_slice(token.as_bytes());
+        bytes.extend_from_slice(&amount.to_le_bytes());
+        bytes
+    }
+}
+
+/// Converts a decimal amount such as `12.5` into base units of a token
+/// with `decimals` places, refusing anything it cannot represent exactly.
+pub fn parse_units(amount: &str, decimals: u8) -> Result<u64, String> {
+    let invalid = || format!("Invalid token amount {}", amount);
+    let (whole, fraction) = amount.split_once('.').unwrap_or((amount, ""));
+    if whole.is_empty() && fraction.is_empty()
+        || !whole
+            .chars()
+            .chain(fraction.chars())
+            .all(|c| c.is_ascii_digit())
+    {
+        return Err(invalid());
+    }
+    if fraction.len() > decimals as usize {
+        return Err(format!(
+            "Token amount {} has more than {} decimal places",
+            amount, decimals
+        ));
+    }
+    let digits = format!("{}{:0<width$}", whole, fraction, width = decimals as usize);
+    digits.parse().map_err(|_| invalid())
+}
+
+/// Formats base units with the token's decimal places.
+pub fn format_units(units: u128, decimals: u8) -> String {
+    let digits = format!("{:0>width$}", units, width = decimals as usize + 1);
+    let (whole, fraction) = digits.split_at(digits.len() - decimals as usize);
+    if fraction.is_empty() {
+        whole.to_string()
+    } else {
+        format!("{}.{}", whole, fraction)
+    }
+}
+
+/// Formats a signed change in base units, always with its sign.
+pub fn format_change(units: i128, decimals: u8) -> String {
+    let sign = if units < 0 { '-' } else { '+' };
+    format!("{}{}", sign, format_units(units.unsigned_abs(), decimals))
+}
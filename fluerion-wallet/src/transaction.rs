@@ -0,0 +1,273 @@
+use crate::address::{self, Payload};
+use crate::keys::{self, KeyPair};
+use crate::token::TokenOp;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+/// Most bytes a transaction's `data` payload may carry.
+pub const MAX_DATA_SIZE: usize = 80;
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Transaction {
+    pub sender: String,
+    pub receiver: String,
+    pub amount: f64,
+    #[serde(default)]
+    pub fee: f64,
+    pub timestamp: u64,
+    /// How many transactions the sender sent before this one; the node
+    /// takes each nonce once, in order.
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub nonce: u64,
+    /// Hex locking script of a script-address sender; the signature then
+    /// holds the unlocking script.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub script: Option<String>,
+    /// Earliest block height, or below 500000000 a Unix time, the
+    /// transaction may be mined at.
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub lock_time: u64,
+    /// Blocks that must pass after the sender last received funds.
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub relative_lock: u64,
+    /// Hex payload of up to `MAX_DATA_SIZE` bytes, signed with the rest.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub data: Option<String>,
+    /// Token operation carried alongside the coin amount, which may then be
+    /// zero.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub token: Option<TokenOp>,
+    pub signature: Option<String>,
+}
+
+/// Optional fields to put on a transaction being built: timelocks, where
+/// zero leaves it unlocked, a hex data payload and a token operation.
+#[derive(Debug, Default, Clone)]
+pub struct Extras {
+    pub lock_time: u64,
+    pub relative_lock: u64,
+    pub data: Option<String>,
+    pub token: Option<TokenOp>,
+}
+
+impl Transaction {
+    pub fn new(sender: String, receiver: String, amount: f64, fee: f64) -> Self {
+        let timestamp = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+
+        Transaction {
+            sender,
+            receiver,
+            amount,
+            fee,
+            timestamp,
+            nonce: 0,
+            script: None,
+            lock_time: 0,
+            relative_lock: 0,
+            data: None,
+            token: None,
+            signature: None,
+        }
+    }
+
+    /// The bytes a sender signs, laid out exactly as the node checks them:
+    /// every field except the signature itself.
+    pub fn signing_data(&self) -> Vec<u8> {
+        let mut data = Vec::new();
+        data.extend_from_slice(self.sender.as_bytes());
+        data.extend_from_slice(self.receiver.as_bytes());
+        data.extend_from_slice(&self.amount.to_le_bytes());
+        data.extend_from_slice(&self.fee.to_le_bytes());
+        data.extend_from_slice(&self.timestamp.to_le_bytes());
+        if self.nonce != 0 {
+            push_field(&mut data, FIELD_NONCE, &self.nonce.to_le_bytes());
+        }
+        if let Some(script) = &self.script {
+            push_field(&mut data, FIELD_SCRIPT, script.as_bytes());
+        }
+        if self.lock_time != 0 {
+            push_field(&mut data, FIELD_LOCK_TIME, &self.lock_time.to_le_bytes());
+        }
+        if self.relative_lock != 0 {
+            push_field(
+                &mut data,
+                FIELD_RELATIVE_LOCK,
+                &self.relative_lock.to_le_bytes(),
+            );
+        }
+        if let Some(payload) = &self.data {
+            push_field(&mut data, FIELD_DATA, payload.as_bytes());
+        }
+        if let Some(token) = &self.token {
+            push_field(&mut data, FIELD_TOKEN, &token.signing_bytes());
+        }
+        data
+    }
+
+    pub fn set_extras(&mut self, extras: Extras) {
+        self.lock_time = extras.lock_time;
+        self.relative_lock = extras.relative_lock;
+        self.data = extras.data;
+        self.token = extras.token;
+    }
+
+    /// The fee that pays `fee_rate` per 1000 bytes once the transaction
+    /// carries a signature of `signature_len` characters, rounded up to the
+    /// smallest unit so it never falls short of the rate. A nonce still
+    /// unset is counted as if it were, since it is only known once the
+    /// paying address is.
+    pub fn fee_for(&self, fee_rate: f64, signature_len: usize) -> f64 {
+        let mut size = self.signing_data().len() + signature_len;
+        if self.nonce == 0 {
+            size += NONCE_SIZE;
+        }
+        (fee_rate * size as f64 / 1000.0 * 1e8).ceil() / 1e8
+    }
+
+    /// The id the node gives the transaction once signed, hex.
+    pub fn id(&self) -> String {
+        let mut data = self.signing_data();
+        if let Some(signature) = &self.signature {
+            data.extend_from_slice(signature.as_bytes());
+        }
+        hex::encode(Sha256::digest(&data))
+    }
+
+    /// The id the node gives a token the transaction issues, hex: the hash
+    /// of the signed bytes alone, so it is known before signing.
+    pub fn token_id(&self) -> String {
+        hex::encode(Sha256::digest(self.signing_data()))
+    }
+
+    pub fn sign(&mut self, key_pair: &KeyPair) -> Result<(), String> {
+        if key_pair.address() != self.sender {
+            return Err(format!("Key does not belong to sender {}", self.sender));
+        }
+        self.signature = Some(key_pair.sign(&self.signing_data()));
+        Ok(())
+    }
+
+    /// The same check the node applies before accepting the transaction.
+    /// For a script spend only the script's hash is checked here; the node
+    /// runs it.
+    pub fn verify(&self) -> Result<(), String> {
+        address::decode(&self.receiver)?;
+        let signature = self
+            .signature
+            .as_deref()
+            .ok_or("Transaction is not signed")?;
+        if let Some(script) = &self.script {
+            let script = hex::decode(script).map_err(|_| "Malformed script")?;
+            let hash: [u8; 32] = Sha256::digest(&script).into();
+            return match address::decode(&self.sender)? {
+                Payload::ScriptHash(expected) if expected == hash => Ok(()),
+                _ => Err("Script does not match the sender address".to_string()),
+            };
+        }
+        keys::verify(&self.sender, &self.signing_data(), signature)
+    }
+}
+
+/// Tags the node gives the optional fields in the signing data.
+const FIELD_SCRIPT: u8 = 1;
+const FIELD_LOCK_TIME: u8 = 2;
+const FIELD_RELATIVE_LOCK: u8 = 3;
+const FIELD_DATA: u8 = 4;
+const FIELD_TOKEN: u8 = 5;
+const FIELD_NONCE: u8 = 6;
+
+/// Bytes a nonce adds to the signing data once it is not zero.
+const NONCE_SIZE: usize = 1 + 4 + 8;
+
+/// Writes an optional field as its tag, its length and its contents.
+fn push_field(data: &mut Vec<u8>, tag: u8, bytes: &[u8]) {
+    data.push(tag);
+    data.extend_from_slice(&(bytes.len() as u32).to_le_bytes());
+    data.extend_from_slice(bytes);
+}
+
+fn is_zero(value: &u64) -> bool {
+    *value == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SENDER: &str = "flrt14sdcqezufk9g8pnn0sve5yk2wqfllsg02qxxv2";
+    const RECEIVER: &str = "flrt13v0g8knefk035awswd2pvthk4wsd3w55kgxdzx";
+
+    fn transaction() -> Transaction {
+        let mut tx = Transaction::new(SENDER.to_string(), RECEIVER.to_string(), 1.5, 0.01);
+        tx.timestamp = 1_792_384_545;
+        tx.signature = Some("ab".repeat(97));
+        tx
+    }
+
+    /// Every combination of optional fields the wallet can set.
+    fn variants() -> Vec<Transaction> {
+        let token = |json: serde_json::Value| Some(serde_json::from_value(json).unwrap());
+        let mut variants = vec![transaction()];
+        let mut push = |change: &dyn Fn(&mut Transaction)| {
+            let mut tx = transaction();
+            change(&mut tx);
+            variants.push(tx);
+        };
+        push(&|tx| tx.nonce = 4);
+        push(&|tx| tx.script = Some("51".to_string()));
+        push(&|tx| tx.lock_time = 120);
+        push(&|tx| tx.relative_lock = 6);
+        push(&|tx| tx.data = Some("deadbeef".to_string()));
+        push(&|tx| {
+            tx.token = token(serde_json::json!({
+                "op": "issue", "name": "Gold", "symbol": "GLD",
+                "decimals": 2, "supply": 1000, "mintable": true,
+            }))
+        });
+        for op in ["transfer", "mint", "burn"] {
+            push(&|tx| {
+                tx.token = token(serde_json::json!({
+                    "op": op, "token": "00".repeat(32), "amount": 7,
+                }))
+            });
+        }
+        push(&|tx| {
+            tx.script = Some("51".to_string());
+            tx.lock_time = 120;
+            tx.relative_lock = 6;
+            tx.data = Some("deadbeef".to_string());
+        });
+        variants
+    }
+
+    fn on_node(tx: &Transaction) -> fluerion::transaction::Transaction {
+        serde_json::from_str(&serde_json::to_string(tx).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn signs_the_bytes_the_node_checks() {
+        for tx in variants() {
+            assert_eq!(tx.signing_data(), on_node(&tx).signing_data());
+        }
+    }
+
+    #[test]
+    fn ids_match_the_node() {
+        for tx in variants() {
+            assert_eq!(tx.id(), hex::encode(on_node(&tx).id()));
+        }
+    }
+
+    #[test]
+    fn token_ids_match_the_node() {
+        for tx in variants() {
+            assert_eq!(tx.token_id(), hex::encode(on_node(&tx).token_id()));
+            let mut resigned = tx.clone();
+            resigned.signature = Some("cd".repeat(97));
+            assert_eq!(resigned.token_id(), tx.token_id());
+        }
+    }
+}
@@ -0,0 +1,314 @@
+use crate::address;
+use crate::chainparams::params;
+use crate::hd::{Account, HdWallet};
+use crate::keys::{KeyPair, PUBKEY_SIZE};
+use crate::keystore::{AccountRecord, Keystore, Secret, WatchedAddress};
+use crate::multisig::MultisigAddress;
+use k256::PublicKey;
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+/// A keystore opened for use. Addresses are always available from the
+/// account public keys; the decrypted seed is kept only between `unlock`
+/// and `lock`, or until `auto_lock` passes without it being used.
+pub struct Wallet {
+    keystore: Keystore,
+    pub accounts: Vec<Account>,
+    unlocked: Option<(HdWallet, Instant)>,
+    auto_lock: Duration,
+}
+
+impl Wallet {
+    /// Encrypts a freshly generated or restored seed into a new wallet file.
+    pub fn create(
+        path: &Path,
+        hd: HdWallet,
+        accounts: Vec<Account>,
+        mnemonic_passphrase: &str,
+        passphrase: &str,
+        auto_lock: Duration,
+    ) -> Result<Self, String> {
+        let secret = Secret {
+            mnemonic: hd.phrase().to_string(),
+            passphrase: mnemonic_passphrase.to_string(),
+        };
+        let keystore = Keystore::create(path, &secret, passphrase, records(&accounts))?;
+        Ok(Wallet {
+            keystore,
+            accounts,
+            unlocked: Some((hd, Instant::now())),
+            auto_lock,
+        })
+    }
+
+    pub fn create_watch_only(
+        path: &Path,
+        accounts: Vec<Account>,
+        auto_lock: Duration,
+    ) -> Result<Self, String> {
+        let keystore = Keystore::create_watch_only(path, records(&accounts))?;
+        Ok(Wallet {
+            keystore,
+            accounts,
+            unlocked: None,
+            auto_lock,
+        })
+    }
+
+    /// Opens an existing wallet file in the locked state.
+    pub fn open(path: &Path, auto_lock: Duration) -> Result<Self, String> {
+        let keystore = Keystore::load(path)?;
+        if keystore.network != params().network {
+            return Err(format!(
+                "Wallet {} is a {} wallet, not {}",
+                path.display(),
+                keystore.network,
+                params().network
+            ));
+        }
+        let accounts = keystore
+            .accounts
+            .iter()
+            .map(|record| Account::from_xpub(record.index, &record.xpub, record.address_count))
+            .collect::<Result<Vec<_>, _>>()?;
+        if accounts.is_empty() {
+            return Err("Wallet file has no accounts".to_string());
+        }
+        Ok(Wallet {
+            keystore,
+            accounts,
+            unlocked: None,
+            auto_lock,
+        })
+    }
+
+    pub fn unlock(&mut self, passphrase: &str) -> Result<(), String> {
+        let secret = self.keystore.decrypt(passphrase)?;
+        let hd = HdWallet::from_phrase(&secret.mnemonic, &secret.passphrase)?;
+        self.unlocked = Some((hd, Instant::now()));
+        Ok(())
+    }
+
+    pub fn is_watch_only(&self) -> bool {
+        self.keystore.is_watch_only()
+    }
+
+    pub fn lock(&mut self) {
+        self.unlocked = None;
+    }
+
+    pub fn is_locked(&mut self) -> bool {
+        self.seed().is_none()
+    }
+
+    /// The decrypted seed, unless the wallet is locked or has sat idle past
+    /// the auto-lock timeout. Each use restarts the timer.
+    fn seed(&mut self) -> Option<&HdWallet> {
+        if let Some((_, last_used)) = &self.unlocked {
+            if last_used.elapsed() >= self.auto_lock {
+                self.unlocked = None;
+            }
+        }
+        self.unlocked.as_mut().map(|(hd, last_used)| {
+            *last_used = Instant::now();
+            &*hd
+        })
+    }
+
+    pub fn phrase(&mut self) -> Result<String, String> {
+        Ok(self.seed().ok_or("Wallet is locked")?.phrase().to_string())
+    }
+
+    pub fn key_pair(&mut self, account: u32, index: u32) -> Result<KeyPair, String> {
+        Ok(self
+            .seed()
+            .ok_or("Wallet is locked")?
+            .key_pair(account, index))
+    }
+
+    /// Adding an account needs the seed, since accounts use hardened
+    /// derivation.
+    pub fn add_account(&mut self) -> Result<u32, String> {
+        let index = self.accounts.len() as u32;
+        let account = self.seed().ok_or("Wallet is locked")?.account(index);
+        self.accounts.push(account);
+        self.save()?;
+        Ok(index)
+    }
+
+    pub fn new_address(&mut self, account: usize) -> Result<String, String> {
+        let address = self.accounts[account].new_address();
+        self.save()?;
+        Ok(address)
+    }
+
+    pub fn multisig_addresses(&self) -> &[MultisigAddress] {
+        &self.keystore.multisig
+    }
+
+    pub fn find_multisig(&self, address: &str) -> Option<&MultisigAddress> {
+        self.keystore
+            .multisig
+            .iter()
+            .find(|multisig| multisig.address() == address)
+    }
+
+    pub fn add_multisig(&mut self, multisig: MultisigAddress) -> Result<(), String> {
+        if self.find_multisig(&multisig.address()).is_none() {
+            self.keystore.multisig.push(multisig);
+            self.save()?;
+        }
+        Ok(())
+    }
+
+    /// The account and index of one of this wallet's addresses.
+    pub fn find_address(&self, address: &str) -> Option<(u32, u32)> {
+        self.accounts.iter().find_map(|account| {
+            (0..account.address_count)
+                .find(|&index| account.address(index) == address)
+                .map(|index| (account.index, index))
+        })
+    }
+
+    pub fn birthday(&self) -> u64 {
+        self.keystore.birthday
+    }
+
+    pub fn set_birthday(&mut self, height: u64) -> Result<(), String> {
+        self.keystore.birthday = height;
+        self.save()
+    }
+
+    /// Raises an account's address count after a rescan found later
+    /// addresses in use. Returns whether it grew.
+    pub fn extend_addresses(&mut self, account: usize, count: u32) -> Result<bool, String> {
+        if count <= self.accounts[account].address_count {
+            return Ok(false);
+        }
+        self.accounts[account].address_count = count;
+        self.save()?;
+        Ok(true)
+    }
+
+    pub fn watched_addresses(&self) -> &[WatchedAddress] {
+        &self.keystore.watched
+    }
+
+    pub fn is_watched(&self, address: &str) -> bool {
+        self.keystore
+            .watched
+            .iter()
+            .any(|watched| watched.address == address)
+    }
+
+    /// Starts following an address this wallet cannot spend from.
+    /// Importing it again only updates the label.
+    pub fn import_address(&mut self, address: &str, label: Option<String>) -> Result<(), String> {
+        address::decode(address)?;
+        self.import(address.to_string(), None, label)
+    }
+
+    /// Like `import_address` for the address of a compressed public key,
+    /// keeping the key. Returns the address.
+    pub fn import_pubkey(&mut self, pubkey: &str, label: Option<String>) -> Result<String, String> {
+        let bytes = hex::decode(pubkey).map_err(|_| "Public key is not hex")?;
+        if bytes.len() != PUBKEY_SIZE || PublicKey::from_sec1_bytes(&bytes).is_err() {
+            return Err("Not a compressed public key".to_string());
+        }
+        let address = address::encode(&address::pubkey_hash(&bytes));
+        self.import(address.clone(), Some(hex::encode(bytes)), label)?;
+        Ok(address)
+    }
+
+    fn import(
+        &mut self,
+        address: String,
+        pubkey: Option<String>,
+        label: Option<String>,
+    ) -> Result<(), String> {
+        if self.find_address(&address).is_some() || self.find_multisig(&address).is_some() {
+            return Err(format!("{} already belongs to this wallet", address));
+        }
+        match self
+            .keystore
+            .watched
+            .iter_mut()
+            .find(|watched| watched.address == address)
+        {
+            Some(watched) => {
+                watched.pubkey = pubkey.or(watched.pubkey.take());
+                watched.label = label.or(watched.label.take());
+            }
+            None => self.keystore.watched.push(WatchedAddress {
+                address,
+                pubkey,
+                label,
+            }),
+        }
+        self.save()
+    }
+
+    pub fn address_book(&self) -> &BTreeMap<String, String> {
+        &self.keystore.address_book
+    }
+
+    /// Saves `address` under `label`, replacing whatever the label held.
+    /// Labels may not look like addresses, so a receiver is never ambiguous.
+    pub fn set_contact(&mut self, label: &str, address: &str) -> Result<(), String> {
+        if label.trim().is_empty() {
+            return Err("Label cannot be empty".to_string());
+        }
+        if address::decode(label).is_ok() {
+            return Err("Label cannot be an address".to_string());
+        }
+        address::decode(address)?;
+        self.keystore
+            .address_book
+            .insert(label.to_string(), address.to_string());
+        self.save()
+    }
+
+    pub fn remove_contact(&mut self, label: &str) -> Result<String, String> {
+        let address = self
+            .keystore
+            .address_book
+            .remove(label)
+            .ok_or_else(|| format!("No contact named {}", label))?;
+        self.save()?;
+        Ok(address)
+    }
+
+    /// The address a receiver argument names: an address as given, or the
+    /// address saved under that label.
+    pub fn resolve_receiver(&self, receiver: &str) -> Result<String, String> {
+        if address::decode(receiver).is_ok() {
+            return Ok(receiver.to_string());
+        }
+        self.keystore
+            .address_book
+            .get(receiver)
+            .cloned()
+            .ok_or_else(|| format!("{} is neither an address nor a contact", receiver))
+    }
+
+    pub fn change_passphrase(&mut self, old: &str, new: &str) -> Result<(), String> {
+        self.keystore.change_passphrase(old, new)
+    }
+
+    pub fn save(&mut self) -> Result<(), String> {
+        self.keystore.accounts = records(&self.accounts);
+        self.keystore.save()
+    }
+}
+
+fn records(accounts: &[Account]) -> Vec<AccountRecord> {
+    accounts
+        .iter()
+        .map(|account| AccountRecord {
+            index: account.index,
+            xpub: account.xpub(),
+            address_count: account.address_count,
+        })
+        .collect()
+}
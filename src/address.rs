@@ -0,0 +1,60 @@
+use bech32::primitives::decode::CheckedHrpstring;
+use bech32::{Bech32m, Hrp};
+
+use crate::chainparams::params;
+use crate::hash::{sha256, Hash256};
+
+pub const PUBKEY_HASH_SIZE: usize = 20;
+pub const SCRIPT_HASH_SIZE: usize = 32;
+
+/// What an address commits to. The two kinds are told apart by the length
+/// of the payload.
+#[derive(Debug, PartialEq)]
+pub enum Payload {
+    /// A single key: the hash of its compressed public key.
+    PubkeyHash([u8; PUBKEY_HASH_SIZE]),
+    /// An m-of-n key set or a locking script: see `multisig::redeem_hash`
+    /// and `script::hash`. A spend that reveals a script is checked as one.
+    ScriptHash(Hash256),
+}
+
+/// Encodes a public key or script hash as a bech32m string with the
+/// network's prefix, e.g. `fl1...` on mainnet.
+pub fn encode(hash: &[u8]) -> String {
+    let hrp = Hrp::parse(params().address_prefix).expect("valid address prefix");
+    bech32::encode::<Bech32m>(hrp, hash).expect("address fits in bech32m")
+}
+
+/// Checks the prefix, bech32m checksum and length of an address and returns
+/// what it commits to.
+pub fn decode(address: &str) -> Result<Payload, String> {
+    let checked = CheckedHrpstring::new::<Bech32m>(address)
+        .map_err(|e| format!("Invalid address {}: {}", address, e))?;
+
+    let prefix = params().address_prefix;
+    if checked.hrp().to_lowercase() != prefix {
+        return Err(format!(
+            "Address {} is not for this network (expected prefix {})",
+            address, prefix
+        ));
+    }
+
+    let bytes: Vec<u8> = checked.byte_iter().collect();
+    match bytes.len() {
+        PUBKEY_HASH_SIZE => Ok(Payload::PubkeyHash(bytes.try_into().unwrap())),
+        SCRIPT_HASH_SIZE => Ok(Payload::ScriptHash(bytes.try_into().unwrap())),
+        _ => Err(format!("Invalid address {}: wrong length", address)),
+    }
+}
+
+/// The hash an address commits to: the first 20 bytes of the SHA-256 of a
+/// compressed public key.
+pub fn pubkey_hash(pubkey: &[u8]) -> [u8; PUBKEY_HASH_SIZE] {
+    let mut hash = [0u8; PUBKEY_HASH_SIZE];
+    hash.copy_from_slice(&sha256(pubkey)[..PUBKEY_HASH_SIZE]);
+    hash
+}
+
+pub fn validate(address: &str) -> Result<(), String> {
+    decode(address).map(|_| ())
+}
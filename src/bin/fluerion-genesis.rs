@@ -0,0 +1,105 @@
+use fluerion::chainparams::{self, ChainParams, Network};
+use fluerion::clock;
+use fluerion::genesis::{Allocation, Genesis};
+use std::env;
+use std::fs;
+use std::path::Path;
+
+const USAGE: &str = "Usage: fluerion-genesis <file> [--network mainnet|testnet|regtest]
+                        [--message <text>] [--difficulty <n>]
+                        [--allocate <address>=<amount>]...
+
+Mines a genesis block for a private network and writes its description,
+nonce and hash to <file>. A new file starts from the network's own genesis
+stamped with the current time; an existing one is read, changed by the
+flags and mined again. Start every node of the network with
+--network <network> --genesis <file>.";
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    if let Err(e) = run(&args) {
+        eprintln!("{}\n{}", e, USAGE);
+        std::process::exit(1);
+    }
+}
+
+fn run(args: &[String]) -> Result<(), String> {
+    let mut path = None;
+    let mut network = Network::Mainnet;
+    let mut message = None;
+    let mut difficulty = None;
+    let mut allocations = Vec::new();
+    let mut i = 1;
+    while i < args.len() {
+        let flag = args[i].as_str();
+        let value = args.get(i + 1).map(String::as_str);
+        let required = || value.ok_or_else(|| format!("{} needs a value", flag));
+        match flag {
+            "--network" => network = required()?.parse()?,
+            "--message" => message = Some(required()?.to_string()),
+            "--difficulty" => {
+                difficulty = Some(
+                    required()?
+                        .parse()
+                        .map_err(|_| format!("Invalid value for {}", flag))?,
+                )
+            }
+            "--allocate" => allocations.push(parse_allocation(required()?)?),
+            _ if flag.starts_with("--") => return Err(format!("Unknown argument: {}", flag)),
+            _ if path.is_none() => {
+                path = Some(flag.to_string());
+                i += 1;
+                continue;
+            }
+            _ => return Err(format!("Unexpected argument: {}", flag)),
+        }
+        i += 2;
+    }
+    let path = path.ok_or("Missing genesis file")?;
+
+    // Allocation addresses are checked against the network's prefix.
+    chainparams::select(ChainParams::for_network(network))?;
+    let mut genesis = if Path::new(&path).exists() {
+        Genesis::from_file(&path)?
+    } else {
+        Genesis {
+            timestamp: clock::now(),
+            allocations: Vec::new(),
+            ..ChainParams::for_network(network).genesis
+        }
+    };
+    if let Some(message) = message {
+        genesis.message = message;
+    }
+    if let Some(difficulty) = difficulty {
+        genesis.difficulty = difficulty;
+    }
+    genesis.allocations.extend(allocations);
+
+    let block = genesis.mine()?;
+    fs::write(&path, genesis.to_toml()?).map_err(|e| format!("Cannot write {}: {}", path, e))?;
+    println!(
+        "Genesis {} for {} written to {}",
+        genesis.hash, network, path
+    );
+    println!(
+        "Nonce {}, {} transactions",
+        genesis.nonce,
+        block.get_transactions().len()
+    );
+    Ok(())
+}
+
+/// Parses `<address>=<amount>`.
+fn parse_allocation(value: &str) -> Result<Allocation, String> {
+    let (address, amount) = value
+        .split_once('=')
+        .ok_or_else(|| format!("Allocation {} must be <address>=<amount>", value))?;
+    let amount = amount
+        .parse()
+        .map_err(|_| format!("Invalid amount in allocation {}", value))?;
+    Ok(Allocation {
+        address: address.to_string(),
+        amount,
+    })
+}
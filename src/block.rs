@@ -1,9 +1,14 @@
 use serde::{Deserialize, Serialize};
 
-use crate::hash::Hash256;
+use crate::clock;
+use crate::genesis::{Genesis, GENESIS_SENDER};
+use crate::hash::{sha256, Hash256};
 use crate::transaction::Transaction;
 
-#[derive(Debug, Serialize, Deserialize)]
+pub const HEADER_SIZE: usize = 80;
+pub const NONCE_OFFSET: usize = 72;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Block {
     timestamp: u64,
     prev_block_hash: Hash256,
@@ -12,12 +17,19 @@ pub struct Block {
     nonce: u64,
 }
 
+/// A block without its transactions: what it takes to link, order and look
+/// up blocks.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct BlockHeader {
+    pub hash: Hash256,
+    pub prev_block_hash: Hash256,
+    pub timestamp: u64,
+    pub nonce: u64,
+}
+
 impl Block {
     pub fn new(transactions: Vec<Transaction>, prev_block_hash: Hash256) -> Self {
-        let timestamp = std::time::SystemTime::now()
-            .duration_since(std::time::UNIX_EPOCH)
-            .unwrap()
-            .as_secs();
+        let timestamp = clock::now();
 
         let mut block = Block {
             timestamp,
@@ -31,19 +43,69 @@ impl Block {
         block
     }
 
+    /// Serializes the header as timestamp, previous hash, transaction root and
+    /// nonce, the same 80-byte layout `fluerion-miner` hashes.
+    pub fn header(&self) -> [u8; HEADER_SIZE] {
+        let mut header = [0u8; HEADER_SIZE];
+        header[0..8].copy_from_slice(&self.timestamp.to_le_bytes());
+        header[8..40].copy_from_slice(&self.prev_block_hash);
+        header[40..72].copy_from_slice(&self.transaction_root());
+        header[NONCE_OFFSET..].copy_from_slice(&self.nonce.to_le_bytes());
+        header
+    }
+
+    pub fn transaction_root(&self) -> Hash256 {
+        let mut data = Vec::new();
+        for tx in &self.transactions {
+            data.extend_from_slice(&tx.signing_data());
+            if let Some(signature) = &tx.signature {
+                data.extend_from_slice(signature.as_bytes());
+            }
+        }
+        sha256(&data)
+    }
+
     pub fn calculate_hash(&self) -> Hash256 {
-        let data = self
-            .transactions
-            .iter()
-            .map(|tx| tx.to_string())
-            .collect::<Vec<String>>()
-            .join(", ");
-        crate::hash::calculate_hash(&self.timestamp.to_string(), &self.prev_block_hash, &data)
+        sha256(&self.header())
     }
 
-    pub fn genesis() -> Self {
-        let genesis_tx = Transaction::new("Genesis".to_string(), "Genesis".to_string(), 0.0);
-        Block::new(vec![genesis_tx], [0; 32])
+    /// The network's first block. Everything in it comes from `genesis`,
+    /// so every node on the network builds the same one: a transaction
+    /// carrying the message, then one per allocation.
+    pub fn genesis(genesis: &Genesis) -> Self {
+        let mut message_tx =
+            Transaction::new(GENESIS_SENDER.to_string(), GENESIS_SENDER.to_string(), 0.0);
+        message_tx.data = Some(hex::encode(&genesis.message));
+        let allocations = genesis.allocations.iter().map(|allocation| {
+            Transaction::new(
+                GENESIS_SENDER.to_string(),
+                allocation.address.clone(),
+                allocation.amount,
+            )
+        });
+        let mut transactions: Vec<Transaction> =
+            std::iter::once(message_tx).chain(allocations).collect();
+        for tx in &mut transactions {
+            tx.timestamp = genesis.timestamp;
+        }
+        let mut block = Block {
+            timestamp: genesis.timestamp,
+            prev_block_hash: [0; 32],
+            hash: [0; 32],
+            transactions,
+            nonce: genesis.nonce,
+        };
+        block.hash = block.calculate_hash();
+        block
+    }
+
+    pub fn block_header(&self) -> BlockHeader {
+        BlockHeader {
+            hash: self.hash,
+            prev_block_hash: self.prev_block_hash,
+            timestamp: self.timestamp,
+            nonce: self.nonce,
+        }
     }
 
     pub fn get_hash(&self) -> Hash256 {
@@ -62,6 +124,10 @@ impl Block {
         &self.transactions
     }
 
+    pub fn set_timestamp(&mut self, timestamp: u64) {
+        self.timestamp = timestamp;
+    }
+
     pub fn set_nonce(&mut self, nonce: u64) {
         self.nonce = nonce;
     }
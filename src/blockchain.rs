@@ -1,80 +1,704 @@
-use crate::block::Block;
-use crate::hash::{calculate_hash, hash_to_hex, Hash256};
-use crate::transaction::Transaction;
-use serde_json; // Added this import
+use crate::block::{Block, BlockHeader, NONCE_OFFSET};
+use crate::chainparams::{self, ChainParams};
+use crate::clock;
+use crate::filter;
+use crate::genesis::GENESIS_SENDER;
+use crate::hash::{hash_to_hex, meets_difficulty, sha256, Hash256};
+use crate::script::{self, Context};
+use crate::store::{ChainState, ChainStore, MemoryStore};
+use crate::token::{Ledger, Token};
+use crate::transaction::{Transaction, COINBASE_SENDER};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
 
-const DIFFICULTY: usize = 4; // Number of leading zeros required in the hash
+/// Most transaction bytes a block may hold, so that fees decide what gets in
+/// when the mempool outgrows a block.
+pub const MAX_BLOCK_SIZE: usize = 100_000;
+
+/// Lowest fee rate, per 1000 bytes, the mempool accepts. Also the floor of
+/// every fee estimate.
+pub const MIN_FEE_RATE: f64 = 0.0001;
+
+/// How many recent blocks fee estimation looks back over.
+pub const FEE_HISTORY_BLOCKS: usize = 20;
+
+/// Furthest confirmation target `estimate_fee` accepts.
+pub const MAX_FEE_TARGET: usize = 100;
+
+/// How many blocks the median time past is taken over.
+pub const MEDIAN_TIME_SPAN: usize = 11;
+
+/// How far, in seconds, a new block's timestamp may run ahead of the
+/// node's clock.
+pub const MAX_FUTURE_BLOCK_TIME: u64 = 2 * 60 * 60;
+
+#[derive(Debug, Serialize)]
+pub struct Balance {
+    pub address: String,
+    /// Net amount from transactions already in blocks.
+    pub confirmed: f64,
+    /// Net amount from transactions still waiting in the mempool.
+    pub unconfirmed: f64,
+    /// What a new transaction from the address may spend: the confirmed
+    /// amount less what its pending transactions already spend.
+    pub spendable: f64,
+    /// The nonce a new transaction from the address must carry.
+    pub next_nonce: u64,
+}
+
+/// Native balances by address, kept the way `Ledger` keeps token
+/// balances, along with the coinbase payments that have not matured yet
+/// and how many transactions each address has sent.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Funds {
+    balances: HashMap<String, f64>,
+    nonces: HashMap<String, u64>,
+    /// Coinbase payments of the last `coinbase_maturity` blocks, as the
+    /// height, receiver and amount.
+    coinbases: Vec<(u64, String, f64)>,
+}
+
+impl Funds {
+    pub fn balance(&self, address: &str) -> f64 {
+        self.balances.get(address).copied().unwrap_or(0.0)
+    }
+
+    /// Coinbase payments to `address` a transaction in the block at
+    /// `height` cannot spend yet.
+    fn immature(&self, address: &str, height: u64) -> f64 {
+        let maturity = chainparams::params().coinbase_maturity;
+        self.coinbases
+            .iter()
+            .filter(|(paid, receiver, _)| receiver == address && paid + maturity > height)
+            .map(|(_, _, amount)| amount)
+            .sum()
+    }
+
+    /// The nonce the next transaction from `address` must carry: the
+    /// number it has sent so far.
+    pub fn nonce(&self, address: &str) -> u64 {
+        self.nonces.get(address).copied().unwrap_or(0)
+    }
+
+    /// Whether `transaction` is the next one its sender may send. Each
+    /// nonce is taken once, so a transaction cannot be replayed.
+    fn in_sequence(&self, transaction: &Transaction) -> bool {
+        transaction.nonce == self.nonce(&transaction.sender)
+    }
+
+    /// What `address` may spend in the block at `height`.
+    pub fn spendable(&self, address: &str, height: u64) -> f64 {
+        self.balance(address) - self.immature(address, height)
+    }
+
+    /// Whether the sender can spend the amount and fee `transaction` moves
+    /// in the block at `height`. Balances are floats, so allow for rounding.
+    fn covers(&self, transaction: &Transaction, height: u64) -> bool {
+        transaction.amount + transaction.fee <= self.spendable(&transaction.sender, height) + 1e-9
+    }
+
+    /// Takes the amount and fee off the sender and uses up its nonce; a
+    /// coinbase or genesis payment has no sender to take them from.
+    fn debit(&mut self, transaction: &Transaction) {
+        if transaction.sender != COINBASE_SENDER && transaction.sender != GENESIS_SENDER {
+            *self.balances.entry(transaction.sender.clone()).or_default() -=
+                transaction.amount + transaction.fee;
+            *self.nonces.entry(transaction.sender.clone()).or_default() += 1;
+        }
+    }
+
+    /// Applies `transaction` from the block at `height`.
+    fn apply(&mut self, transaction: &Transaction, height: u64) {
+        self.debit(transaction);
+        *self
+            .balances
+            .entry(transaction.receiver.clone())
+            .or_default() += transaction.amount;
+        if transaction.sender == COINBASE_SENDER {
+            let maturity = chainparams::params().coinbase_maturity;
+            self.coinbases
+                .retain(|(paid, _, _)| paid + maturity > height);
+            self.coinbases
+                .push((height, transaction.receiver.clone(), transaction.amount));
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Direction {
+    Received,
+    Sent,
+    /// Sender and receiver are the same address.
+    #[serde(rename = "self")]
+    ToSelf,
+}
+
+#[derive(Debug, Serialize)]
+pub struct HistoryEntry {
+    pub transaction: Transaction,
+    /// `None` while the transaction is still pending.
+    pub height: Option<u64>,
+    pub confirmations: u64,
+    pub direction: Direction,
+}
+
+#[derive(Debug, Serialize)]
+pub struct History {
+    pub address: String,
+    /// Number of matching transactions across all pages.
+    pub total: usize,
+    pub offset: usize,
+    pub transactions: Vec<HistoryEntry>,
+}
+
+/// A transaction carrying a searched-for data payload.
+#[derive(Debug, Serialize)]
+pub struct DataMatch {
+    pub transaction: Transaction,
+    /// `None` while the transaction is still pending.
+    pub height: Option<u64>,
+    pub confirmations: u64,
+}
+
+/// A transaction looked up by id.
+#[derive(Debug, Serialize)]
+pub struct TransactionInfo {
+    pub transaction: Transaction,
+    /// Whether it is still waiting in the mempool.
+    pub in_mempool: bool,
+    /// The block confirming it; `None` while it is pending, as are
+    /// `height` and `position`.
+    pub block_hash: Option<String>,
+    pub height: Option<u64>,
+    /// Where it sits among the block's transactions.
+    pub position: Option<usize>,
+    pub confirmations: u64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TokenBalance {
+    pub token: String,
+    pub symbol: String,
+    pub decimals: u8,
+    /// Base units held as of the tip.
+    pub confirmed: u64,
+    /// Net change from transactions still waiting in the mempool.
+    pub unconfirmed: i128,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TokenBalances {
+    pub address: String,
+    pub balances: Vec<TokenBalance>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct BlockFilter {
+    pub height: u64,
+    pub block_hash: String,
+    /// Hex of the filter bytes, see `filter::build`.
+    pub filter: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct FilterHeaders {
+    pub tip: u64,
+    pub start: u64,
+    /// Header of the filter just before `start`, so the first one can be
+    /// checked too; all zeros for the genesis block.
+    pub prev_header: String,
+    pub headers: Vec<String>,
+}
 
 pub struct Blockchain {
-    blocks: Vec<Block>,
+    /// Blocks of the active chain with their filters and indexes, and the
+    /// chain state below, as of the last change.
+    store: Box<dyn ChainStore>,
+    /// Tokens and token balances as of the tip.
+    tokens: Ledger,
+    /// Native balances as of the tip.
+    funds: Funds,
     pending_transactions: Vec<Transaction>,
-    difficulty: usize,
+    /// Blocks `invalidate_block` marked invalid; neither they nor anything
+    /// built on them goes back on the chain.
+    invalid: HashSet<Hash256>,
+    /// Blocks taken off the chain, kept in case their branch becomes the
+    /// longest valid one again.
+    side_blocks: Vec<Block>,
+    /// Whether confirmed transactions are indexed by id in the store.
+    txindex: bool,
+    params: &'static ChainParams,
+    template_version: Arc<AtomicU64>,
+}
+
+impl Default for Blockchain {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Blockchain {
+    /// A chain holding just the selected network's genesis block, kept in
+    /// memory.
     pub fn new() -> Self {
-        Blockchain {
-            blocks: vec![Block::genesis()],
+        Self::open(Box::new(MemoryStore::default()), false).unwrap()
+    }
+
+    /// The chain in `store`, which gets the selected network's genesis
+    /// block if it is empty. A store holding another network's chain is
+    /// refused. With `txindex`, the transaction index is kept up to date,
+    /// and built first if the store does not hold one for the tip.
+    pub fn open(store: Box<dyn ChainStore>, txindex: bool) -> Result<Self, String> {
+        let params = chainparams::params();
+        let mut blockchain = Blockchain {
+            store,
+            tokens: Ledger::default(),
+            funds: Funds::default(),
             pending_transactions: Vec::new(),
-            difficulty: DIFFICULTY,
+            invalid: HashSet::new(),
+            side_blocks: Vec::new(),
+            txindex,
+            params,
+            template_version: Arc::new(AtomicU64::new(0)),
+        };
+        let genesis = Block::genesis(&params.genesis);
+        match blockchain.store.header(0)? {
+            None => blockchain.push_block(genesis),
+            Some(header) if header.hash != genesis.get_hash() => {
+                return Err(format!(
+                    "Chain store starts from genesis {}, not {}'s {}",
+                    hash_to_hex(&header.hash),
+                    params.network,
+                    hash_to_hex(&genesis.get_hash())
+                ))
+            }
+            Some(_) => {}
+        }
+
+        let state = blockchain.store.chain_state()?.unwrap_or_default();
+        blockchain.invalid = state.invalid.into_iter().collect();
+        blockchain.side_blocks = state.side_blocks;
+        let current = state.tip == blockchain.tip().hash;
+        match state.funds {
+            Some(funds) if current => {
+                blockchain.tokens = state.tokens;
+                blockchain.funds = funds;
+            }
+            _ => blockchain.replay_blocks(),
+        }
+        if txindex && !(current && state.txindex) {
+            blockchain.rebuild_txindex()?;
+        }
+        blockchain.save_state();
+        Ok(blockchain)
+    }
+
+    /// Adds a validated block to the store, with its filter chained onto
+    /// the tip's, and applies its payments and token operations.
+    fn push_block(&mut self, block: Block) {
+        let prev_header = match self.block_count() {
+            0 => [0; 32],
+            count => self.stored_filter(count - 1).1,
+        };
+        let block_filter = filter::build(&block);
+        let header = filter::header(&block_filter, &prev_header);
+        if let Err(e) = self.store.push_block(&block, &block_filter, &header) {
+            store_failed(e);
+        }
+        let height = self.block_count() - 1;
+        if self.txindex {
+            if let Err(e) = self.store.index_transactions(height, &block) {
+                store_failed(e);
+            }
+        }
+        for tx in block.get_transactions() {
+            // Blocks are validated before they get here, so this applies.
+            let _ = self.tokens.apply(tx);
+            self.funds.apply(tx, height);
+        }
+    }
+
+    /// Replays every block's payments and token operations from scratch.
+    fn replay_blocks(&mut self) {
+        let mut tokens = Ledger::default();
+        let mut funds = Funds::default();
+        for (height, block) in self.blocks().enumerate() {
+            for tx in block.get_transactions() {
+                let _ = tokens.apply(tx);
+                funds.apply(tx, height as u64);
+            }
+        }
+        self.tokens = tokens;
+        self.funds = funds;
+    }
+
+    /// Indexes every block's transactions from scratch.
+    fn rebuild_txindex(&mut self) -> Result<(), String> {
+        self.store.clear_transaction_index()?;
+        for height in 0..self.block_count() {
+            let block = self.stored_block(height);
+            self.store.index_transactions(height, &block)?;
+        }
+        Ok(())
+    }
+
+    /// Writes the chain state for the current tip.
+    fn save_state(&mut self) {
+        let state = ChainState {
+            tip: self.tip().hash,
+            tokens: self.tokens.clone(),
+            funds: Some(self.funds.clone()),
+            invalid: self.invalid.iter().copied().collect(),
+            side_blocks: self.side_blocks.clone(),
+            txindex: self.txindex,
+        };
+        if let Err(e) = self.store.set_chain_state(&state) {
+            store_failed(e);
         }
     }
 
-    pub fn add_transaction(&mut self, transaction: Transaction) {
+    fn block_count(&self) -> u64 {
+        self.stored(self.store.block_count())
+    }
+
+    /// The block at `height`, which must be on the chain.
+    fn stored_block(&self, height: u64) -> Block {
+        self.stored(self.store.block(height))
+            .unwrap_or_else(|| store_failed(format!("Block {} is missing", height)))
+    }
+
+    /// The header at `height`, which must be on the chain.
+    fn stored_header(&self, height: u64) -> BlockHeader {
+        self.stored(self.store.header(height))
+            .unwrap_or_else(|| store_failed(format!("Header {} is missing", height)))
+    }
+
+    /// The compact filter and filter header at `height`, which must be on
+    /// the chain.
+    fn stored_filter(&self, height: u64) -> (Vec<u8>, Hash256) {
+        self.stored(self.store.filter(height))
+            .unwrap_or_else(|| store_failed(format!("Filter {} is missing", height)))
+    }
+
+    fn tip(&self) -> BlockHeader {
+        self.stored_header(self.height())
+    }
+
+    /// Every block on the chain, from genesis up.
+    fn blocks(&self) -> impl DoubleEndedIterator<Item = Block> + '_ {
+        (0..self.block_count()).map(|height| self.stored_block(height))
+    }
+
+    fn stored<T>(&self, result: Result<T, String>) -> T {
+        result.unwrap_or_else(|e| store_failed(e))
+    }
+
+    pub fn add_transaction(&mut self, transaction: Transaction) -> Result<(), String> {
+        transaction.validate(&self.mempool_context())?;
+        let next_nonce = self.next_nonce(&transaction.sender);
+        if transaction.nonce != next_nonce {
+            return Err(format!(
+                "Nonce {} is out of sequence; {} sends {} next",
+                transaction.nonce, transaction.sender, next_nonce
+            ));
+        }
+        self.check_relative_lock(
+            &transaction,
+            self.height() + 1,
+            self.pending_transactions.iter(),
+        )?;
+        self.mempool_ledger().apply(&transaction)?;
+        let spendable = self.spendable(&transaction.sender);
+        if transaction.amount + transaction.fee > spendable + 1e-9 {
+            return Err(format!(
+                "Insufficient funds: {} spendable, {} needed",
+                spendable,
+                transaction.amount + transaction.fee
+            ));
+        }
+        if let Some(locking) = transaction.locking_script()? {
+            let unlocking = hex::decode(transaction.signature.as_deref().unwrap_or_default())
+                .map_err(|_| "Malformed signature")?;
+            script::check_standard(&locking, &unlocking)?;
+        }
+        if transaction.fee_rate() < MIN_FEE_RATE {
+            return Err(format!(
+                "Fee below the minimum of {} per 1000 bytes",
+                MIN_FEE_RATE
+            ));
+        }
         self.pending_transactions.push(transaction);
+        self.template_changed();
+        Ok(())
+    }
+
+    /// The confirmed balance of `address` less coinbase payments that have
+    /// not matured by the next block and the amounts and fees its pending
+    /// transactions spend. Pending payments to it do not count until they
+    /// confirm.
+    pub fn spendable(&self, address: &str) -> f64 {
+        let pending: f64 = self
+            .pending_transactions
+            .iter()
+            .filter(|tx| tx.sender == address)
+            .map(|tx| tx.amount + tx.fee)
+            .sum();
+        self.funds.spendable(address, self.height() + 1) - pending
+    }
+
+    /// The nonce of `address`'s confirmed transactions plus those waiting
+    /// in the mempool, which must go into blocks in order.
+    pub fn next_nonce(&self, address: &str) -> u64 {
+        let pending = self
+            .pending_transactions
+            .iter()
+            .filter(|tx| tx.sender == address)
+            .count();
+        self.funds.nonce(address) + pending as u64
+    }
+
+    /// The token ledger once every pending transaction has gone through.
+    fn mempool_ledger(&self) -> Ledger {
+        let mut ledger = self.tokens.clone();
+        for tx in &self.pending_transactions {
+            let _ = ledger.apply(tx);
+        }
+        ledger
+    }
+
+    /// Transactions in the mempool are checked as if they went into the
+    /// next block.
+    fn mempool_context(&self) -> Context {
+        self.context_at(self.height() + 1)
+    }
+
+    fn context_at(&self, height: u64) -> Context {
+        Context {
+            height,
+            time: self.median_time_past(height),
+        }
+    }
+
+    /// Median timestamp of the `MEDIAN_TIME_SPAN` blocks below `height`.
+    /// Every block must be timestamped after the median below it, so the
+    /// median never goes back, and new blocks at most
+    /// `MAX_FUTURE_BLOCK_TIME` ahead of the clock, so it cannot be pushed
+    /// far ahead either.
+    pub fn median_time_past(&self, height: u64) -> u64 {
+        let end = height.min(self.block_count());
+        let start = end.saturating_sub(MEDIAN_TIME_SPAN as u64);
+        let mut times: Vec<u64> = (start..end)
+            .map(|height| self.stored_header(height).timestamp)
+            .collect();
+        times.sort();
+        times.get(times.len() / 2).copied().unwrap_or(0)
+    }
+
+    /// Checks `relative_lock` for a transaction going into the block at
+    /// `height` behind `earlier`, the transactions ahead of it in that block
+    /// or the mempool. Funds count from the last block that paid the
+    /// sender; any payment in `earlier` makes them brand new.
+    fn check_relative_lock<'a>(
+        &self,
+        transaction: &Transaction,
+        height: u64,
+        mut earlier: impl Iterator<Item = &'a Transaction>,
+    ) -> Result<(), String> {
+        if transaction.relative_lock == 0 {
+            return Ok(());
+        }
+        let sender = &transaction.sender;
+        if earlier.any(|tx| &tx.receiver == sender) {
+            return Err("Funds being spent are not confirmed yet".to_string());
+        }
+        let funded = (0..height.min(self.block_count()))
+            .rev()
+            .find(|&at| {
+                self.stored_block(at)
+                    .get_transactions()
+                    .iter()
+                    .any(|tx| &tx.receiver == sender)
+            })
+            .ok_or("Sender has no confirmed funds")?;
+        let age = height - funded;
+        if age < transaction.relative_lock {
+            return Err(format!(
+                "Funds being spent are {} blocks old; the relative lock needs {}",
+                age, transaction.relative_lock
+            ));
+        }
+        Ok(())
+    }
+
+    /// Bumped whenever the block a miner should be working on changes, so
+    /// miners can notice without taking the chain lock.
+    pub fn template_version(&self) -> Arc<AtomicU64> {
+        Arc::clone(&self.template_version)
     }
 
-    pub fn mine_pending_transactions(&mut self) {
-        let new_block = self.new_block();
-        self.blocks.push(new_block);
-        self.pending_transactions.clear();
+    fn template_changed(&self) {
+        self.template_version.fetch_add(1, Ordering::SeqCst);
     }
 
-    pub fn new_block(&self) -> Block {
-        let prev_block = self.get_latest_block();
-        let mut new_block = Block::new(self.pending_transactions.clone(), prev_block.get_hash());
+    pub fn params(&self) -> &'static ChainParams {
+        self.params
+    }
 
-        let (nonce, hash) = self.proof_of_work(&new_block);
-        new_block.set_nonce(nonce);
-        new_block.set_hash(hash);
+    /// Difficulty the next block must meet.
+    pub fn difficulty(&self) -> usize {
+        self.difficulty_at(self.block_count())
+    }
 
-        new_block
+    /// Difficulty of the block at `height`, at most one above the tip: the
+    /// genesis difficulty, retargeted at every multiple of
+    /// `retarget_interval` from the time the interval below it took.
+    fn difficulty_at(&self, height: u64) -> usize {
+        let mut difficulty = self.params.genesis.difficulty;
+        let Some(interval) = self.params.retarget_interval else {
+            return difficulty;
+        };
+        for boundary in (interval..=height).step_by(interval as usize) {
+            let first = self.stored_header(boundary - interval).timestamp;
+            let last = self.stored_header(boundary - 1).timestamp;
+            difficulty = self.params.retarget(difficulty, last.saturating_sub(first));
+        }
+        difficulty
     }
 
-    pub fn proof_of_work(&self, block: &Block) -> (u64, Hash256) {
-        let mut nonce = 0;
+    /// Pending transactions, highest fee rate first, as many as fit in a
+    /// block. Ties keep arrival order. Each sender's transactions go in
+    /// nonce order, so one waits for those before it however little they
+    /// pay. Transactions whose relative lock the ones ahead of them would
+    /// break, including the coinbase paying `miner`, wait for a later block,
+    /// as do token operations that depend on ones not selected yet and
+    /// spends their sender cannot cover.
+    fn select_transactions(&self, miner: Option<&str>) -> Vec<Transaction> {
+        let mut pending: Vec<&Transaction> = self.pending_transactions.iter().collect();
+        pending.sort_by(|a, b| b.fee_rate().total_cmp(&a.fee_rate()));
+
+        let height = self.height() + 1;
+        let coinbase = miner.map(|miner| Transaction::coinbase(miner.to_string(), 0.0, height, 0));
+        let mut ledger = self.tokens.clone();
+        let mut funds = self.funds.clone();
+        let mut size = 0;
+        let mut selected = Vec::new();
+        // Selecting one transaction can let a better-paying one from the
+        // same sender follow, so go round until nothing more fits.
         loop {
-            let hash = self.calculate_hash_with_nonce(block, nonce);
-            if self.valid_proof(&hash) {
-                return (nonce, hash);
+            let count = selected.len();
+            pending.retain(|&tx| {
+                let fits = size + tx.size() <= MAX_BLOCK_SIZE
+                    && self
+                        .check_relative_lock(tx, height, coinbase.iter().chain(&selected))
+                        .is_ok()
+                    && funds.in_sequence(tx)
+                    && funds.covers(tx, height)
+                    && ledger.apply(tx).is_ok();
+                if fits {
+                    funds.apply(tx, height);
+                    size += tx.size();
+                    selected.push(tx.clone());
+                }
+                !fits
+            });
+            if selected.len() == count {
+                return selected;
             }
-            nonce += 1;
         }
     }
 
-    fn calculate_hash_with_nonce(&self, block: &Block, nonce: u64) -> Hash256 {
-        let data = block
-            .get_transactions()
-            .iter()
-            .map(|tx| tx.to_string())
-            .collect::<Vec<String>>()
-            .join(", ");
-        calculate_hash(
-            &block.get_timestamp().to_string(),
-            &block.get_prev_hash(),
-            &format!("{}{}", data, nonce),
-        )
+    /// A block of the best-paying pending transactions on top of the tip, led
+    /// by a coinbase paying the block reward at its height and their fees to
+    /// `address`, timestamped now or just past the median time past if that
+    /// is later.
+    pub fn block_template(&self, address: &str, extra_nonce: u64) -> Block {
+        let selected = self.select_transactions(Some(address));
+        let fees: f64 = selected.iter().map(|tx| tx.fee).sum();
+        let height = self.height() + 1;
+        let reward = self.params.block_reward(height);
+        let coinbase =
+            Transaction::coinbase(address.to_string(), reward + fees, height, extra_nonce);
+        let mut transactions = vec![coinbase];
+        transactions.extend(selected);
+        self.next_block(transactions)
+    }
+
+    /// A block of `transactions` on top of the tip, timestamped now or, for
+    /// blocks mined within the same second as the ones below, just past
+    /// their median time.
+    fn next_block(&self, transactions: Vec<Transaction>) -> Block {
+        let mut block = Block::new(transactions, self.tip().hash);
+        let earliest = self.median_time_past(self.height() + 1) + 1;
+        if block.get_timestamp() < earliest {
+            block.set_timestamp(earliest);
+        }
+        block
     }
 
+    /// Fee rate, per 1000 bytes, a transaction should pay to be mined within
+    /// `target_blocks` blocks. It is the higher of two rates:
+    ///
+    /// - the rate it takes to outbid enough of the mempool to fit in the
+    ///   next `target_blocks` blocks, and
+    /// - the median of the lowest rate each of the last `FEE_HISTORY_BLOCKS`
+    ///   blocks accepted,
+    ///
+    /// and never below `MIN_FEE_RATE`.
+    pub fn estimate_fee(&self, target_blocks: usize) -> f64 {
+        let target_blocks = target_blocks.clamp(1, MAX_FEE_TARGET);
+
+        let mut pending: Vec<&Transaction> = self.pending_transactions.iter().collect();
+        pending.sort_by(|a, b| b.fee_rate().total_cmp(&a.fee_rate()));
+        let capacity = target_blocks * MAX_BLOCK_SIZE;
+        let mut size = 0;
+        let mut mempool_rate = 0.0;
+        for tx in pending {
+            size += tx.size();
+            if size > capacity {
+                mempool_rate = tx.fee_rate();
+                break;
+            }
+        }
+
+        let mut block_minimums: Vec<f64> = self
+            .blocks()
+            .rev()
+            .take(FEE_HISTORY_BLOCKS)
+            .filter_map(|block| {
+                block
+                    .get_transactions()
+                    .iter()
+                    .filter(|tx| tx.sender != COINBASE_SENDER)
+                    .map(Transaction::fee_rate)
+                    .min_by(f64::total_cmp)
+            })
+            .collect();
+        block_minimums.sort_by(f64::total_cmp);
+        let recent_rate = block_minimums
+            .get(block_minimums.len() / 2)
+            .copied()
+            .unwrap_or(0.0);
+
+        mempool_rate.max(recent_rate).max(MIN_FEE_RATE)
+    }
+
+    /// Whether `hash` meets the difficulty of the next block.
     pub fn valid_proof(&self, hash: &Hash256) -> bool {
-        let hash_str = hash_to_hex(hash);
-        hash_str.starts_with(&"0".repeat(self.difficulty))
+        meets_difficulty(hash, self.difficulty())
     }
 
     pub fn is_valid(&self) -> bool {
-        for i in 1..self.blocks.len() {
-            let current_block = &self.blocks[i];
-            let prev_block = &self.blocks[i - 1];
+        let mut ledger = Ledger::default();
+        let mut funds = Funds::default();
+        for tx in self.stored_block(0).get_transactions() {
+            funds.apply(tx, 0);
+        }
+        for i in 1..self.block_count() as usize {
+            let current_block = &self.stored_block(i as u64);
+            let prev_block = &self.stored_block(i as u64 - 1);
 
             if current_block.get_hash() != current_block.calculate_hash() {
                 return false;
@@ -84,19 +708,31 @@ impl Blockchain {
                 return false;
             }
 
-            if !self.valid_proof(&current_block.get_hash()) {
+            if !meets_difficulty(&current_block.get_hash(), self.difficulty_at(i as u64)) {
+                return false;
+            }
+
+            if !self.matches_checkpoint(i as u64, &current_block.get_hash()) {
+                return false;
+            }
+
+            if current_block.get_timestamp() <= self.median_time_past(i as u64) {
+                return false;
+            }
+
+            if !self.valid_transactions(current_block, i as u64, &mut ledger, &mut funds, true) {
                 return false;
             }
         }
         true
     }
 
-    pub fn get_latest_block(&self) -> &Block {
-        self.blocks.last().unwrap()
+    pub fn get_latest_block(&self) -> Block {
+        self.stored_block(self.height())
     }
 
     pub fn print_chain(&self) {
-        for (index, block) in self.blocks.iter().enumerate() {
+        for (index, block) in self.blocks().enumerate() {
             println!("Block #{}", index);
             println!("Timestamp: {}", block.get_timestamp());
             println!("Previous Hash: {}", hash_to_hex(&block.get_prev_hash()));
@@ -104,54 +740,563 @@ impl Blockchain {
             println!("Nonce: {}", block.get_nonce());
             println!("Transactions:");
             for tx in block.get_transactions() {
-                println!("  {}", tx.to_string());
+                println!("  {}", tx);
             }
             println!("------------------------");
         }
     }
 
+    pub fn height(&self) -> u64 {
+        self.block_count() - 1
+    }
+
+    pub fn block_at(&self, height: u64) -> Option<Block> {
+        self.stored(self.store.block(height))
+    }
+
+    /// Up to `count` filters from `start`, stopping at the tip.
+    pub fn block_filters(&self, start: u64, count: usize) -> Vec<BlockFilter> {
+        let end = self.block_count().min(start.saturating_add(count as u64));
+        (start..end)
+            .map(|height| {
+                let (block_filter, _) = self.stored_filter(height);
+                BlockFilter {
+                    height,
+                    block_hash: hash_to_hex(&self.stored_header(height).hash),
+                    filter: hex::encode(block_filter),
+                }
+            })
+            .collect()
+    }
+
+    /// Up to `count` filter headers from `start`, stopping at the tip.
+    pub fn filter_headers(&self, start: u64, count: usize) -> FilterHeaders {
+        let start = start.min(self.block_count());
+        let prev_header = match start {
+            0 => [0; 32],
+            _ => self.stored_filter(start - 1).1,
+        };
+        let end = self.block_count().min(start.saturating_add(count as u64));
+        FilterHeaders {
+            tip: self.height(),
+            start,
+            prev_header: hash_to_hex(&prev_header),
+            headers: (start..end)
+                .map(|height| hash_to_hex(&self.stored_filter(height).1))
+                .collect(),
+        }
+    }
+
     pub fn get_chain_json(&self) -> String {
-        serde_json::to_string(&self.blocks).unwrap()
+        serde_json::to_string(&self.blocks().collect::<Vec<_>>()).unwrap()
+    }
+
+    pub fn add_mined_block(&mut self, block: Block) -> bool {
+        if !self.is_valid_new_block(&block, true) {
+            return false;
+        }
+        self.connect_block(block);
+        true
+    }
+
+    /// Connects blocks downloaded during initial sync on top of the tip, in
+    /// order. When the assume-valid block is among them, it and the blocks
+    /// before it skip signature checks. Returns how many blocks were
+    /// connected and how many of those skipped the checks.
+    pub fn sync_blocks(&mut self, blocks: Vec<Block>) -> Result<(usize, usize), String> {
+        let assumed = self
+            .params
+            .assume_valid
+            .and_then(|assume_valid| {
+                blocks
+                    .iter()
+                    .position(|block| block.get_hash() == assume_valid)
+            })
+            .map_or(0, |i| i + 1);
+        let count = blocks.len();
+        for (i, block) in blocks.into_iter().enumerate() {
+            if !self.is_valid_new_block(&block, i >= assumed) {
+                return Err(format!(
+                    "Block {} at height {} is invalid",
+                    hash_to_hex(&block.get_hash()),
+                    self.block_count()
+                ));
+            }
+            self.connect_block(block);
+        }
+        Ok((count, assumed))
+    }
+
+    fn connect_block(&mut self, block: Block) {
+        let mined: Vec<Hash256> = block
+            .get_transactions()
+            .iter()
+            .map(Transaction::id)
+            .collect();
+        self.pending_transactions
+            .retain(|tx| !mined.contains(&tx.id()));
+        let hash = block.get_hash();
+        self.side_blocks.retain(|side| side.get_hash() != hash);
+        self.push_block(block);
+        // Drop transactions the block made impossible, such as a second
+        // spend of the same tokens, funds or nonce.
+        let mut ledger = self.tokens.clone();
+        let mut funds = self.funds.clone();
+        let height = self.height() + 1;
+        self.pending_transactions.retain(|tx| {
+            let keep =
+                funds.in_sequence(tx) && funds.covers(tx, height) && ledger.apply(tx).is_ok();
+            if keep {
+                funds.debit(tx);
+            }
+            keep
+        });
+        self.save_state();
+        self.template_changed();
+    }
+
+    /// Mines `count` blocks paying `address` on top of the tip right away,
+    /// which only makes sense where the proof of work is trivial.
+    pub fn generate(&mut self, count: u64, address: &str) -> Result<Vec<Hash256>, String> {
+        let mut hashes = Vec::new();
+        for _ in 0..count {
+            let mut block = self.block_template(address, 0);
+            let mut header = block.header();
+            let nonce = (0..u64::MAX)
+                .find(|nonce| {
+                    header[NONCE_OFFSET..].copy_from_slice(&nonce.to_le_bytes());
+                    self.valid_proof(&sha256(&header))
+                })
+                .ok_or("No nonce meets the difficulty")?;
+            block.set_nonce(nonce);
+            block.set_hash(block.calculate_hash());
+            let hash = block.get_hash();
+            if !self.add_mined_block(block) {
+                return Err("Generated block was rejected".to_string());
+            }
+            hashes.push(hash);
+        }
+        Ok(hashes)
+    }
+
+    /// Marks a block invalid and takes it and every block above it off the
+    /// chain. Their transactions go back to the mempool where they still
+    /// apply, and the longest remaining valid branch becomes the chain.
+    pub fn invalidate_block(&mut self, hash: &Hash256) -> Result<(), String> {
+        match self.stored(self.store.height_of(hash)) {
+            Some(0) => return Err("Cannot invalidate the genesis block".to_string()),
+            Some(height) if self.last_checkpoint() >= height => {
+                return Err(format!(
+                    "Cannot invalidate blocks up to the checkpoint at height {}",
+                    self.last_checkpoint()
+                ))
+            }
+            Some(height) => {
+                self.invalid.insert(*hash);
+                self.disconnect_from(height);
+            }
+            None if self.side_blocks.iter().any(|b| &b.get_hash() == hash) => {
+                self.invalid.insert(*hash);
+            }
+            None => return Err(format!("Unknown block {}", hash_to_hex(hash))),
+        }
+        self.activate_best_chain();
+        self.save_state();
+        Ok(())
     }
 
-    pub fn add_mined_block(&mut self, block: Block) {
-        if self.is_valid_new_block(&block) {
-            self.blocks.push(block);
-            self.pending_transactions.clear();
+    /// Undoes `invalidate_block`, switching back to the block's branch if it
+    /// is now the longest.
+    pub fn reconsider_block(&mut self, hash: &Hash256) -> Result<(), String> {
+        if !self.invalid.remove(hash) {
+            return Err(format!("Block {} is not marked invalid", hash_to_hex(hash)));
         }
+        self.activate_best_chain();
+        self.save_state();
+        Ok(())
     }
 
-    fn is_valid_new_block(&self, block: &Block) -> bool {
-        let latest_block = self.get_latest_block();
-        if block.get_prev_hash() != latest_block.get_hash() {
+    /// Takes the blocks from `height` up off the chain and keeps them as
+    /// side blocks. The token ledger is rebuilt from what is left.
+    fn disconnect_from(&mut self, height: u64) {
+        let removed: Vec<Block> = (height..self.block_count())
+            .map(|at| self.stored_block(at))
+            .collect();
+        if let Err(e) = self.store.truncate(height) {
+            store_failed(e);
+        }
+        self.replay_blocks();
+
+        let pending = std::mem::take(&mut self.pending_transactions);
+        let returned = removed
+            .iter()
+            .flat_map(Block::get_transactions)
+            .filter(|tx| tx.sender != COINBASE_SENDER)
+            .cloned();
+        for tx in returned.chain(pending) {
+            let _ = self.add_transaction(tx);
+        }
+        self.side_blocks.extend(removed);
+        self.save_state();
+        self.template_changed();
+    }
+
+    /// Switches to the longest branch of side blocks that avoids invalid
+    /// ones, for as long as one is longer than the chain. Ties keep the
+    /// chain as it is. A side block that fails validation on the way is
+    /// marked invalid.
+    fn activate_best_chain(&mut self) {
+        while let Some((fork, branch)) = self.best_side_branch() {
+            if fork + (branch.len() as u64) < self.block_count() {
+                return;
+            }
+            self.disconnect_from(fork + 1);
+            for hash in branch {
+                let i = self
+                    .side_blocks
+                    .iter()
+                    .position(|block| block.get_hash() == hash)
+                    .unwrap();
+                let block = self.side_blocks.remove(i);
+                if !self.is_valid_new_block(&block, true) {
+                    self.invalid.insert(hash);
+                    self.side_blocks.push(block);
+                    break;
+                }
+                self.add_mined_block(block);
+            }
+        }
+    }
+
+    /// The longest valid branch of side blocks off the chain, as the height
+    /// it forks from and its block hashes in order. Branches forking below
+    /// the last checkpoint the chain has reached are left out.
+    fn best_side_branch(&self) -> Option<(u64, Vec<Hash256>)> {
+        (self.last_checkpoint()..self.block_count())
+            .filter_map(|height| {
+                let branch = self.longest_side_chain(&self.stored_header(height).hash);
+                (!branch.is_empty()).then_some((height, branch))
+            })
+            .max_by_key(|(height, branch)| height + branch.len() as u64)
+    }
+
+    /// The longest run of valid side blocks building on `parent`.
+    fn longest_side_chain(&self, parent: &Hash256) -> Vec<Hash256> {
+        self.side_blocks
+            .iter()
+            .filter(|block| &block.get_prev_hash() == parent)
+            .filter(|block| !self.invalid.contains(&block.get_hash()))
+            .map(|block| {
+                let mut chain = vec![block.get_hash()];
+                chain.extend(self.longest_side_chain(&block.get_hash()));
+                chain
+            })
+            .max_by_key(Vec::len)
+            .unwrap_or_default()
+    }
+
+    /// Height of the highest checkpoint at or below the tip; 0, the genesis
+    /// block, if there is none.
+    fn last_checkpoint(&self) -> u64 {
+        self.params
+            .checkpoints
+            .iter()
+            .map(|(height, _)| *height)
+            .filter(|height| *height <= self.height())
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// Whether `hash` may be at `height`: anything goes except at a
+    /// checkpoint, which pins the hash.
+    fn matches_checkpoint(&self, height: u64, hash: &Hash256) -> bool {
+        self.params
+            .checkpoint(height)
+            .is_none_or(|checkpoint| checkpoint == hash)
+    }
+
+    /// Checks a block to go on top of the tip. Without `verify_signatures`,
+    /// as for blocks below the assume-valid block, everything but the
+    /// signatures is still checked.
+    fn is_valid_new_block(&self, block: &Block, verify_signatures: bool) -> bool {
+        if self.invalid.contains(&block.get_hash()) {
+            return false;
+        }
+        if block.get_prev_hash() != self.tip().hash {
+            return false;
+        }
+        if block.get_hash() != block.calculate_hash() {
             return false;
         }
         if !self.valid_proof(&block.get_hash()) {
             return false;
         }
-        true
+        let height = self.block_count();
+        if !self.matches_checkpoint(height, &block.get_hash()) {
+            return false;
+        }
+        if !self.valid_timestamp(block.get_timestamp(), height) {
+            return false;
+        }
+        let mut ledger = self.tokens.clone();
+        let mut funds = self.funds.clone();
+        self.valid_transactions(block, height, &mut ledger, &mut funds, verify_signatures)
     }
 
-    pub fn get_balance(&self, address: &str) -> f64 {
-        let mut balance = 0.0;
-        for block in &self.blocks {
-            for tx in block.get_transactions() {
-                if tx.sender == address {
-                    balance -= tx.amount;
-                }
-                if tx.receiver == address {
-                    balance += tx.amount;
+    /// A new block at `height` must be timestamped after the median time
+    /// past below it and no more than `MAX_FUTURE_BLOCK_TIME` ahead of the
+    /// clock.
+    fn valid_timestamp(&self, timestamp: u64, height: u64) -> bool {
+        timestamp > self.median_time_past(height)
+            && timestamp <= clock::now() + MAX_FUTURE_BLOCK_TIME
+    }
+
+    /// Only the first transaction may be a coinbase, and it may claim no
+    /// more than the block reward at `height` plus the block's fees. Every other
+    /// transaction must carry a valid signature from its sender, and all of
+    /// them must fit in `MAX_BLOCK_SIZE`. Locks and scripts see the block's
+    /// height and the median time past below it. Token operations must go
+    /// through `ledger`, the token state below the block, in order, and
+    /// every sender must carry its next nonce and cover its amount and fee
+    /// from `funds`, the balances below the block plus what earlier
+    /// transactions in it paid, less coinbase payments younger than
+    /// `coinbase_maturity` blocks. Without
+    /// `verify_signatures` the signatures are taken as valid.
+    fn valid_transactions(
+        &self,
+        block: &Block,
+        height: u64,
+        ledger: &mut Ledger,
+        funds: &mut Funds,
+        verify_signatures: bool,
+    ) -> bool {
+        let context = self.context_at(height);
+        let transactions = block.get_transactions();
+        let size: usize = transactions.iter().map(Transaction::size).sum();
+        if size > MAX_BLOCK_SIZE {
+            return false;
+        }
+
+        let validate: fn(&Transaction, &Context) -> Result<(), String> = if verify_signatures {
+            Transaction::validate
+        } else {
+            Transaction::validate_structure
+        };
+        let mut fees = 0.0;
+        let mut coinbase_amount = 0.0;
+        for (i, tx) in transactions.iter().enumerate() {
+            if tx.sender == COINBASE_SENDER {
+                if i != 0
+                    || tx.nonce != height
+                    || tx.validate_addresses().is_err()
+                    || tx.token.is_some()
+                {
+                    return false;
                 }
+                coinbase_amount = tx.amount;
+            } else if validate(tx, &context).is_ok()
+                && self
+                    .check_relative_lock(tx, height, transactions[..i].iter())
+                    .is_ok()
+                && funds.in_sequence(tx)
+                && funds.covers(tx, height)
+                && ledger.apply(tx).is_ok()
+            {
+                fees += tx.fee;
+            } else {
+                return false;
             }
+            funds.apply(tx, height);
         }
-        balance
+        // Fees are floats, so allow for rounding in the miner's sum.
+        coinbase_amount <= self.params.block_reward(height) + fees + 1e-9
+    }
+
+    pub fn get_balance(&self, address: &str) -> f64 {
+        self.funds.balance(address)
+    }
+
+    pub fn get_token_balance(&self, token: &str, address: &str) -> u64 {
+        self.tokens.balance(token, address)
+    }
+
+    /// Every token `address` holds or has pending, in confirmed base units
+    /// plus the net pending change.
+    pub fn token_balances(&self, address: &str) -> TokenBalances {
+        let pending = self.mempool_ledger();
+        let mut balances: Vec<TokenBalance> = pending
+            .tokens()
+            .filter_map(|token| {
+                let confirmed = self.tokens.balance(&token.id, address);
+                let after = pending.balance(&token.id, address);
+                (confirmed > 0 || after > 0).then(|| TokenBalance {
+                    token: token.id.clone(),
+                    symbol: token.symbol.clone(),
+                    decimals: token.decimals,
+                    confirmed,
+                    unconfirmed: after as i128 - confirmed as i128,
+                })
+            })
+            .collect();
+        balances.sort_by(|a, b| a.symbol.cmp(&b.symbol).then(a.token.cmp(&b.token)));
+        TokenBalances {
+            address: address.to_string(),
+            balances,
+        }
+    }
+
+    /// A token as of the tip.
+    pub fn token(&self, id: &str) -> Option<&Token> {
+        self.tokens.token(id)
+    }
+
+    /// Every confirmed token, by symbol.
+    pub fn tokens(&self) -> Vec<&Token> {
+        let mut tokens: Vec<&Token> = self.tokens.tokens().collect();
+        tokens.sort_by(|a, b| a.symbol.cmp(&b.symbol).then(a.id.cmp(&b.id)));
+        tokens
+    }
+
+    pub fn balance(&self, address: &str) -> Balance {
+        Balance {
+            address: address.to_string(),
+            confirmed: self.get_balance(address),
+            unconfirmed: net_amount(self.pending_transactions.iter(), address),
+            spendable: self.spendable(address),
+            next_nonce: self.next_nonce(address),
+        }
+    }
+
+    /// Transactions touching `address`, newest first: pending ones, then
+    /// confirmed ones from the tip down. `offset` and `limit` select a page.
+    pub fn history(&self, address: &str, offset: usize, limit: usize) -> History {
+        let tip = self.height();
+        let pending = self
+            .pending_transactions
+            .iter()
+            .rev()
+            .map(|tx| (tx.clone(), None));
+        let confirmed = (0..=tip).rev().flat_map(|height| {
+            self.stored_block(height)
+                .get_transactions()
+                .iter()
+                .rev()
+                .map(|tx| (tx.clone(), Some(height)))
+                .collect::<Vec<_>>()
+        });
+
+        let mut total = 0;
+        let mut transactions = Vec::new();
+        for (tx, height) in pending.chain(confirmed) {
+            let direction = match (tx.sender == address, tx.receiver == address) {
+                (true, true) => Direction::ToSelf,
+                (true, false) => Direction::Sent,
+                (false, true) => Direction::Received,
+                (false, false) => continue,
+            };
+            if total >= offset && transactions.len() < limit {
+                transactions.push(HistoryEntry {
+                    transaction: tx,
+                    height,
+                    confirmations: height.map_or(0, |height| tip - height + 1),
+                    direction,
+                });
+            }
+            total += 1;
+        }
+
+        History {
+            address: address.to_string(),
+            total,
+            offset,
+            transactions,
+        }
+    }
+
+    /// Transactions whose data payload is exactly `payload`: pending ones,
+    /// then confirmed ones from the tip down.
+    pub fn find_data(&self, payload: &[u8]) -> Vec<DataMatch> {
+        let tip = self.height();
+        let pending = self
+            .pending_transactions
+            .iter()
+            .rev()
+            .filter(|tx| matches!(tx.payload(), Ok(Some(p)) if p == payload))
+            .map(|tx| DataMatch {
+                transaction: tx.clone(),
+                height: None,
+                confirmations: 0,
+            });
+        let confirmed = self
+            .stored(self.store.data_positions(payload))
+            .into_iter()
+            .rev()
+            .map(|(height, i)| DataMatch {
+                transaction: self.stored_block(height).get_transactions()[i].clone(),
+                height: Some(height),
+                confirmations: tip - height + 1,
+            });
+        pending.chain(confirmed).collect()
+    }
+
+    /// The transaction with `id`, whether still pending or, if the
+    /// transaction index is on, confirmed.
+    pub fn get_transaction(&self, id: &Hash256) -> Result<TransactionInfo, String> {
+        if let Some(tx) = self.pending_transactions.iter().find(|tx| tx.id() == *id) {
+            return Ok(TransactionInfo {
+                transaction: tx.clone(),
+                in_mempool: true,
+                block_hash: None,
+                height: None,
+                position: None,
+                confirmations: 0,
+            });
+        }
+        if !self.txindex {
+            return Err(format!(
+                "Transaction {} is not in the mempool; looking up confirmed ones needs the transaction index (--txindex)",
+                hash_to_hex(id)
+            ));
+        }
+        let (height, position) = self
+            .stored(self.store.transaction_position(id))
+            .ok_or_else(|| format!("Unknown transaction {}", hash_to_hex(id)))?;
+        let block = self.stored_block(height);
+        Ok(TransactionInfo {
+            transaction: block.get_transactions()[position].clone(),
+            in_mempool: false,
+            block_hash: Some(hash_to_hex(&block.get_hash())),
+            height: Some(height),
+            position: Some(position),
+            confirmations: self.height() - height + 1,
+        })
     }
 
     pub fn get_block_to_mine(&self) -> Option<Block> {
         if self.pending_transactions.is_empty() {
             None
         } else {
-            Some(Block::new(self.pending_transactions.clone(), self.get_latest_block().get_hash()))
+            Some(self.next_block(self.select_transactions(None)))
         }
     }
-}
\ No newline at end of file
+}
+
+/// Stops the node when the chain store fails: carrying on would leave the
+/// chain in memory and the one on disk apart.
+fn store_failed(e: String) -> ! {
+    eprintln!("Chain store failed: {}", e);
+    std::process::exit(1)
+}
+
+fn net_amount<'a>(transactions: impl Iterator<Item = &'a Transaction>, address: &str) -> f64 {
+    let mut balance = 0.0;
+    for tx in transactions {
+        if tx.sender == address {
+            balance -= tx.amount + tx.fee;
+        }
+        if tx.receiver == address {
+            balance += tx.amount;
+        }
+    }
+    balance
+}
@@ -0,0 +1,267 @@
+use crate::genesis::Genesis;
+use crate::hash::Hash256;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+use std::sync::OnceLock;
+
+/// Which chain a node, wallet or miner is on. Every network has its own
+/// genesis block, address prefix and magic, so their coins never mix.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Network {
+    #[default]
+    Mainnet,
+    Testnet,
+    /// A private chain for tests: trivial proof of work and a short subsidy
+    /// schedule.
+    Regtest,
+}
+
+impl Network {
+    pub fn name(self) -> &'static str {
+        match self {
+            Network::Mainnet => "mainnet",
+            Network::Testnet => "testnet",
+            Network::Regtest => "regtest",
+        }
+    }
+}
+
+impl fmt::Display for Network {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for Network {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, String> {
+        match name {
+            "mainnet" | "main" => Ok(Network::Mainnet),
+            "testnet" | "test" => Ok(Network::Testnet),
+            "regtest" => Ok(Network::Regtest),
+            _ => Err(format!(
+                "Unknown network {} (expected mainnet, testnet or regtest)",
+                name
+            )),
+        }
+    }
+}
+
+/// Everything that sets one network apart from another.
+#[derive(Debug, Clone)]
+pub struct ChainParams {
+    pub network: Network,
+    /// What the genesis block is built from, including the difficulty
+    /// every block needs.
+    pub genesis: Genesis,
+    /// Seconds the network aims to spend on each block.
+    pub target_block_time: u64,
+    /// Blocks between difficulty adjustments, see `retarget`. Without one
+    /// every block keeps the genesis difficulty.
+    pub retarget_interval: Option<u64>,
+    /// Block reward until the first halving.
+    pub initial_subsidy: f64,
+    /// Blocks between halvings of the block reward.
+    pub halving_interval: u64,
+    /// Blocks a coinbase payment waits before it can be spent: one in the
+    /// block at height h is spendable from height h + `coinbase_maturity`.
+    pub coinbase_maturity: u64,
+    /// Human-readable prefix of every address.
+    pub address_prefix: &'static str,
+    /// Port a node listens on when its address names none.
+    pub default_port: u16,
+    /// Sent by peers to prove they are on the same network.
+    pub magic: [u8; 4],
+    /// Heights whose block hash is fixed in advance. A block conflicting
+    /// with one is rejected, and the chain never forks below the highest
+    /// one it has reached.
+    pub checkpoints: Vec<(u64, Hash256)>,
+    /// Block whose ancestors are taken to be correctly signed. During
+    /// initial sync, blocks up to it skip signature checks, though their
+    /// structure and proof of work are still checked.
+    pub assume_valid: Option<Hash256>,
+    /// Whether the node takes the test-only commands that mine blocks on
+    /// demand, mock the clock and invalidate blocks.
+    pub test_commands: bool,
+}
+
+impl ChainParams {
+    pub fn mainnet() -> Self {
+        ChainParams {
+            network: Network::Mainnet,
+            genesis: Genesis {
+                timestamp: 1_704_067_200,
+                message: "Fluerion mainnet genesis".to_string(),
+                difficulty: 4,
+                nonce: 28285,
+                hash: "0000c8be0b240b6def597f565c4a038fdc6f6a0e4498f3e418af589c2efcada0"
+                    .to_string(),
+                allocations: Vec::new(),
+            },
+            target_block_time: 60,
+            retarget_interval: Some(1440),
+            initial_subsidy: 50.0,
+            halving_interval: 210_000,
+            coinbase_maturity: 100,
+            address_prefix: "fl",
+            default_port: 8080,
+            magic: [0xf1, 0x75, 0xe7, 0x10],
+            checkpoints: Vec::new(),
+            assume_valid: None,
+            test_commands: false,
+        }
+    }
+
+    pub fn testnet() -> Self {
+        ChainParams {
+            network: Network::Testnet,
+            genesis: Genesis {
+                timestamp: 1_704_067_201,
+                message: "Fluerion testnet genesis".to_string(),
+                difficulty: 3,
+                nonce: 5358,
+                hash: "000653454956220ed5db451d6e89666df9ea06d2d90a97e6757c08e85bda5918"
+                    .to_string(),
+                allocations: Vec::new(),
+            },
+            target_block_time: 60,
+            retarget_interval: Some(1440),
+            initial_subsidy: 50.0,
+            halving_interval: 210_000,
+            coinbase_maturity: 100,
+            address_prefix: "tf",
+            default_port: 18080,
+            magic: [0x0b, 0x11, 0x09, 0x07],
+            checkpoints: Vec::new(),
+            assume_valid: None,
+            test_commands: false,
+        }
+    }
+
+    pub fn regtest() -> Self {
+        ChainParams {
+            network: Network::Regtest,
+            genesis: Genesis {
+                timestamp: 1_704_067_202,
+                message: "Fluerion regtest genesis".to_string(),
+                difficulty: 1,
+                nonce: 3,
+                hash: "0e6c43867aadb881f1a1bf0f8e702a9cc146aad6f078c329457f3274604ec0cc"
+                    .to_string(),
+                allocations: Vec::new(),
+            },
+            target_block_time: 1,
+            retarget_interval: None,
+            initial_subsidy: 50.0,
+            halving_interval: 150,
+            coinbase_maturity: 100,
+            address_prefix: "flrt",
+            default_port: 28080,
+            magic: [0xfa, 0xbf, 0xb5, 0xda],
+            checkpoints: Vec::new(),
+            assume_valid: None,
+            test_commands: true,
+        }
+    }
+
+    pub fn for_network(network: Network) -> Self {
+        match network {
+            Network::Mainnet => Self::mainnet(),
+            Network::Testnet => Self::testnet(),
+            Network::Regtest => Self::regtest(),
+        }
+    }
+
+    /// `network`'s parameters with its genesis replaced by the one in
+    /// `path`, for a private network that shares the rest of its rules.
+    pub fn with_genesis_file(network: Network, path: &str) -> Result<Self, String> {
+        Ok(ChainParams {
+            genesis: Genesis::from_file(path)?,
+            ..Self::for_network(network)
+        })
+    }
+
+    /// The block reward at `height`: the initial subsidy, halved every
+    /// `halving_interval` blocks until nothing is left.
+    pub fn block_reward(&self, height: u64) -> f64 {
+        match height / self.halving_interval {
+            halvings @ 0..=63 => self.initial_subsidy / (1u64 << halvings) as f64,
+            _ => 0.0,
+        }
+    }
+
+    /// The difficulty following `difficulty` after `retarget_interval`
+    /// blocks whose timestamps span `elapsed` seconds, first to last. Each
+    /// step is another leading zero hex digit, 16 times the work, so it
+    /// only moves once blocks come over 4 times faster or slower than
+    /// `target_block_time`, and never below 1 or above 64.
+    pub fn retarget(&self, difficulty: usize, elapsed: u64) -> usize {
+        let Some(interval) = self.retarget_interval else {
+            return difficulty;
+        };
+        let expected = interval.saturating_sub(1) * self.target_block_time;
+        if elapsed < expected / 4 {
+            (difficulty + 1).min(64)
+        } else if elapsed > expected.saturating_mul(4) && difficulty > 1 {
+            difficulty - 1
+        } else {
+            difficulty
+        }
+    }
+
+    /// The hash pinned at `height`, if it is a checkpoint.
+    pub fn checkpoint(&self, height: u64) -> Option<&Hash256> {
+        self.checkpoints
+            .iter()
+            .find(|(checkpoint, _)| *checkpoint == height)
+            .map(|(_, hash)| hash)
+    }
+
+    /// Pins `hash` at `height` on top of the network's own checkpoints,
+    /// which it cannot contradict.
+    pub fn add_checkpoint(&mut self, height: u64, hash: Hash256) -> Result<(), String> {
+        match self.checkpoint(height) {
+            Some(pinned) if *pinned != hash => Err(format!(
+                "Checkpoint at height {} conflicts with {}'s",
+                height, self.network
+            )),
+            Some(_) => Ok(()),
+            None => {
+                self.checkpoints.push((height, hash));
+                self.checkpoints.sort_by_key(|(height, _)| *height);
+                Ok(())
+            }
+        }
+    }
+
+    pub fn magic_hex(&self) -> String {
+        hex::encode(self.magic)
+    }
+
+    /// `address` with the default port added if it names none.
+    pub fn with_default_port(&self, address: &str) -> String {
+        if address.contains(':') {
+            address.to_string()
+        } else {
+            format!("{}:{}", address, self.default_port)
+        }
+    }
+}
+
+static PARAMS: OnceLock<ChainParams> = OnceLock::new();
+
+/// Chooses the network for the rest of the process. Only the first call
+/// counts; anything that reads the parameters before it gets mainnet.
+pub fn select(params: ChainParams) -> Result<(), String> {
+    PARAMS
+        .set(params)
+        .map_err(|_| "Network already selected".to_string())
+}
+
+/// Parameters of the selected network.
+pub fn params() -> &'static ChainParams {
+    PARAMS.get_or_init(ChainParams::mainnet)
+}
@@ -0,0 +1,22 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Unix time the clock is pinned at, or zero for the real clock.
+static MOCK_TIME: AtomicU64 = AtomicU64::new(0);
+
+/// The current Unix time as the node sees it: the system clock, unless a
+/// test pinned it with `set_mock_time`.
+pub fn now() -> u64 {
+    match MOCK_TIME.load(Ordering::Relaxed) {
+        0 => SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs(),
+        mock => mock,
+    }
+}
+
+/// Pins the clock at `time`; zero puts it back on the system clock.
+pub fn set_mock_time(time: u64) {
+    MOCK_TIME.store(time, Ordering::Relaxed);
+}
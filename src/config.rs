@@ -0,0 +1,222 @@
+use crate::address;
+use crate::chainparams::{self, ChainParams, Network};
+use crate::hash::{hex_to_hash, Hash256};
+use crate::store::{self, ChainStore, StoreKind};
+use serde::Deserialize;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+pub const USAGE: &str = "Usage: fluerion <node_address> [bootstrap_node] [--config <file>]
+                [--network mainnet|testnet|regtest] [--genesis <file>] [--generate]
+                [--genproclimit <threads>] [--mining-address <addr>]
+                [--assume-valid <block_hash>] [--checkpoint <height>:<hash>]...
+                [--store memory|flatfile|kv] [--datadir <dir>] [--txindex]
+
+A node address without a port gets the network's default port. --genesis
+starts a private network from a genesis file made by fluerion-genesis; the
+rest of its rules come from --network. A node given a bootstrap node syncs
+its chain from it, skipping signature checks up to the --assume-valid block
+(0 checks every signature). Each --checkpoint pins the hash of the block at
+a height on top of the network's own checkpoints; a chain with another block
+there is rejected.
+
+--store picks where the chain is kept: memory (the default) keeps nothing
+across restarts, flatfile and kv keep it under --datadir, which defaults to
+fluerion-data/<network>. --txindex keeps an index of confirmed transactions
+by id, so that GETTRANSACTION finds them and not just pending ones; it is
+built on startup if the store does not have one yet.";
+
+/// Node settings, read from an optional TOML file and then overridden by
+/// command-line arguments. For example:
+///
+/// ```toml
+/// network = "testnet"
+/// genesis = "genesis.toml"
+/// generate = true
+/// genproclimit = 2
+/// mining_address = "fl1xkt3hehfhvpy4z24stlqushqfpy2smd9ue9h55"
+/// assume_valid = "0"
+/// checkpoints = ["1000:00001f3c9b2e6f0d4a7c8e1b5d3f9a2c6e0b4d8f1a3c5e7b9d2f4a6c8e0b1d3f"]
+/// store = "kv"
+/// datadir = "/var/lib/fluerion"
+/// txindex = true
+/// ```
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct NodeConfig {
+    #[serde(skip)]
+    pub address: String,
+    #[serde(skip)]
+    pub bootstrap_node: Option<String>,
+    pub network: Network,
+    /// Genesis file replacing the network's built-in genesis block.
+    pub genesis: Option<String>,
+    /// Run the built-in miner from startup.
+    pub generate: bool,
+    /// Threads the built-in miner uses, at most one per core.
+    pub genproclimit: usize,
+    /// Address the built-in miner pays its coinbase to.
+    pub mining_address: Option<String>,
+    /// Hex hash replacing the network's assume-valid block; "0" turns
+    /// assume-valid off.
+    pub assume_valid: Option<String>,
+    /// Extra checkpoints, each a height and a hex block hash joined by a
+    /// colon.
+    pub checkpoints: Vec<String>,
+    /// Backend the chain is kept in.
+    pub store: StoreKind,
+    /// Directory a persistent store keeps its files in.
+    pub datadir: Option<PathBuf>,
+    /// Index confirmed transactions by id.
+    pub txindex: bool,
+}
+
+impl Default for NodeConfig {
+    fn default() -> Self {
+        NodeConfig {
+            address: String::new(),
+            bootstrap_node: None,
+            network: Network::Mainnet,
+            genesis: None,
+            generate: false,
+            genproclimit: 1,
+            mining_address: None,
+            assume_valid: None,
+            checkpoints: Vec::new(),
+            store: StoreKind::Memory,
+            datadir: None,
+            txindex: false,
+        }
+    }
+}
+
+impl NodeConfig {
+    pub fn from_args(args: &[String]) -> Result<Self, String> {
+        let mut config = match args.iter().position(|arg| arg == "--config") {
+            Some(i) => Self::from_file(required("--config", args.get(i + 1))?)?,
+            None => NodeConfig::default(),
+        };
+
+        let mut positional = Vec::new();
+        let mut i = 1;
+        while i < args.len() {
+            let flag = args[i].as_str();
+            let value = args.get(i + 1);
+            match flag {
+                "--config" => i += 1,
+                "--network" => {
+                    config.network = required(flag, value)?.parse()?;
+                    i += 1;
+                }
+                "--genesis" => {
+                    config.genesis = Some(required(flag, value)?.to_string());
+                    i += 1;
+                }
+                "--generate" => config.generate = true,
+                "--genproclimit" => {
+                    config.genproclimit = required(flag, value)?
+                        .parse()
+                        .map_err(|_| format!("Invalid value for {}", flag))?;
+                    i += 1;
+                }
+                "--mining-address" => {
+                    config.mining_address = Some(required(flag, value)?.to_string());
+                    i += 1;
+                }
+                "--assume-valid" => {
+                    config.assume_valid = Some(required(flag, value)?.to_string());
+                    i += 1;
+                }
+                "--checkpoint" => {
+                    config.checkpoints.push(required(flag, value)?.to_string());
+                    i += 1;
+                }
+                "--store" => {
+                    config.store = required(flag, value)?.parse()?;
+                    i += 1;
+                }
+                "--datadir" => {
+                    config.datadir = Some(required(flag, value)?.into());
+                    i += 1;
+                }
+                "--txindex" => config.txindex = true,
+                _ if flag.starts_with("--") => return Err(format!("Unknown argument: {}", flag)),
+                _ => positional.push(flag.to_string()),
+            }
+            i += 1;
+        }
+
+        let params = ChainParams::for_network(config.network);
+        let mut positional = positional.into_iter();
+        config.address = positional
+            .next()
+            .map(|address| params.with_default_port(&address))
+            .ok_or_else(|| "Missing node address".to_string())?;
+        config.bootstrap_node = positional
+            .next()
+            .map(|address| params.with_default_port(&address));
+        Ok(config)
+    }
+
+    /// Parameters of the network this node runs on.
+    pub fn chain_params(&self) -> Result<ChainParams, String> {
+        let mut params = match &self.genesis {
+            Some(path) => ChainParams::with_genesis_file(self.network, path)?,
+            None => ChainParams::for_network(self.network),
+        };
+        match self.assume_valid.as_deref() {
+            Some("0") => params.assume_valid = None,
+            Some(hash) => {
+                params.assume_valid =
+                    Some(hex_to_hash(hash).map_err(|e| format!("Invalid --assume-valid: {}", e))?)
+            }
+            None => {}
+        }
+        for checkpoint in &self.checkpoints {
+            let (height, hash) = parse_checkpoint(checkpoint)
+                .map_err(|e| format!("Invalid checkpoint {}: {}", checkpoint, e))?;
+            params.add_checkpoint(height, hash)?;
+        }
+        Ok(params)
+    }
+
+    /// Opens the configured chain store.
+    pub fn open_store(&self) -> Result<Box<dyn ChainStore>, String> {
+        let dir = self
+            .datadir
+            .clone()
+            .unwrap_or_else(|| Path::new("fluerion-data").join(self.network.name()));
+        store::open(self.store, &dir)
+    }
+
+    /// Checks the settings that depend on the selected network, including
+    /// that its genesis block comes out with the pinned hash.
+    pub fn validate(&self) -> Result<(), String> {
+        chainparams::params().genesis.check()?;
+        if let Some(mining_address) = &self.mining_address {
+            address::validate(mining_address)?;
+        }
+        Ok(())
+    }
+
+    fn from_file(path: &str) -> Result<Self, String> {
+        let contents =
+            fs::read_to_string(path).map_err(|e| format!("Cannot read {}: {}", path, e))?;
+        toml::from_str(&contents).map_err(|e| format!("Invalid config {}: {}", path, e))
+    }
+}
+
+/// Reads a checkpoint written as `<height>:<hash>`.
+fn parse_checkpoint(checkpoint: &str) -> Result<(u64, Hash256), String> {
+    let (height, hash) = checkpoint
+        .split_once(':')
+        .ok_or("expected <height>:<hash>")?;
+    let height = height.parse().map_err(|_| "height is not a number")?;
+    Ok((height, hex_to_hash(hash)?))
+}
+
+fn required<'a>(flag: &str, value: Option<&'a String>) -> Result<&'a str, String> {
+    value
+        .map(String::as_str)
+        .ok_or_else(|| format!("{} needs a value", flag))
+}
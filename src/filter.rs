@@ -0,0 +1,103 @@
+use crate::block::Block;
+use crate::hash::{sha256, Hash256};
+use crate::transaction::COINBASE_SENDER;
+use siphasher::sip::SipHasher24;
+
+/// Golomb-Rice parameter: each delta keeps its low `FILTER_P` bits verbatim.
+pub const FILTER_P: u8 = 19;
+
+/// Inverse false positive rate, as in BIP158's basic filter.
+pub const FILTER_M: u64 = 784_931;
+
+/// A compact filter over every address a block's transactions send from or
+/// to, so a light wallet can tell which blocks to fetch without seeing the
+/// rest. Follows BIP158: the items are hashed with SipHash-2-4 keyed by the
+/// block hash into `[0, N * M)`, sorted, and the differences Golomb-Rice
+/// coded after a CompactSize count.
+pub fn build(block: &Block) -> Vec<u8> {
+    let mut items: Vec<&[u8]> = block
+        .get_transactions()
+        .iter()
+        .flat_map(|tx| [tx.sender.as_str(), tx.receiver.as_str()])
+        .filter(|address| *address != COINBASE_SENDER)
+        .map(str::as_bytes)
+        .collect();
+    items.sort();
+    items.dedup();
+
+    let n = items.len() as u64;
+    let key = sip_key(&block.get_hash());
+    let mut values: Vec<u64> = items
+        .iter()
+        .map(|item| hash_to_range(&key, item, n * FILTER_M))
+        .collect();
+    values.sort();
+
+    let mut filter = compact_size(n);
+    let mut writer = BitWriter::default();
+    let mut last = 0;
+    for value in values {
+        let delta = value - last;
+        last = value;
+        for _ in 0..delta >> FILTER_P {
+            writer.write(1, 1);
+        }
+        writer.write(0, 1);
+        writer.write(delta, FILTER_P);
+    }
+    filter.extend(writer.finish());
+    filter
+}
+
+/// Commits to a filter and, through `prev_header`, to every filter before
+/// it. The genesis filter links to all zeros.
+pub fn header(filter: &[u8], prev_header: &Hash256) -> Hash256 {
+    let mut data = sha256(filter).to_vec();
+    data.extend_from_slice(prev_header);
+    sha256(&data)
+}
+
+fn sip_key(block_hash: &Hash256) -> SipHasher24 {
+    let k0 = u64::from_le_bytes(block_hash[0..8].try_into().unwrap());
+    let k1 = u64::from_le_bytes(block_hash[8..16].try_into().unwrap());
+    SipHasher24::new_with_keys(k0, k1)
+}
+
+/// Maps an item uniformly onto `[0, range)` without a modulo.
+fn hash_to_range(key: &SipHasher24, item: &[u8], range: u64) -> u64 {
+    ((key.hash(item) as u128 * range as u128) >> 64) as u64
+}
+
+fn compact_size(n: u64) -> Vec<u8> {
+    match n {
+        0..=0xfc => vec![n as u8],
+        0xfd..=0xffff => [&[0xfd][..], &(n as u16).to_le_bytes()].concat(),
+        0x10000..=0xffff_ffff => [&[0xfe][..], &(n as u32).to_le_bytes()].concat(),
+        _ => [&[0xff][..], &n.to_le_bytes()].concat(),
+    }
+}
+
+/// Packs bits most significant first, padding the last byte with zeros.
+#[derive(Default)]
+struct BitWriter {
+    bytes: Vec<u8>,
+    used: u8,
+}
+
+impl BitWriter {
+    /// Appends the low `count` bits of `value`.
+    fn write(&mut self, value: u64, count: u8) {
+        for i in (0..count).rev() {
+            if self.used == 0 {
+                self.bytes.push(0);
+            }
+            let bit = (value >> i) & 1;
+            *self.bytes.last_mut().unwrap() |= (bit as u8) << (7 - self.used);
+            self.used = (self.used + 1) % 8;
+        }
+    }
+
+    fn finish(self) -> Vec<u8> {
+        self.bytes
+    }
+}
@@ -0,0 +1,142 @@
+use crate::address;
+use crate::block::Block;
+use crate::hash::{hash_to_hex, meets_difficulty};
+use crate::transaction::MAX_DATA_SIZE;
+use serde::{Deserialize, Serialize};
+use std::fs;
+
+/// Sender of every genesis transaction; no key can spend from it.
+pub const GENESIS_SENDER: &str = "Genesis";
+
+/// Everything the genesis block is built from. Every node on a network
+/// builds the same block from it, and `hash` pins the result so a node
+/// with a different description refuses to start. A private network keeps
+/// it in a TOML file that `fluerion-genesis` creates:
+///
+/// ```toml
+/// timestamp = 1735689600
+/// message = "Our private chain"
+/// difficulty = 2
+/// nonce = 117
+/// hash = "00a4..."
+///
+/// [[allocation]]
+/// address = "flrt1..."
+/// amount = 1000.0
+/// ```
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Genesis {
+    /// Timestamp of the genesis block and of its transactions.
+    pub timestamp: u64,
+    /// Text the first genesis transaction carries as its data payload.
+    pub message: String,
+    /// Leading zero hex digits the genesis block hash, and every block
+    /// after it, needs.
+    pub difficulty: usize,
+    #[serde(default)]
+    pub nonce: u64,
+    /// Hex hash the block must come out with; empty until mined.
+    #[serde(default)]
+    pub hash: String,
+    /// Coins paid out in the genesis block, one transaction each.
+    #[serde(default, rename = "allocation", skip_serializing_if = "Vec::is_empty")]
+    pub allocations: Vec<Allocation>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Allocation {
+    pub address: String,
+    pub amount: f64,
+}
+
+impl Genesis {
+    pub fn from_file(path: &str) -> Result<Self, String> {
+        let contents =
+            fs::read_to_string(path).map_err(|e| format!("Cannot read {}: {}", path, e))?;
+        toml::from_str(&contents).map_err(|e| format!("Invalid genesis {}: {}", path, e))
+    }
+
+    pub fn to_toml(&self) -> Result<String, String> {
+        toml::to_string(self).map_err(|e| format!("Cannot write genesis: {}", e))
+    }
+
+    /// Checks the description against the selected network's address
+    /// prefix and rebuilds the block, which must meet its own difficulty and
+    /// come out with the pinned hash.
+    pub fn check(&self) -> Result<Block, String> {
+        self.check_contents()?;
+        let block = Block::genesis(self);
+        let hash = hash_to_hex(&block.get_hash());
+        if self.hash.is_empty() {
+            return Err("Genesis has no hash; mine it with fluerion-genesis".to_string());
+        }
+        if hash != self.hash {
+            return Err(format!(
+                "Genesis block hash is {}, but the genesis pins {}",
+                hash, self.hash
+            ));
+        }
+        if !meets_difficulty(&block.get_hash(), self.difficulty) {
+            return Err(format!(
+                "Genesis block {} does not meet difficulty {}",
+                hash, self.difficulty
+            ));
+        }
+        Ok(block)
+    }
+
+    /// Searches for the first nonce from zero that gives the block a hash
+    /// meeting `difficulty`, and pins that hash.
+    pub fn mine(&mut self) -> Result<Block, String> {
+        self.check_contents()?;
+        self.nonce = 0;
+        let mut block = Block::genesis(self);
+        loop {
+            let hash = block.calculate_hash();
+            if meets_difficulty(&hash, self.difficulty) {
+                block.set_hash(hash);
+                self.hash = hash_to_hex(&hash);
+                return Ok(block);
+            }
+            self.nonce = self
+                .nonce
+                .checked_add(1)
+                .ok_or("No nonce meets the genesis difficulty")?;
+            block.set_nonce(self.nonce);
+        }
+    }
+
+    fn check_contents(&self) -> Result<(), String> {
+        if self.message.is_empty() || self.message.len() > MAX_DATA_SIZE {
+            return Err(format!(
+                "Genesis message must be 1 to {} bytes",
+                MAX_DATA_SIZE
+            ));
+        }
+        if self.difficulty > 64 {
+            return Err("Genesis difficulty must be at most 64".to_string());
+        }
+        for (i, allocation) in self.allocations.iter().enumerate() {
+            if self.allocations[..i]
+                .iter()
+                .any(|earlier| earlier.address == allocation.address)
+            {
+                return Err(format!(
+                    "Genesis allocates to {} more than once",
+                    allocation.address
+                ));
+            }
+            address::validate(&allocation.address)
+                .map_err(|e| format!("Genesis allocation to {}: {}", allocation.address, e))?;
+            if !allocation.amount.is_finite() || allocation.amount <= 0.0 {
+                return Err(format!(
+                    "Genesis allocation to {} must be a positive amount",
+                    allocation.address
+                ));
+            }
+        }
+        Ok(())
+    }
+}
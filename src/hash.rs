@@ -21,3 +21,23 @@ pub fn calculate_hash(timestamp: &str, prev_block_hash: &Hash256, data: &str) ->
 pub fn hash_to_hex(hash: &Hash256) -> String {
     hash.iter().map(|byte| format!("{:02x}", byte)).collect()
 }
+
+/// Parses the 64 hex digits `hash_to_hex` writes.
+pub fn hex_to_hash(hex_hash: &str) -> Result<Hash256, String> {
+    let hash = hex::decode(hex_hash).map_err(|_| "Block hash is not hex")?;
+    hash.try_into()
+        .map_err(|_| "Block hash must be 32 bytes".to_string())
+}
+
+pub fn sha256(data: &[u8]) -> Hash256 {
+    Sha256::digest(data).into()
+}
+
+/// Whether `hash` starts with `difficulty` zero hex digits.
+pub fn meets_difficulty(hash: &Hash256, difficulty: usize) -> bool {
+    if difficulty > hash.len() * 2 {
+        return false;
+    }
+    let (bytes, nibble) = (difficulty / 2, difficulty % 2);
+    hash[..bytes].iter().all(|&b| b == 0) && (nibble == 0 || hash[bytes] >> 4 == 0)
+}
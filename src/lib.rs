@@ -0,0 +1,16 @@
+pub mod address;
+pub mod block;
+pub mod blockchain;
+pub mod chainparams;
+pub mod clock;
+pub mod config;
+pub mod filter;
+pub mod genesis;
+pub mod hash;
+pub mod miner;
+pub mod multisig;
+pub mod network;
+pub mod script;
+pub mod store;
+pub mod token;
+pub mod transaction;
@@ -1,33 +1,56 @@
-mod block;
-mod blockchain;
-mod hash;
-mod network;
-mod transaction;
-
-use network::Node;
+use fluerion::blockchain::Blockchain;
+use fluerion::chainparams;
+use fluerion::config::{self, NodeConfig};
+use fluerion::network::Node;
 use std::env;
-use transaction::Transaction;
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let args: Vec<String> = env::args().collect();
-    if args.len() < 2 {
-        eprintln!("Usage: {} <node_address> [bootstrap_node]", args[0]);
-        std::process::exit(1);
+    let config = NodeConfig::from_args(&args)
+        .and_then(|config| {
+            chainparams::select(config.chain_params()?)?;
+            config.validate()?;
+            Ok(config)
+        })
+        .unwrap_or_else(|e| {
+            eprintln!("{}\n{}", e, config::USAGE);
+            std::process::exit(1);
+        });
+    println!("Running on {}", config.network);
+
+    let blockchain = config
+        .open_store()
+        .and_then(|store| Blockchain::open(store, config.txindex))
+        .unwrap_or_else(|e| {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        });
+    println!(
+        "Chain kept in {} store at height {}",
+        config.store,
+        blockchain.height()
+    );
+    if config.txindex {
+        println!("Transaction index on");
     }
 
-    let node_address = args[1].clone();
-    let mut node = Node::new(node_address.clone());
+    let node = Node::new(
+        config.address.clone(),
+        config.mining_address.clone(),
+        blockchain,
+    );
 
-    if args.len() > 2 {
-        let bootstrap_node = &args[2];
+    if let Some(bootstrap_node) = &config.bootstrap_node {
         println!("Discovering peers from bootstrap node: {}", bootstrap_node);
         node.discover_peers(bootstrap_node).await?;
+        node.sync_chain(bootstrap_node).await?;
     }
 
-    // Create and broadcast a transaction
-    let transaction = Transaction::new("Alice".to_string(), "Bob".to_string(), 50.0);
-    node.broadcast_transaction(&transaction).await;
+    if config.generate {
+        node.set_generate(true, config.genproclimit)?;
+        println!("Built-in miner running on {} threads", config.genproclimit);
+    }
 
     // Start the node
     node.start().await?;
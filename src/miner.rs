@@ -0,0 +1,153 @@
+use crate::block::{Block, NONCE_OFFSET};
+use crate::blockchain::Blockchain;
+use crate::hash::{hash_to_hex, meets_difficulty, sha256};
+use std::num::NonZeroUsize;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Condvar, Mutex as StdMutex};
+use std::thread;
+use tokio::sync::Mutex;
+
+/// Hashes between checks for a new template or changed settings.
+const BATCH_SIZE: u64 = 10_000;
+
+/// Most threads the miner runs: one per core the machine offers.
+pub fn max_threads() -> usize {
+    thread::available_parallelism().map_or(1, NonZeroUsize::get)
+}
+
+struct Settings {
+    generate: bool,
+    threads: usize,
+    spawned: usize,
+}
+
+/// Mines on top of the node's own chain from a pool of dedicated threads,
+/// so proof of work never runs on the async runtime or under the chain lock.
+/// Each worker puts its id in the coinbase extra nonce, so no two workers
+/// ever hash the same header.
+pub struct BackgroundMiner {
+    blockchain: Arc<Mutex<Blockchain>>,
+    template_version: Arc<AtomicU64>,
+    address: Option<String>,
+    settings: StdMutex<Settings>,
+    wake: Condvar,
+}
+
+impl BackgroundMiner {
+    pub fn new(
+        blockchain: Arc<Mutex<Blockchain>>,
+        template_version: Arc<AtomicU64>,
+        address: Option<String>,
+    ) -> Arc<Self> {
+        Arc::new(BackgroundMiner {
+            blockchain,
+            template_version,
+            address,
+            settings: StdMutex::new(Settings {
+                generate: false,
+                threads: 0,
+                spawned: 0,
+            }),
+            wake: Condvar::new(),
+        })
+    }
+
+    /// Starts or stops generation, growing the thread pool as needed.
+    pub fn set_generate(self: &Arc<Self>, generate: bool, threads: usize) -> Result<(), String> {
+        if generate && self.address.is_none() {
+            return Err("No mining address configured".to_string());
+        }
+        if generate && threads == 0 {
+            return Err("Need at least one mining thread".to_string());
+        }
+        if threads > max_threads() {
+            return Err(format!("At most {} mining threads", max_threads()));
+        }
+
+        let mut settings = self.settings.lock().unwrap();
+        settings.generate = generate;
+        settings.threads = threads;
+        while settings.spawned < threads {
+            let miner = Arc::clone(self);
+            let id = settings.spawned;
+            thread::Builder::new()
+                .name(format!("miner-{}", id))
+                .spawn(move || miner.work(id))
+                .map_err(|e| format!("Cannot start mining thread: {}", e))?;
+            settings.spawned += 1;
+        }
+
+        self.wake.notify_all();
+        Ok(())
+    }
+
+    /// Whether generation is on, and on how many threads.
+    pub fn status(&self) -> (bool, usize) {
+        let settings = self.settings.lock().unwrap();
+        (settings.generate, settings.threads)
+    }
+
+    fn should_run(&self, id: usize) -> bool {
+        let settings = self.settings.lock().unwrap();
+        settings.generate && id < settings.threads
+    }
+
+    fn work(&self, id: usize) {
+        let address = self.address.clone().unwrap_or_default();
+        loop {
+            {
+                let settings = self.settings.lock().unwrap();
+                let _settings = self
+                    .wake
+                    .wait_while(settings, |s| !(s.generate && id < s.threads))
+                    .unwrap();
+            }
+
+            let version = self.template_version.load(Ordering::SeqCst);
+            let (template, difficulty) = {
+                let blockchain = self.blockchain.blocking_lock();
+                (
+                    blockchain.block_template(&address, id as u64),
+                    blockchain.difficulty(),
+                )
+            };
+
+            if let Some(block) = self.search(id, template, difficulty, version) {
+                let hash = hash_to_hex(&block.get_hash());
+                if self.blockchain.blocking_lock().add_mined_block(block) {
+                    println!("Background miner {} found block {}", id, hash);
+                } else {
+                    println!("Background miner {} found stale block {}", id, hash);
+                }
+            }
+        }
+    }
+
+    /// Walks the nonce space until a hash meets the difficulty, the template
+    /// goes stale or this worker is told to stop.
+    fn search(
+        &self,
+        id: usize,
+        mut block: Block,
+        difficulty: usize,
+        version: u64,
+    ) -> Option<Block> {
+        let mut header = block.header();
+        for nonce in 0..u64::MAX {
+            header[NONCE_OFFSET..].copy_from_slice(&nonce.to_le_bytes());
+            let hash = sha256(&header);
+            if meets_difficulty(&hash, difficulty) {
+                block.set_nonce(nonce);
+                block.set_hash(hash);
+                return Some(block);
+            }
+
+            if nonce % BATCH_SIZE == 0
+                && (self.template_version.load(Ordering::SeqCst) != version || !self.should_run(id))
+            {
+                return None;
+            }
+        }
+        None
+    }
+}
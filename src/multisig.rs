@@ -0,0 +1,54 @@
+use crate::hash::{sha256, Hash256};
+use crate::transaction::{verify_ecdsa, PUBKEY_SIZE};
+
+/// Largest key set a multisig address may commit to.
+pub const MAX_KEYS: usize = 15;
+
+const ECDSA_SIZE: usize = 64;
+
+/// The hash a multisig address commits to: SHA-256 over the threshold, the
+/// key count and the compressed public keys in order.
+pub fn redeem_hash(threshold: u8, pubkeys: &[u8]) -> Hash256 {
+    let mut data = vec![threshold, (pubkeys.len() / PUBKEY_SIZE) as u8];
+    data.extend_from_slice(pubkeys);
+    sha256(&data)
+}
+
+/// Checks the signature bytes of a transaction spending from a multisig
+/// address. They are laid out as
+///
+/// ```text
+/// threshold (1) | n (1) | n public keys (33 each) | threshold × (key index (1) | signature (64))
+/// ```
+///
+/// The keys must hash to the address, and the signatures must come from
+/// distinct keys, listed in increasing key order.
+pub fn verify(hash: &Hash256, data: &[u8], bytes: &[u8]) -> Result<(), String> {
+    let malformed = || "Malformed multisig signature".to_string();
+    let (&threshold, rest) = bytes.split_first().ok_or_else(malformed)?;
+    let (&count, rest) = rest.split_first().ok_or_else(malformed)?;
+    let (threshold, count) = (threshold as usize, count as usize);
+    if threshold == 0 || threshold > count || count > MAX_KEYS {
+        return Err(malformed());
+    }
+    if rest.len() != count * PUBKEY_SIZE + threshold * (1 + ECDSA_SIZE) {
+        return Err(malformed());
+    }
+
+    let (pubkeys, signatures) = rest.split_at(count * PUBKEY_SIZE);
+    if redeem_hash(threshold as u8, pubkeys) != *hash {
+        return Err("Multisig keys do not match the sender address".to_string());
+    }
+    let pubkeys: Vec<&[u8]> = pubkeys.chunks(PUBKEY_SIZE).collect();
+
+    let mut next_key = 0;
+    for signature in signatures.chunks(1 + ECDSA_SIZE) {
+        let key = signature[0] as usize;
+        if key < next_key || key >= count {
+            return Err("Multisig signatures out of key order".to_string());
+        }
+        verify_ecdsa(pubkeys[key], data, &signature[1..])?;
+        next_key = key + 1;
+    }
+    Ok(())
+}
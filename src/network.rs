@@ -1,62 +1,105 @@
+use crate::address;
 use crate::block::Block;
 use crate::blockchain::Blockchain;
-use crate::transaction::Transaction;
-use serde_json;
+use crate::chainparams::params;
+use crate::clock;
+use crate::hash::{hash_to_hex, hex_to_hash, Hash256};
+use crate::miner::BackgroundMiner;
+use crate::script;
+use crate::transaction::{Transaction, MAX_DATA_SIZE};
 use std::collections::HashSet;
+use std::sync::atomic::Ordering;
 use std::sync::Arc;
-use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
 use tokio::net::{TcpListener, TcpStream};
 use tokio::sync::Mutex;
 
+/// Longest request the node reads. Every request is one line, ended by a
+/// newline; a full block's JSON fits well within.
+pub const MAX_MESSAGE_SIZE: usize = 1 << 20;
+
+/// Transactions per `GET_HISTORY` page when the request names no limit.
+pub const HISTORY_PAGE_SIZE: usize = 20;
+pub const MAX_HISTORY_PAGE_SIZE: usize = 100;
+
+/// Most filters one `GET_CFILTERS` reply carries.
+pub const MAX_FILTERS_PER_REQUEST: usize = 1000;
+/// Most headers one `GET_CFHEADERS` reply carries.
+pub const MAX_FILTER_HEADERS_PER_REQUEST: usize = 2000;
+/// Most blocks one `GENERATE` mines; the chain stays locked until it is
+/// done.
+pub const MAX_GENERATE: u64 = 1000;
+
 pub struct Node {
     blockchain: Arc<Mutex<Blockchain>>,
     peers: Arc<Mutex<HashSet<String>>>,
+    miner: Arc<BackgroundMiner>,
     address: String,
 }
 
 impl Node {
-    pub fn new(address: String) -> Self {
+    pub fn new(address: String, mining_address: Option<String>, blockchain: Blockchain) -> Self {
+        let template_version = blockchain.template_version();
+        let blockchain = Arc::new(Mutex::new(blockchain));
+        let miner = BackgroundMiner::new(Arc::clone(&blockchain), template_version, mining_address);
         Node {
-            blockchain: Arc::new(Mutex::new(Blockchain::new())),
+            blockchain,
             peers: Arc::new(Mutex::new(HashSet::new())),
+            miner,
             address,
         }
     }
 
+    pub fn set_generate(&self, generate: bool, threads: usize) -> Result<(), String> {
+        self.miner.set_generate(generate, threads)
+    }
+
     pub async fn start(&self) -> Result<(), Box<dyn std::error::Error>> {
         let listener = TcpListener::bind(&self.address).await?;
         println!("Node listening on {}", self.address);
 
         loop {
-            let (mut socket, _) = listener.accept().await?;
+            let (socket, peer) = listener.accept().await?;
+            let local = peer.ip().is_loopback();
             let blockchain = Arc::clone(&self.blockchain);
             let peers = Arc::clone(&self.peers);
-            let address = self.address.clone();
+            let miner = Arc::clone(&self.miner);
             tokio::spawn(async move {
-                Self::handle_connection(socket, blockchain, peers, address).await;
+                Self::handle_connection(socket, local, blockchain, peers, miner).await;
             });
         }
     }
 
+    /// Answers one request. `local` tells whether it came from this
+    /// machine, which the commands controlling the node require.
     async fn handle_connection(
         mut socket: TcpStream,
+        local: bool,
         blockchain: Arc<Mutex<Blockchain>>,
         peers: Arc<Mutex<HashSet<String>>>,
-        address: String,
+        miner: Arc<BackgroundMiner>,
     ) {
-        let mut buffer = [0; 1024];
-        let n = socket.read(&mut buffer).await.unwrap();
-        let message = String::from_utf8_lossy(&buffer[..n]);
+        let message = match Self::read_message(&mut socket).await {
+            Ok(message) => message,
+            Err(e) => {
+                let _ = socket.write_all(format!("Error: {}", e).as_bytes()).await;
+                return;
+            }
+        };
         println!("Received message: {}", message);
 
         let response = match message.trim() {
             _ if message.starts_with("NEW_TRANSACTION:") => {
-                let tx_json = &message[16..];
-                let transaction: Transaction = serde_json::from_str(tx_json).unwrap();
-                let tx_hash = transaction.calculate_hash();
-                println!("Transaction hash: {:?}", tx_hash);
-                blockchain.lock().await.add_transaction(transaction);
-                "Transaction added".to_string()
+                match serde_json::from_str::<Transaction>(&message[16..]) {
+                    Ok(transaction) => {
+                        println!("Transaction id: {}", hash_to_hex(&transaction.id()));
+                        match blockchain.lock().await.add_transaction(transaction) {
+                            Ok(()) => "Transaction added".to_string(),
+                            Err(e) => format!("Transaction rejected: {}", e),
+                        }
+                    }
+                    Err(e) => format!("Error: Invalid transaction: {}", e),
+                }
             }
             _ if message.starts_with("GET_BLOCK_TO_MINE") => {
                 let block_to_mine = blockchain.lock().await.get_block_to_mine();
@@ -66,31 +109,389 @@ impl Node {
                 }
             }
             _ if message.starts_with("MINED_BLOCK:") => {
-                let block: Block = serde_json::from_str(&message[12..]).unwrap();
-                println!("Received mined block with nonce: {}", block.get_nonce());
-                blockchain.lock().await.add_mined_block(block);
-                "Mined block added to blockchain".to_string()
-            }
-            _ if message.starts_with("ADD_PEER:") => {
-                let new_peer = message[9..].to_string();
-                peers.lock().await.insert(new_peer.clone());
-                format!("Peer {} added", new_peer)
-            }
-            "GET_PEERS" => {
-                let peer_list = peers
-                    .lock()
-                    .await
-                    .iter()
-                    .cloned()
-                    .collect::<Vec<String>>()
-                    .join(",");
-                format!("PEER_LIST:{}", peer_list)
+                match serde_json::from_str::<Block>(&message[12..]) {
+                    Ok(block) => {
+                        println!("Received mined block with nonce: {}", block.get_nonce());
+                        let mut blockchain = blockchain.lock().await;
+                        if block.get_prev_hash() != blockchain.get_latest_block().get_hash() {
+                            "Mined block is stale".to_string()
+                        } else if blockchain.add_mined_block(block) {
+                            "Mined block added to blockchain".to_string()
+                        } else {
+                            "Mined block rejected".to_string()
+                        }
+                    }
+                    Err(e) => format!("Error: Invalid block: {}", e),
+                }
+            }
+            _ if message.starts_with("ADD_PEER:") => match Self::check_magic(&message[9..]) {
+                Ok(new_peer) => {
+                    peers.lock().await.insert(new_peer.to_string());
+                    format!("Peer {} added", new_peer)
+                }
+                Err(e) => format!("Error: {}", e),
+            },
+            _ if message.starts_with("SET_GENERATE:") => {
+                let mut args = message[13..].trim().split(':');
+                let generate = args.next() == Some("true");
+                let threads = match args.next().map(str::parse) {
+                    Some(Ok(threads)) => Ok(threads),
+                    Some(Err(_)) => Err("Invalid thread count".to_string()),
+                    None => Ok(miner.status().1.max(1)),
+                };
+                match Self::local_command(local)
+                    .and(threads)
+                    .and_then(|threads| miner.set_generate(generate, threads).map(|()| threads))
+                {
+                    Ok(threads) if generate => {
+                        format!("Generation enabled on {} threads", threads)
+                    }
+                    Ok(_) => "Generation disabled".to_string(),
+                    Err(e) => format!("Error: {}", e),
+                }
+            }
+            _ if message.starts_with("GET_BALANCE:") => {
+                let address = message[12..].trim();
+                match address::validate(address) {
+                    Ok(()) => {
+                        let balance = blockchain.lock().await.balance(address);
+                        serde_json::to_string(&balance).unwrap()
+                    }
+                    Err(e) => format!("Error: {}", e),
+                }
+            }
+            _ if message.starts_with("GET_HISTORY:") => {
+                match Self::parse_history_request(message[12..].trim()) {
+                    Ok((address, offset, limit)) => {
+                        let history = blockchain.lock().await.history(address, offset, limit);
+                        serde_json::to_string(&history).unwrap()
+                    }
+                    Err(e) => format!("Error: {}", e),
+                }
+            }
+            _ if message.starts_with("ESTIMATE_FEE:") => match message[13..].trim().parse() {
+                Ok(target_blocks) => {
+                    let fee_rate = blockchain.lock().await.estimate_fee(target_blocks);
+                    format!(
+                        "{{\"target_blocks\":{},\"fee_rate\":{}}}",
+                        target_blocks, fee_rate
+                    )
+                }
+                Err(_) => "Error: Invalid target".to_string(),
+            },
+            _ if message.starts_with("GET_CFILTERS:") => {
+                match Self::parse_range(message[13..].trim(), MAX_FILTERS_PER_REQUEST) {
+                    Ok((start, count)) => {
+                        let blockchain = blockchain.lock().await;
+                        let filters = blockchain.block_filters(start, count);
+                        format!(
+                            "{{\"tip\":{},\"filters\":{}}}",
+                            blockchain.height(),
+                            serde_json::to_string(&filters).unwrap()
+                        )
+                    }
+                    Err(e) => format!("Error: {}", e),
+                }
+            }
+            _ if message.starts_with("GET_CFHEADERS:") => {
+                match Self::parse_range(message[14..].trim(), MAX_FILTER_HEADERS_PER_REQUEST) {
+                    Ok((start, count)) => {
+                        let headers = blockchain.lock().await.filter_headers(start, count);
+                        serde_json::to_string(&headers).unwrap()
+                    }
+                    Err(e) => format!("Error: {}", e),
+                }
+            }
+            _ if message.starts_with("GET_BLOCK:") => match message[10..].trim().parse() {
+                Ok(height) => match blockchain.lock().await.block_at(height) {
+                    Some(block) => serde_json::to_string(&block).unwrap(),
+                    None => format!("Error: No block at height {}", height),
+                },
+                Err(_) => "Error: Invalid height".to_string(),
+            },
+            _ if message.starts_with("GET_TOKEN_BALANCES:") => {
+                let address = message[19..].trim();
+                match address::validate(address) {
+                    Ok(()) => {
+                        let balances = blockchain.lock().await.token_balances(address);
+                        serde_json::to_string(&balances).unwrap()
+                    }
+                    Err(e) => format!("Error: {}", e),
+                }
+            }
+            _ if message.starts_with("GET_TOKEN:") => {
+                let id = message[10..].trim();
+                match blockchain.lock().await.token(id) {
+                    Some(token) => serde_json::to_string(token).unwrap(),
+                    None => format!("Error: Unknown token {}", id),
+                }
+            }
+            "LIST_TOKENS" => serde_json::to_string(&blockchain.lock().await.tokens()).unwrap(),
+            _ if message.starts_with("FIND_DATA:") => {
+                match Self::parse_payload(message[10..].trim()) {
+                    Ok(payload) => {
+                        let matches = blockchain.lock().await.find_data(&payload);
+                        let found = serde_json::json!({
+                            "data": hex::encode(&payload),
+                            "transactions": matches,
+                        });
+                        found.to_string()
+                    }
+                    Err(e) => format!("Error: {}", e),
+                }
+            }
+            _ if message.starts_with("GETTRANSACTION:") => {
+                let blockchain = blockchain.lock().await;
+                match hex_to_hash(message[15..].trim())
+                    .map_err(|_| "Transaction id must be 64 hex digits".to_string())
+                    .and_then(|id| blockchain.get_transaction(&id))
+                {
+                    Ok(info) => serde_json::to_string(&info).unwrap(),
+                    Err(e) => format!("Error: {}", e),
+                }
+            }
+            _ if message.starts_with("DECODE_SCRIPT:") => {
+                match Self::decode_script(message[14..].trim()) {
+                    Ok(decoded) => decoded,
+                    Err(e) => format!("Error: {}", e),
+                }
+            }
+            _ if message.starts_with("GENERATE:") => {
+                match Self::parse_generate(message[9..].trim()) {
+                    Ok((count, address)) => {
+                        match blockchain.lock().await.generate(count, address) {
+                            Ok(hashes) => {
+                                let hashes: Vec<String> = hashes.iter().map(hash_to_hex).collect();
+                                serde_json::to_string(&hashes).unwrap()
+                            }
+                            Err(e) => format!("Error: {}", e),
+                        }
+                    }
+                    Err(e) => format!("Error: {}", e),
+                }
+            }
+            _ if message.starts_with("SETMOCKTIME:") => {
+                match Self::test_command().and_then(|()| {
+                    message[12..]
+                        .trim()
+                        .parse::<u64>()
+                        .map_err(|_| "Invalid time".to_string())
+                }) {
+                    Ok(0) => {
+                        clock::set_mock_time(0);
+                        "Mock time cleared".to_string()
+                    }
+                    Ok(time) => {
+                        clock::set_mock_time(time);
+                        format!("Mock time set to {}", time)
+                    }
+                    Err(e) => format!("Error: {}", e),
+                }
+            }
+            _ if message.starts_with("INVALIDATEBLOCK:") => {
+                let mut blockchain = blockchain.lock().await;
+                match Self::parse_block_hash(message[16..].trim())
+                    .and_then(|hash| blockchain.invalidate_block(&hash))
+                {
+                    Ok(()) => Self::tip(&blockchain),
+                    Err(e) => format!("Error: {}", e),
+                }
+            }
+            _ if message.starts_with("RECONSIDERBLOCK:") => {
+                let mut blockchain = blockchain.lock().await;
+                match Self::parse_block_hash(message[16..].trim())
+                    .and_then(|hash| blockchain.reconsider_block(&hash))
+                {
+                    Ok(()) => Self::tip(&blockchain),
+                    Err(e) => format!("Error: {}", e),
+                }
+            }
+            "GET_HEIGHT" => blockchain.lock().await.height().to_string(),
+            "GET_TEMPLATE_VERSION" => blockchain
+                .lock()
+                .await
+                .template_version()
+                .load(Ordering::SeqCst)
+                .to_string(),
+            "GET_GENERATE" => {
+                let (generate, threads) = miner.status();
+                format!("GENERATE:{}:{}", generate, threads)
+            }
+            "GET_CHAIN" => blockchain.lock().await.get_chain_json(),
+            _ if message.starts_with("GET_PEERS:") => match Self::check_magic(&message[10..]) {
+                Ok(_) => {
+                    let peer_list = peers
+                        .lock()
+                        .await
+                        .iter()
+                        .cloned()
+                        .collect::<Vec<String>>()
+                        .join(",");
+                    format!("PEER_LIST:{}", peer_list)
+                }
+                Err(e) => format!("Error: {}", e),
+            },
+            "GET_NETWORK" => {
+                let blockchain = blockchain.lock().await;
+                let params = blockchain.params();
+                let genesis = blockchain.block_at(0).unwrap().get_hash();
+                let info = serde_json::json!({
+                    "network": params.network,
+                    "magic": params.magic_hex(),
+                    "genesis": hash_to_hex(&genesis),
+                    "height": blockchain.height(),
+                    "difficulty": blockchain.difficulty(),
+                    "target_block_time": params.target_block_time,
+                    "retarget_interval": params.retarget_interval,
+                    "block_reward": params.block_reward(blockchain.height() + 1),
+                    "halving_interval": params.halving_interval,
+                    "coinbase_maturity": params.coinbase_maturity,
+                    "address_prefix": params.address_prefix,
+                    "default_port": params.default_port,
+                });
+                info.to_string()
             }
             _ => "Unknown command".to_string(),
         };
 
         println!("Sending response: {}", response);
-        socket.write_all(response.as_bytes()).await.unwrap();
+        if let Err(e) = socket.write_all(response.as_bytes()).await {
+            println!("Cannot send response: {}", e);
+        }
+    }
+
+    /// Reads one request: everything up to the first newline, or up to the
+    /// end of the stream from a client that shuts its write half instead.
+    async fn read_message(socket: &mut TcpStream) -> Result<String, String> {
+        let mut reader = BufReader::new(socket).take(MAX_MESSAGE_SIZE as u64 + 1);
+        let mut bytes = Vec::new();
+        reader
+            .read_until(b'\n', &mut bytes)
+            .await
+            .map_err(|e| e.to_string())?;
+        if bytes.last() == Some(&b'\n') {
+            bytes.pop();
+        } else if bytes.len() > MAX_MESSAGE_SIZE {
+            return Err(format!("Message exceeds {} bytes", MAX_MESSAGE_SIZE));
+        }
+        String::from_utf8(bytes).map_err(|_| "Message is not UTF-8".to_string())
+    }
+
+    /// Splits the magic off `<magic>[:rest]`, refusing peers from another
+    /// network, and returns the rest.
+    fn check_magic(args: &str) -> Result<&str, String> {
+        let args = args.trim();
+        let (magic, rest) = args.split_once(':').unwrap_or((args, ""));
+        if magic != params().magic_hex() {
+            return Err(format!("This node is on {}", params().network));
+        }
+        Ok(rest)
+    }
+
+    /// Refuses the commands controlling the node from other machines.
+    fn local_command(local: bool) -> Result<(), String> {
+        if local {
+            Ok(())
+        } else {
+            Err("Only accepted from this machine".to_string())
+        }
+    }
+
+    /// Refuses the test-only commands outside networks that allow them.
+    fn test_command() -> Result<(), String> {
+        if params().test_commands {
+            Ok(())
+        } else {
+            Err(format!("Not available on {}", params().network))
+        }
+    }
+
+    /// Parses `<count>:<address>` for `GENERATE`.
+    fn parse_generate(args: &str) -> Result<(u64, &str), String> {
+        Self::test_command()?;
+        let (count, address) = args.split_once(':').ok_or("Expected <count>:<address>")?;
+        let count = count.parse().map_err(|_| "Invalid count")?;
+        if count > MAX_GENERATE {
+            return Err(format!("At most {} blocks per request", MAX_GENERATE));
+        }
+        address::validate(address)?;
+        Ok((count, address))
+    }
+
+    /// Parses the hex hash of a block to invalidate or reconsider.
+    fn parse_block_hash(hex_hash: &str) -> Result<Hash256, String> {
+        Self::test_command()?;
+        hex_to_hash(hex_hash)
+    }
+
+    /// The tip after a change of chain, as JSON.
+    fn tip(blockchain: &Blockchain) -> String {
+        let tip = serde_json::json!({
+            "height": blockchain.height(),
+            "hash": hash_to_hex(&blockchain.get_latest_block().get_hash()),
+        });
+        tip.to_string()
+    }
+
+    /// Parses `<address>[:offset[:limit]]`.
+    fn parse_history_request(args: &str) -> Result<(&str, usize, usize), String> {
+        let mut args = args.split(':');
+        let address = args.next().unwrap_or_default();
+        address::validate(address)?;
+        let offset = match args.next() {
+            Some(offset) => offset.parse().map_err(|_| "Invalid offset")?,
+            None => 0,
+        };
+        let limit = match args.next() {
+            Some(limit) => limit.parse().map_err(|_| "Invalid limit")?,
+            None => HISTORY_PAGE_SIZE,
+        };
+        Ok((address, offset, usize::min(limit, MAX_HISTORY_PAGE_SIZE)))
+    }
+
+    /// Parses a hex data payload to search for.
+    fn parse_payload(hex_payload: &str) -> Result<Vec<u8>, String> {
+        let payload = hex::decode(hex_payload).map_err(|_| "Data is not hex")?;
+        if payload.is_empty() || payload.len() > MAX_DATA_SIZE {
+            return Err(format!("Data must be 1 to {} bytes", MAX_DATA_SIZE));
+        }
+        Ok(payload)
+    }
+
+    /// Describes a hex locking script: its opcodes, the standard template it
+    /// follows if any, and the address that commits to it.
+    fn decode_script(hex_script: &str) -> Result<String, String> {
+        let bytes = hex::decode(hex_script).map_err(|_| "Script is not hex")?;
+        let kind = match script::classify(&bytes) {
+            Some(script::Standard::PubKey) => "pubkey".to_string(),
+            Some(script::Standard::PubkeyHash) => "pubkeyhash".to_string(),
+            Some(script::Standard::Multisig { threshold, keys }) => {
+                format!("multisig {}-of-{}", threshold, keys)
+            }
+            Some(script::Standard::Htlc) => "htlc".to_string(),
+            None => "nonstandard".to_string(),
+        };
+        let decoded = serde_json::json!({
+            "asm": script::to_asm(&bytes)?,
+            "type": kind,
+            "address": script::address(&bytes),
+        });
+        Ok(decoded.to_string())
+    }
+
+    /// Parses `<start>[:count]`, capping the count at `max`.
+    fn parse_range(args: &str, max: usize) -> Result<(u64, usize), String> {
+        let mut args = args.split(':');
+        let start = args
+            .next()
+            .unwrap_or_default()
+            .parse()
+            .map_err(|_| "Invalid start height")?;
+        let count = match args.next() {
+            Some(count) => count.parse().map_err(|_| "Invalid count")?,
+            None => max,
+        };
+        Ok((start, usize::min(count, max)))
     }
 
     pub async fn broadcast_transaction(&self, transaction: &Transaction) {
@@ -98,17 +499,19 @@ impl Node {
         let message = format!("NEW_TRANSACTION:{}", tx_json);
 
         for peer in self.peers.lock().await.iter() {
-            if let Ok(mut stream) = TcpStream::connect(peer).await {
-                stream.write_all(message.as_bytes()).await.unwrap();
-            }
+            let _ = Self::request(peer, &message).await;
         }
     }
 
+    /// Introduces this node to `address`, keeping it as a peer unless it
+    /// turns out to be on another network.
     pub async fn add_peer(&self, address: String) {
         self.peers.lock().await.insert(address.clone());
-        let message = format!("ADD_PEER:{}", self.address);
-        if let Ok(mut stream) = TcpStream::connect(&address).await {
-            stream.write_all(message.as_bytes()).await.unwrap();
+        let message = format!("ADD_PEER:{}:{}", params().magic_hex(), self.address);
+        if let Ok(reply) = Self::request(&address, &message).await {
+            if reply.starts_with("Error:") {
+                self.peers.lock().await.remove(&address);
+            }
         }
     }
 
@@ -116,16 +519,14 @@ impl Node {
         &self,
         bootstrap_node: &str,
     ) -> Result<(), Box<dyn std::error::Error>> {
-        let mut stream = TcpStream::connect(bootstrap_node).await?;
-        stream.write_all(b"GET_PEERS").await?;
-
-        let mut buffer = [0; 1024];
-        let n = stream.read(&mut buffer).await?;
-        let response = String::from_utf8_lossy(&buffer[..n]);
+        let message = format!("GET_PEERS:{}", params().magic_hex());
+        let response = Self::request(bootstrap_node, &message).await?;
+        if let Some(e) = response.strip_prefix("Error: ") {
+            return Err(format!("Bootstrap node {}: {}", bootstrap_node, e).into());
+        }
 
-        if response.starts_with("PEER_LIST:") {
-            let peer_list = response[10..].split(',');
-            for peer in peer_list {
+        if let Some(peer_list) = response.strip_prefix("PEER_LIST:") {
+            for peer in peer_list.split(',') {
                 if peer != self.address {
                     self.add_peer(peer.to_string()).await;
                 }
@@ -134,4 +535,49 @@ impl Node {
 
         Ok(())
     }
-}
\ No newline at end of file
+
+    /// Initial sync: downloads the blocks `peer` has above this node's tip
+    /// and connects them, skipping signature checks up to the assume-valid
+    /// block if it is among them.
+    pub async fn sync_chain(&self, peer: &str) -> Result<(), Box<dyn std::error::Error>> {
+        let reply = Self::request(peer, "GET_HEIGHT").await?;
+        let peer_height: u64 = reply
+            .trim()
+            .parse()
+            .map_err(|_| format!("Peer {}: unexpected reply {}", peer, reply))?;
+        let start = self.blockchain.lock().await.height() + 1;
+        let mut blocks = Vec::new();
+        for height in start..=peer_height {
+            let reply = Self::request(peer, &format!("GET_BLOCK:{}", height)).await?;
+            if let Some(e) = reply.strip_prefix("Error: ") {
+                return Err(format!("Peer {}: {}", peer, e).into());
+            }
+            let block: Block = serde_json::from_str(&reply)
+                .map_err(|e| format!("Peer {}: bad block at height {}: {}", peer, height, e))?;
+            blocks.push(block);
+        }
+
+        let (synced, assumed) = self
+            .blockchain
+            .lock()
+            .await
+            .sync_blocks(blocks)
+            .map_err(|e| format!("Peer {}: {}", peer, e))?;
+        println!("Synced {} blocks from {}", synced, peer);
+        if assumed > 0 {
+            println!("Signatures of {} of them assumed valid", assumed);
+        }
+        Ok(())
+    }
+
+    /// Sends one message to `peer` and reads the whole reply.
+    async fn request(peer: &str, message: &str) -> Result<String, Box<dyn std::error::Error>> {
+        let mut stream = TcpStream::connect(peer).await?;
+        stream
+            .write_all(format!("{}\n", message).as_bytes())
+            .await?;
+        let mut reply = String::new();
+        stream.read_to_string(&mut reply).await?;
+        Ok(reply)
+    }
+}
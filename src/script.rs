@@ -0,0 +1,556 @@
+use crate::address::{self, PUBKEY_HASH_SIZE};
+use crate::hash::{sha256, Hash256};
+use crate::multisig::MAX_KEYS;
+use crate::transaction::{verify_ecdsa, PUBKEY_SIZE};
+
+// Opcodes keep Bitcoin's numbering where Bitcoin has the same operation.
+pub const OP_0: u8 = 0x00;
+pub const OP_PUSHDATA1: u8 = 0x4c;
+pub const OP_PUSHDATA2: u8 = 0x4d;
+pub const OP_1: u8 = 0x51;
+pub const OP_16: u8 = 0x60;
+pub const OP_IF: u8 = 0x63;
+pub const OP_NOTIF: u8 = 0x64;
+pub const OP_ELSE: u8 = 0x67;
+pub const OP_ENDIF: u8 = 0x68;
+pub const OP_VERIFY: u8 = 0x69;
+pub const OP_RETURN: u8 = 0x6a;
+pub const OP_DROP: u8 = 0x75;
+pub const OP_DUP: u8 = 0x76;
+pub const OP_SWAP: u8 = 0x7c;
+pub const OP_SIZE: u8 = 0x82;
+pub const OP_EQUAL: u8 = 0x87;
+pub const OP_EQUALVERIFY: u8 = 0x88;
+pub const OP_SHA256: u8 = 0xa8;
+/// First 20 bytes of SHA-256, the hash single-key addresses commit to.
+pub const OP_PUBKEYHASH: u8 = 0xa9;
+pub const OP_CHECKSIG: u8 = 0xac;
+pub const OP_CHECKSIGVERIFY: u8 = 0xad;
+pub const OP_CHECKMULTISIG: u8 = 0xae;
+pub const OP_CHECKMULTISIGVERIFY: u8 = 0xaf;
+pub const OP_CHECKLOCKTIMEVERIFY: u8 = 0xb1;
+
+pub const MAX_SCRIPT_SIZE: usize = 10_000;
+pub const MAX_ELEMENT_SIZE: usize = 520;
+pub const MAX_STACK_SIZE: usize = 1000;
+/// Non-push operations one spend may execute, with every key a
+/// `CHECKMULTISIG` checks counted as one more.
+pub const MAX_OPS: usize = 201;
+/// Size of the preimage a standard HTLC is claimed with. Pinning it keeps
+/// a swap from being claimable on one chain but not on the other.
+pub const HTLC_SECRET_SIZE: usize = 32;
+
+/// Largest unlocking script the node relays.
+pub const MAX_STANDARD_UNLOCKING_SIZE: usize = 1650;
+
+/// Lock times below this, whether `CHECKLOCKTIMEVERIFY` operands or a
+/// transaction's `lock_time`, are block heights; from it on they are Unix
+/// times.
+pub const LOCKTIME_THRESHOLD: u64 = 500_000_000;
+
+const ECDSA_SIZE: usize = 64;
+
+/// Where a spend is being checked: the height of the block it goes into, or
+/// of the next block for the mempool, and the median time past before it.
+/// Lock times are measured against the median rather than the block's own
+/// timestamp, which its miner picks.
+#[derive(Debug, Clone, Copy)]
+pub struct Context {
+    pub height: u64,
+    pub time: u64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Op {
+    Push(Vec<u8>),
+    Code(u8),
+}
+
+/// The locking script templates the node relays and mines.
+#[derive(Debug, PartialEq)]
+pub enum Standard {
+    PubKey,
+    PubkeyHash,
+    Multisig {
+        threshold: u8,
+        keys: u8,
+    },
+    /// A hash time-locked contract: the recipient claims with the preimage
+    /// of a SHA-256 hash, or the sender takes the funds back once the lock
+    /// time has passed.
+    Htlc,
+}
+
+/// What a script address commits to.
+pub fn hash(script: &[u8]) -> Hash256 {
+    sha256(script)
+}
+
+pub fn address(script: &[u8]) -> String {
+    address::encode(&hash(script))
+}
+
+/// Splits a script into pushes and opcodes, rejecting truncated pushes and
+/// oversized scripts or elements.
+pub fn parse(script: &[u8]) -> Result<Vec<Op>, String> {
+    if script.len() > MAX_SCRIPT_SIZE {
+        return Err("Script too large".to_string());
+    }
+    let truncated = || "Truncated push in script".to_string();
+    let mut ops = Vec::new();
+    let mut i = 0;
+    while i < script.len() {
+        let opcode = script[i];
+        i += 1;
+        let len = match opcode {
+            0x01..=0x4b => opcode as usize,
+            OP_PUSHDATA1 => {
+                let len = *script.get(i).ok_or_else(truncated)? as usize;
+                i += 1;
+                len
+            }
+            OP_PUSHDATA2 => {
+                let bytes = script.get(i..i + 2).ok_or_else(truncated)?;
+                i += 2;
+                u16::from_le_bytes([bytes[0], bytes[1]]) as usize
+            }
+            _ => {
+                ops.push(Op::Code(opcode));
+                continue;
+            }
+        };
+        if len > MAX_ELEMENT_SIZE {
+            return Err("Push exceeds the element size limit".to_string());
+        }
+        let data = script.get(i..i + len).ok_or_else(truncated)?;
+        ops.push(Op::Push(data.to_vec()));
+        i += len;
+    }
+    Ok(ops)
+}
+
+/// Serializes ops back into script bytes, using the shortest push for each.
+pub fn build(ops: &[Op]) -> Vec<u8> {
+    let mut script = Vec::new();
+    for op in ops {
+        match op {
+            Op::Code(opcode) => script.push(*opcode),
+            Op::Push(data) => {
+                match data.len() {
+                    0..=0x4b => script.push(data.len() as u8),
+                    0x4c..=0xff => script.extend_from_slice(&[OP_PUSHDATA1, data.len() as u8]),
+                    len => {
+                        script.push(OP_PUSHDATA2);
+                        script.extend_from_slice(&(len as u16).to_le_bytes());
+                    }
+                }
+                script.extend_from_slice(data);
+            }
+        }
+    }
+    script
+}
+
+/// Human-readable form, e.g. `OP_DUP OP_PUBKEYHASH <20 bytes hex> ...`.
+pub fn to_asm(script: &[u8]) -> Result<String, String> {
+    let asm: Vec<String> = parse(script)?
+        .iter()
+        .map(|op| match op {
+            Op::Push(data) if data.is_empty() => "OP_0".to_string(),
+            Op::Push(data) => hex::encode(data),
+            Op::Code(opcode) => opcode_name(*opcode),
+        })
+        .collect();
+    Ok(asm.join(" "))
+}
+
+fn opcode_name(opcode: u8) -> String {
+    let name = match opcode {
+        OP_0 => "OP_0",
+        OP_IF => "OP_IF",
+        OP_NOTIF => "OP_NOTIF",
+        OP_ELSE => "OP_ELSE",
+        OP_ENDIF => "OP_ENDIF",
+        OP_VERIFY => "OP_VERIFY",
+        OP_RETURN => "OP_RETURN",
+        OP_DROP => "OP_DROP",
+        OP_DUP => "OP_DUP",
+        OP_SWAP => "OP_SWAP",
+        OP_SIZE => "OP_SIZE",
+        OP_EQUAL => "OP_EQUAL",
+        OP_EQUALVERIFY => "OP_EQUALVERIFY",
+        OP_SHA256 => "OP_SHA256",
+        OP_PUBKEYHASH => "OP_PUBKEYHASH",
+        OP_CHECKSIG => "OP_CHECKSIG",
+        OP_CHECKSIGVERIFY => "OP_CHECKSIGVERIFY",
+        OP_CHECKMULTISIG => "OP_CHECKMULTISIG",
+        OP_CHECKMULTISIGVERIFY => "OP_CHECKMULTISIGVERIFY",
+        OP_CHECKLOCKTIMEVERIFY => "OP_CHECKLOCKTIMEVERIFY",
+        OP_1..=OP_16 => return format!("OP_{}", opcode - OP_1 + 1),
+        _ => return format!("OP_UNKNOWN_{:02x}", opcode),
+    };
+    name.to_string()
+}
+
+/// Runs a spend: the unlocking script, which may only push data, leaves its
+/// stack to the locking script, which must finish with a true value on top.
+/// `data` is what signatures are checked against.
+pub fn verify(
+    unlocking: &[u8],
+    locking: &[u8],
+    data: &[u8],
+    context: &Context,
+) -> Result<(), String> {
+    let unlocking = parse(unlocking)?;
+    let mut stack = Vec::new();
+    for op in unlocking {
+        match op {
+            Op::Push(item) => stack.push(item),
+            Op::Code(OP_0) => stack.push(Vec::new()),
+            Op::Code(opcode @ OP_1..=OP_16) => stack.push(vec![opcode - OP_1 + 1]),
+            Op::Code(_) => return Err("Unlocking script may only push data".to_string()),
+        }
+    }
+    if stack.len() > MAX_STACK_SIZE {
+        return Err("Stack size limit exceeded".to_string());
+    }
+
+    Interpreter {
+        stack,
+        data,
+        context,
+        ops: 0,
+    }
+    .run(&parse(locking)?)
+}
+
+struct Interpreter<'a> {
+    stack: Vec<Vec<u8>>,
+    data: &'a [u8],
+    context: &'a Context,
+    ops: usize,
+}
+
+impl Interpreter<'_> {
+    fn run(mut self, script: &[Op]) -> Result<(), String> {
+        // One entry per open IF: whether its current branch runs.
+        let mut branches: Vec<bool> = Vec::new();
+        for op in script {
+            let executing = branches.iter().all(|&taken| taken);
+            let opcode = match op {
+                Op::Push(data) => {
+                    if executing {
+                        self.push(data.clone())?;
+                    }
+                    continue;
+                }
+                Op::Code(opcode) => *opcode,
+            };
+            if opcode > OP_16 {
+                self.count_ops(1)?;
+            }
+
+            match opcode {
+                OP_IF | OP_NOTIF => {
+                    let mut taken = false;
+                    if executing {
+                        taken = is_true(&self.pop()?);
+                        if opcode == OP_NOTIF {
+                            taken = !taken;
+                        }
+                    }
+                    branches.push(taken);
+                }
+                OP_ELSE => {
+                    let last = branches.last_mut().ok_or("OP_ELSE without OP_IF")?;
+                    *last = !*last;
+                }
+                OP_ENDIF => {
+                    branches.pop().ok_or("OP_ENDIF without OP_IF")?;
+                }
+                _ if !executing => {}
+                _ => self.execute(opcode)?,
+            }
+        }
+        if !branches.is_empty() {
+            return Err("Unbalanced conditional".to_string());
+        }
+        match self.stack.last() {
+            Some(top) if is_true(top) => Ok(()),
+            _ => Err("Script evaluated to false".to_string()),
+        }
+    }
+
+    fn execute(&mut self, opcode: u8) -> Result<(), String> {
+        match opcode {
+            OP_0 => self.push(Vec::new())?,
+            OP_1..=OP_16 => self.push(vec![opcode - OP_1 + 1])?,
+            OP_VERIFY => {
+                if !is_true(&self.pop()?) {
+                    return Err("OP_VERIFY failed".to_string());
+                }
+            }
+            OP_RETURN => return Err("OP_RETURN executed".to_string()),
+            OP_DROP => {
+                self.pop()?;
+            }
+            OP_DUP => {
+                let top = self.stack.last().ok_or("Stack underflow")?.clone();
+                self.push(top)?;
+            }
+            OP_SWAP => {
+                let (b, a) = (self.pop()?, self.pop()?);
+                self.push(b)?;
+                self.push(a)?;
+            }
+            OP_SIZE => {
+                let size = self.stack.last().ok_or("Stack underflow")?.len();
+                self.push(encode_num(size as i64))?;
+            }
+            OP_EQUAL | OP_EQUALVERIFY => {
+                let equal = self.pop()? == self.pop()?;
+                if opcode == OP_EQUALVERIFY {
+                    if !equal {
+                        return Err("OP_EQUALVERIFY failed".to_string());
+                    }
+                } else {
+                    self.push_bool(equal)?;
+                }
+            }
+            OP_SHA256 => {
+                let item = self.pop()?;
+                self.push(sha256(&item).to_vec())?;
+            }
+            OP_PUBKEYHASH => {
+                let item = self.pop()?;
+                self.push(address::pubkey_hash(&item).to_vec())?;
+            }
+            OP_CHECKSIG | OP_CHECKSIGVERIFY => {
+                let pubkey = self.pop()?;
+                let signature = self.pop()?;
+                let valid = self.check_signature(&pubkey, &signature)?;
+                if opcode == OP_CHECKSIGVERIFY {
+                    if !valid {
+                        return Err("OP_CHECKSIGVERIFY failed".to_string());
+                    }
+                } else {
+                    self.push_bool(valid)?;
+                }
+            }
+            OP_CHECKMULTISIG | OP_CHECKMULTISIGVERIFY => {
+                let valid = self.check_multisig()?;
+                if opcode == OP_CHECKMULTISIGVERIFY {
+                    if !valid {
+                        return Err("OP_CHECKMULTISIGVERIFY failed".to_string());
+                    }
+                } else {
+                    self.push_bool(valid)?;
+                }
+            }
+            OP_CHECKLOCKTIMEVERIFY => {
+                let top = self.stack.last().ok_or("Stack underflow")?;
+                let lock_time = decode_num(top, 5)?;
+                if lock_time < 0 {
+                    return Err("Negative locktime".to_string());
+                }
+                let lock_time = lock_time as u64;
+                let reached = if lock_time < LOCKTIME_THRESHOLD {
+                    self.context.height >= lock_time
+                } else {
+                    self.context.time >= lock_time
+                };
+                if !reached {
+                    return Err("Locktime not reached".to_string());
+                }
+            }
+            _ => return Err(format!("Unknown opcode 0x{:02x}", opcode)),
+        }
+        Ok(())
+    }
+
+    /// An empty signature is a deliberate "no"; any other signature must be
+    /// valid, so a failed check cannot be turned into success with OP_NOTIF.
+    fn check_signature(&self, pubkey: &[u8], signature: &[u8]) -> Result<bool, String> {
+        if signature.is_empty() {
+            return Ok(false);
+        }
+        if pubkey.len() != PUBKEY_SIZE || signature.len() != ECDSA_SIZE {
+            return Err("Malformed key or signature".to_string());
+        }
+        verify_ecdsa(pubkey, self.data, signature)?;
+        Ok(true)
+    }
+
+    /// Pops `n`, n keys, `m` and m signatures, then matches the signatures
+    /// against the keys in order. As with `check_signature`, a check may
+    /// only fail with every signature empty.
+    fn check_multisig(&mut self) -> Result<bool, String> {
+        let keys = decode_num(&self.pop()?, 4)?;
+        if !(0..=MAX_KEYS as i64).contains(&keys) {
+            return Err("Too many multisig keys".to_string());
+        }
+        self.count_ops(keys as usize)?;
+        let mut pubkeys = Vec::new();
+        for _ in 0..keys {
+            pubkeys.push(self.pop()?);
+        }
+        pubkeys.reverse();
+
+        let threshold = decode_num(&self.pop()?, 4)?;
+        if !(0..=keys).contains(&threshold) {
+            return Err("Invalid multisig threshold".to_string());
+        }
+        let mut signatures = Vec::new();
+        for _ in 0..threshold {
+            signatures.push(self.pop()?);
+        }
+        signatures.reverse();
+
+        let valid = self.match_signatures(&pubkeys, &signatures);
+        if !valid && signatures.iter().any(|signature| !signature.is_empty()) {
+            return Err("Failed multisig check with non-empty signatures".to_string());
+        }
+        Ok(valid)
+    }
+
+    /// Whether each signature, in order, is valid for a key after the one
+    /// the signature before it matched.
+    fn match_signatures(&self, pubkeys: &[Vec<u8>], signatures: &[Vec<u8>]) -> bool {
+        let mut pubkeys = pubkeys.iter();
+        signatures.iter().all(|signature| {
+            !signature.is_empty()
+                && pubkeys.any(|pubkey| {
+                    pubkey.len() == PUBKEY_SIZE
+                        && signature.len() == ECDSA_SIZE
+                        && verify_ecdsa(pubkey, self.data, signature).is_ok()
+                })
+        })
+    }
+
+    fn count_ops(&mut self, count: usize) -> Result<(), String> {
+        self.ops += count;
+        if self.ops > MAX_OPS {
+            return Err("Operation limit exceeded".to_string());
+        }
+        Ok(())
+    }
+
+    fn push(&mut self, item: Vec<u8>) -> Result<(), String> {
+        if item.len() > MAX_ELEMENT_SIZE {
+            return Err("Element size limit exceeded".to_string());
+        }
+        if self.stack.len() >= MAX_STACK_SIZE {
+            return Err("Stack size limit exceeded".to_string());
+        }
+        self.stack.push(item);
+        Ok(())
+    }
+
+    fn push_bool(&mut self, value: bool) -> Result<(), String> {
+        self.push(if value { vec![1] } else { Vec::new() })
+    }
+
+    fn pop(&mut self) -> Result<Vec<u8>, String> {
+        self.stack
+            .pop()
+            .ok_or_else(|| "Stack underflow".to_string())
+    }
+}
+
+/// Anything but empty, all zeros, or negative zero.
+fn is_true(item: &[u8]) -> bool {
+    match item.split_last() {
+        None => false,
+        Some((&last, rest)) => rest.iter().any(|&b| b != 0) || (last & 0x7f) != 0,
+    }
+}
+
+/// Script numbers are little-endian with the sign in the top bit of the
+/// last byte, as in Bitcoin.
+pub fn encode_num(value: i64) -> Vec<u8> {
+    if value == 0 {
+        return Vec::new();
+    }
+    let mut magnitude = value.unsigned_abs();
+    let mut bytes = Vec::new();
+    while magnitude > 0 {
+        bytes.push(magnitude as u8);
+        magnitude >>= 8;
+    }
+    if bytes.last().unwrap() & 0x80 != 0 {
+        bytes.push(if value < 0 { 0x80 } else { 0 });
+    } else if value < 0 {
+        *bytes.last_mut().unwrap() |= 0x80;
+    }
+    bytes
+}
+
+fn decode_num(bytes: &[u8], max_size: usize) -> Result<i64, String> {
+    if bytes.len() > max_size {
+        return Err("Number too large".to_string());
+    }
+    let Some((&last, _)) = bytes.split_last() else {
+        return Ok(0);
+    };
+    let mut value = 0i64;
+    for (i, &byte) in bytes.iter().enumerate() {
+        value |= (byte as i64) << (8 * i);
+    }
+    if last & 0x80 != 0 {
+        value &= !(0x80i64 << (8 * (bytes.len() - 1)));
+        value = -value;
+    }
+    Ok(value)
+}
+
+/// Which template a locking script follows, if any.
+pub fn classify(script: &[u8]) -> Option<Standard> {
+    let ops = parse(script).ok()?;
+    match ops.as_slice() {
+        [Op::Push(pubkey), Op::Code(OP_CHECKSIG)] if pubkey.len() == PUBKEY_SIZE => {
+            Some(Standard::PubKey)
+        }
+        [Op::Code(OP_DUP), Op::Code(OP_PUBKEYHASH), Op::Push(hash), Op::Code(OP_EQUALVERIFY), Op::Code(OP_CHECKSIG)]
+            if hash.len() == PUBKEY_HASH_SIZE =>
+        {
+            Some(Standard::PubkeyHash)
+        }
+        [Op::Code(m @ OP_1..=OP_16), keys @ .., Op::Code(n @ OP_1..=OP_16), Op::Code(OP_CHECKMULTISIG)] =>
+        {
+            let (threshold, count) = (m - OP_1 + 1, n - OP_1 + 1);
+            let all_keys = keys
+                .iter()
+                .all(|key| matches!(key, Op::Push(key) if key.len() == PUBKEY_SIZE));
+            (all_keys
+                && keys.len() == count as usize
+                && threshold <= count
+                && count as usize <= MAX_KEYS)
+                .then_some(Standard::Multisig {
+                    threshold,
+                    keys: count,
+                })
+        }
+        [Op::Code(OP_IF), Op::Code(OP_SIZE), Op::Push(size), Op::Code(OP_EQUALVERIFY), Op::Code(OP_SHA256), Op::Push(hash), Op::Code(OP_EQUALVERIFY), Op::Code(OP_DUP), Op::Code(OP_PUBKEYHASH), Op::Push(recipient), Op::Code(OP_ELSE), Op::Push(lock_time), Op::Code(OP_CHECKLOCKTIMEVERIFY), Op::Code(OP_DROP), Op::Code(OP_DUP), Op::Code(OP_PUBKEYHASH), Op::Push(refund), Op::Code(OP_ENDIF), Op::Code(OP_EQUALVERIFY), Op::Code(OP_CHECKSIG)]
+            if *size == encode_num(HTLC_SECRET_SIZE as i64)
+                && hash.len() == 32
+                && recipient.len() == PUBKEY_HASH_SIZE
+                && refund.len() == PUBKEY_HASH_SIZE
+                && decode_num(lock_time, 5).is_ok_and(|lock_time| lock_time > 0) =>
+        {
+            Some(Standard::Htlc)
+        }
+        _ => None,
+    }
+}
+
+/// Relay policy on top of the consensus rules: the locking script must follow
+/// a known template and the unlocking script stay small.
+pub fn check_standard(locking: &[u8], unlocking: &[u8]) -> Result<(), String> {
+    if classify(locking).is_none() {
+        return Err("Non-standard locking script".to_string());
+    }
+    if unlocking.len() > MAX_STANDARD_UNLOCKING_SIZE {
+        return Err("Unlocking script too large".to_string());
+    }
+    Ok(())
+}
@@ -0,0 +1,148 @@
+mod flat_file;
+mod kv;
+mod memory;
+
+pub use flat_file::FlatFileStore;
+pub use kv::KvStore;
+pub use memory::MemoryStore;
+
+use crate::block::{Block, BlockHeader};
+use crate::blockchain::Funds;
+use crate::hash::Hash256;
+use crate::token::Ledger;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::path::Path;
+use std::str::FromStr;
+
+/// Where a node keeps its chain: the blocks of the active chain by height,
+/// their headers, the indexes over them and the chain state as of the tip.
+/// Every backend must behave the same; `tests/store.rs` holds them to it.
+pub trait ChainStore: Send {
+    /// Blocks on the active chain, genesis included.
+    fn block_count(&self) -> Result<u64, String>;
+
+    /// Adds `block` at height `block_count()`, along with its compact filter
+    /// and filter header, and indexes its hash and data payloads.
+    fn push_block(
+        &mut self,
+        block: &Block,
+        filter: &[u8],
+        filter_header: &Hash256,
+    ) -> Result<(), String>;
+
+    /// Removes the blocks from `height` up and everything indexed about
+    /// them.
+    fn truncate(&mut self, height: u64) -> Result<(), String>;
+
+    fn block(&self, height: u64) -> Result<Option<Block>, String>;
+
+    fn header(&self, height: u64) -> Result<Option<BlockHeader>, String>;
+
+    /// The compact filter and filter header of the block at `height`.
+    fn filter(&self, height: u64) -> Result<Option<(Vec<u8>, Hash256)>, String>;
+
+    /// Height of the block with `hash` on the active chain.
+    fn height_of(&self, hash: &Hash256) -> Result<Option<u64>, String>;
+
+    /// Height and position of every confirmed transaction carrying
+    /// `payload`, oldest first.
+    fn data_positions(&self, payload: &[u8]) -> Result<Vec<(u64, usize)>, String>;
+
+    /// Records the height and position of every transaction in the block
+    /// at `height` under its id, for the optional transaction index.
+    /// `truncate` drops the entries again along with the block.
+    fn index_transactions(&mut self, height: u64, block: &Block) -> Result<(), String>;
+
+    /// Height and position of the confirmed transaction `id`, if the
+    /// transaction index holds it.
+    fn transaction_position(&self, id: &Hash256) -> Result<Option<(u64, usize)>, String>;
+
+    /// Empties the transaction index.
+    fn clear_transaction_index(&mut self) -> Result<(), String>;
+
+    fn chain_state(&self) -> Result<Option<ChainState>, String>;
+
+    fn set_chain_state(&mut self, state: &ChainState) -> Result<(), String>;
+}
+
+/// What the chain knows beyond its blocks, written after every change to
+/// the tip.
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct ChainState {
+    /// Hash of the tip the state is for. A state left behind by a crash
+    /// between writing a block and writing the state is rebuilt instead.
+    pub tip: Hash256,
+    /// Tokens and token balances as of the tip.
+    pub tokens: Ledger,
+    /// Native balances as of the tip; a state written before they were
+    /// kept has none, and they are rebuilt.
+    #[serde(default)]
+    pub funds: Option<Funds>,
+    /// Blocks marked invalid.
+    pub invalid: Vec<Hash256>,
+    /// Blocks off the active chain.
+    pub side_blocks: Vec<Block>,
+    /// Whether the transaction index covers every block up to the tip.
+    #[serde(default)]
+    pub txindex: bool,
+}
+
+/// The storage backends a node can run on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StoreKind {
+    /// Nothing survives a restart.
+    #[default]
+    Memory,
+    /// Blocks appended to one file, indexes rebuilt when it is opened.
+    Flatfile,
+    /// An embedded key-value database holding blocks and indexes alike.
+    Kv,
+}
+
+impl fmt::Display for StoreKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            StoreKind::Memory => "memory",
+            StoreKind::Flatfile => "flatfile",
+            StoreKind::Kv => "kv",
+        })
+    }
+}
+
+impl FromStr for StoreKind {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, String> {
+        match name {
+            "memory" => Ok(StoreKind::Memory),
+            "flatfile" => Ok(StoreKind::Flatfile),
+            "kv" => Ok(StoreKind::Kv),
+            _ => Err(format!(
+                "Unknown store {} (expected memory, flatfile or kv)",
+                name
+            )),
+        }
+    }
+}
+
+/// Opens a store of `kind` in `dir`, creating it if need be. The memory
+/// store ignores `dir`.
+pub fn open(kind: StoreKind, dir: &Path) -> Result<Box<dyn ChainStore>, String> {
+    Ok(match kind {
+        StoreKind::Memory => Box::new(MemoryStore::default()),
+        StoreKind::Flatfile => Box::new(FlatFileStore::open(dir)?),
+        StoreKind::Kv => Box::new(KvStore::open(dir)?),
+    })
+}
+
+/// Position and payload of every transaction in `block` carrying a data
+/// payload, which is what the data index maps.
+fn data_payloads(block: &Block) -> impl Iterator<Item = (usize, Vec<u8>)> + '_ {
+    block
+        .get_transactions()
+        .iter()
+        .enumerate()
+        .filter_map(|(i, tx)| Some((i, tx.payload().ok()??)))
+}
@@ -0,0 +1,299 @@
+use super::{data_payloads, ChainState, ChainStore};
+use crate::block::{Block, BlockHeader};
+use crate::hash::Hash256;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufReader, ErrorKind, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+const BLOCKS_FILE: &str = "blocks.dat";
+const STATE_FILE: &str = "chainstate.json";
+/// Transaction index entries, each a transaction id followed by height
+/// and position, little-endian.
+const TXINDEX_FILE: &str = "txindex.dat";
+const TXINDEX_ENTRY: usize = 32 + 8 + 8;
+
+/// One block in `blocks.dat`, written as its JSON length, little-endian,
+/// and the JSON itself.
+#[derive(Serialize, Deserialize)]
+struct Record {
+    block: Block,
+    /// Hex of the compact filter.
+    filter: String,
+    filter_header: Hash256,
+}
+
+/// Appends blocks to a single file and keeps the chain state beside it.
+/// The indexes live in memory and are rebuilt from the file on opening;
+/// a record cut short by a crash is dropped then. The transaction index,
+/// being optional, is kept in a file of its own and read back instead.
+pub struct FlatFileStore {
+    dir: PathBuf,
+    file: File,
+    /// Where each block's record starts, by height.
+    offsets: Vec<u64>,
+    headers: Vec<BlockHeader>,
+    heights: HashMap<Hash256, u64>,
+    data_index: HashMap<Vec<u8>, Vec<(u64, usize)>>,
+    txindex: File,
+    transactions: HashMap<Hash256, (u64, usize)>,
+}
+
+impl FlatFileStore {
+    pub fn open(dir: &Path) -> Result<Self, String> {
+        fs::create_dir_all(dir).map_err(|e| format!("Cannot create {}: {}", dir.display(), e))?;
+        let path = dir.join(BLOCKS_FILE);
+        let file = OpenOptions::new()
+            .read(true)
+            .append(true)
+            .create(true)
+            .open(&path)
+            .map_err(|e| format!("Cannot open {}: {}", path.display(), e))?;
+        let txindex_path = dir.join(TXINDEX_FILE);
+        let txindex = OpenOptions::new()
+            .read(true)
+            .append(true)
+            .create(true)
+            .open(&txindex_path)
+            .map_err(|e| format!("Cannot open {}: {}", txindex_path.display(), e))?;
+        let mut store = FlatFileStore {
+            dir: dir.to_path_buf(),
+            file,
+            offsets: Vec::new(),
+            headers: Vec::new(),
+            heights: HashMap::new(),
+            data_index: HashMap::new(),
+            txindex,
+            transactions: HashMap::new(),
+        };
+        store.reindex()?;
+        store.read_txindex()?;
+        Ok(store)
+    }
+
+    /// Reads every record to rebuild the indexes, cutting the file back to
+    /// the last complete one.
+    fn reindex(&mut self) -> Result<(), String> {
+        let mut reader = BufReader::new(self.file.try_clone().map_err(io_error)?);
+        reader.seek(SeekFrom::Start(0)).map_err(io_error)?;
+        let mut offset = 0;
+        while let Some((record, size)) = read_record(&mut reader)? {
+            self.index(&record.block, offset);
+            offset += size;
+        }
+        self.file.set_len(offset).map_err(io_error)
+    }
+
+    /// Loads the transaction index, leaving out entries for blocks no
+    /// longer in the file and a last entry cut short.
+    fn read_txindex(&mut self) -> Result<(), String> {
+        let mut reader = BufReader::new(self.txindex.try_clone().map_err(io_error)?);
+        reader.seek(SeekFrom::Start(0)).map_err(io_error)?;
+        let mut entry = [0u8; TXINDEX_ENTRY];
+        while read_full(&mut reader, &mut entry)? {
+            let (id, height, position) = parse_txindex_entry(&entry);
+            if height < self.offsets.len() as u64 {
+                self.transactions.insert(id, (height, position));
+            }
+        }
+        self.write_txindex()
+    }
+
+    /// Rewrites the transaction index file from memory.
+    fn write_txindex(&mut self) -> Result<(), String> {
+        let bytes: Vec<u8> = self
+            .transactions
+            .iter()
+            .flat_map(|(id, &(height, position))| txindex_entry(id, height, position))
+            .collect();
+        self.txindex.set_len(0).map_err(io_error)?;
+        self.txindex.write_all(&bytes).map_err(io_error)?;
+        self.txindex.sync_data().map_err(io_error)
+    }
+
+    fn index(&mut self, block: &Block, offset: u64) {
+        let height = self.offsets.len() as u64;
+        for (i, payload) in data_payloads(block) {
+            self.data_index
+                .entry(payload)
+                .or_default()
+                .push((height, i));
+        }
+        self.heights.insert(block.get_hash(), height);
+        self.headers.push(block.block_header());
+        self.offsets.push(offset);
+    }
+
+    fn record(&self, height: u64) -> Result<Option<Record>, String> {
+        let Some(&offset) = self.offsets.get(height as usize) else {
+            return Ok(None);
+        };
+        let mut reader = BufReader::new(&self.file);
+        reader.seek(SeekFrom::Start(offset)).map_err(io_error)?;
+        match read_record(&mut reader)? {
+            Some((record, _)) => Ok(Some(record)),
+            None => Err(format!("Block record at height {} is missing", height)),
+        }
+    }
+}
+
+impl ChainStore for FlatFileStore {
+    fn block_count(&self) -> Result<u64, String> {
+        Ok(self.offsets.len() as u64)
+    }
+
+    fn push_block(
+        &mut self,
+        block: &Block,
+        filter: &[u8],
+        filter_header: &Hash256,
+    ) -> Result<(), String> {
+        let record = Record {
+            block: block.clone(),
+            filter: hex::encode(filter),
+            filter_header: *filter_header,
+        };
+        let json = serde_json::to_vec(&record).map_err(|e| e.to_string())?;
+        let offset = self.file.metadata().map_err(io_error)?.len();
+        let mut bytes = (json.len() as u32).to_le_bytes().to_vec();
+        bytes.extend_from_slice(&json);
+        self.file.write_all(&bytes).map_err(io_error)?;
+        self.file.sync_data().map_err(io_error)?;
+        self.index(block, offset);
+        Ok(())
+    }
+
+    fn truncate(&mut self, height: u64) -> Result<(), String> {
+        let Some(&offset) = self.offsets.get(height as usize) else {
+            return Ok(());
+        };
+        self.file.set_len(offset).map_err(io_error)?;
+        self.file.sync_data().map_err(io_error)?;
+        self.offsets.truncate(height as usize);
+        for header in self.headers.drain(height as usize..) {
+            self.heights.remove(&header.hash);
+        }
+        for positions in self.data_index.values_mut() {
+            positions.retain(|&(at, _)| at < height);
+        }
+        self.data_index.retain(|_, positions| !positions.is_empty());
+        let indexed = self.transactions.len();
+        self.transactions.retain(|_, &mut (at, _)| at < height);
+        if self.transactions.len() != indexed {
+            self.write_txindex()?;
+        }
+        Ok(())
+    }
+
+    fn block(&self, height: u64) -> Result<Option<Block>, String> {
+        Ok(self.record(height)?.map(|record| record.block))
+    }
+
+    fn header(&self, height: u64) -> Result<Option<BlockHeader>, String> {
+        Ok(self.headers.get(height as usize).copied())
+    }
+
+    fn filter(&self, height: u64) -> Result<Option<(Vec<u8>, Hash256)>, String> {
+        let Some(record) = self.record(height)? else {
+            return Ok(None);
+        };
+        let filter = hex::decode(&record.filter)
+            .map_err(|_| format!("Malformed filter at height {}", height))?;
+        Ok(Some((filter, record.filter_header)))
+    }
+
+    fn height_of(&self, hash: &Hash256) -> Result<Option<u64>, String> {
+        Ok(self.heights.get(hash).copied())
+    }
+
+    fn data_positions(&self, payload: &[u8]) -> Result<Vec<(u64, usize)>, String> {
+        Ok(self.data_index.get(payload).cloned().unwrap_or_default())
+    }
+
+    fn index_transactions(&mut self, height: u64, block: &Block) -> Result<(), String> {
+        let mut bytes = Vec::new();
+        for (i, tx) in block.get_transactions().iter().enumerate() {
+            bytes.extend(txindex_entry(&tx.id(), height, i));
+        }
+        self.txindex.write_all(&bytes).map_err(io_error)?;
+        self.txindex.sync_data().map_err(io_error)?;
+        for (i, tx) in block.get_transactions().iter().enumerate() {
+            self.transactions.insert(tx.id(), (height, i));
+        }
+        Ok(())
+    }
+
+    fn transaction_position(&self, id: &Hash256) -> Result<Option<(u64, usize)>, String> {
+        Ok(self.transactions.get(id).copied())
+    }
+
+    fn clear_transaction_index(&mut self) -> Result<(), String> {
+        self.transactions.clear();
+        self.write_txindex()
+    }
+
+    fn chain_state(&self) -> Result<Option<ChainState>, String> {
+        let path = self.dir.join(STATE_FILE);
+        match fs::read(&path) {
+            Ok(json) => serde_json::from_slice(&json)
+                .map(Some)
+                .map_err(|e| format!("Invalid {}: {}", path.display(), e)),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(io_error(e)),
+        }
+    }
+
+    /// Writes the state to a temporary file first, so a crash leaves
+    /// either the old state or the new one.
+    fn set_chain_state(&mut self, state: &ChainState) -> Result<(), String> {
+        let json = serde_json::to_vec(state).map_err(|e| e.to_string())?;
+        let path = self.dir.join(STATE_FILE);
+        let temp = path.with_extension("tmp");
+        fs::write(&temp, json).map_err(io_error)?;
+        fs::rename(&temp, &path).map_err(io_error)
+    }
+}
+
+/// The next record and its size in the file, or `None` at the end of the
+/// file or at a record cut short.
+fn read_record(reader: &mut impl Read) -> Result<Option<(Record, u64)>, String> {
+    let mut length = [0u8; 4];
+    if !read_full(reader, &mut length)? {
+        return Ok(None);
+    }
+    let mut json = vec![0u8; u32::from_le_bytes(length) as usize];
+    if !read_full(reader, &mut json)? {
+        return Ok(None);
+    }
+    let record =
+        serde_json::from_slice(&json).map_err(|e| format!("Corrupt block record: {}", e))?;
+    Ok(Some((record, 4 + json.len() as u64)))
+}
+
+fn txindex_entry(id: &Hash256, height: u64, position: usize) -> Vec<u8> {
+    let mut entry = id.to_vec();
+    entry.extend_from_slice(&height.to_le_bytes());
+    entry.extend_from_slice(&(position as u64).to_le_bytes());
+    entry
+}
+
+fn parse_txindex_entry(entry: &[u8; TXINDEX_ENTRY]) -> (Hash256, u64, usize) {
+    let id = entry[..32].try_into().unwrap();
+    let height = u64::from_le_bytes(entry[32..40].try_into().unwrap());
+    let position = u64::from_le_bytes(entry[40..].try_into().unwrap());
+    (id, height, position as usize)
+}
+
+/// Fills `buffer`, or returns false if the file ends first.
+fn read_full(reader: &mut impl Read, buffer: &mut [u8]) -> Result<bool, String> {
+    match reader.read_exact(buffer) {
+        Ok(()) => Ok(true),
+        Err(e) if e.kind() == ErrorKind::UnexpectedEof => Ok(false),
+        Err(e) => Err(io_error(e)),
+    }
+}
+
+fn io_error(e: std::io::Error) -> String {
+    format!("Chain store: {}", e)
+}
@@ -0,0 +1,260 @@
+use super::{data_payloads, ChainState, ChainStore};
+use crate::block::{Block, BlockHeader};
+use crate::hash::Hash256;
+use redb::{Database, ReadableDatabase, ReadableTableMetadata, TableDefinition, TableError};
+use std::fs;
+use std::path::Path;
+
+const DATABASE_FILE: &str = "chain.redb";
+
+/// Block JSON by height.
+const BLOCKS: TableDefinition<u64, &[u8]> = TableDefinition::new("blocks");
+/// Header JSON by height.
+const HEADERS: TableDefinition<u64, &[u8]> = TableDefinition::new("headers");
+/// Filter header followed by the compact filter, by height.
+const FILTERS: TableDefinition<u64, &[u8]> = TableDefinition::new("filters");
+/// Height by block hash.
+const HEIGHTS: TableDefinition<&[u8], u64> = TableDefinition::new("heights");
+/// One key per data payload occurrence, see `data_key`.
+const DATA: TableDefinition<&[u8], ()> = TableDefinition::new("data");
+/// Height and position by transaction id, for the optional transaction
+/// index.
+const TXINDEX: TableDefinition<&[u8], (u64, u64)> = TableDefinition::new("txindex");
+/// Chain state JSON under `STATE_KEY`.
+const STATE: TableDefinition<&str, &[u8]> = TableDefinition::new("state");
+const STATE_KEY: &str = "chain";
+
+/// Keeps blocks, indexes and chain state in an embedded key-value
+/// database. Each change is one transaction, so a crash never leaves a
+/// block half written.
+pub struct KvStore {
+    db: Database,
+}
+
+impl KvStore {
+    pub fn open(dir: &Path) -> Result<Self, String> {
+        fs::create_dir_all(dir).map_err(|e| format!("Cannot create {}: {}", dir.display(), e))?;
+        let path = dir.join(DATABASE_FILE);
+        let db = Database::create(&path)
+            .map_err(|e| format!("Cannot open {}: {}", path.display(), e))?;
+        Ok(KvStore { db })
+    }
+
+    /// Looks `key` up in `table`, treating a table not written yet as
+    /// empty.
+    fn get<K, V, T>(
+        &self,
+        table: TableDefinition<K, V>,
+        key: K::SelfType<'_>,
+        read: impl FnOnce(V::SelfType<'_>) -> Result<T, String>,
+    ) -> Result<Option<T>, String>
+    where
+        K: redb::Key + 'static,
+        V: redb::Value + 'static,
+    {
+        let txn = self.db.begin_read().map_err(kv_error)?;
+        let table = match txn.open_table(table) {
+            Ok(table) => table,
+            Err(TableError::TableDoesNotExist(_)) => return Ok(None),
+            Err(e) => return Err(kv_error(e)),
+        };
+        match table.get(key).map_err(kv_error)? {
+            Some(value) => read(value.value()).map(Some),
+            None => Ok(None),
+        }
+    }
+}
+
+impl ChainStore for KvStore {
+    fn block_count(&self) -> Result<u64, String> {
+        let txn = self.db.begin_read().map_err(kv_error)?;
+        match txn.open_table(BLOCKS) {
+            Ok(table) => table.len().map_err(kv_error),
+            Err(TableError::TableDoesNotExist(_)) => Ok(0),
+            Err(e) => Err(kv_error(e)),
+        }
+    }
+
+    fn push_block(
+        &mut self,
+        block: &Block,
+        filter: &[u8],
+        filter_header: &Hash256,
+    ) -> Result<(), String> {
+        let height = self.block_count()?;
+        let block_json = serde_json::to_vec(block).map_err(|e| e.to_string())?;
+        let header_json = serde_json::to_vec(&block.block_header()).map_err(|e| e.to_string())?;
+        let mut filter_entry = filter_header.to_vec();
+        filter_entry.extend_from_slice(filter);
+
+        let txn = self.db.begin_write().map_err(kv_error)?;
+        {
+            let mut blocks = txn.open_table(BLOCKS).map_err(kv_error)?;
+            blocks
+                .insert(height, block_json.as_slice())
+                .map_err(kv_error)?;
+            let mut headers = txn.open_table(HEADERS).map_err(kv_error)?;
+            headers
+                .insert(height, header_json.as_slice())
+                .map_err(kv_error)?;
+            let mut filters = txn.open_table(FILTERS).map_err(kv_error)?;
+            filters
+                .insert(height, filter_entry.as_slice())
+                .map_err(kv_error)?;
+            let mut heights = txn.open_table(HEIGHTS).map_err(kv_error)?;
+            heights
+                .insert(block.get_hash().as_slice(), height)
+                .map_err(kv_error)?;
+            let mut data = txn.open_table(DATA).map_err(kv_error)?;
+            for (i, payload) in data_payloads(block) {
+                data.insert(data_key(&payload, height, i as u64).as_slice(), ())
+                    .map_err(kv_error)?;
+            }
+        }
+        txn.commit().map_err(kv_error)
+    }
+
+    fn truncate(&mut self, height: u64) -> Result<(), String> {
+        let count = self.block_count()?;
+        if height >= count {
+            return Ok(());
+        }
+        let removed: Vec<Block> = (height..count)
+            .map(|at| {
+                self.block(at)?
+                    .ok_or("Block missing from store".to_string())
+            })
+            .collect::<Result<_, _>>()?;
+
+        let txn = self.db.begin_write().map_err(kv_error)?;
+        {
+            let mut blocks = txn.open_table(BLOCKS).map_err(kv_error)?;
+            let mut headers = txn.open_table(HEADERS).map_err(kv_error)?;
+            let mut filters = txn.open_table(FILTERS).map_err(kv_error)?;
+            let mut heights = txn.open_table(HEIGHTS).map_err(kv_error)?;
+            let mut data = txn.open_table(DATA).map_err(kv_error)?;
+            let mut txindex = txn.open_table(TXINDEX).map_err(kv_error)?;
+            for (at, block) in (height..).zip(&removed) {
+                blocks.remove(at).map_err(kv_error)?;
+                headers.remove(at).map_err(kv_error)?;
+                filters.remove(at).map_err(kv_error)?;
+                heights
+                    .remove(block.get_hash().as_slice())
+                    .map_err(kv_error)?;
+                for (i, payload) in data_payloads(block) {
+                    data.remove(data_key(&payload, at, i as u64).as_slice())
+                        .map_err(kv_error)?;
+                }
+                for tx in block.get_transactions() {
+                    txindex.remove(tx.id().as_slice()).map_err(kv_error)?;
+                }
+            }
+        }
+        txn.commit().map_err(kv_error)
+    }
+
+    fn block(&self, height: u64) -> Result<Option<Block>, String> {
+        self.get(BLOCKS, height, |json| {
+            serde_json::from_slice(json).map_err(|e| format!("Corrupt block: {}", e))
+        })
+    }
+
+    fn header(&self, height: u64) -> Result<Option<BlockHeader>, String> {
+        self.get(HEADERS, height, |json| {
+            serde_json::from_slice(json).map_err(|e| format!("Corrupt header: {}", e))
+        })
+    }
+
+    fn filter(&self, height: u64) -> Result<Option<(Vec<u8>, Hash256)>, String> {
+        self.get(FILTERS, height, |entry| {
+            if entry.len() < 32 {
+                return Err(format!("Corrupt filter at height {}", height));
+            }
+            let (header, filter) = entry.split_at(32);
+            Ok((filter.to_vec(), header.try_into().unwrap()))
+        })
+    }
+
+    fn height_of(&self, hash: &Hash256) -> Result<Option<u64>, String> {
+        self.get(HEIGHTS, hash.as_slice(), Ok)
+    }
+
+    fn data_positions(&self, payload: &[u8]) -> Result<Vec<(u64, usize)>, String> {
+        let txn = self.db.begin_read().map_err(kv_error)?;
+        let data = match txn.open_table(DATA) {
+            Ok(table) => table,
+            Err(TableError::TableDoesNotExist(_)) => return Ok(Vec::new()),
+            Err(e) => return Err(kv_error(e)),
+        };
+        let start = data_key(payload, 0, 0);
+        let end = data_key(payload, u64::MAX, u64::MAX);
+        let mut positions = Vec::new();
+        for entry in data
+            .range(start.as_slice()..=end.as_slice())
+            .map_err(kv_error)?
+        {
+            let (key, _) = entry.map_err(kv_error)?;
+            let key = key.value();
+            let suffix = &key[key.len() - 16..];
+            let height = u64::from_be_bytes(suffix[..8].try_into().unwrap());
+            let i = u64::from_be_bytes(suffix[8..].try_into().unwrap());
+            positions.push((height, i as usize));
+        }
+        Ok(positions)
+    }
+
+    fn index_transactions(&mut self, height: u64, block: &Block) -> Result<(), String> {
+        let txn = self.db.begin_write().map_err(kv_error)?;
+        {
+            let mut txindex = txn.open_table(TXINDEX).map_err(kv_error)?;
+            for (i, tx) in block.get_transactions().iter().enumerate() {
+                txindex
+                    .insert(tx.id().as_slice(), (height, i as u64))
+                    .map_err(kv_error)?;
+            }
+        }
+        txn.commit().map_err(kv_error)
+    }
+
+    fn transaction_position(&self, id: &Hash256) -> Result<Option<(u64, usize)>, String> {
+        self.get(TXINDEX, id.as_slice(), |(height, position)| {
+            Ok((height, position as usize))
+        })
+    }
+
+    fn clear_transaction_index(&mut self) -> Result<(), String> {
+        let txn = self.db.begin_write().map_err(kv_error)?;
+        txn.delete_table(TXINDEX).map_err(kv_error)?;
+        txn.commit().map_err(kv_error)
+    }
+
+    fn chain_state(&self) -> Result<Option<ChainState>, String> {
+        self.get(STATE, STATE_KEY, |json| {
+            serde_json::from_slice(json).map_err(|e| format!("Corrupt chain state: {}", e))
+        })
+    }
+
+    fn set_chain_state(&mut self, state: &ChainState) -> Result<(), String> {
+        let json = serde_json::to_vec(state).map_err(|e| e.to_string())?;
+        let txn = self.db.begin_write().map_err(kv_error)?;
+        {
+            let mut table = txn.open_table(STATE).map_err(kv_error)?;
+            table.insert(STATE_KEY, json.as_slice()).map_err(kv_error)?;
+        }
+        txn.commit().map_err(kv_error)
+    }
+}
+
+/// Payload length, payload, then height and position big-endian, so that
+/// the keys for one payload sort together and by height.
+fn data_key(payload: &[u8], height: u64, position: u64) -> Vec<u8> {
+    let mut key = vec![payload.len() as u8];
+    key.extend_from_slice(payload);
+    key.extend_from_slice(&height.to_be_bytes());
+    key.extend_from_slice(&position.to_be_bytes());
+    key
+}
+
+fn kv_error(e: impl std::fmt::Display) -> String {
+    format!("Chain store: {}", e)
+}
@@ -0,0 +1,100 @@
+use super::{data_payloads, ChainState, ChainStore};
+use crate::block::{Block, BlockHeader};
+use crate::hash::Hash256;
+use std::collections::HashMap;
+
+/// Keeps everything in memory, so nothing survives a restart. Good for
+/// tests and throwaway nodes.
+#[derive(Default)]
+pub struct MemoryStore {
+    blocks: Vec<Block>,
+    filters: Vec<(Vec<u8>, Hash256)>,
+    heights: HashMap<Hash256, u64>,
+    data_index: HashMap<Vec<u8>, Vec<(u64, usize)>>,
+    transactions: HashMap<Hash256, (u64, usize)>,
+    state: Option<ChainState>,
+}
+
+impl ChainStore for MemoryStore {
+    fn block_count(&self) -> Result<u64, String> {
+        Ok(self.blocks.len() as u64)
+    }
+
+    fn push_block(
+        &mut self,
+        block: &Block,
+        filter: &[u8],
+        filter_header: &Hash256,
+    ) -> Result<(), String> {
+        let height = self.blocks.len() as u64;
+        for (i, payload) in data_payloads(block) {
+            self.data_index
+                .entry(payload)
+                .or_default()
+                .push((height, i));
+        }
+        self.heights.insert(block.get_hash(), height);
+        self.filters.push((filter.to_vec(), *filter_header));
+        self.blocks.push(block.clone());
+        Ok(())
+    }
+
+    fn truncate(&mut self, height: u64) -> Result<(), String> {
+        let height = height.min(self.blocks.len() as u64);
+        for block in self.blocks.drain(height as usize..) {
+            self.heights.remove(&block.get_hash());
+        }
+        self.filters.truncate(height as usize);
+        for positions in self.data_index.values_mut() {
+            positions.retain(|&(at, _)| at < height);
+        }
+        self.data_index.retain(|_, positions| !positions.is_empty());
+        self.transactions.retain(|_, &mut (at, _)| at < height);
+        Ok(())
+    }
+
+    fn block(&self, height: u64) -> Result<Option<Block>, String> {
+        Ok(self.blocks.get(height as usize).cloned())
+    }
+
+    fn header(&self, height: u64) -> Result<Option<BlockHeader>, String> {
+        Ok(self.blocks.get(height as usize).map(Block::block_header))
+    }
+
+    fn filter(&self, height: u64) -> Result<Option<(Vec<u8>, Hash256)>, String> {
+        Ok(self.filters.get(height as usize).cloned())
+    }
+
+    fn height_of(&self, hash: &Hash256) -> Result<Option<u64>, String> {
+        Ok(self.heights.get(hash).copied())
+    }
+
+    fn data_positions(&self, payload: &[u8]) -> Result<Vec<(u64, usize)>, String> {
+        Ok(self.data_index.get(payload).cloned().unwrap_or_default())
+    }
+
+    fn index_transactions(&mut self, height: u64, block: &Block) -> Result<(), String> {
+        for (i, tx) in block.get_transactions().iter().enumerate() {
+            self.transactions.insert(tx.id(), (height, i));
+        }
+        Ok(())
+    }
+
+    fn transaction_position(&self, id: &Hash256) -> Result<Option<(u64, usize)>, String> {
+        Ok(self.transactions.get(id).copied())
+    }
+
+    fn clear_transaction_index(&mut self) -> Result<(), String> {
+        self.transactions.clear();
+        Ok(())
+    }
+
+    fn chain_state(&self) -> Result<Option<ChainState>, String> {
+        Ok(self.state.clone())
+    }
+
+    fn set_chain_state(&mut self, state: &ChainState) -> Result<(), String> {
+        self.state = Some(state.clone());
+        Ok(())
+    }
+}
@@ -0,0 +1,277 @@
+use crate::hash::hash_to_hex;
+use crate::transaction::Transaction;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+pub const MAX_NAME_LENGTH: usize = 32;
+pub const MAX_SYMBOL_LENGTH: usize = 8;
+/// Token amounts are whole base units; `decimals` only says where a
+/// wallet puts the point.
+pub const MAX_DECIMALS: u8 = 18;
+
+/// What a transaction does with tokens, on top of any base coin it moves.
+/// Tokens are named by the `token_id` of the transaction that issued them.
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+#[serde(tag = "op", rename_all = "lowercase")]
+pub enum TokenOp {
+    /// Creates a token with the sender as its issuer and credits `supply`
+    /// to the receiver. Only a `mintable` token can grow later.
+    Issue {
+        name: String,
+        symbol: String,
+        decimals: u8,
+        supply: u64,
+        #[serde(default)]
+        mintable: bool,
+    },
+    /// Moves `amount` from the sender to the receiver.
+    Transfer { token: String, amount: u64 },
+    /// Issuer only: credits new units to the receiver.
+    Mint { token: String, amount: u64 },
+    /// Issuer only: destroys units from the issuer's own balance.
+    Burn { token: String, amount: u64 },
+}
+
+impl TokenOp {
+    /// The bytes a transaction signs for its token operation: a byte for
+    /// the kind of operation, then its fields.
+    pub fn signing_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        match self {
+            TokenOp::Issue {
+                name,
+                symbol,
+                decimals,
+                supply,
+                mintable,
+            } => {
+                bytes.push(1);
+                bytes.push(name.len() as u8);
+                bytes.extend_from_slice(name.as_bytes());
+                bytes.push(symbol.len() as u8);
+                bytes.extend_from_slice(symbol.as_bytes());
+                bytes.push(*decimals);
+                bytes.extend_from_slice(&supply.to_le_bytes());
+                bytes.push(*mintable as u8);
+            }
+            TokenOp::Transfer { token, amount } => push_move(&mut bytes, 2, token, *amount),
+            TokenOp::Mint { token, amount } => push_move(&mut bytes, 3, token, *amount),
+            TokenOp::Burn { token, amount } => push_move(&mut bytes, 4, token, *amount),
+        }
+        bytes
+    }
+}
+
+fn push_move(bytes: &mut Vec<u8>, kind: u8, token: &str, amount: u64) {
+    bytes.push(kind);
+    bytes.extend_from_slice(token.as_bytes());
+    bytes.extend_from_slice(&amount.to_le_bytes());
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct Token {
+    /// `token_id` of the issuing transaction, hex.
+    pub id: String,
+    pub name: String,
+    pub symbol: String,
+    pub decimals: u8,
+    pub issuer: String,
+    pub mintable: bool,
+    /// Units in existence after every mint and burn.
+    pub supply: u64,
+}
+
+/// Every token and who holds how much of it.
+#[derive(Clone, Default, Serialize, Deserialize)]
+#[serde(from = "LedgerEntries", into = "LedgerEntries")]
+pub struct Ledger {
+    tokens: HashMap<String, Token>,
+    balances: HashMap<(String, String), u64>,
+}
+
+/// How a `Ledger` is stored: maps keyed by pairs have no JSON form, so
+/// balances are written as token, holder and amount.
+#[derive(Serialize, Deserialize)]
+struct LedgerEntries {
+    tokens: Vec<Token>,
+    balances: Vec<(String, String, u64)>,
+}
+
+impl From<Ledger> for LedgerEntries {
+    fn from(ledger: Ledger) -> Self {
+        LedgerEntries {
+            tokens: ledger.tokens.into_values().collect(),
+            balances: ledger
+                .balances
+                .into_iter()
+                .map(|((token, holder), amount)| (token, holder, amount))
+                .collect(),
+        }
+    }
+}
+
+impl From<LedgerEntries> for Ledger {
+    fn from(entries: LedgerEntries) -> Self {
+        Ledger {
+            tokens: entries
+                .tokens
+                .into_iter()
+                .map(|token| (token.id.clone(), token))
+                .collect(),
+            balances: entries
+                .balances
+                .into_iter()
+                .map(|(token, holder, amount)| ((token, holder), amount))
+                .collect(),
+        }
+    }
+}
+
+impl Ledger {
+    pub fn token(&self, id: &str) -> Option<&Token> {
+        self.tokens.get(id)
+    }
+
+    pub fn tokens(&self) -> impl Iterator<Item = &Token> {
+        self.tokens.values()
+    }
+
+    pub fn balance(&self, token: &str, address: &str) -> u64 {
+        self.balances
+            .get(&(token.to_string(), address.to_string()))
+            .copied()
+            .unwrap_or(0)
+    }
+
+    /// Tokens `address` holds any of, with the amounts.
+    pub fn holdings(&self, address: &str) -> Vec<(&Token, u64)> {
+        self.balances
+            .iter()
+            .filter(|((_, holder), amount)| holder == address && **amount > 0)
+            .map(|((token, _), amount)| (&self.tokens[token], *amount))
+            .collect()
+    }
+
+    /// Applies a transaction's token operation, or leaves the ledger as it
+    /// was and says why it cannot. Transactions without one always apply.
+    pub fn apply(&mut self, tx: &Transaction) -> Result<(), String> {
+        let Some(op) = &tx.token else {
+            return Ok(());
+        };
+        match op {
+            TokenOp::Issue {
+                name,
+                symbol,
+                decimals,
+                supply,
+                mintable,
+            } => {
+                check_name(name, symbol)?;
+                if *decimals > MAX_DECIMALS {
+                    return Err(format!("Decimals cannot exceed {}", MAX_DECIMALS));
+                }
+                if *supply == 0 && !mintable {
+                    return Err("A token that cannot be minted needs a supply".to_string());
+                }
+                let id = hash_to_hex(&tx.token_id());
+                if self.tokens.contains_key(&id) {
+                    return Err("Token already issued".to_string());
+                }
+                self.tokens.insert(
+                    id.clone(),
+                    Token {
+                        id: id.clone(),
+                        name: name.clone(),
+                        symbol: symbol.clone(),
+                        decimals: *decimals,
+                        issuer: tx.sender.clone(),
+                        mintable: *mintable,
+                        supply: *supply,
+                    },
+                );
+                self.credit(&id, &tx.receiver, *supply)
+            }
+            TokenOp::Transfer { token, amount } => {
+                self.existing(token, *amount)?;
+                self.debit(token, &tx.sender, *amount)?;
+                self.credit(token, &tx.receiver, *amount)
+            }
+            TokenOp::Mint { token, amount } => {
+                let existing = self.existing(token, *amount)?;
+                if existing.issuer != tx.sender {
+                    return Err("Only the issuer can mint".to_string());
+                }
+                if !existing.mintable {
+                    return Err("Token cannot be minted".to_string());
+                }
+                let supply = existing
+                    .supply
+                    .checked_add(*amount)
+                    .ok_or("Token supply overflow")?;
+                self.credit(token, &tx.receiver, *amount)?;
+                self.tokens.get_mut(token).unwrap().supply = supply;
+                Ok(())
+            }
+            TokenOp::Burn { token, amount } => {
+                if self.existing(token, *amount)?.issuer != tx.sender {
+                    return Err("Only the issuer can burn".to_string());
+                }
+                self.debit(token, &tx.sender, *amount)?;
+                self.tokens.get_mut(token).unwrap().supply -= amount;
+                Ok(())
+            }
+        }
+    }
+
+    fn existing(&self, token: &str, amount: u64) -> Result<&Token, String> {
+        if amount == 0 {
+            return Err("Token amount must be positive".to_string());
+        }
+        self.tokens
+            .get(token)
+            .ok_or_else(|| format!("Unknown token {}", token))
+    }
+
+    fn credit(&mut self, token: &str, address: &str, amount: u64) -> Result<(), String> {
+        let balance = self
+            .balances
+            .entry((token.to_string(), address.to_string()))
+            .or_default();
+        *balance = balance
+            .checked_add(amount)
+            .ok_or("Token balance overflow")?;
+        Ok(())
+    }
+
+    fn debit(&mut self, token: &str, address: &str, amount: u64) -> Result<(), String> {
+        let key = (token.to_string(), address.to_string());
+        let balance = self.balances.get(&key).copied().unwrap_or(0);
+        if balance < amount {
+            return Err(format!(
+                "Insufficient token balance: {} held, {} needed",
+                balance, amount
+            ));
+        }
+        self.balances.insert(key, balance - amount);
+        Ok(())
+    }
+}
+
+fn check_name(name: &str, symbol: &str) -> Result<(), String> {
+    if name.is_empty() || name.len() > MAX_NAME_LENGTH || name.chars().any(char::is_control) {
+        return Err(format!(
+            "Token name must be 1 to {} printable bytes",
+            MAX_NAME_LENGTH
+        ));
+    }
+    if symbol.is_empty()
+        || symbol.len() > MAX_SYMBOL_LENGTH
+        || !symbol.chars().all(|c| c.is_ascii_alphanumeric())
+    {
+        return Err(format!(
+            "Token symbol must be 1 to {} letters or digits",
+            MAX_SYMBOL_LENGTH
+        ));
+    }
+    Ok(())
+}
@@ -1,32 +1,266 @@
-use crate::hash::{calculate_hash, Hash256};
+use crate::address::{self, Payload};
+use crate::clock;
+use crate::hash::{calculate_hash, sha256, Hash256};
+use crate::multisig;
+use crate::script::{self, Context, LOCKTIME_THRESHOLD};
+use crate::token::TokenOp;
+use k256::ecdsa::signature::Verifier;
+use k256::ecdsa::{Signature, VerifyingKey};
 use serde::{Deserialize, Serialize};
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::fmt;
+
+pub const COINBASE_SENDER: &str = "Coinbase";
+
+/// A signature is the sender's 33-byte compressed public key followed by a
+/// 64-byte compact ECDSA signature, hex-encoded.
+pub const PUBKEY_SIZE: usize = 33;
+pub const SIGNATURE_SIZE: usize = PUBKEY_SIZE + 64;
+
+/// Most bytes a transaction's `data` payload may carry.
+pub const MAX_DATA_SIZE: usize = 80;
 
 #[derive(Clone, Serialize, Deserialize, Debug)]
 pub struct Transaction {
     pub sender: String,
     pub receiver: String,
     pub amount: f64,
+    /// Paid to the miner of the block that includes the transaction.
+    #[serde(default)]
+    pub fee: f64,
     pub timestamp: u64,
+    /// How many transactions the sender sent before this one. Each nonce
+    /// is accepted once and in order, so a signed transaction cannot be
+    /// replayed.
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub nonce: u64,
+    /// Hex locking script when the sender is a script address, which
+    /// commits to its hash. The signature slot then holds the hex unlocking
+    /// script.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub script: Option<String>,
+    /// Earliest block the transaction may go into: a height below
+    /// `LOCKTIME_THRESHOLD`, otherwise a Unix time the median time past of
+    /// the chain must have reached. Zero means unlocked.
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub lock_time: u64,
+    /// How many blocks must have passed since the sender last received
+    /// funds. Zero means unlocked.
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub relative_lock: u64,
+    /// Hex payload of 1 to `MAX_DATA_SIZE` bytes, such as a document hash,
+    /// signed along with everything else.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub data: Option<String>,
+    /// Token issue, transfer, mint or burn carried alongside the coin
+    /// amount, which may then be zero.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub token: Option<TokenOp>,
     pub signature: Option<String>,
 }
 
 impl Transaction {
     pub fn new(sender: String, receiver: String, amount: f64) -> Self {
-        let timestamp = SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .unwrap()
-            .as_secs();
+        let timestamp = clock::now();
 
         Transaction {
             sender,
             receiver,
             amount,
+            fee: 0.0,
             timestamp,
+            nonce: 0,
+            script: None,
+            lock_time: 0,
+            relative_lock: 0,
+            data: None,
+            token: None,
             signature: None,
         }
     }
 
+    /// The transaction paying the reward of the block at `height` to
+    /// `receiver`. The height goes in its nonce, so no two coinbases on a
+    /// chain share an id, and its signature slot carries `extra_nonce`,
+    /// since a coinbase has nobody to sign it.
+    pub fn coinbase(receiver: String, amount: f64, height: u64, extra_nonce: u64) -> Self {
+        let mut coinbase = Transaction::new(COINBASE_SENDER.to_string(), receiver, amount);
+        coinbase.nonce = height;
+        coinbase.signature = Some(format!("{:016x}", extra_nonce));
+        coinbase
+    }
+
+    /// Rejects transactions whose sender or receiver is not a well-formed
+    /// address, so a mistyped receiver never makes it into a block.
+    pub fn validate_addresses(&self) -> Result<(), String> {
+        if self.sender != COINBASE_SENDER {
+            address::validate(&self.sender)?;
+        }
+        address::validate(&self.receiver)
+    }
+
+    /// The bytes a sender signs: every field except the signature itself.
+    /// Blocks commit to the same bytes followed by the signature. The
+    /// nonce, the locking script, each lock, the data payload and the token
+    /// operation are only written when set, each as a tag byte, its length as four
+    /// little-endian bytes and then its contents, so none can pass for
+    /// another or run into the next.
+    pub fn signing_data(&self) -> Vec<u8> {
+        let mut data = Vec::new();
+        data.extend_from_slice(self.sender.as_bytes());
+        data.extend_from_slice(self.receiver.as_bytes());
+        data.extend_from_slice(&self.amount.to_le_bytes());
+        data.extend_from_slice(&self.fee.to_le_bytes());
+        data.extend_from_slice(&self.timestamp.to_le_bytes());
+        if self.nonce != 0 {
+            push_field(&mut data, FIELD_NONCE, &self.nonce.to_le_bytes());
+        }
+        if let Some(script) = &self.script {
+            push_field(&mut data, FIELD_SCRIPT, script.as_bytes());
+        }
+        if self.lock_time != 0 {
+            push_field(&mut data, FIELD_LOCK_TIME, &self.lock_time.to_le_bytes());
+        }
+        if self.relative_lock != 0 {
+            push_field(
+                &mut data,
+                FIELD_RELATIVE_LOCK,
+                &self.relative_lock.to_le_bytes(),
+            );
+        }
+        if let Some(payload) = &self.data {
+            push_field(&mut data, FIELD_DATA, payload.as_bytes());
+        }
+        if let Some(token) = &self.token {
+            push_field(&mut data, FIELD_TOKEN, &token.signing_bytes());
+        }
+        data
+    }
+
+    /// Bytes the transaction takes up in a block, which is what fees are
+    /// charged against.
+    pub fn size(&self) -> usize {
+        self.signing_data().len() + self.signature.as_ref().map_or(0, String::len)
+    }
+
+    /// Fee paid per 1000 bytes.
+    pub fn fee_rate(&self) -> f64 {
+        self.fee * 1000.0 / self.size() as f64
+    }
+
+    /// Identifies the transaction, signature included.
+    pub fn id(&self) -> Hash256 {
+        let mut data = self.signing_data();
+        if let Some(signature) = &self.signature {
+            data.extend_from_slice(signature.as_bytes());
+        }
+        sha256(&data)
+    }
+
+    /// Id of the token the transaction issues, if it does: the hash of
+    /// `signing_data`. Unlike `id`, it leaves out the signature, which
+    /// anyone relaying the transaction could encode differently.
+    pub fn token_id(&self) -> Hash256 {
+        sha256(&self.signing_data())
+    }
+
+    /// Checks that the signature was made over `signing_data` by the key
+    /// whose hash the sender address commits to, or, for a multisig sender,
+    /// by enough keys of the set it commits to. A spend revealing a locking
+    /// script instead runs it against the unlocking script at `context`.
+    pub fn verify_signature(&self, context: &Context) -> Result<(), String> {
+        let signature = self
+            .signature
+            .as_deref()
+            .ok_or("Transaction is not signed")?;
+        let bytes = hex::decode(signature).map_err(|_| "Malformed signature")?;
+
+        if let Some(locking) = self.locking_script()? {
+            let Payload::ScriptHash(hash) = address::decode(&self.sender)? else {
+                return Err("Only a script address can spend with a script".to_string());
+            };
+            if script::hash(&locking) != hash {
+                return Err("Script does not match the sender address".to_string());
+            }
+            return script::verify(&bytes, &locking, &self.signing_data(), context);
+        }
+
+        match address::decode(&self.sender)? {
+            Payload::PubkeyHash(hash) => {
+                if bytes.len() != SIGNATURE_SIZE {
+                    return Err("Malformed signature".to_string());
+                }
+                let (pubkey, signature) = bytes.split_at(PUBKEY_SIZE);
+                if address::pubkey_hash(pubkey) != hash {
+                    return Err("Signature key does not match the sender address".to_string());
+                }
+                verify_ecdsa(pubkey, &self.signing_data(), signature)
+            }
+            Payload::ScriptHash(hash) => multisig::verify(&hash, &self.signing_data(), &bytes),
+        }
+    }
+
+    pub fn locking_script(&self) -> Result<Option<Vec<u8>>, String> {
+        self.script
+            .as_deref()
+            .map(|script| hex::decode(script).map_err(|_| "Malformed script".to_string()))
+            .transpose()
+    }
+
+    /// The decoded `data` payload, checked against `MAX_DATA_SIZE`.
+    pub fn payload(&self) -> Result<Option<Vec<u8>>, String> {
+        let Some(data) = self.data.as_deref() else {
+            return Ok(None);
+        };
+        let bytes = hex::decode(data).map_err(|_| "Malformed data payload")?;
+        if bytes.is_empty() || bytes.len() > MAX_DATA_SIZE {
+            return Err(format!("Data payload must be 1 to {} bytes", MAX_DATA_SIZE));
+        }
+        Ok(Some(bytes))
+    }
+
+    /// Whether `lock_time` allows the transaction into a block at `context`.
+    pub fn check_lock_time(&self, context: &Context) -> Result<(), String> {
+        if self.lock_time < LOCKTIME_THRESHOLD {
+            if context.height < self.lock_time {
+                return Err(format!(
+                    "Transaction is locked until height {}",
+                    self.lock_time
+                ));
+            }
+        } else if context.time < self.lock_time {
+            return Err(format!(
+                "Transaction is locked until time {}",
+                self.lock_time
+            ));
+        }
+        Ok(())
+    }
+
+    /// Amount, address, payload, lock time and signature checks for
+    /// anything but a coinbase.
+    pub fn validate(&self, context: &Context) -> Result<(), String> {
+        self.validate_structure(context)?;
+        if self.sender != COINBASE_SENDER {
+            self.verify_signature(context)?;
+        }
+        Ok(())
+    }
+
+    /// Everything `validate` checks except the signature, for blocks below
+    /// the assume-valid block.
+    pub fn validate_structure(&self, context: &Context) -> Result<(), String> {
+        let moves_tokens = self.token.is_some() && self.amount == 0.0;
+        if !(self.amount > 0.0 && self.amount.is_finite() || moves_tokens) {
+            return Err("Amount must be positive".to_string());
+        }
+        if !(self.fee >= 0.0 && self.fee.is_finite()) {
+            return Err("Fee cannot be negative".to_string());
+        }
+        self.validate_addresses()?;
+        self.payload()?;
+        self.check_lock_time(context)
+    }
+
     pub fn calculate_hash(&self) -> Hash256 {
         let data = format!(
             "{}{}{}{}",
@@ -43,17 +277,6 @@ impl Transaction {
         self.signature.is_some()
     }
 
-    pub fn to_string(&self) -> String {
-        format!(
-            "From: {} To: {} Amount: {} Time: {} Signed: {}",
-            self.sender,
-            self.receiver,
-            self.amount,
-            self.timestamp,
-            self.is_signed()
-        )
-    }
-
     pub fn from_json(json: &str) -> Self {
         serde_json::from_str(json).unwrap()
     }
@@ -62,3 +285,44 @@ impl Transaction {
         serde_json::to_string(self).unwrap()
     }
 }
+
+/// Tags of the optional fields in `signing_data`.
+const FIELD_SCRIPT: u8 = 1;
+const FIELD_LOCK_TIME: u8 = 2;
+const FIELD_RELATIVE_LOCK: u8 = 3;
+const FIELD_DATA: u8 = 4;
+const FIELD_TOKEN: u8 = 5;
+const FIELD_NONCE: u8 = 6;
+
+fn push_field(data: &mut Vec<u8>, tag: u8, bytes: &[u8]) {
+    data.push(tag);
+    data.extend_from_slice(&(bytes.len() as u32).to_le_bytes());
+    data.extend_from_slice(bytes);
+}
+
+fn is_zero(value: &u64) -> bool {
+    *value == 0
+}
+
+/// Checks one compact ECDSA signature over `data` by a compressed public key.
+pub fn verify_ecdsa(pubkey: &[u8], data: &[u8], signature: &[u8]) -> Result<(), String> {
+    let key = VerifyingKey::from_sec1_bytes(pubkey).map_err(|_| "Malformed public key")?;
+    let signature = Signature::from_slice(signature).map_err(|_| "Malformed signature")?;
+    key.verify(data, &signature)
+        .map_err(|_| "Invalid signature".to_string())
+}
+
+impl fmt::Display for Transaction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "From: {} To: {} Amount: {} Fee: {} Time: {} Signed: {}",
+            self.sender,
+            self.receiver,
+            self.amount,
+            self.fee,
+            self.timestamp,
+            self.is_signed()
+        )
+    }
+}
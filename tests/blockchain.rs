@@ -0,0 +1,527 @@
+//! Rules a `Blockchain` holds transactions and blocks to.
+
+use fluerion::address;
+use fluerion::block::Block;
+use fluerion::blockchain::{Blockchain, Direction, MAX_FUTURE_BLOCK_TIME};
+use fluerion::chainparams::{self, ChainParams};
+use fluerion::clock;
+use fluerion::transaction::Transaction;
+use k256::ecdsa::signature::Signer;
+use k256::ecdsa::{Signature, SigningKey};
+
+const MINER: &str = "flrt14sdcqezufk9g8pnn0sve5yk2wqfllsg02qxxv2";
+const RECEIVER: &str = "flrt13v0g8knefk035awswd2pvthk4wsd3w55kgxdzx";
+
+fn regtest_chain() -> Blockchain {
+    let _ = chainparams::select(ChainParams::regtest());
+    Blockchain::new()
+}
+
+fn key(seed: u8) -> SigningKey {
+    SigningKey::from_bytes(&[seed; 32].into()).unwrap()
+}
+
+fn address_of(key: &SigningKey) -> String {
+    let pubkey = key.verifying_key().to_encoded_point(true);
+    address::encode(&address::pubkey_hash(pubkey.as_bytes()))
+}
+
+/// Mines a block paying `key`'s address and enough on top for the payment
+/// to mature, and returns what it paid.
+fn fund(blockchain: &mut Blockchain, key: &SigningKey) -> f64 {
+    let address = address_of(key);
+    blockchain.generate(1, &address).unwrap();
+    let maturity = chainparams::params().coinbase_maturity;
+    blockchain.generate(maturity, MINER).unwrap();
+    blockchain.get_balance(&address)
+}
+
+/// The first payment from `key`'s address, signed.
+fn pay(key: &SigningKey, receiver: &str, amount: f64) -> Transaction {
+    pay_nth(key, 0, receiver, amount)
+}
+
+/// A payment from `key`'s address after the `nonce` it sent before it,
+/// signed.
+fn pay_nth(key: &SigningKey, nonce: u64, receiver: &str, amount: f64) -> Transaction {
+    let mut transaction = Transaction::new(address_of(key), receiver.to_string(), amount);
+    transaction.fee = 0.01;
+    transaction.nonce = nonce;
+    sign(key, &mut transaction);
+    transaction
+}
+
+fn sign(key: &SigningKey, transaction: &mut Transaction) {
+    let signed: Signature = key.sign(&transaction.signing_data());
+    let mut signature = key
+        .verifying_key()
+        .to_encoded_point(true)
+        .as_bytes()
+        .to_vec();
+    signature.extend_from_slice(&signed.to_bytes());
+    transaction.sign(hex::encode(signature));
+}
+
+/// A block of `transactions` on top of the tip, led by a coinbase paying
+/// the reward and their fees to `MINER`, timestamped as a template would be.
+fn mine(blockchain: &Blockchain, transactions: Vec<Transaction>) -> Block {
+    let earliest = blockchain.median_time_past(blockchain.height() + 1) + 1;
+    mine_at(blockchain, transactions, clock::now().max(earliest))
+}
+
+fn mine_at(blockchain: &Blockchain, transactions: Vec<Transaction>, timestamp: u64) -> Block {
+    let height = blockchain.height() + 1;
+    let fees: f64 = transactions.iter().map(|tx| tx.fee).sum();
+    let reward = chainparams::params().block_reward(height);
+    let mut all = vec![Transaction::coinbase(
+        MINER.to_string(),
+        reward + fees,
+        height,
+        0,
+    )];
+    all.extend(transactions);
+    seal(blockchain, all, timestamp)
+}
+
+/// A block of `transactions`, coinbase included, on top of the tip, with a
+/// nonce that meets the difficulty.
+fn seal(blockchain: &Blockchain, transactions: Vec<Transaction>, timestamp: u64) -> Block {
+    let mut block = Block::new(transactions, blockchain.get_latest_block().get_hash());
+    block.set_timestamp(timestamp);
+    let nonce = (0..)
+        .find(|&nonce| {
+            block.set_nonce(nonce);
+            blockchain.valid_proof(&block.calculate_hash())
+        })
+        .unwrap();
+    block.set_nonce(nonce);
+    block.set_hash(block.calculate_hash());
+    block
+}
+
+#[test]
+fn rejects_a_spend_beyond_the_balance() {
+    let mut blockchain = regtest_chain();
+    let sender = key(1);
+    let balance = fund(&mut blockchain, &sender);
+
+    let e = blockchain
+        .add_transaction(pay(&sender, RECEIVER, balance))
+        .unwrap_err();
+    assert!(e.starts_with("Insufficient funds"), "{}", e);
+
+    let unfunded = key(2);
+    let e = blockchain
+        .add_transaction(pay(&unfunded, RECEIVER, 1.0))
+        .unwrap_err();
+    assert!(e.starts_with("Insufficient funds"), "{}", e);
+}
+
+#[test]
+fn rejects_a_replayed_spend() {
+    let mut blockchain = regtest_chain();
+    let sender = key(15);
+    fund(&mut blockchain, &sender);
+
+    // The same signed payment twice in one block.
+    let payment = pay(&sender, RECEIVER, 5.0);
+    let block = mine(&blockchain, vec![payment.clone(), payment.clone()]);
+    assert!(!blockchain.add_mined_block(block));
+
+    // And again once it is mined, straight into a block or via the mempool.
+    let block = mine(&blockchain, vec![payment.clone()]);
+    assert!(blockchain.add_mined_block(block));
+    let block = mine(&blockchain, vec![payment.clone()]);
+    assert!(!blockchain.add_mined_block(block.clone()));
+    assert!(blockchain.sync_blocks(vec![block]).is_err());
+    assert!(blockchain.add_transaction(payment).is_err());
+    assert_eq!(blockchain.get_balance(RECEIVER), 5.0);
+}
+
+#[test]
+fn counts_pending_spends() {
+    let mut blockchain = regtest_chain();
+    let sender = key(3);
+    let from = address_of(&sender);
+    let half = fund(&mut blockchain, &sender) / 2.0;
+
+    blockchain
+        .add_transaction(pay(&sender, RECEIVER, half))
+        .unwrap();
+    assert!((blockchain.balance(&from).spendable - (half - 0.01)).abs() < 1e-9);
+    let e = blockchain
+        .add_transaction(pay_nth(&sender, 1, RECEIVER, half))
+        .unwrap_err();
+    assert!(e.starts_with("Insufficient funds"), "{}", e);
+
+    // Pending payments to an address are not spendable yet.
+    let receiver = key(4);
+    blockchain
+        .add_transaction(pay_nth(&sender, 1, &address_of(&receiver), 1.0))
+        .unwrap();
+    assert!(blockchain
+        .add_transaction(pay(&receiver, RECEIVER, 0.5))
+        .is_err());
+}
+
+#[test]
+fn splits_confirmed_and_unconfirmed_balances() {
+    let mut blockchain = regtest_chain();
+    let sender = key(16);
+    let (from, to) = (address_of(&sender), address_of(&key(17)));
+    let funds = fund(&mut blockchain, &sender);
+
+    blockchain.add_transaction(pay(&sender, &to, 10.0)).unwrap();
+    let balance = blockchain.balance(&from);
+    assert_eq!(balance.confirmed, funds);
+    assert!((balance.unconfirmed + 10.01).abs() < 1e-9);
+    assert!((balance.spendable - (funds - 10.01)).abs() < 1e-9);
+    assert_eq!(balance.next_nonce, 1);
+    let balance = blockchain.balance(&to);
+    assert_eq!((balance.confirmed, balance.unconfirmed), (0.0, 10.0));
+    assert_eq!(balance.spendable, 0.0);
+
+    blockchain.generate(1, MINER).unwrap();
+    let balance = blockchain.balance(&from);
+    assert!((balance.confirmed - (funds - 10.01)).abs() < 1e-9);
+    assert_eq!(balance.unconfirmed, 0.0);
+    assert_eq!(balance.spendable, balance.confirmed);
+    assert_eq!(balance.next_nonce, 1);
+    let balance = blockchain.balance(&to);
+    assert_eq!((balance.confirmed, balance.unconfirmed), (10.0, 0.0));
+    assert_eq!(balance.spendable, 10.0);
+}
+
+#[test]
+fn pages_through_history_newest_first() {
+    let mut blockchain = regtest_chain();
+    let sender = key(18);
+    let (from, to) = (address_of(&sender), address_of(&key(19)));
+    fund(&mut blockchain, &sender);
+    let funded = blockchain.height() - chainparams::params().coinbase_maturity;
+
+    let sent = pay(&sender, &to, 1.0);
+    blockchain.add_transaction(sent.clone()).unwrap();
+    blockchain.generate(1, MINER).unwrap();
+    let sent_at = blockchain.height();
+    let to_self = pay_nth(&sender, 1, &from, 2.0);
+    blockchain.add_transaction(to_self.clone()).unwrap();
+    blockchain.generate(1, MINER).unwrap();
+    let pending = pay_nth(&sender, 2, &to, 3.0);
+    blockchain.add_transaction(pending.clone()).unwrap();
+    let tip = blockchain.height();
+
+    let history = blockchain.history(&from, 0, 100);
+    assert_eq!((history.total, history.offset), (4, 0));
+    let entries: Vec<_> = history
+        .transactions
+        .iter()
+        .map(|entry| (entry.transaction.id(), entry.direction, entry.height))
+        .collect();
+    assert_eq!(
+        entries[..3],
+        [
+            (pending.id(), Direction::Sent, None),
+            (to_self.id(), Direction::ToSelf, Some(tip)),
+            (sent.id(), Direction::Sent, Some(sent_at)),
+        ]
+    );
+    assert_eq!(entries[3].1, Direction::Received);
+    assert_eq!(entries[3].2, Some(funded));
+    let confirmations: Vec<u64> = history
+        .transactions
+        .iter()
+        .map(|entry| entry.confirmations)
+        .collect();
+    assert_eq!(confirmations, [0, 1, 2, tip - funded + 1]);
+
+    let page = |offset, limit| {
+        let history = blockchain.history(&from, offset, limit);
+        assert_eq!((history.total, history.offset), (4, offset));
+        history
+            .transactions
+            .iter()
+            .map(|entry| entry.transaction.id())
+            .collect::<Vec<_>>()
+    };
+    let all: Vec<_> = history
+        .transactions
+        .iter()
+        .map(|e| e.transaction.id())
+        .collect();
+    assert_eq!(page(0, 2), all[..2]);
+    assert_eq!(page(2, 2), all[2..]);
+    assert_eq!(page(3, 10), all[3..]);
+    assert!(page(4, 10).is_empty());
+    assert!(page(0, 0).is_empty());
+
+    let history = blockchain.history(&to, 0, 100);
+    assert_eq!(history.total, 2);
+    let directions: Vec<_> = history.transactions.iter().map(|e| e.direction).collect();
+    assert_eq!(directions, [Direction::Received, Direction::Received]);
+}
+
+#[test]
+fn rejects_a_block_that_overdraws() {
+    let mut blockchain = regtest_chain();
+    let sender = key(5);
+    let balance = fund(&mut blockchain, &sender);
+
+    let block = mine(&blockchain, vec![pay(&sender, RECEIVER, balance)]);
+    assert!(!blockchain.add_mined_block(block));
+
+    // Two spends that fit one at a time but not together.
+    let half = balance / 2.0 + 1.0;
+    let block = mine(
+        &blockchain,
+        vec![
+            pay(&sender, RECEIVER, half),
+            pay_nth(&sender, 1, MINER, half),
+        ],
+    );
+    assert!(!blockchain.add_mined_block(block));
+    assert_eq!(
+        blockchain.height(),
+        1 + chainparams::params().coinbase_maturity
+    );
+}
+
+#[test]
+fn spends_funds_paid_earlier_in_the_block() {
+    let mut blockchain = regtest_chain();
+    let sender = key(6);
+    let middle = key(7);
+    fund(&mut blockchain, &sender);
+
+    let block = mine(
+        &blockchain,
+        vec![
+            pay(&sender, &address_of(&middle), 10.0),
+            pay(&middle, RECEIVER, 5.0),
+        ],
+    );
+    assert!(blockchain.add_mined_block(block));
+    assert!(blockchain.is_valid());
+    assert!((blockchain.get_balance(&address_of(&middle)) - 4.99).abs() < 1e-9);
+}
+
+#[test]
+fn needs_a_timestamp_past_the_median() {
+    let mut blockchain = regtest_chain();
+    // All in the same second, so each template has to move past the median.
+    blockchain.generate(15, MINER).unwrap();
+    assert!(blockchain.is_valid());
+
+    let median = blockchain.median_time_past(blockchain.height() + 1);
+    let block = mine_at(&blockchain, Vec::new(), median);
+    assert!(!blockchain.add_mined_block(block));
+    let block = mine_at(&blockchain, Vec::new(), median - 1000);
+    assert!(!blockchain.add_mined_block(block.clone()));
+    let e = blockchain.sync_blocks(vec![block]).unwrap_err();
+    assert!(e.contains("is invalid"), "{}", e);
+
+    let block = mine_at(&blockchain, Vec::new(), median + 1);
+    assert!(blockchain.add_mined_block(block));
+}
+
+#[test]
+fn hands_out_templates_past_the_median() {
+    let mut blockchain = regtest_chain();
+    let sender = key(10);
+    fund(&mut blockchain, &sender);
+    blockchain
+        .add_transaction(pay(&sender, RECEIVER, 1.0))
+        .unwrap();
+
+    let median = blockchain.median_time_past(blockchain.height() + 1);
+    let template = blockchain.get_block_to_mine().unwrap();
+    assert!(template.get_timestamp() > median);
+    assert!(template.get_timestamp() >= blockchain.block_template(MINER, 0).get_timestamp());
+}
+
+#[test]
+fn refuses_a_timestamp_far_in_the_future() {
+    let mut blockchain = regtest_chain();
+    blockchain.generate(1, MINER).unwrap();
+
+    let too_late = clock::now() + MAX_FUTURE_BLOCK_TIME + 60;
+    let block = mine_at(&blockchain, Vec::new(), too_late);
+    assert!(!blockchain.add_mined_block(block.clone()));
+    assert!(blockchain.sync_blocks(vec![block]).is_err());
+
+    let ahead = clock::now() + MAX_FUTURE_BLOCK_TIME / 2;
+    let block = mine_at(&blockchain, Vec::new(), ahead);
+    assert!(blockchain.add_mined_block(block));
+}
+
+#[test]
+fn waits_for_a_coinbase_to_mature() {
+    let mut blockchain = regtest_chain();
+    let miner = key(8);
+    let maturity = chainparams::params().coinbase_maturity;
+    blockchain.generate(1, &address_of(&miner)).unwrap();
+    blockchain.generate(maturity - 2, MINER).unwrap();
+
+    // The next block is one short of maturity.
+    let e = blockchain
+        .add_transaction(pay(&miner, RECEIVER, 1.0))
+        .unwrap_err();
+    assert!(e.starts_with("Insufficient funds"), "{}", e);
+    assert_eq!(blockchain.balance(&address_of(&miner)).spendable, 0.0);
+    let block = mine(&blockchain, vec![pay(&miner, RECEIVER, 1.0)]);
+    assert!(!blockchain.add_mined_block(block));
+
+    blockchain.generate(1, MINER).unwrap();
+    blockchain
+        .add_transaction(pay(&miner, RECEIVER, 1.0))
+        .unwrap();
+    let block = mine(&blockchain, vec![pay(&miner, RECEIVER, 2.0)]);
+    assert!(blockchain.add_mined_block(block));
+    assert!(blockchain.is_valid());
+}
+
+#[test]
+fn needs_the_height_in_the_coinbase() {
+    let mut blockchain = regtest_chain();
+    blockchain.generate(2, MINER).unwrap();
+
+    let height = blockchain.height() + 1;
+    let reward = chainparams::params().block_reward(height);
+    let timestamp = clock::now().max(blockchain.median_time_past(height) + 1);
+    for wrong in [0, height - 1, height + 1] {
+        let coinbase = Transaction::coinbase(MINER.to_string(), reward, wrong, 0);
+        let block = seal(&blockchain, vec![coinbase], timestamp);
+        assert!(!blockchain.add_mined_block(block));
+    }
+    let coinbase = Transaction::coinbase(MINER.to_string(), reward, height, 0);
+    assert!(blockchain.add_mined_block(seal(&blockchain, vec![coinbase], timestamp)));
+}
+
+#[test]
+fn cannot_spend_its_own_coinbase() {
+    let mut blockchain = regtest_chain();
+    let miner = key(9);
+    fund(&mut blockchain, &miner);
+
+    // A block paying `miner` its coinbase, which a later transaction in
+    // the same block tries to pass on.
+    let height = blockchain.height() + 1;
+    let reward = chainparams::params().block_reward(height);
+    let balance = blockchain.get_balance(&address_of(&miner));
+    let transactions = vec![
+        Transaction::coinbase(address_of(&miner), reward + 0.01, height, 0),
+        pay(&miner, RECEIVER, balance + 1.0),
+    ];
+    let timestamp = blockchain.median_time_past(height) + 1;
+    let block = seal(&blockchain, transactions, clock::now().max(timestamp));
+    assert!(!blockchain.add_mined_block(block));
+}
+
+#[test]
+fn finds_transactions_by_data() {
+    let mut blockchain = regtest_chain();
+    let sender = key(11);
+    fund(&mut blockchain, &sender);
+
+    let mut transaction = Transaction::new(address_of(&sender), RECEIVER.to_string(), 1.0);
+    transaction.fee = 0.01;
+    transaction.data = Some("deadbeef".to_string());
+    sign(&sender, &mut transaction);
+    blockchain.add_transaction(transaction.clone()).unwrap();
+
+    let found = blockchain.find_data(&[0xde, 0xad, 0xbe, 0xef]);
+    assert_eq!(found.len(), 1);
+    assert_eq!(found[0].transaction.id(), transaction.id());
+    assert_eq!((found[0].height, found[0].confirmations), (None, 0));
+
+    let block = mine(&blockchain, vec![transaction]);
+    assert!(blockchain.add_mined_block(block));
+    blockchain.generate(1, MINER).unwrap();
+    let found = blockchain.find_data(&[0xde, 0xad, 0xbe, 0xef]);
+    assert_eq!(found.len(), 1);
+    assert_eq!(found[0].height, Some(blockchain.height() - 1));
+    assert_eq!(found[0].confirmations, 2);
+    assert!(blockchain.find_data(&[0xde, 0xad]).is_empty());
+}
+
+#[test]
+fn signs_optional_fields_apart() {
+    let mut plain = Transaction::new(MINER.to_string(), RECEIVER.to_string(), 1.0);
+    plain.timestamp = 0;
+    let with = |change: &dyn Fn(&mut Transaction)| {
+        let mut transaction = plain.clone();
+        change(&mut transaction);
+        transaction.signing_data()
+    };
+    let layouts = [
+        plain.signing_data(),
+        with(&|tx| tx.script = Some("5151".to_string())),
+        with(&|tx| tx.script = Some("51".to_string())),
+        with(&|tx| tx.data = Some("5151".to_string())),
+        with(&|tx| {
+            tx.script = Some("51".to_string());
+            tx.data = Some("51".to_string());
+        }),
+        with(&|tx| tx.lock_time = 1),
+        with(&|tx| tx.relative_lock = 1),
+    ];
+    for (i, layout) in layouts.iter().enumerate() {
+        assert!(!layouts[..i].contains(layout), "layout {} repeats", i);
+    }
+}
+
+#[test]
+fn refuses_to_replay_a_mined_transaction() {
+    let mut blockchain = regtest_chain();
+    let sender = key(12);
+    let receiver = key(13);
+    fund(&mut blockchain, &sender);
+
+    let payment = pay(&sender, &address_of(&receiver), 5.0);
+    blockchain.add_transaction(payment.clone()).unwrap();
+    blockchain.generate(1, MINER).unwrap();
+    assert_eq!(blockchain.get_balance(&address_of(&receiver)), 5.0);
+
+    let e = blockchain.add_transaction(payment.clone()).unwrap_err();
+    assert!(e.starts_with("Nonce 0 is out of sequence"), "{}", e);
+    let block = mine(&blockchain, vec![payment]);
+    assert!(!blockchain.add_mined_block(block));
+    blockchain.generate(1, MINER).unwrap();
+    assert_eq!(blockchain.get_balance(&address_of(&receiver)), 5.0);
+
+    // The next nonce still goes through.
+    blockchain
+        .add_transaction(pay_nth(&sender, 1, &address_of(&receiver), 5.0))
+        .unwrap();
+    blockchain.generate(1, MINER).unwrap();
+    assert_eq!(blockchain.get_balance(&address_of(&receiver)), 10.0);
+    assert!(blockchain.is_valid());
+}
+
+#[test]
+fn mines_a_sender_s_transactions_in_nonce_order() {
+    let mut blockchain = regtest_chain();
+    let sender = key(14);
+    fund(&mut blockchain, &sender);
+
+    let first = pay(&sender, RECEIVER, 1.0);
+    let mut second = Transaction::new(address_of(&sender), RECEIVER.to_string(), 1.0);
+    second.fee = 1.0;
+    second.nonce = 1;
+    sign(&sender, &mut second);
+    let e = blockchain.add_transaction(second.clone()).unwrap_err();
+    assert!(e.starts_with("Nonce 1 is out of sequence"), "{}", e);
+    blockchain.add_transaction(first).unwrap();
+    blockchain.add_transaction(second).unwrap();
+    assert_eq!(blockchain.balance(&address_of(&sender)).next_nonce, 2);
+
+    let template = blockchain.block_template(MINER, 0);
+    let nonces: Vec<u64> = template.get_transactions()[1..]
+        .iter()
+        .map(|tx| tx.nonce)
+        .collect();
+    assert_eq!(nonces, [0, 1]);
+    blockchain.generate(1, MINER).unwrap();
+    assert!(blockchain.get_block_to_mine().is_none());
+    assert_eq!(blockchain.balance(&address_of(&sender)).next_nonce, 2);
+}
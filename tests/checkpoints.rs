@@ -0,0 +1,152 @@
+//! Checkpoints and assume-valid, on a regtest chain built before the
+//! network is selected, so its block hashes can go in the parameters.
+
+use fluerion::address;
+use fluerion::block::Block;
+use fluerion::blockchain::Blockchain;
+use fluerion::chainparams::{self, ChainParams};
+use fluerion::hash::{meets_difficulty, Hash256};
+use fluerion::transaction::Transaction;
+use k256::ecdsa::signature::Signer;
+use k256::ecdsa::{Signature, SigningKey};
+use std::sync::OnceLock;
+
+/// Address of `key()`, written out since encoding one reads the selected
+/// network.
+const MINER: &str = "flrt178gjqyjqdwr6lvnldhgk4s98dlx6540des4zmz";
+const OTHER_MINER: &str = "flrt13v0g8knefk035awswd2pvthk4wsd3w55kgxdzx";
+
+fn key() -> SigningKey {
+    SigningKey::from_bytes(&[1; 32].into()).unwrap()
+}
+
+/// A block at `height` on top of `prev` paying the reward to `receiver`,
+/// a minute after the one below it, sealed.
+fn block(prev: &Block, height: u64, receiver: &str, mut transactions: Vec<Transaction>) -> Block {
+    let params = ChainParams::regtest();
+    let timestamp = prev.get_timestamp() + 60;
+    let mut coinbase =
+        Transaction::coinbase(receiver.to_string(), params.block_reward(height), height, 0);
+    coinbase.timestamp = timestamp;
+    transactions.insert(0, coinbase);
+    let mut block = Block::new(transactions, prev.get_hash());
+    block.set_timestamp(timestamp);
+    let nonce = (0..)
+        .find(|&nonce| {
+            block.set_nonce(nonce);
+            meets_difficulty(&block.calculate_hash(), params.genesis.difficulty)
+        })
+        .unwrap();
+    block.set_nonce(nonce);
+    block.set_hash(block.calculate_hash());
+    block
+}
+
+/// The first block pays `MINER`; once it has matured, the last block
+/// spends from it with a signature over other bytes than it carries.
+fn badly_signed_chain() -> &'static Vec<Block> {
+    static CHAIN: OnceLock<Vec<Block>> = OnceLock::new();
+    CHAIN.get_or_init(|| {
+        let params = ChainParams::regtest();
+        let mut blocks = vec![Block::genesis(&params.genesis)];
+        for height in 1..=params.coinbase_maturity + 1 {
+            let prev = blocks.last().unwrap();
+            blocks.push(block(prev, height, MINER, Vec::new()));
+        }
+
+        let mut payment = Transaction::new(MINER.to_string(), OTHER_MINER.to_string(), 1.0);
+        payment.fee = 0.01;
+        payment.timestamp = blocks.last().unwrap().get_timestamp();
+        let signed: Signature = key().sign(&payment.signing_data());
+        let mut signature = key()
+            .verifying_key()
+            .to_encoded_point(true)
+            .as_bytes()
+            .to_vec();
+        signature.extend_from_slice(&signed.to_bytes());
+        payment.sign(hex::encode(signature));
+        payment.amount = 2.0;
+
+        let height = blocks.len() as u64;
+        let bad = block(blocks.last().unwrap(), height, MINER, vec![payment]);
+        blocks.push(bad);
+        blocks
+    })
+}
+
+/// Regtest, with block 1 of `badly_signed_chain` as a checkpoint and its
+/// badly signed tip as the assume-valid block.
+fn regtest_chain() -> Blockchain {
+    let blocks = badly_signed_chain();
+    let mut params = ChainParams::regtest();
+    params.add_checkpoint(1, blocks[1].get_hash()).unwrap();
+    params.assume_valid = Some(blocks.last().unwrap().get_hash());
+    let _ = chainparams::select(params);
+    Blockchain::new()
+}
+
+fn tip_hash(blockchain: &Blockchain) -> Hash256 {
+    blockchain.get_latest_block().get_hash()
+}
+
+#[test]
+fn rejects_a_block_conflicting_with_a_checkpoint() {
+    let mut blockchain = regtest_chain();
+    let genesis = blockchain.get_latest_block();
+    let conflicting = block(&genesis, 1, OTHER_MINER, Vec::new());
+
+    assert!(!blockchain.add_mined_block(conflicting.clone()));
+    let e = blockchain.sync_blocks(vec![conflicting]).unwrap_err();
+    assert!(e.contains("is invalid"), "{}", e);
+    assert_eq!(blockchain.height(), 0);
+
+    let pinned = badly_signed_chain()[1].clone();
+    assert!(blockchain.add_mined_block(pinned.clone()));
+    assert_eq!(tip_hash(&blockchain), pinned.get_hash());
+}
+
+#[test]
+fn skips_signatures_up_to_the_assume_valid_block() {
+    let blocks = badly_signed_chain();
+    let tip = blocks.last().unwrap();
+
+    // Mined one at a time, the bad signature is caught.
+    let mut blockchain = regtest_chain();
+    for block in &blocks[1..blocks.len() - 1] {
+        assert!(blockchain.add_mined_block(block.clone()));
+    }
+    assert!(!blockchain.add_mined_block(tip.clone()));
+
+    // Synced up to the assume-valid block, it is not looked at.
+    let mut blockchain = regtest_chain();
+    let (connected, assumed) = blockchain.sync_blocks(blocks[1..].to_vec()).unwrap();
+    assert_eq!((connected, assumed), (blocks.len() - 1, blocks.len() - 1));
+    assert_eq!(tip_hash(&blockchain), tip.get_hash());
+}
+
+#[test]
+fn checks_signatures_past_the_assume_valid_block() {
+    let blocks = badly_signed_chain();
+    let mut blockchain = regtest_chain();
+    let below = &blocks[1..blocks.len() - 1];
+    blockchain.sync_blocks(below.to_vec()).unwrap();
+
+    // The same spend in another block than the assumed one.
+    let bad = blocks.last().unwrap();
+    let other = block(
+        &blocks[blocks.len() - 2],
+        blocks.len() as u64 - 1,
+        OTHER_MINER,
+        bad.get_transactions()[1..].to_vec(),
+    );
+    let e = blockchain.sync_blocks(vec![other]).unwrap_err();
+    assert!(e.contains("is invalid"), "{}", e);
+}
+
+#[test]
+fn knows_the_address_of_its_key() {
+    let _ = regtest_chain();
+    let pubkey = key().verifying_key().to_encoded_point(true);
+    let address = address::encode(&address::pubkey_hash(pubkey.as_bytes()));
+    assert_eq!(address, MINER);
+}
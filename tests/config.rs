@@ -0,0 +1,135 @@
+//! How the node reads its settings from flags and a config file.
+
+use fluerion::chainparams::{ChainParams, Network};
+use fluerion::config::NodeConfig;
+use fluerion::hash::hex_to_hash;
+use std::fs;
+
+const HASH: &str = "00001f3c9b2e6f0d4a7c8e1b5d3f9a2c6e0b4d8f1a3c5e7b9d2f4a6c8e0b1d3f";
+const OTHER_HASH: &str = "0000aa3c9b2e6f0d4a7c8e1b5d3f9a2c6e0b4d8f1a3c5e7b9d2f4a6c8e0b1d3f";
+
+fn parse(args: &[&str]) -> Result<NodeConfig, String> {
+    let args: Vec<String> = std::iter::once("fluerion")
+        .chain(args.iter().copied())
+        .map(str::to_string)
+        .collect();
+    NodeConfig::from_args(&args)
+}
+
+fn params(args: &[&str]) -> Result<ChainParams, String> {
+    parse(args)?.chain_params()
+}
+
+#[test]
+fn selects_the_network() {
+    let config = parse(&["127.0.0.1"]).unwrap();
+    assert_eq!(config.network, Network::Mainnet);
+    assert_eq!(config.address, "127.0.0.1:8080");
+
+    for (name, network, address) in [
+        ("testnet", Network::Testnet, "127.0.0.1:18080"),
+        ("test", Network::Testnet, "127.0.0.1:18080"),
+        ("regtest", Network::Regtest, "127.0.0.1:28080"),
+        ("main", Network::Mainnet, "127.0.0.1:8080"),
+    ] {
+        let config = parse(&["127.0.0.1", "--network", name, "10.0.0.1:9"]).unwrap();
+        assert_eq!(config.network, network);
+        assert_eq!(config.address, address);
+        assert_eq!(config.bootstrap_node.as_deref(), Some("10.0.0.1:9"));
+        assert_eq!(config.chain_params().unwrap().network, network);
+    }
+
+    let e = parse(&["127.0.0.1", "--network", "simnet"]).unwrap_err();
+    assert!(e.starts_with("Unknown network simnet"), "{}", e);
+    assert!(parse(&["127.0.0.1", "--network"]).is_err());
+}
+
+#[test]
+fn keeps_each_network_apart() {
+    let networks = [Network::Mainnet, Network::Testnet, Network::Regtest];
+    let params = networks.map(ChainParams::for_network);
+    for (i, a) in params.iter().enumerate() {
+        assert_eq!(a.network, networks[i]);
+        for b in &params[i + 1..] {
+            assert_ne!(a.magic, b.magic);
+            assert_ne!(a.address_prefix, b.address_prefix);
+            assert_ne!(a.default_port, b.default_port);
+            assert_ne!(a.genesis.hash, b.genesis.hash);
+        }
+    }
+
+    let [mainnet, testnet, regtest] = params;
+    for live in [&mainnet, &testnet] {
+        assert!(!live.test_commands);
+        assert_eq!(live.target_block_time, 60);
+        assert_eq!(live.retarget_interval, Some(1440));
+        assert_eq!(live.block_reward(live.halving_interval), 25.0);
+    }
+    assert!(regtest.test_commands);
+    assert_eq!(regtest.retarget_interval, None);
+    assert_eq!(regtest.genesis.difficulty, 1);
+    assert_eq!(regtest.block_reward(150), 25.0);
+    assert_eq!(regtest.block_reward(64 * 150), 0.0);
+}
+
+#[test]
+fn adds_checkpoints_from_flags() {
+    let checkpoint = format!("1000:{}", HASH);
+    let other = format!("10:{}", OTHER_HASH);
+    let params = params(&[
+        "127.0.0.1",
+        "--network",
+        "regtest",
+        "--checkpoint",
+        &checkpoint,
+        "--checkpoint",
+        &other,
+    ])
+    .unwrap();
+    assert_eq!(params.checkpoint(1000), Some(&hex_to_hash(HASH).unwrap()));
+    assert_eq!(
+        params.checkpoint(10),
+        Some(&hex_to_hash(OTHER_HASH).unwrap())
+    );
+    assert_eq!(params.checkpoints[0].0, 10);
+    assert_eq!(params.checkpoint(11), None);
+}
+
+#[test]
+fn refuses_malformed_checkpoints() {
+    for checkpoint in ["1000", "x:00", "1000:zz", &format!("-1:{}", HASH)] {
+        let e = params(&["127.0.0.1", "--checkpoint", checkpoint]).unwrap_err();
+        assert!(e.starts_with("Invalid checkpoint"), "{}", e);
+    }
+    let repeated = [format!("5:{}", HASH), format!("5:{}", OTHER_HASH)];
+    let e = params(&[
+        "127.0.0.1",
+        "--checkpoint",
+        &repeated[0],
+        "--checkpoint",
+        &repeated[1],
+    ])
+    .unwrap_err();
+    assert!(e.contains("conflicts"), "{}", e);
+    assert!(parse(&["127.0.0.1", "--checkpoint"]).is_err());
+}
+
+#[test]
+fn reads_checkpoints_from_the_config_file() {
+    let path = std::env::temp_dir().join(format!("fluerion-config-{}.toml", std::process::id()));
+    fs::write(
+        &path,
+        format!(
+            "network = \"testnet\"\ncheckpoints = [\"7:{}\"]\nassume_valid = \"{}\"\n",
+            HASH, OTHER_HASH
+        ),
+    )
+    .unwrap();
+    let config = parse(&["127.0.0.1", "--config", path.to_str().unwrap()]).unwrap();
+    fs::remove_file(&path).unwrap();
+
+    assert_eq!(config.network, Network::Testnet);
+    let params = config.chain_params().unwrap();
+    assert_eq!(params.checkpoint(7), Some(&hex_to_hash(HASH).unwrap()));
+    assert_eq!(params.assume_valid, Some(hex_to_hash(OTHER_HASH).unwrap()));
+}
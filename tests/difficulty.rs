@@ -0,0 +1,105 @@
+//! Difficulty retargeting, on a regtest chain that retargets every few
+//! blocks.
+
+use fluerion::block::Block;
+use fluerion::blockchain::Blockchain;
+use fluerion::chainparams::{self, ChainParams};
+use fluerion::hash::{meets_difficulty, Hash256};
+use fluerion::transaction::Transaction;
+
+const INTERVAL: u64 = 10;
+const MINER: &str = "flrt178gjqyjqdwr6lvnldhgk4s98dlx6540des4zmz";
+
+fn params() -> ChainParams {
+    ChainParams {
+        target_block_time: 60,
+        retarget_interval: Some(INTERVAL),
+        ..ChainParams::regtest()
+    }
+}
+
+fn regtest_chain() -> Blockchain {
+    let _ = chainparams::select(params());
+    Blockchain::new()
+}
+
+/// A block on top of the tip, `gap` seconds after it, with the first nonce
+/// whose hash passes `accept`.
+fn block(blockchain: &Blockchain, gap: u64, accept: impl Fn(&Hash256) -> bool) -> Block {
+    let tip = blockchain.get_latest_block();
+    let height = blockchain.height() + 1;
+    let timestamp = tip.get_timestamp() + gap;
+    let reward = params().block_reward(height);
+    let mut coinbase = Transaction::coinbase(MINER.to_string(), reward, height, 0);
+    coinbase.timestamp = timestamp;
+    let mut block = Block::new(vec![coinbase], tip.get_hash());
+    block.set_timestamp(timestamp);
+    let nonce = (0..)
+        .find(|&nonce| {
+            block.set_nonce(nonce);
+            accept(&block.calculate_hash())
+        })
+        .unwrap();
+    block.set_nonce(nonce);
+    block.set_hash(block.calculate_hash());
+    block
+}
+
+/// Mines blocks `gap` seconds apart until the next one is due a retarget.
+fn mine_interval(blockchain: &mut Blockchain, gap: u64) {
+    while !(blockchain.height() + 1).is_multiple_of(INTERVAL) {
+        let difficulty = blockchain.difficulty();
+        let block = block(blockchain, gap, |hash| meets_difficulty(hash, difficulty));
+        assert!(blockchain.add_mined_block(block));
+    }
+}
+
+#[test]
+fn raises_the_difficulty_when_blocks_come_too_fast() {
+    let mut blockchain = regtest_chain();
+    let genesis_difficulty = blockchain.difficulty();
+
+    mine_interval(&mut blockchain, 1);
+    assert_eq!(blockchain.height(), INTERVAL - 1);
+    assert_eq!(blockchain.difficulty(), genesis_difficulty + 1);
+
+    let easy = block(&blockchain, 1, |hash| {
+        meets_difficulty(hash, genesis_difficulty) && !blockchain.valid_proof(hash)
+    });
+    assert!(!blockchain.add_mined_block(easy));
+    let hard = block(&blockchain, 1, |hash| blockchain.valid_proof(hash));
+    assert!(blockchain.add_mined_block(hard));
+    assert!(blockchain.is_valid());
+}
+
+#[test]
+fn lowers_the_difficulty_when_blocks_come_too_slowly() {
+    let mut blockchain = regtest_chain();
+    let genesis_difficulty = blockchain.difficulty();
+    mine_interval(&mut blockchain, 1);
+
+    // Blocks 10 to 19 take five times the target each.
+    let hard = block(&blockchain, 300, |hash| blockchain.valid_proof(hash));
+    assert!(blockchain.add_mined_block(hard));
+    mine_interval(&mut blockchain, 300);
+    assert_eq!(blockchain.height(), 2 * INTERVAL - 1);
+    assert_eq!(blockchain.difficulty(), genesis_difficulty);
+    assert!(blockchain.is_valid());
+}
+
+#[test]
+fn keeps_the_difficulty_near_the_target() {
+    let mut blockchain = regtest_chain();
+    mine_interval(&mut blockchain, 60);
+    assert_eq!(blockchain.difficulty(), params().genesis.difficulty);
+
+    let params = params();
+    let expected = (INTERVAL - 1) * params.target_block_time;
+    assert_eq!(params.retarget(5, expected / 4), 5);
+    assert_eq!(params.retarget(5, expected * 4), 5);
+    assert_eq!(params.retarget(5, expected / 4 - 1), 6);
+    assert_eq!(params.retarget(5, expected * 4 + 1), 4);
+    assert_eq!(params.retarget(64, 0), 64);
+    assert_eq!(params.retarget(1, u64::MAX), 1);
+    assert_eq!(ChainParams::regtest().retarget(5, 0), 5);
+}
@@ -0,0 +1,142 @@
+//! Built-in genesis blocks, and a private regtest network started from a
+//! genesis with allocations.
+
+use fluerion::block::Block;
+use fluerion::blockchain::Blockchain;
+use fluerion::chainparams::{self, ChainParams};
+use fluerion::genesis::{Allocation, Genesis, GENESIS_SENDER};
+use fluerion::hash::{hash_to_hex, meets_difficulty};
+use fluerion::transaction::Transaction;
+use k256::ecdsa::signature::Signer;
+use k256::ecdsa::{Signature, SigningKey};
+use std::sync::OnceLock;
+
+/// Regtest address of `key()`, written out since checking one reads the
+/// selected network.
+const ALLOCATED: &str = "flrt178gjqyjqdwr6lvnldhgk4s98dlx6540des4zmz";
+const RECEIVER: &str = "flrt13v0g8knefk035awswd2pvthk4wsd3w55kgxdzx";
+
+fn key() -> SigningKey {
+    SigningKey::from_bytes(&[1; 32].into()).unwrap()
+}
+
+fn private_genesis() -> Genesis {
+    Genesis {
+        timestamp: 1_735_689_600,
+        message: "Our private chain".to_string(),
+        difficulty: 2,
+        nonce: 0,
+        hash: String::new(),
+        allocations: vec![
+            Allocation {
+                address: ALLOCATED.to_string(),
+                amount: 1000.0,
+            },
+            Allocation {
+                address: RECEIVER.to_string(),
+                amount: 250.0,
+            },
+        ],
+    }
+}
+
+/// Selects regtest with `private_genesis`, its nonce found by hand since
+/// `Genesis::mine` checks the allocations against the selected network.
+fn private_chain() -> Blockchain {
+    static PARAMS: OnceLock<()> = OnceLock::new();
+    PARAMS.get_or_init(|| {
+        let mut genesis = private_genesis();
+        let mut block = Block::genesis(&genesis);
+        while !meets_difficulty(&block.calculate_hash(), genesis.difficulty) {
+            genesis.nonce += 1;
+            block.set_nonce(genesis.nonce);
+        }
+        genesis.hash = hash_to_hex(&block.calculate_hash());
+        chainparams::select(ChainParams {
+            genesis,
+            ..ChainParams::regtest()
+        })
+        .unwrap();
+    });
+    Blockchain::new()
+}
+
+#[test]
+fn built_in_genesis_blocks_hash_to_their_pins() {
+    for params in [
+        ChainParams::mainnet(),
+        ChainParams::testnet(),
+        ChainParams::regtest(),
+    ] {
+        let block = Block::genesis(&params.genesis);
+        assert_eq!(hash_to_hex(&block.get_hash()), params.genesis.hash);
+        assert_eq!(block.get_hash(), block.calculate_hash());
+        let checked = params.genesis.check().unwrap();
+        assert_eq!(checked.get_hash(), block.get_hash(), "{}", params.network);
+    }
+}
+
+#[test]
+fn refuses_a_tampered_genesis() {
+    let genesis = ChainParams::mainnet().genesis;
+    let tamper = |change: &dyn Fn(&mut Genesis)| {
+        let mut tampered = genesis.clone();
+        change(&mut tampered);
+        tampered.check().unwrap_err()
+    };
+
+    let e = tamper(&|g| g.message.push('!'));
+    assert!(e.starts_with("Genesis block hash is"), "{}", e);
+    let e = tamper(&|g| g.timestamp += 1);
+    assert!(e.starts_with("Genesis block hash is"), "{}", e);
+    let e = tamper(&|g| g.nonce += 1);
+    assert!(e.starts_with("Genesis block hash is"), "{}", e);
+    let e = tamper(&|g| g.hash = "00".repeat(32));
+    assert!(e.starts_with("Genesis block hash is"), "{}", e);
+    let e = tamper(&|g| g.hash.clear());
+    assert!(e.contains("no hash"), "{}", e);
+    let e = tamper(&|g| g.difficulty = 64);
+    assert!(e.contains("does not meet difficulty"), "{}", e);
+}
+
+#[test]
+fn mines_a_genesis_that_checks_out() {
+    let blockchain = private_chain();
+    let mut genesis = private_genesis();
+    let mined = genesis.mine().unwrap();
+    assert_eq!(genesis.check().unwrap().get_hash(), mined.get_hash());
+    assert_eq!(genesis.hash, blockchain.params().genesis.hash);
+    assert_eq!(blockchain.block_at(0).unwrap().get_hash(), mined.get_hash());
+
+    genesis.allocations.push(Allocation {
+        address: RECEIVER.to_string(),
+        amount: 1.0,
+    });
+    assert!(genesis.mine().unwrap_err().contains("more than once"));
+}
+
+#[test]
+fn pays_out_allocations_without_a_sender() {
+    let mut blockchain = private_chain();
+    assert_eq!(blockchain.get_balance(ALLOCATED), 1000.0);
+    assert_eq!(blockchain.get_balance(RECEIVER), 250.0);
+    assert_eq!(blockchain.get_balance(GENESIS_SENDER), 0.0);
+    assert_eq!(blockchain.next_nonce(GENESIS_SENDER), 0);
+
+    let mut payment = Transaction::new(ALLOCATED.to_string(), RECEIVER.to_string(), 100.0);
+    payment.fee = 0.01;
+    let signed: Signature = key().sign(&payment.signing_data());
+    let mut signature = key()
+        .verifying_key()
+        .to_encoded_point(true)
+        .as_bytes()
+        .to_vec();
+    signature.extend_from_slice(&signed.to_bytes());
+    payment.sign(hex::encode(signature));
+    blockchain.add_transaction(payment).unwrap();
+    blockchain.generate(1, RECEIVER).unwrap();
+
+    assert_eq!(blockchain.get_balance(ALLOCATED), 1000.0 - 100.01);
+    assert_eq!(blockchain.next_nonce(ALLOCATED), 1);
+    assert!(blockchain.is_valid());
+}
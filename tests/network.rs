@@ -0,0 +1,182 @@
+//! Talks to a node over TCP the way the wallet and the miner do.
+
+use fluerion::address;
+use fluerion::blockchain::Blockchain;
+use fluerion::chainparams::{self, ChainParams};
+use fluerion::multisig::{self, MAX_KEYS};
+use fluerion::network::Node;
+use fluerion::transaction::Transaction;
+use k256::ecdsa::signature::Signer;
+use k256::ecdsa::{Signature, SigningKey};
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+const RECEIVER: &str = "flrt14sdcqezufk9g8pnn0sve5yk2wqfllsg02qxxv2";
+
+/// Starts a regtest node on `address` and waits until it listens.
+async fn start_node(address: &str) {
+    start_mining_node(address, None).await
+}
+
+async fn start_mining_node(address: &str, mining_address: Option<&str>) {
+    let _ = chainparams::select(ChainParams::regtest());
+    let node = Node::new(
+        address.to_string(),
+        mining_address.map(str::to_string),
+        Blockchain::new(),
+    );
+    tokio::spawn(async move {
+        let _ = node.start().await;
+    });
+    for _ in 0..50 {
+        if TcpStream::connect(address).await.is_ok() {
+            return;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    panic!("Node did not start on {}", address);
+}
+
+/// Sends one newline-terminated request and reads the reply up to the
+/// node hanging up.
+async fn request(address: &str, message: &str) -> String {
+    let mut stream = TcpStream::connect(address).await.unwrap();
+    stream
+        .write_all(format!("{}\n", message).as_bytes())
+        .await
+        .unwrap();
+    let mut reply = String::new();
+    stream.read_to_string(&mut reply).await.unwrap();
+    reply
+}
+
+/// A funded 15-of-15 multisig spend carries a signature of several
+/// thousand hex digits, far more than one read of the socket returns.
+#[tokio::test]
+async fn accepts_a_large_multisig_spend() {
+    let node = "127.0.0.1:28461";
+    start_node(node).await;
+
+    let keys: Vec<SigningKey> = (1..=MAX_KEYS as u8)
+        .map(|i| SigningKey::from_bytes(&[i; 32].into()).unwrap())
+        .collect();
+    let pubkeys: Vec<u8> = keys
+        .iter()
+        .flat_map(|key| {
+            key.verifying_key()
+                .to_encoded_point(true)
+                .as_bytes()
+                .to_vec()
+        })
+        .collect();
+    let threshold = MAX_KEYS as u8;
+    let sender = address::encode(&multisig::redeem_hash(threshold, &pubkeys));
+
+    let reply = request(node, &format!("GENERATE:1:{}", sender)).await;
+    assert!(reply.starts_with('['), "{}", reply);
+    let maturity = chainparams::params().coinbase_maturity;
+    let reply = request(node, &format!("GENERATE:{}:{}", maturity, RECEIVER)).await;
+    assert!(reply.starts_with('['), "{}", reply);
+
+    let mut transaction = Transaction::new(sender, RECEIVER.to_string(), 1.0);
+    transaction.fee = 0.01;
+    let data = transaction.signing_data();
+    let mut signature = vec![threshold, MAX_KEYS as u8];
+    signature.extend_from_slice(&pubkeys);
+    for (i, key) in keys.iter().enumerate() {
+        let signed: Signature = key.sign(&data);
+        signature.push(i as u8);
+        signature.extend_from_slice(&signed.to_bytes());
+    }
+    transaction.sign(hex::encode(signature));
+
+    let message = format!("NEW_TRANSACTION:{}", transaction.to_json());
+    assert!(message.len() > 2 * 1024);
+    assert_eq!(request(node, &message).await, "Transaction added");
+    let mined = request(node, "GET_BLOCK_TO_MINE").await;
+    assert!(mined.contains(&transaction.signature.unwrap()));
+}
+
+#[tokio::test]
+async fn rejects_malformed_json() {
+    let node = "127.0.0.1:28462";
+    start_node(node).await;
+    let reply = request(node, "NEW_TRANSACTION:{\"sender\":").await;
+    assert!(reply.starts_with("Error: Invalid transaction"), "{}", reply);
+    let reply = request(node, "MINED_BLOCK:not json").await;
+    assert!(reply.starts_with("Error: Invalid block"), "{}", reply);
+    assert_eq!(request(node, "GET_HEIGHT").await, "0");
+}
+
+#[tokio::test]
+async fn caps_mining_threads() {
+    let node = "127.0.0.1:28463";
+    start_mining_node(node, Some(RECEIVER)).await;
+    let reply = request(node, "SET_GENERATE:true:1000000").await;
+    assert!(reply.starts_with("Error: At most"), "{}", reply);
+    assert_eq!(request(node, "GET_GENERATE").await, "GENERATE:false:0");
+    for threads in ["many", "-1", ""] {
+        let reply = request(node, &format!("SET_GENERATE:true:{}", threads)).await;
+        assert_eq!(reply, "Error: Invalid thread count");
+    }
+    assert_eq!(request(node, "GET_GENERATE").await, "GENERATE:false:0");
+}
+
+#[tokio::test]
+async fn caps_generated_blocks() {
+    let node = "127.0.0.1:28464";
+    start_node(node).await;
+    let reply = request(node, &format!("GENERATE:{}:{}", u64::MAX, RECEIVER)).await;
+    assert!(reply.starts_with("Error: At most"), "{}", reply);
+    assert_eq!(request(node, "GET_HEIGHT").await, "0");
+}
+
+#[tokio::test]
+async fn finds_data_payloads() {
+    let node = "127.0.0.1:28465";
+    start_node(node).await;
+
+    let key = SigningKey::from_bytes(&[1; 32].into()).unwrap();
+    let pubkey = key.verifying_key().to_encoded_point(true);
+    let sender = address::encode(&address::pubkey_hash(pubkey.as_bytes()));
+    request(node, &format!("GENERATE:1:{}", sender)).await;
+    let maturity = chainparams::params().coinbase_maturity;
+    request(node, &format!("GENERATE:{}:{}", maturity, RECEIVER)).await;
+
+    let mut transaction = Transaction::new(sender, RECEIVER.to_string(), 1.0);
+    transaction.fee = 0.01;
+    transaction.data = Some("c0ffee".to_string());
+    let signed: Signature = key.sign(&transaction.signing_data());
+    let mut signature = pubkey.as_bytes().to_vec();
+    signature.extend_from_slice(&signed.to_bytes());
+    transaction.sign(hex::encode(signature));
+    let message = format!("NEW_TRANSACTION:{}", transaction.to_json());
+    assert_eq!(request(node, &message).await, "Transaction added");
+
+    let found: serde_json::Value =
+        serde_json::from_str(&request(node, "FIND_DATA:C0FFEE").await).unwrap();
+    assert_eq!(found["data"], "c0ffee");
+    assert_eq!(found["transactions"][0]["height"], serde_json::Value::Null);
+
+    request(node, &format!("GENERATE:1:{}", RECEIVER)).await;
+    let found: serde_json::Value =
+        serde_json::from_str(&request(node, "FIND_DATA:c0ffee").await).unwrap();
+    assert_eq!(found["transactions"][0]["height"], maturity + 2);
+    assert_eq!(found["transactions"][0]["confirmations"], 1);
+
+    let reply = request(node, "FIND_DATA:xyz").await;
+    assert!(reply.starts_with("Error:"), "{}", reply);
+}
+
+#[tokio::test]
+async fn bumps_the_template_version() {
+    let node = "127.0.0.1:28466";
+    start_node(node).await;
+    let version = |reply: String| reply.parse::<u64>().unwrap();
+
+    let before = version(request(node, "GET_TEMPLATE_VERSION").await);
+    assert_eq!(version(request(node, "GET_TEMPLATE_VERSION").await), before);
+    request(node, &format!("GENERATE:1:{}", RECEIVER)).await;
+    assert!(version(request(node, "GET_TEMPLATE_VERSION").await) > before);
+}
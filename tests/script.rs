@@ -0,0 +1,163 @@
+//! Runs spends through the script interpreter, up to and past its limits.
+
+use fluerion::script::{
+    self, Context, Op, MAX_ELEMENT_SIZE, MAX_OPS, MAX_STACK_SIZE, OP_0, OP_1, OP_CHECKMULTISIG,
+    OP_CHECKSIG, OP_DROP, OP_DUP, OP_ELSE, OP_ENDIF, OP_NOTIF,
+};
+use k256::ecdsa::signature::Signer;
+use k256::ecdsa::{Signature, SigningKey};
+
+const DATA: &[u8] = b"spend";
+const CONTEXT: Context = Context { height: 1, time: 0 };
+
+fn key(seed: u8) -> SigningKey {
+    SigningKey::from_bytes(&[seed; 32].into()).unwrap()
+}
+
+fn pubkey(key: &SigningKey) -> Vec<u8> {
+    key.verifying_key()
+        .to_encoded_point(true)
+        .as_bytes()
+        .to_vec()
+}
+
+fn sign(key: &SigningKey, data: &[u8]) -> Vec<u8> {
+    let signature: Signature = key.sign(data);
+    signature.to_bytes().to_vec()
+}
+
+fn run(unlocking: &[Op], locking: &[Op]) -> Result<(), String> {
+    script::verify(
+        &script::build(unlocking),
+        &script::build(locking),
+        DATA,
+        &CONTEXT,
+    )
+}
+
+/// A 1-of-1 multisig check whose failure the script turns into success.
+fn inverted_multisig(key: &SigningKey) -> Vec<Op> {
+    vec![
+        Op::Code(OP_1),
+        Op::Push(pubkey(key)),
+        Op::Code(OP_1),
+        Op::Code(OP_CHECKMULTISIG),
+        Op::Code(OP_NOTIF),
+        Op::Code(OP_1),
+        Op::Code(OP_ELSE),
+        Op::Code(OP_0),
+        Op::Code(OP_ENDIF),
+    ]
+}
+
+#[test]
+fn failing_multisig_takes_only_empty_signatures() {
+    let signer = key(1);
+    let locking = inverted_multisig(&signer);
+
+    assert_eq!(run(&[Op::Code(OP_0)], &locking), Ok(()));
+
+    let wrong_key = sign(&key(2), DATA);
+    let e = run(&[Op::Push(wrong_key)], &locking).unwrap_err();
+    assert!(e.contains("non-empty signatures"), "{}", e);
+
+    let wrong_data = sign(&signer, b"other");
+    assert!(run(&[Op::Push(wrong_data)], &locking).is_err());
+
+    let malformed = vec![1; 10];
+    assert!(run(&[Op::Push(malformed)], &locking).is_err());
+
+    let valid = sign(&signer, DATA);
+    let e = run(&[Op::Push(valid)], &locking).unwrap_err();
+    assert_eq!(e, "Script evaluated to false");
+}
+
+#[test]
+fn failing_checksig_takes_only_an_empty_signature() {
+    let signer = key(3);
+    let locking = [
+        Op::Push(pubkey(&signer)),
+        Op::Code(OP_CHECKSIG),
+        Op::Code(OP_NOTIF),
+        Op::Code(OP_1),
+        Op::Code(OP_ELSE),
+        Op::Code(OP_0),
+        Op::Code(OP_ENDIF),
+    ];
+    assert_eq!(run(&[Op::Code(OP_0)], &locking), Ok(()));
+    assert!(run(&[Op::Push(sign(&key(4), DATA))], &locking).is_err());
+}
+
+#[test]
+fn multisig_matches_signatures_in_key_order() {
+    let keys = [key(5), key(6), key(7)];
+    let locking = [
+        Op::Code(OP_1 + 1),
+        Op::Push(pubkey(&keys[0])),
+        Op::Push(pubkey(&keys[1])),
+        Op::Push(pubkey(&keys[2])),
+        Op::Code(OP_1 + 2),
+        Op::Code(OP_CHECKMULTISIG),
+    ];
+    let first = sign(&keys[0], DATA);
+    let third = sign(&keys[2], DATA);
+    assert_eq!(
+        run(
+            &[Op::Push(first.clone()), Op::Push(third.clone())],
+            &locking
+        ),
+        Ok(())
+    );
+    assert!(run(&[Op::Push(third), Op::Push(first)], &locking).is_err());
+}
+
+/// `pairs` rounds of `OP_DUP OP_DROP` on top of a true value.
+fn dup_drop(pairs: usize) -> Vec<Op> {
+    (0..pairs)
+        .flat_map(|_| [Op::Code(OP_DUP), Op::Code(OP_DROP)])
+        .collect()
+}
+
+#[test]
+fn stops_at_the_operation_limit() {
+    let unlocking = [Op::Code(OP_1)];
+    assert_eq!(run(&unlocking, &dup_drop(MAX_OPS / 2)), Ok(()));
+    let e = run(&unlocking, &dup_drop(MAX_OPS / 2 + 1)).unwrap_err();
+    assert_eq!(e, "Operation limit exceeded");
+}
+
+#[test]
+fn counts_every_multisig_key_as_an_operation() {
+    let keys: Vec<SigningKey> = (1..=15).map(key).collect();
+    let mut locking = dup_drop((MAX_OPS - 16) / 2);
+    locking.push(Op::Code(OP_1));
+    locking.extend(keys.iter().map(|key| Op::Push(pubkey(key))));
+    locking.push(Op::Push(script::encode_num(keys.len() as i64)));
+    locking.push(Op::Code(OP_CHECKMULTISIG));
+    let unlocking = [Op::Push(sign(&keys[0], DATA))];
+    assert_eq!(run(&unlocking, &locking), Ok(()));
+
+    let locking: Vec<Op> = dup_drop(1).into_iter().chain(locking).collect();
+    let e = run(&unlocking, &locking).unwrap_err();
+    assert_eq!(e, "Operation limit exceeded");
+}
+
+#[test]
+fn stops_at_the_stack_limit() {
+    let full: Vec<Op> = (0..MAX_STACK_SIZE).map(|_| Op::Code(OP_1)).collect();
+    assert_eq!(run(&full, &[]), Ok(()));
+    let e = run(&full, &[Op::Code(OP_DUP)]).unwrap_err();
+    assert_eq!(e, "Stack size limit exceeded");
+
+    let over: Vec<Op> = (0..=MAX_STACK_SIZE).map(|_| Op::Code(OP_1)).collect();
+    let e = run(&over, &[]).unwrap_err();
+    assert_eq!(e, "Stack size limit exceeded");
+}
+
+#[test]
+fn stops_at_the_element_limit() {
+    let largest = [Op::Push(vec![1; MAX_ELEMENT_SIZE])];
+    assert_eq!(run(&largest, &[Op::Code(OP_DROP), Op::Code(OP_1)]), Ok(()));
+    let e = run(&[Op::Push(vec![1; MAX_ELEMENT_SIZE + 1])], &[]).unwrap_err();
+    assert_eq!(e, "Push exceeds the element size limit");
+}
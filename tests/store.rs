@@ -0,0 +1,412 @@
+//! Conformance suite every `ChainStore` backend must pass, plus checks that
+//! a `Blockchain` comes back the same after reopening its store.
+
+use fluerion::block::Block;
+use fluerion::blockchain::Blockchain;
+use fluerion::chainparams::{self, ChainParams};
+use fluerion::hash::{hash_to_hex, Hash256};
+use fluerion::store::{ChainState, ChainStore, FlatFileStore, KvStore, MemoryStore};
+use fluerion::transaction::Transaction;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::PathBuf;
+
+const MINER: &str = "flrt14sdcqezufk9g8pnn0sve5yk2wqfllsg02qxxv2";
+const OTHER_MINER: &str = "flrt13v0g8knefk035awswd2pvthk4wsd3w55kgxdzx";
+
+/// A fresh directory for one test.
+fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("fluerion-store-{}-{}", std::process::id(), name));
+    let _ = fs::remove_dir_all(&dir);
+    dir
+}
+
+/// A block on top of `prev` whose second transaction carries `payload`.
+fn block(prev: Hash256, payload: &[u8], nonce: u64) -> Block {
+    let coinbase = Transaction::coinbase(MINER.to_string(), 50.0, nonce, 0);
+    let mut data = Transaction::new(MINER.to_string(), MINER.to_string(), 1.0 + nonce as f64);
+    data.data = Some(hex::encode(payload));
+    let mut block = Block::new(vec![coinbase, data], prev);
+    block.set_nonce(nonce);
+    block.set_hash(block.calculate_hash());
+    block
+}
+
+/// Three linked blocks; the first and last carry the same payload.
+fn chain() -> Vec<Block> {
+    let genesis = block([0; 32], b"genesis", 0);
+    let first = block(genesis.get_hash(), b"repeated", 1);
+    let second = block(first.get_hash(), b"repeated", 2);
+    vec![genesis, first, second]
+}
+
+fn filter_of(block: &Block) -> (Vec<u8>, Hash256) {
+    (block.get_hash()[..4].to_vec(), block.get_prev_hash())
+}
+
+fn push_all(store: &mut dyn ChainStore, blocks: &[Block]) {
+    for block in blocks {
+        let (filter, header) = filter_of(block);
+        store.push_block(block, &filter, &header).unwrap();
+    }
+}
+
+fn assert_holds(store: &dyn ChainStore, blocks: &[Block]) {
+    assert_eq!(store.block_count().unwrap(), blocks.len() as u64);
+    for (height, block) in blocks.iter().enumerate() {
+        let height = height as u64;
+        let stored = store.block(height).unwrap().unwrap();
+        assert_eq!(stored.get_hash(), block.get_hash());
+        assert_eq!(
+            stored.get_transactions().len(),
+            block.get_transactions().len()
+        );
+        assert_eq!(store.header(height).unwrap(), Some(block.block_header()));
+        assert_eq!(store.filter(height).unwrap(), Some(filter_of(block)));
+        assert_eq!(store.height_of(&block.get_hash()).unwrap(), Some(height));
+    }
+    let end = blocks.len() as u64;
+    assert!(store.block(end).unwrap().is_none());
+    assert!(store.header(end).unwrap().is_none());
+    assert!(store.filter(end).unwrap().is_none());
+}
+
+fn starts_empty(store: &mut dyn ChainStore) {
+    assert_eq!(store.block_count().unwrap(), 0);
+    assert!(store.block(0).unwrap().is_none());
+    assert!(store.header(0).unwrap().is_none());
+    assert!(store.filter(0).unwrap().is_none());
+    assert!(store.height_of(&[1; 32]).unwrap().is_none());
+    assert!(store.data_positions(b"repeated").unwrap().is_empty());
+    assert!(store.chain_state().unwrap().is_none());
+}
+
+fn keeps_blocks(store: &mut dyn ChainStore) {
+    let blocks = chain();
+    push_all(store, &blocks);
+    assert_holds(store, &blocks);
+}
+
+fn indexes_data(store: &mut dyn ChainStore) {
+    push_all(store, &chain());
+    assert_eq!(
+        store.data_positions(b"repeated").unwrap(),
+        vec![(1, 1), (2, 1)]
+    );
+    assert_eq!(store.data_positions(b"genesis").unwrap(), vec![(0, 1)]);
+    assert!(store.data_positions(b"repeat").unwrap().is_empty());
+    assert!(store.data_positions(b"repeatedly").unwrap().is_empty());
+}
+
+fn truncates(store: &mut dyn ChainStore) {
+    let blocks = chain();
+    push_all(store, &blocks);
+    store.truncate(1).unwrap();
+    assert_holds(store, &blocks[..1]);
+    assert!(store.height_of(&blocks[2].get_hash()).unwrap().is_none());
+    assert!(store.data_positions(b"repeated").unwrap().is_empty());
+
+    // Truncating past the end changes nothing.
+    store.truncate(5).unwrap();
+    assert_eq!(store.block_count().unwrap(), 1);
+
+    let other = block(blocks[0].get_hash(), b"other", 7);
+    push_all(store, std::slice::from_ref(&other));
+    assert_holds(store, &[blocks[0].clone(), other]);
+    assert_eq!(store.data_positions(b"other").unwrap(), vec![(1, 1)]);
+}
+
+fn index_all(store: &mut dyn ChainStore, blocks: &[Block]) {
+    for (height, block) in blocks.iter().enumerate() {
+        store.index_transactions(height as u64, block).unwrap();
+    }
+}
+
+/// Every transaction in `blocks` is found where it sits.
+fn assert_indexed(store: &dyn ChainStore, blocks: &[Block]) {
+    for (height, block) in blocks.iter().enumerate() {
+        for (i, tx) in block.get_transactions().iter().enumerate() {
+            assert_eq!(
+                store.transaction_position(&tx.id()).unwrap(),
+                Some((height as u64, i))
+            );
+        }
+    }
+}
+
+fn indexes_transactions(store: &mut dyn ChainStore) {
+    let blocks = chain();
+    push_all(store, &blocks);
+    assert!(store
+        .transaction_position(&blocks[1].get_transactions()[1].id())
+        .unwrap()
+        .is_none());
+    index_all(store, &blocks);
+    assert_indexed(store, &blocks);
+
+    store.truncate(2).unwrap();
+    assert_indexed(store, &blocks[..2]);
+    let dropped = blocks[2].get_transactions()[0].id();
+    assert!(store.transaction_position(&dropped).unwrap().is_none());
+
+    store.clear_transaction_index().unwrap();
+    let genesis = blocks[0].get_transactions()[0].id();
+    assert!(store.transaction_position(&genesis).unwrap().is_none());
+}
+
+fn keeps_chain_state(store: &mut dyn ChainStore) {
+    let blocks = chain();
+    let state = ChainState {
+        tip: blocks[2].get_hash(),
+        invalid: vec![[9; 32]],
+        side_blocks: vec![blocks[1].clone()],
+        ..ChainState::default()
+    };
+    store.set_chain_state(&state).unwrap();
+    let stored = store.chain_state().unwrap().unwrap();
+    assert_eq!(stored.tip, state.tip);
+    assert_eq!(stored.invalid, state.invalid);
+    assert_eq!(stored.side_blocks.len(), 1);
+    assert_eq!(stored.side_blocks[0].get_hash(), blocks[1].get_hash());
+
+    let state = ChainState {
+        tip: blocks[0].get_hash(),
+        ..ChainState::default()
+    };
+    store.set_chain_state(&state).unwrap();
+    let stored = store.chain_state().unwrap().unwrap();
+    assert_eq!(stored.tip, blocks[0].get_hash());
+    assert!(stored.invalid.is_empty());
+    assert!(stored.side_blocks.is_empty());
+}
+
+/// Runs the suite against a backend, each check on a store of its own.
+macro_rules! conformance {
+    ($backend:ident, $open:expr) => {
+        mod $backend {
+            use super::*;
+
+            /// Runs `check` on a store of its own, then removes its files.
+            fn run(name: &str, check: fn(&mut dyn ChainStore)) {
+                let dir = temp_dir(&format!("{}-{}", stringify!($backend), name));
+                #[allow(clippy::redundant_closure_call)]
+                let mut store: Box<dyn ChainStore> = ($open)(dir.clone());
+                check(store.as_mut());
+                drop(store);
+                let _ = fs::remove_dir_all(dir);
+            }
+
+            #[test]
+            fn starts_empty() {
+                run("empty", super::starts_empty);
+            }
+
+            #[test]
+            fn keeps_blocks() {
+                run("blocks", super::keeps_blocks);
+            }
+
+            #[test]
+            fn indexes_data() {
+                run("data", super::indexes_data);
+            }
+
+            #[test]
+            fn truncates() {
+                run("truncate", super::truncates);
+            }
+
+            #[test]
+            fn indexes_transactions() {
+                run("txindex", super::indexes_transactions);
+            }
+
+            #[test]
+            fn keeps_chain_state() {
+                run("state", super::keeps_chain_state);
+            }
+        }
+    };
+}
+
+conformance!(memory, |_| Box::new(MemoryStore::default()));
+conformance!(flat_file, |dir: PathBuf| Box::new(
+    FlatFileStore::open(&dir).unwrap()
+));
+conformance!(kv, |dir: PathBuf| Box::new(KvStore::open(&dir).unwrap()));
+
+/// What a persistent store held is there again after reopening it.
+fn survives_reopening(open: fn(&PathBuf) -> Box<dyn ChainStore>, name: &str) {
+    let dir = temp_dir(name);
+    let blocks = chain();
+    {
+        let mut store = open(&dir);
+        push_all(store.as_mut(), &blocks);
+        index_all(store.as_mut(), &blocks);
+        store.truncate(2).unwrap();
+        let state = ChainState {
+            tip: blocks[1].get_hash(),
+            ..ChainState::default()
+        };
+        store.set_chain_state(&state).unwrap();
+    }
+    let store = open(&dir);
+    assert_holds(store.as_ref(), &blocks[..2]);
+    assert_eq!(store.data_positions(b"repeated").unwrap(), vec![(1, 1)]);
+    assert_indexed(store.as_ref(), &blocks[..2]);
+    let dropped = blocks[2].get_transactions()[0].id();
+    assert!(store.transaction_position(&dropped).unwrap().is_none());
+    assert_eq!(
+        store.chain_state().unwrap().unwrap().tip,
+        blocks[1].get_hash()
+    );
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn flat_file_survives_reopening() {
+    survives_reopening(
+        |dir| Box::new(FlatFileStore::open(dir).unwrap()),
+        "flat-file-reopen",
+    );
+}
+
+#[test]
+fn kv_survives_reopening() {
+    survives_reopening(|dir| Box::new(KvStore::open(dir).unwrap()), "kv-reopen");
+}
+
+#[test]
+fn flat_file_drops_a_torn_record() {
+    let dir = temp_dir("flat-file-torn");
+    let blocks = chain();
+    {
+        let mut store = FlatFileStore::open(&dir).unwrap();
+        push_all(&mut store, &blocks[..2]);
+    }
+    let mut file = OpenOptions::new()
+        .append(true)
+        .open(dir.join("blocks.dat"))
+        .unwrap();
+    file.write_all(&[200, 0, 0, 0, b'{']).unwrap();
+
+    let mut store = FlatFileStore::open(&dir).unwrap();
+    assert_holds(&store, &blocks[..2]);
+    push_all(&mut store, &blocks[2..]);
+    drop(store);
+    assert_holds(&FlatFileStore::open(&dir).unwrap(), &blocks);
+    fs::remove_dir_all(dir).unwrap();
+}
+
+/// A chain mined, partly invalidated and reopened comes back with the same
+/// tip, balances and invalid blocks.
+fn blockchain_survives_reopening(open: fn(&PathBuf) -> Box<dyn ChainStore>, name: &str) {
+    let _ = chainparams::select(ChainParams::regtest());
+    let dir = temp_dir(name);
+    let third = {
+        let mut blockchain = Blockchain::open(open(&dir), false).unwrap();
+        let hashes = blockchain.generate(3, MINER).unwrap();
+        blockchain.invalidate_block(&hashes[2]).unwrap();
+        hashes[2]
+    };
+
+    let mut blockchain = Blockchain::open(open(&dir), false).unwrap();
+    assert_eq!(blockchain.height(), 2);
+    assert_eq!(blockchain.get_balance(MINER), 100.0);
+    assert!(blockchain.is_valid());
+    blockchain.reconsider_block(&third).unwrap();
+    assert_eq!(blockchain.get_latest_block().get_hash(), third);
+    drop(blockchain);
+
+    let blockchain = Blockchain::open(open(&dir), false).unwrap();
+    assert_eq!(blockchain.height(), 3);
+    assert_eq!(blockchain.get_balance(MINER), 150.0);
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn blockchain_survives_reopening_flat_file() {
+    blockchain_survives_reopening(
+        |dir| Box::new(FlatFileStore::open(dir).unwrap()),
+        "blockchain-flat-file",
+    );
+}
+
+#[test]
+fn blockchain_survives_reopening_kv() {
+    blockchain_survives_reopening(|dir| Box::new(KvStore::open(dir).unwrap()), "blockchain-kv");
+}
+
+/// The transaction index is built when first asked for, kept up as the
+/// chain grows and rebuilt when the node ran a while without it.
+fn blockchain_keeps_txindex(open: fn(&PathBuf) -> Box<dyn ChainStore>, name: &str) {
+    let _ = chainparams::select(ChainParams::regtest());
+    let dir = temp_dir(name);
+    let coinbase_at = |blockchain: &Blockchain, height| {
+        blockchain.block_at(height).unwrap().get_transactions()[0].id()
+    };
+
+    let first = {
+        let mut blockchain = Blockchain::open(open(&dir), false).unwrap();
+        blockchain.generate(2, MINER).unwrap();
+        let first = coinbase_at(&blockchain, 1);
+        let e = blockchain.get_transaction(&first).unwrap_err();
+        assert!(e.contains("--txindex"), "{}", e);
+        first
+    };
+
+    let (second, third) = {
+        let mut blockchain = Blockchain::open(open(&dir), true).unwrap();
+        let info = blockchain.get_transaction(&first).unwrap();
+        assert!(!info.in_mempool);
+        assert_eq!(info.height, Some(1));
+        assert_eq!(info.position, Some(0));
+        assert_eq!(info.confirmations, 2);
+
+        let hashes = blockchain.generate(1, OTHER_MINER).unwrap();
+        let third = coinbase_at(&blockchain, 3);
+        let info = blockchain.get_transaction(&third).unwrap();
+        assert_eq!(info.block_hash, Some(hash_to_hex(&hashes[0])));
+        (coinbase_at(&blockchain, 2), third)
+    };
+
+    {
+        // A block taken off while the index is off.
+        let mut blockchain = Blockchain::open(open(&dir), false).unwrap();
+        let tip = blockchain.get_latest_block().get_hash();
+        blockchain.invalidate_block(&tip).unwrap();
+    }
+
+    let blockchain = Blockchain::open(open(&dir), true).unwrap();
+    let e = blockchain.get_transaction(&third).unwrap_err();
+    assert!(e.contains("Unknown transaction"), "{}", e);
+    let info = blockchain.get_transaction(&second).unwrap();
+    assert_eq!(info.height, Some(2));
+    assert_eq!(info.confirmations, 1);
+    drop(blockchain);
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn blockchain_keeps_txindex_flat_file() {
+    blockchain_keeps_txindex(
+        |dir| Box::new(FlatFileStore::open(dir).unwrap()),
+        "txindex-flat-file",
+    );
+}
+
+#[test]
+fn blockchain_keeps_txindex_kv() {
+    blockchain_keeps_txindex(|dir| Box::new(KvStore::open(dir).unwrap()), "txindex-kv");
+}
+
+#[test]
+fn blockchain_refuses_another_networks_store() {
+    let _ = chainparams::select(ChainParams::regtest());
+    let mut store = MemoryStore::default();
+    let foreign = block([0; 32], b"elsewhere", 0);
+    let (filter, header) = filter_of(&foreign);
+    store.push_block(&foreign, &filter, &header).unwrap();
+    let e = Blockchain::open(Box::new(store), false).err().unwrap();
+    assert!(e.contains("not regtest's"), "{}", e);
+}
@@ -0,0 +1,230 @@
+//! Token operations as `Ledger::apply` takes them, signatures aside.
+
+use fluerion::hash::hash_to_hex;
+use fluerion::token::{Ledger, TokenOp};
+use fluerion::transaction::Transaction;
+
+const ISSUER: &str = "issuer";
+const HOLDER: &str = "holder";
+const OTHER: &str = "other";
+
+fn op(sender: &str, receiver: &str, token: TokenOp) -> Transaction {
+    let mut tx = Transaction::new(sender.to_string(), receiver.to_string(), 0.0);
+    tx.timestamp = 1_704_067_300;
+    tx.token = Some(token);
+    tx
+}
+
+fn issue(supply: u64, mintable: bool) -> Transaction {
+    op(
+        ISSUER,
+        ISSUER,
+        TokenOp::Issue {
+            name: "Gold".to_string(),
+            symbol: "GLD".to_string(),
+            decimals: 2,
+            supply,
+            mintable,
+        },
+    )
+}
+
+fn transfer(sender: &str, receiver: &str, token: &str, amount: u64) -> Transaction {
+    let token = token.to_string();
+    op(sender, receiver, TokenOp::Transfer { token, amount })
+}
+
+fn mint(sender: &str, receiver: &str, token: &str, amount: u64) -> Transaction {
+    let token = token.to_string();
+    op(sender, receiver, TokenOp::Mint { token, amount })
+}
+
+fn burn(sender: &str, token: &str, amount: u64) -> Transaction {
+    let token = token.to_string();
+    op(sender, sender, TokenOp::Burn { token, amount })
+}
+
+/// A ledger holding one token issued to `ISSUER`, and the token's id.
+fn issued(supply: u64, mintable: bool) -> (Ledger, String) {
+    let mut ledger = Ledger::default();
+    let tx = issue(supply, mintable);
+    ledger.apply(&tx).unwrap();
+    (ledger, hash_to_hex(&tx.token_id()))
+}
+
+/// Applies `tx`, which must fail with an error containing `expected`, and
+/// checks the ledger is left as it was.
+fn refuses(ledger: &mut Ledger, tx: Transaction, expected: &str) {
+    let before = serde_json::to_value(&*ledger).unwrap();
+    let e = ledger.apply(&tx).unwrap_err();
+    assert!(e.contains(expected), "{}", e);
+    assert_eq!(serde_json::to_value(&*ledger).unwrap(), before);
+}
+
+#[test]
+fn issues_a_token_to_the_receiver() {
+    let mut ledger = Ledger::default();
+    let tx = op(
+        ISSUER,
+        HOLDER,
+        TokenOp::Issue {
+            name: "Gold".to_string(),
+            symbol: "GLD".to_string(),
+            decimals: 2,
+            supply: 1000,
+            mintable: false,
+        },
+    );
+    ledger.apply(&tx).unwrap();
+
+    let id = hash_to_hex(&tx.token_id());
+    let token = ledger.token(&id).unwrap();
+    assert_eq!((token.symbol.as_str(), token.decimals), ("GLD", 2));
+    assert_eq!((token.issuer.as_str(), token.supply), (ISSUER, 1000));
+    assert!(!token.mintable);
+    assert_eq!(ledger.balance(&id, HOLDER), 1000);
+    assert_eq!(ledger.balance(&id, ISSUER), 0);
+
+    refuses(&mut ledger, tx, "already issued");
+}
+
+#[test]
+fn names_a_token_apart_from_the_signature() {
+    let mut tx = issue(1000, false);
+    tx.signature = Some("ab".repeat(97));
+    let id = tx.token_id();
+    tx.signature = Some("cd".repeat(97));
+    assert_eq!(tx.token_id(), id);
+    assert_ne!(tx.id(), id);
+
+    // The same issue sent again, with the next nonce, is another token.
+    tx.nonce += 1;
+    assert_ne!(tx.token_id(), id);
+}
+
+#[test]
+fn checks_what_is_issued() {
+    let mut ledger = Ledger::default();
+    let with = |change: &dyn Fn(&mut String, &mut String, &mut u8, &mut u64)| {
+        let (mut name, mut symbol, mut decimals, mut supply) =
+            ("Gold".to_string(), "GLD".to_string(), 2, 1000);
+        change(&mut name, &mut symbol, &mut decimals, &mut supply);
+        op(
+            ISSUER,
+            ISSUER,
+            TokenOp::Issue {
+                name,
+                symbol,
+                decimals,
+                supply,
+                mintable: false,
+            },
+        )
+    };
+    refuses(&mut ledger, with(&|name, _, _, _| name.clear()), "name");
+    refuses(
+        &mut ledger,
+        with(&|name, _, _, _| *name = "G".repeat(33)),
+        "name",
+    );
+    refuses(&mut ledger, with(&|name, _, _, _| name.push('\n')), "name");
+    refuses(
+        &mut ledger,
+        with(&|_, symbol, _, _| *symbol = "GL-D".to_string()),
+        "symbol",
+    );
+    refuses(
+        &mut ledger,
+        with(&|_, symbol, _, _| *symbol = "G".repeat(9)),
+        "symbol",
+    );
+    refuses(
+        &mut ledger,
+        with(&|_, _, decimals, _| *decimals = 19),
+        "Decimals",
+    );
+    refuses(
+        &mut ledger,
+        with(&|_, _, _, supply| *supply = 0),
+        "needs a supply",
+    );
+    // A mintable token may start out empty.
+    ledger.apply(&issue(0, true)).unwrap();
+}
+
+#[test]
+fn transfers_between_holders() {
+    let (mut ledger, id) = issued(1000, false);
+    ledger.apply(&transfer(ISSUER, HOLDER, &id, 300)).unwrap();
+    ledger.apply(&transfer(HOLDER, OTHER, &id, 100)).unwrap();
+    assert_eq!(ledger.balance(&id, ISSUER), 700);
+    assert_eq!(ledger.balance(&id, HOLDER), 200);
+    assert_eq!(ledger.balance(&id, OTHER), 100);
+    assert_eq!(ledger.token(&id).unwrap().supply, 1000);
+
+    refuses(
+        &mut ledger,
+        transfer(HOLDER, OTHER, &id, 201),
+        "Insufficient token balance",
+    );
+    refuses(
+        &mut ledger,
+        transfer(HOLDER, OTHER, &id, 0),
+        "must be positive",
+    );
+    refuses(
+        &mut ledger,
+        transfer(HOLDER, OTHER, &"00".repeat(32), 1),
+        "Unknown token",
+    );
+}
+
+#[test]
+fn mints_only_for_the_issuer_of_a_mintable_token() {
+    let (mut ledger, id) = issued(1000, true);
+    ledger.apply(&mint(ISSUER, HOLDER, &id, 500)).unwrap();
+    assert_eq!(ledger.balance(&id, HOLDER), 500);
+    assert_eq!(ledger.token(&id).unwrap().supply, 1500);
+
+    refuses(
+        &mut ledger,
+        mint(HOLDER, HOLDER, &id, 1),
+        "Only the issuer can mint",
+    );
+    refuses(&mut ledger, mint(ISSUER, HOLDER, &id, u64::MAX), "overflow");
+
+    let (mut fixed, fixed_id) = issued(1000, false);
+    refuses(
+        &mut fixed,
+        mint(ISSUER, ISSUER, &fixed_id, 1),
+        "cannot be minted",
+    );
+}
+
+#[test]
+fn burns_only_from_the_issuer_s_own_balance() {
+    let (mut ledger, id) = issued(1000, false);
+    ledger.apply(&transfer(ISSUER, HOLDER, &id, 400)).unwrap();
+    ledger.apply(&burn(ISSUER, &id, 250)).unwrap();
+    assert_eq!(ledger.balance(&id, ISSUER), 350);
+    assert_eq!(ledger.token(&id).unwrap().supply, 750);
+
+    refuses(
+        &mut ledger,
+        burn(HOLDER, &id, 1),
+        "Only the issuer can burn",
+    );
+    refuses(
+        &mut ledger,
+        burn(ISSUER, &id, 351),
+        "Insufficient token balance",
+    );
+    refuses(&mut ledger, burn(ISSUER, &id, 0), "must be positive");
+}
+
+#[test]
+fn lets_plain_payments_through() {
+    let (mut ledger, _) = issued(1000, false);
+    let payment = Transaction::new(HOLDER.to_string(), OTHER.to_string(), 5.0);
+    ledger.apply(&payment).unwrap();
+}